@@ -3,21 +3,48 @@ use std::{
     ops::Deref,
 };
 
+use serde::{Deserialize, Serialize};
+
 use crate::common::{AllocMode, StructKind};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
 pub struct Position {
     pub line: u32,
     pub col: usize,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
 pub struct Range {
     pub from: Position,
     pub to: Position,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+// ソースファイルの番号。`hirou` に渡したファイル (パッケージではエントリー) が MAIN、埋め込みの標準ライブラリが STDLIB、
+// `--extern` で読み込んだライブラリはその順に続く。パッケージのそれ以外のソースファイルは別の範囲から数える
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct FileId(pub u32);
+
+impl FileId {
+    pub const MAIN: FileId = FileId(0);
+    pub const STDLIB: FileId = FileId(1);
+
+    pub fn library(index: usize) -> FileId {
+        FileId(2 + index as u32)
+    }
+
+    pub fn package_source(index: usize) -> FileId {
+        FileId(0x8000_0000 + index as u32)
+    }
+}
+
+// ファイルを含めたソース上の位置。resolver より後の段階はこれで位置を持ち回る
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub file: FileId,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Located<T> {
     pub range: Range,
     pub value: T,
@@ -95,12 +122,20 @@ impl<T> Located<Box<T>> {
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum BinaryOp {
     Add,
     Sub,
     Mul,
     Div,
+    Rem,
+    BitAnd,
+    BitOr,
+    BitXor,
+    // 左シフト
+    Shl,
+    // 右シフト。符号付き整数なら算術シフト、符号なし整数なら論理シフトになる
+    Shr,
     Equals,
     NotEquals,
     LessThan,
@@ -109,18 +144,22 @@ pub enum BinaryOp {
     GreaterThanOrEquals,
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum UnaryOp {
     Not,
+    // 符号の反転 `(- x)`
+    Neg,
+    // ビット反転 `(~ x)`
+    BitNot,
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum MultiOp {
     And,
     Or,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct NamespacePath {
     pub segments: Vec<String>,
 }
@@ -139,113 +178,171 @@ impl NamespacePath {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CallExpr {
     pub name: NamespacePath,
     pub generic_args: Option<Vec<Located<UnresolvedType>>>,
     pub args: Vec<LocatedExpr>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SizeOfExpr {
     pub ty: Located<UnresolvedType>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CastExpr {
+    pub ty: Located<UnresolvedType>,
+    pub target: LocatedExpr,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VariableRefExpr {
     pub name: String,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct NumberLiteralExpr {
     pub value: String,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StringLiteralExpr {
     pub value: String,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BoolLiteralExpr {
     pub value: bool,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StructLiteralExpr {
     pub name: String,
     pub generic_args: Option<Vec<Located<UnresolvedType>>>,
     pub fields: Vec<(String, LocatedExpr)>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ArrayLiteralExpr {
     pub elements: Vec<LocatedExpr>,
 }
 
+// `(tuple a b ...)`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TupleLiteralExpr {
+    pub elements: Vec<LocatedExpr>,
+}
+
 pub type LocatedExpr = Located<Box<Expression>>;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BinaryExpr {
     pub op: BinaryOp,
     pub lhs: LocatedExpr,
     pub rhs: LocatedExpr,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct UnaryExpr {
     pub op: UnaryOp,
     pub operand: LocatedExpr,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MultiExpr {
     pub op: MultiOp,
     pub operands: Vec<LocatedExpr>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DerefExpr {
     pub target: LocatedExpr,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AddressOfExpr {
     pub target: LocatedExpr,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct IndexAccessExpr {
     pub target: LocatedExpr,
     pub index: LocatedExpr,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FieldAccessExpr {
     pub target: LocatedExpr,
     pub field_name: String,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct IfExpr {
     pub cond: LocatedExpr,
     pub then: LocatedExpr,
     pub els: LocatedExpr,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct WhenExpr {
     pub cond: LocatedExpr,
     pub then: LocatedExpr,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+// `(do e1 e2 ... en)`: 文を順に実行し、最後の式の値を返す
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DoExpr {
+    pub statements: Vec<Located<Statement>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct WhileExpr {
+    // `(while 'outer cond body)` のラベル
+    pub label: Option<String>,
     pub cond: LocatedExpr,
     pub body: LocatedExpr,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+// `for` で繰り返す対象
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ForIter {
+    // `(for i start end body)`: start から end の手前までの整数
+    Range { start: LocatedExpr, end: LocatedExpr },
+    // `(for x collection body)`: iter-len / iter-get を実装した値の各要素
+    Collection(LocatedExpr),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForExpr {
+    pub label: Option<String>,
+    pub var: String,
+    pub iter: ForIter,
+    pub body: LocatedExpr,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BreakExpr {
+    pub label: Option<String>,
+    // `(break value)` でループの値を返す
+    pub value: Option<LocatedExpr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContinueExpr {
+    pub label: Option<String>,
+}
+
+// `(fn (x: i32) [: T] body...)`: 外側の変数を値としてコピーして取り込む関数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LambdaExpr {
+    pub args: Vec<(String, Located<UnresolvedType>)>,
+    // 省略した場合は本体の値から推論する
+    pub return_type: Option<Located<UnresolvedType>>,
+    pub body: Vec<Located<Statement>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AssignExpr {
     pub deref_count: u32,
     pub index_access: Option<LocatedExpr>,
@@ -253,27 +350,36 @@ pub struct AssignExpr {
     pub value: LocatedExpr,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+// `:=` で宣言する変数。`(a b)` と書くとタプルを分解してそれぞれの変数に束縛する
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum VariablePattern {
+    Name(String),
+    Tuple(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VariableDecl {
-    pub name: String,
+    pub pattern: VariablePattern,
     pub ty: Option<Located<UnresolvedType>>,
     pub value: LocatedExpr,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VariableDeclsExpr {
     pub decls: Vec<Located<VariableDecl>>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Expression {
     SizeOf(SizeOfExpr),
+    Cast(CastExpr),
     VariableRef(VariableRefExpr),
     NumberLiteral(NumberLiteralExpr),
     StringLiteral(StringLiteralExpr),
     BoolLiteral(BoolLiteralExpr),
     StructLiteral(StructLiteralExpr),
     ArrayLiteral(ArrayLiteralExpr),
+    TupleLiteral(TupleLiteralExpr),
     Binary(BinaryExpr),
     Unary(UnaryExpr),
     Multi(MultiExpr),
@@ -284,21 +390,39 @@ pub enum Expression {
     FieldAccess(FieldAccessExpr),
     If(IfExpr),
     When(WhenExpr),
+    Do(DoExpr),
     While(WhileExpr),
+    For(ForExpr),
+    Break(BreakExpr),
+    Continue(ContinueExpr),
+    Lambda(LambdaExpr),
     Assignment(AssignExpr),
     VariableDecl(VariableDeclsExpr),
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct TypeRef {
     pub name: String,
     pub generic_args: Option<Vec<Located<UnresolvedType>>>,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+// `fn(i32, i32): i32`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+pub struct FunctionType {
+    pub args: Vec<Located<UnresolvedType>>,
+    pub return_type: Box<Located<UnresolvedType>>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub enum UnresolvedType {
     TypeRef(TypeRef),
     Ptr(Box<Located<UnresolvedType>>),
+    Dyn(String),
+    Function(FunctionType),
+    // `(i32, bool)`
+    Tuple(Vec<Located<UnresolvedType>>),
+    // `[i32; 4]`
+    Array(Box<Located<UnresolvedType>>, usize),
     Infer,
 }
 
@@ -320,6 +444,32 @@ impl Display for UnresolvedType {
                 write!(f, "{}", inner_type.value)?;
                 f.write_char(']')?;
             }
+            UnresolvedType::Dyn(interface_name) => {
+                write!(f, "dyn {}", interface_name)?;
+            }
+            UnresolvedType::Function(function_ty) => {
+                f.write_str("fn(")?;
+                for (i, arg) in function_ty.args.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", arg.value)?;
+                }
+                write!(f, "): {}", function_ty.return_type.value)?;
+            }
+            UnresolvedType::Tuple(elements) => {
+                f.write_char('(')?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", element.value)?;
+                }
+                f.write_char(')')?;
+            }
+            UnresolvedType::Array(element, len) => {
+                write!(f, "[{}; {}]", element.value, len)?;
+            }
             UnresolvedType::Infer => {
                 f.write_str("_")?;
             }
@@ -328,23 +478,23 @@ impl Display for UnresolvedType {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ReturnStatement {
     pub expression: Option<Located<Expression>>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EffectStatement {
     pub expression: Located<Expression>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Statement {
     Return(ReturnStatement),
     Effect(EffectStatement),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Restriction {
     Interface(String),
 }
@@ -357,7 +507,7 @@ impl Display for Restriction {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GenericArgument {
     pub name: String,
     pub restrictions: Vec<Restriction>,
@@ -379,24 +529,81 @@ impl Display for GenericArgument {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+// `where Vec<T>: A + B` のように、ジェネリック引数を含む型に対する境界
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WhereClause {
+    pub ty: Located<UnresolvedType>,
+    pub restrictions: Vec<Restriction>,
+}
+
+impl Display for WhereClause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.ty.value)?;
+        for (i, restriction) in self.restrictions.iter().enumerate() {
+            if i != 0 {
+                f.write_str(" + ")?;
+            }
+            write!(f, "{}", restriction)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Argument {
     VarArgs,
     SelfArg,
     Normal(Located<UnresolvedType>, String),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FunctionDecl {
     pub alloc_mode: Option<AllocMode>,
     pub name: String,
     pub generic_args: Option<Vec<Located<GenericArgument>>>,
     pub args: Vec<Argument>,
     pub return_type: Located<UnresolvedType>,
-    pub is_intrinsic: bool,
+    pub where_clauses: Vec<Located<WhereClause>>,
+    // `extern "C" fn` で宣言した、本体を持たずCの呼び出し規約で呼ぶ関数
+    pub is_extern: bool,
+    pub attributes: Vec<Located<Attribute>>,
+    // `pub fn` で宣言した、ライブラリの外から呼べる関数
+    pub is_public: bool,
+    // ライブラリのメタデータから読み込んだ宣言。本体はライブラリのオブジェクトファイルにある
+    pub is_imported: bool,
+}
+
+// `#[link("m")]` のように、関数の宣言の前に書く属性
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Attribute {
+    // extern 関数の定義を含むライブラリをリンクする
+    Link(String),
+    // Cから呼べる名前で関数を公開する。名前を省略すると関数名をそのまま使う
+    Export(Option<String>),
+    // `hirou test` で実行するテスト。引数を取らず、成功したら true を返す
+    Test,
+}
+
+impl FunctionDecl {
+    // `#[export]` が付いていれば、Cから呼ぶときの名前
+    pub fn export_name(&self) -> Option<String> {
+        self.attributes
+            .iter()
+            .find_map(|attribute| match &attribute.value {
+                Attribute::Export(name) => Some(name.clone().unwrap_or_else(|| self.name.clone())),
+                _ => None,
+            })
+    }
+
+    // `#[test]` が付いていれば、`hirou test` で実行する
+    pub fn is_test(&self) -> bool {
+        self.attributes
+            .iter()
+            .any(|attribute| attribute.value == Attribute::Test)
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Interface {
     pub name: String,
     pub generic_args: Vec<Located<GenericArgument>>,
@@ -404,13 +611,17 @@ pub struct Interface {
     pub return_type: Located<UnresolvedType>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Function {
     pub decl: FunctionDecl,
+    // `fn` から戻り値の型までの位置。デバッグ情報で関数の位置として使う
+    pub decl_range: Range,
     pub body: Vec<Located<Statement>>,
+    // パーサーは MAIN にする。標準ライブラリは読み込むときに STDLIB に書き換える
+    pub file: FileId,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ImplementationDecl {
     pub alloc_mode: Option<AllocMode>,
     pub name: String,
@@ -418,39 +629,43 @@ pub struct ImplementationDecl {
     pub target_ty: Located<UnresolvedType>,
     pub args: Vec<Argument>,
     pub return_type: Option<Located<UnresolvedType>>,
+    // ライブラリのメタデータから読み込んだ宣言。本体はライブラリのオブジェクトファイルにある
+    pub is_imported: bool,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Implementation {
     pub decl: ImplementationDecl,
     pub body: Vec<Located<Statement>>,
+    pub file: FileId,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StructLikeTypeDef {
     pub struct_kind: StructKind,
     pub generic_args: Option<Vec<Located<GenericArgument>>>,
     pub fields: Vec<(String, Located<UnresolvedType>)>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TypeDefKind {
     StructLike(StructLikeTypeDef),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TypeDef {
     pub name: String,
     pub kind: TypeDefKind,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct UseStatement {
     pub path: NamespacePath,
     pub wildcard: bool, // true for `use Vec::*`, false for `use Vec::push`
+    pub file: FileId,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TopLevel {
     Function(Function),
     Implemantation(Implementation),
@@ -463,3 +678,23 @@ pub enum TopLevel {
 pub struct Module {
     pub toplevels: Vec<Located<TopLevel>>,
 }
+
+impl Module {
+    // `#[link(...)]` で指定されたライブラリを、書かれた順に重複なく返す
+    pub fn link_libraries(&self) -> Vec<String> {
+        let mut libraries: Vec<String> = Vec::new();
+        for toplevel in &self.toplevels {
+            let TopLevel::Function(function) = &toplevel.value else {
+                continue;
+            };
+            for attribute in &function.decl.attributes {
+                if let Attribute::Link(library) = &attribute.value {
+                    if !libraries.contains(library) {
+                        libraries.push(library.clone());
+                    }
+                }
+            }
+        }
+        libraries
+    }
+}
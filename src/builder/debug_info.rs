@@ -0,0 +1,368 @@
+use inkwell::{
+    debug_info::{
+        debug_metadata_version, AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants,
+        DISubprogram, DIType, DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+    },
+    module::FlagBehavior,
+    values::{FunctionValue, PointerValue},
+    AddressSpace,
+};
+use std::cell::Cell;
+use std::path::Path;
+
+use super::ffi::c_layout;
+use super::*;
+use crate::ast::{FileId, SourceLocation};
+use crate::resolver::mangle::demangle;
+
+// DW_ATE_* (DWARFの基本型のエンコーディング)
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_UNSIGNED: u32 = 0x08;
+
+// `-g` のときだけ作る。命令には、それを生成した式の位置を付ける
+pub(super) struct DebugInfo<'a> {
+    builder: DebugInfoBuilder<'a>,
+    compile_unit: DICompileUnit<'a>,
+    files: HashMap<FileId, DIFile<'a>>,
+    // 本体を生成している関数とそのファイル。関数の外の命令には位置を付けない
+    function: Cell<Option<(DISubprogram<'a>, DIFile<'a>)>>,
+    types: RefCell<HashMap<ConcreteType, DIType<'a>>>,
+}
+
+// デバッグ情報に書くファイル名とディレクトリ
+fn split_source_path(source_path: &Path) -> (String, String) {
+    let absolute_path = source_path
+        .canonicalize()
+        .unwrap_or_else(|_| source_path.to_path_buf());
+    let file_name = absolute_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let directory = absolute_path
+        .parent()
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    (file_name, directory)
+}
+
+impl<'a> LLVMCodeGenerator<'a> {
+    pub fn enable_debug_info(&mut self, source_path: &Path) {
+        let (file_name, directory) = split_source_path(source_path);
+
+        let i32_type = self.llvm_context.i32_type();
+        self.llvm_module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            i32_type.const_int(debug_metadata_version() as u64, false),
+        );
+        // macOSのリンカとlldbはDWARF 4を前提にしている
+        if self.target.is_darwin() {
+            self.llvm_module.add_basic_value_flag(
+                "Dwarf Version",
+                FlagBehavior::Warning,
+                i32_type.const_int(4, false),
+            );
+        }
+        let (builder, compile_unit) = self.llvm_module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            &file_name,
+            &directory,
+            "hirou",
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        // 標準ライブラリはコンパイラに埋め込まれているので、ディレクトリは持たない
+        let stdlib_file = builder.create_file("stdlib.hr", "");
+        let files = HashMap::from([
+            (FileId::MAIN, compile_unit.get_file()),
+            (FileId::STDLIB, stdlib_file),
+        ]);
+        self.debug_info = Some(DebugInfo {
+            builder,
+            compile_unit,
+            files,
+            function: Cell::new(None),
+            types: RefCell::new(HashMap::new()),
+        });
+    }
+
+    // `--extern` で読み込んだライブラリのソース。そこからインスタンス化した関数の位置に使う
+    pub fn add_debug_source_file(&mut self, file: FileId, source_path: &Path) {
+        let Some(debug_info) = &mut self.debug_info else {
+            return;
+        };
+        let (file_name, directory) = split_source_path(source_path);
+        let debug_file = debug_info.builder.create_file(&file_name, &directory);
+        debug_info.files.insert(file, debug_file);
+    }
+
+    pub(super) fn finalize_debug_info(&self) {
+        if let Some(debug_info) = &self.debug_info {
+            debug_info.builder.finalize();
+        }
+    }
+
+    // voidは型を持たないのでNone
+    fn debug_type(&self, ty: &ConcreteType) -> Option<DIType<'a>> {
+        let debug_info = self.debug_info.as_ref()?;
+        if let Some(debug_type) = debug_info.types.borrow().get(ty) {
+            return Some(*debug_type);
+        }
+        let word_size = self.target.metrics().word_size as u64;
+        let (size, align) = c_layout(ty, word_size);
+        let basic_type = |encoding: u32| {
+            debug_info
+                .builder
+                .create_basic_type(&ty.to_string(), size * 8, encoding, DIFlags::ZERO)
+                .ok()
+                .map(|basic_type| basic_type.as_type())
+        };
+        let debug_type = match ty {
+            ConcreteType::Void => return None,
+            ConcreteType::Bool => basic_type(DW_ATE_BOOLEAN)?,
+            ConcreteType::I32 | ConcreteType::I64 => basic_type(DW_ATE_SIGNED)?,
+            ConcreteType::U8 | ConcreteType::U32 | ConcreteType::U64 => {
+                basic_type(DW_ATE_UNSIGNED)?
+            }
+            ConcreteType::F32 | ConcreteType::F64 => basic_type(DW_ATE_FLOAT)?,
+            ConcreteType::Ptr(pointee) => {
+                // voidへのポインタはバイト列へのポインタとして見せる
+                let pointee_type = match self.debug_type(pointee) {
+                    Some(pointee_type) => pointee_type,
+                    None => self.debug_type(&ConcreteType::U8)?,
+                };
+                debug_info
+                    .builder
+                    .create_pointer_type(
+                        &ty.to_string(),
+                        pointee_type,
+                        size * 8,
+                        (align * 8) as u32,
+                        AddressSpace::default(),
+                    )
+                    .as_type()
+            }
+            ConcreteType::StructLike(struct_ty) => {
+                let scope = debug_info.compile_unit.as_debug_info_scope();
+                let file = debug_info.compile_unit.get_file();
+                let mut members = Vec::new();
+                let mut offset = 0;
+                for (name, field_ty) in &struct_ty.fields {
+                    let (field_size, field_align) = c_layout(field_ty, word_size);
+                    offset = offset.next_multiple_of(field_align);
+                    if let Some(field_type) = self.debug_type(field_ty) {
+                        let member = debug_info.builder.create_member_type(
+                            scope,
+                            name,
+                            file,
+                            0,
+                            field_size * 8,
+                            (field_align * 8) as u32,
+                            offset * 8,
+                            DIFlags::ZERO,
+                            field_type,
+                        );
+                        members.push(member.as_type());
+                    }
+                    offset += field_size;
+                }
+                debug_info
+                    .builder
+                    .create_struct_type(
+                        scope,
+                        &struct_ty.name,
+                        file,
+                        0,
+                        size * 8,
+                        (align * 8) as u32,
+                        DIFlags::ZERO,
+                        None,
+                        &members,
+                        0,
+                        None,
+                        &struct_ty.name,
+                    )
+                    .as_type()
+            }
+            ConcreteType::Array(element, len) => {
+                let element_type = self.debug_type(element)?;
+                debug_info
+                    .builder
+                    .create_array_type(
+                        element_type,
+                        size * 8,
+                        (align * 8) as u32,
+                        &[0..*len as i64],
+                    )
+                    .as_type()
+            }
+        };
+        debug_info.types.borrow_mut().insert(ty.clone(), debug_type);
+        Some(debug_type)
+    }
+
+    // 関数の本体を生成する前に呼ぶ。以降の命令には関数の宣言の位置が付く
+    pub(super) fn begin_function_debug_info(
+        &self,
+        function: &Function,
+        function_value: FunctionValue<'a>,
+    ) {
+        let Some(debug_info) = &self.debug_info else {
+            return;
+        };
+        let file = debug_info.files[&function.decl.location.file];
+        let line = function.decl.location.range.from.line;
+        let param_types: Vec<DIType> = function
+            .decl
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                Argument::Normal(ty, _) => self.debug_type(ty),
+                Argument::VarArgs => None,
+            })
+            .collect();
+        let subroutine_type = debug_info.builder.create_subroutine_type(
+            file,
+            self.debug_type(&function.decl.return_type),
+            &param_types,
+            DIFlags::ZERO,
+        );
+        // デバッガには `Point::len` のような読める名前で見せる
+        let name = demangle(&function.decl.name).unwrap_or_else(|| function.decl.name.clone());
+        let subprogram = debug_info.builder.create_function(
+            debug_info.compile_unit.as_debug_info_scope(),
+            &name,
+            Some(&function.decl.name),
+            file,
+            line,
+            subroutine_type,
+            false,
+            true,
+            line,
+            DIFlags::ZERO,
+            false,
+        );
+        function_value.set_subprogram(subprogram);
+        debug_info.function.set(Some((subprogram, file)));
+        self.set_debug_location(function.decl.location);
+    }
+
+    pub(super) fn end_function_debug_info(&self) {
+        if let Some(debug_info) = &self.debug_info {
+            debug_info.function.set(None);
+            self.llvm_builder.unset_current_debug_location();
+        }
+    }
+
+    fn set_debug_location(&self, location: SourceLocation) {
+        let Some(debug_info) = &self.debug_info else {
+            return;
+        };
+        let Some((subprogram, _)) = debug_info.function.get() else {
+            return;
+        };
+        let location = debug_info.builder.create_debug_location(
+            self.llvm_context,
+            location.range.from.line,
+            location.range.from.col as u32,
+            subprogram.as_debug_info_scope(),
+            None,
+        );
+        self.llvm_builder.set_current_debug_location(location);
+    }
+
+    // 式の命令を生成する間だけ、その式の位置を付ける。
+    // 内側の式を生成し終えたら、外側の式の位置に戻す
+    pub(super) fn with_debug_location<T>(
+        &self,
+        location: SourceLocation,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        if self.debug_info.is_none() {
+            return f();
+        }
+        let outer_location = self.llvm_builder.get_current_debug_location();
+        self.set_debug_location(location);
+        let result = f();
+        if let Some(outer_location) = outer_location {
+            self.llvm_builder.set_current_debug_location(outer_location);
+        }
+        result
+    }
+
+    // `:=` で宣言した変数を、型と一緒にデバッガから見えるようにする
+    pub(super) fn declare_debug_variable(
+        &self,
+        name: &str,
+        ty: &ConcreteType,
+        ptr: PointerValue<'a>,
+    ) {
+        let Some(debug_info) = &self.debug_info else {
+            return;
+        };
+        let Some((subprogram, file)) = debug_info.function.get() else {
+            return;
+        };
+        // シャドーイングで付いた `x.1` の番号は外す。`for.index.1` のようにresolverが作った変数は見せない
+        let name = match name.split_once('.') {
+            Some((base, suffix)) if suffix.chars().all(|c| c.is_ascii_digit()) => base,
+            Some(_) => return,
+            None => name,
+        };
+        let (Some(location), Some(debug_type), Some(block)) = (
+            self.llvm_builder.get_current_debug_location(),
+            self.debug_type(ty),
+            self.llvm_builder.get_insert_block(),
+        ) else {
+            return;
+        };
+        let (_, align) = c_layout(ty, self.target.metrics().word_size as u64);
+        let variable = debug_info.builder.create_auto_variable(
+            subprogram.as_debug_info_scope(),
+            name,
+            file,
+            location.get_line(),
+            debug_type,
+            true,
+            DIFlags::ZERO,
+            (align * 8) as u32,
+        );
+        debug_info
+            .builder
+            .insert_declare_at_end(ptr, Some(variable), None, location, block);
+    }
+}
+
+#[test]
+fn test_debug_info() {
+    let ir = generate_ir(
+        r#"
+fn main(): i32 {
+  (:= x 1
+      y: f64 2.5)
+  x
+}
+"#,
+        true,
+    );
+    assert!(ir.contains("!DICompileUnit("), "{ir}");
+    assert!(ir.contains("!DISubprogram(name: \"main\""), "{ir}");
+    assert!(ir.contains("!DILocalVariable(name: \"x\""), "{ir}");
+    assert!(ir.contains("!DILocalVariable(name: \"y\""), "{ir}");
+    // LLVMのバージョンによって、組み込み関数の呼び出しかデバッグレコードとして出力される
+    assert!(
+        ir.contains("call void @llvm.dbg.declare(") || ir.contains("#dbg_declare("),
+        "{ir}"
+    );
+}
@@ -1,53 +1,59 @@
 use super::*;
 use crate::{ast::BinaryOp, common::binary::get_cast_type};
+use inkwell::{values::FloatValue, FloatPredicate, IntPredicate};
 
 impl LLVMCodeGenerator<'_> {
+    // 数値の型を変換する。整数の拡張は変換元が符号付きなら符号拡張、そうでなければゼロ拡張になる
     pub(crate) fn gen_try_cast<'ctx>(
         &'ctx self,
         value: BasicValueEnum<'ctx>,
+        from: &ConcreteType,
         ty: &ConcreteType,
-    ) -> BasicValueEnum<'ctx> {
-        match ty {
-            ConcreteType::I32 => self
-                .llvm_builder
-                .build_int_cast_sign_flag(value.into_int_value(), self.llvm_context.i32_type(), true, "(i32)")
-                .unwrap()
-                .as_basic_value_enum(),
-            ConcreteType::U32 => self
+    ) -> Result<BasicValueEnum<'ctx>, BuilderError> {
+        if from == ty {
+            return Ok(value);
+        }
+        let to_ty = self.type_to_basic_type_enum(ty).unwrap();
+        let value = match (from.is_integer_type(), ty.is_integer_type()) {
+            (true, true) => self
                 .llvm_builder
-                .build_int_cast_sign_flag(value.into_int_value(), self.llvm_context.i32_type(), false, "(u32)")
-                .unwrap()
+                .build_int_cast_sign_flag(
+                    value.into_int_value(),
+                    to_ty.into_int_type(),
+                    from.is_signed_integer_type(),
+                    "cast",
+                )?
                 .as_basic_value_enum(),
-            ConcreteType::U64 => self
+            (true, false) if from.is_signed_integer_type() => self
                 .llvm_builder
-                .build_int_cast_sign_flag(value.into_int_value(), self.llvm_context.i64_type(), false, "(u64)")
-                .unwrap()
+                .build_signed_int_to_float(value.into_int_value(), to_ty.into_float_type(), "cast")?
                 .as_basic_value_enum(),
-            ConcreteType::U8 => self
+            (true, false) => self
                 .llvm_builder
-                .build_int_cast_sign_flag(value.into_int_value(), self.llvm_context.i8_type(), false, "(u8)")
-                .unwrap()
+                .build_unsigned_int_to_float(
+                    value.into_int_value(),
+                    to_ty.into_float_type(),
+                    "cast",
+                )?
                 .as_basic_value_enum(),
-            ConcreteType::I64 => self
+            (false, true) if ty.is_signed_integer_type() => self
                 .llvm_builder
-                .build_int_cast(value.into_int_value(), self.llvm_context.i64_type(), "(i64)")
-                .unwrap()
+                .build_float_to_signed_int(value.into_float_value(), to_ty.into_int_type(), "cast")?
                 .as_basic_value_enum(),
-            ConcreteType::F32 => self
+            (false, true) => self
                 .llvm_builder
-                .build_float_cast(value.into_float_value(), self.llvm_context.f32_type(), "(f32)")
-                .unwrap()
+                .build_float_to_unsigned_int(
+                    value.into_float_value(),
+                    to_ty.into_int_type(),
+                    "cast",
+                )?
                 .as_basic_value_enum(),
-            ConcreteType::F64 => self
+            (false, false) => self
                 .llvm_builder
-                .build_float_cast(value.into_float_value(), self.llvm_context.f64_type(), "(f64)")
-                .unwrap()
+                .build_float_cast(value.into_float_value(), to_ty.into_float_type(), "cast")?
                 .as_basic_value_enum(),
-            ConcreteType::Ptr(_) => unreachable!(),
-            ConcreteType::Void => unreachable!(),
-            ConcreteType::StructLike(_) => unreachable!(),
-            ConcreteType::Bool => unreachable!(),
-        }
+        };
+        Ok(value)
     }
     pub(super) fn eval_binary_expr(
         &self,
@@ -56,120 +62,222 @@ impl LLVMCodeGenerator<'_> {
         let mut left = self.gen_expression(&binary_expr.lhs)?.unwrap();
         let mut right = self.gen_expression(&binary_expr.rhs)?.unwrap();
 
+        if matches!(binary_expr.op, BinaryOp::Shl | BinaryOp::Shr) {
+            // シフト量を左辺の型に揃える。結果は左辺の型になる
+            let right = self.gen_try_cast(right, &binary_expr.rhs.ty, &binary_expr.lhs.ty)?;
+            let value = if binary_expr.op == BinaryOp::Shl {
+                self.llvm_builder.build_left_shift(
+                    left.into_int_value(),
+                    right.into_int_value(),
+                    "",
+                )?
+            } else {
+                self.llvm_builder.build_right_shift(
+                    left.into_int_value(),
+                    right.into_int_value(),
+                    binary_expr.lhs.ty.is_signed_integer_type(),
+                    "",
+                )?
+            };
+            return Ok(value.as_basic_value_enum());
+        }
+
         let (lhs_cast_type, rhs_cast_type) =
             get_cast_type(&binary_expr.lhs.ty, &binary_expr.rhs.ty);
 
-        let mut result_type = ConcreteType::I32;
+        // 変換がなければ両辺は同じ型
+        let mut result_type = binary_expr.lhs.ty.clone();
         if let Some(lhs_cast_type) = lhs_cast_type {
-            left = self.gen_try_cast(left, &lhs_cast_type);
+            left = self.gen_try_cast(left, &binary_expr.lhs.ty, &lhs_cast_type)?;
             result_type = lhs_cast_type;
         }
         if let Some(rhs_cast_type) = rhs_cast_type {
-            right = self.gen_try_cast(right, &rhs_cast_type);
+            right = self.gen_try_cast(right, &binary_expr.rhs.ty, &rhs_cast_type)?;
             result_type = rhs_cast_type;
         };
 
+        if result_type.is_float_type() {
+            return self.eval_float_binary_expr(
+                binary_expr.op,
+                left.into_float_value(),
+                right.into_float_value(),
+            );
+        }
+
+        // ここから先は整数 (比較ではboolも) の演算だけが来る
         let value = match binary_expr.op {
-            BinaryOp::Add => {
-                match result_type {
-                    ConcreteType::I32
-                    | ConcreteType::U8
-                    | ConcreteType::U32
-                    | ConcreteType::I64
-                    | ConcreteType::U64 => {}
-                    _ => unimplemented!(),
-                }
-                if result_type.is_integer_type() {
-                    self.llvm_builder.build_int_add(
+            BinaryOp::Add => self.llvm_builder.build_int_add(
+                left.into_int_value(),
+                right.into_int_value(),
+                "",
+            )?,
+            BinaryOp::Sub => self.llvm_builder.build_int_sub(
+                left.into_int_value(),
+                right.into_int_value(),
+                "",
+            )?,
+            BinaryOp::Mul => self.llvm_builder.build_int_mul(
+                left.into_int_value(),
+                right.into_int_value(),
+                "",
+            )?,
+            BinaryOp::Div => {
+                if result_type.is_signed_integer_type() {
+                    self.llvm_builder.build_int_signed_div(
                         left.into_int_value(),
                         right.into_int_value(),
                         "",
                     )?
                 } else {
-                    unimplemented!()
-                }
-            }
-            BinaryOp::Sub => {
-                if result_type.is_integer_type() {
-                    self.llvm_builder.build_int_sub(
+                    self.llvm_builder.build_int_unsigned_div(
                         left.into_int_value(),
                         right.into_int_value(),
                         "",
                     )?
-                } else {
-                    unimplemented!()
                 }
             }
-            BinaryOp::Mul => {
-                if result_type.is_integer_type() {
-                    self.llvm_builder.build_int_mul(
+            BinaryOp::Rem => {
+                if result_type.is_signed_integer_type() {
+                    self.llvm_builder.build_int_signed_rem(
                         left.into_int_value(),
                         right.into_int_value(),
                         "",
                     )?
                 } else {
-                    unimplemented!()
+                    self.llvm_builder.build_int_unsigned_rem(
+                        left.into_int_value(),
+                        right.into_int_value(),
+                        "",
+                    )?
                 }
             }
-            BinaryOp::Div => {
-                if result_type.is_integer_type() {
-                    if result_type.is_signed_integer_type() {
-                        self.llvm_builder.build_int_signed_div(
-                            left.into_int_value(),
-                            right.into_int_value(),
-                            "",
-                        )?
-                    } else {
-                        self.llvm_builder.build_int_unsigned_div(
-                            left.into_int_value(),
-                            right.into_int_value(),
-                            "",
-                        )?
-                    }
-                } else {
-                    unimplemented!()
-                }
+            BinaryOp::BitAnd => {
+                self.llvm_builder
+                    .build_and(left.into_int_value(), right.into_int_value(), "")?
+            }
+            BinaryOp::BitOr => {
+                self.llvm_builder
+                    .build_or(left.into_int_value(), right.into_int_value(), "")?
+            }
+            BinaryOp::BitXor => {
+                self.llvm_builder
+                    .build_xor(left.into_int_value(), right.into_int_value(), "")?
             }
+            BinaryOp::Shl | BinaryOp::Shr => unreachable!(),
             BinaryOp::Equals
             | BinaryOp::NotEquals
             | BinaryOp::LessThan
             | BinaryOp::LessThanOrEquals
             | BinaryOp::GreaterThan
             | BinaryOp::GreaterThanOrEquals => {
-                if result_type.is_integer_type() {
-                    let predicate = if result_type.is_signed_integer_type() {
-                        match binary_expr.op {
-                            BinaryOp::Equals => inkwell::IntPredicate::EQ,
-                            BinaryOp::NotEquals => inkwell::IntPredicate::NE,
-                            BinaryOp::LessThan => inkwell::IntPredicate::SLT,
-                            BinaryOp::LessThanOrEquals => inkwell::IntPredicate::SLE,
-                            BinaryOp::GreaterThan => inkwell::IntPredicate::SGT,
-                            BinaryOp::GreaterThanOrEquals => inkwell::IntPredicate::SGE,
-                            _ => unreachable!(),
-                        }
-                    } else {
-                        match binary_expr.op {
-                            BinaryOp::Equals => inkwell::IntPredicate::EQ,
-                            BinaryOp::NotEquals => inkwell::IntPredicate::NE,
-                            BinaryOp::LessThan => inkwell::IntPredicate::ULT,
-                            BinaryOp::LessThanOrEquals => inkwell::IntPredicate::ULE,
-                            BinaryOp::GreaterThan => inkwell::IntPredicate::UGT,
-                            BinaryOp::GreaterThanOrEquals => inkwell::IntPredicate::UGE,
-                            _ => unreachable!(),
-                        }
-                    };
-                    self.llvm_builder.build_int_compare(
-                        predicate,
-                        left.into_int_value(),
-                        right.into_int_value(),
-                        "",
-                    )?
+                let predicate = if result_type.is_signed_integer_type() {
+                    match binary_expr.op {
+                        BinaryOp::Equals => IntPredicate::EQ,
+                        BinaryOp::NotEquals => IntPredicate::NE,
+                        BinaryOp::LessThan => IntPredicate::SLT,
+                        BinaryOp::LessThanOrEquals => IntPredicate::SLE,
+                        BinaryOp::GreaterThan => IntPredicate::SGT,
+                        BinaryOp::GreaterThanOrEquals => IntPredicate::SGE,
+                        _ => unreachable!(),
+                    }
                 } else {
-                    unimplemented!()
-                }
+                    match binary_expr.op {
+                        BinaryOp::Equals => IntPredicate::EQ,
+                        BinaryOp::NotEquals => IntPredicate::NE,
+                        BinaryOp::LessThan => IntPredicate::ULT,
+                        BinaryOp::LessThanOrEquals => IntPredicate::ULE,
+                        BinaryOp::GreaterThan => IntPredicate::UGT,
+                        BinaryOp::GreaterThanOrEquals => IntPredicate::UGE,
+                        _ => unreachable!(),
+                    }
+                };
+                self.llvm_builder.build_int_compare(
+                    predicate,
+                    left.into_int_value(),
+                    right.into_int_value(),
+                    "",
+                )?
             }
         };
 
         Ok(value.as_basic_value_enum())
     }
+    fn eval_float_binary_expr<'a>(
+        &'a self,
+        op: BinaryOp,
+        left: FloatValue<'a>,
+        right: FloatValue<'a>,
+    ) -> Result<BasicValueEnum<'a>, BuilderError> {
+        let value = match op {
+            BinaryOp::Add => self
+                .llvm_builder
+                .build_float_add(left, right, "")?
+                .as_basic_value_enum(),
+            BinaryOp::Sub => self
+                .llvm_builder
+                .build_float_sub(left, right, "")?
+                .as_basic_value_enum(),
+            BinaryOp::Mul => self
+                .llvm_builder
+                .build_float_mul(left, right, "")?
+                .as_basic_value_enum(),
+            BinaryOp::Div => self
+                .llvm_builder
+                .build_float_div(left, right, "")?
+                .as_basic_value_enum(),
+            BinaryOp::Rem => self
+                .llvm_builder
+                .build_float_rem(left, right, "")?
+                .as_basic_value_enum(),
+            BinaryOp::Equals
+            | BinaryOp::NotEquals
+            | BinaryOp::LessThan
+            | BinaryOp::LessThanOrEquals
+            | BinaryOp::GreaterThan
+            | BinaryOp::GreaterThanOrEquals => {
+                // NaNとの比較は常にfalseになる (ordered)。
+                // ただし `!=` は `=` の否定になるよう、NaNを含む場合にtrueとなる unordered を使う
+                let predicate = match op {
+                    BinaryOp::Equals => FloatPredicate::OEQ,
+                    BinaryOp::NotEquals => FloatPredicate::UNE,
+                    BinaryOp::LessThan => FloatPredicate::OLT,
+                    BinaryOp::LessThanOrEquals => FloatPredicate::OLE,
+                    BinaryOp::GreaterThan => FloatPredicate::OGT,
+                    BinaryOp::GreaterThanOrEquals => FloatPredicate::OGE,
+                    _ => unreachable!(),
+                };
+                self.llvm_builder
+                    .build_float_compare(predicate, left, right, "")?
+                    .as_basic_value_enum()
+            }
+            // ビット演算とシフトは整数にしか使えないことをresolverで確認している
+            BinaryOp::BitAnd
+            | BinaryOp::BitOr
+            | BinaryOp::BitXor
+            | BinaryOp::Shl
+            | BinaryOp::Shr => unreachable!(),
+        };
+        Ok(value)
+    }
+}
+
+#[test]
+fn test_float_binary_expr() {
+    let ir = crate::builder::generate_ir(
+        r#"
+fn main(): i32 {
+  (:= a 1.5
+      b: f32 0.5f32)
+  (:= sum (+ a 2.25)
+      half (* b b))
+  (if (and (< sum 4.0) (!= half b)) 0 1)
+}
+"#,
+        false,
+    );
+    assert!(ir.contains("fadd double"), "{ir}");
+    assert!(ir.contains("fmul float"), "{ir}");
+    assert!(ir.contains("fcmp olt double"), "{ir}");
+    // `!=` はNaNを含むときにtrueになる
+    assert!(ir.contains("fcmp une float"), "{ir}");
 }
@@ -0,0 +1,28 @@
+use super::*;
+
+impl LLVMCodeGenerator<'_> {
+    pub(super) fn eval_cast(
+        &self,
+        cast: &CastExpr,
+        ty: &ConcreteType,
+    ) -> Result<BasicValueEnum, BuilderError> {
+        let value = self.gen_expression(&cast.target)?.unwrap();
+        let from = &cast.target.ty;
+        if from == ty {
+            return Ok(value);
+        }
+        let to_ty = self.type_to_basic_type_enum(ty).unwrap();
+        let value = match (from, ty) {
+            (ConcreteType::Ptr(_), _) => self
+                .llvm_builder
+                .build_ptr_to_int(value.into_pointer_value(), to_ty.into_int_type(), "cast")?
+                .as_basic_value_enum(),
+            (_, ConcreteType::Ptr(_)) => self
+                .llvm_builder
+                .build_int_to_ptr(value.into_int_value(), to_ty.into_pointer_type(), "cast")?
+                .as_basic_value_enum(),
+            _ => self.gen_try_cast(value, from, ty)?,
+        };
+        Ok(value)
+    }
+}
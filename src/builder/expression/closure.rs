@@ -0,0 +1,143 @@
+use super::*;
+use crate::builder::toplevel::closure_env_type;
+use inkwell::types::BasicMetadataTypeEnum;
+
+impl LLVMCodeGenerator<'_> {
+    pub(super) fn eval_closure(
+        &self,
+        closure: &ClosureExpr,
+        ty: &ConcreteType,
+    ) -> Result<BasicValueEnum, BuilderError> {
+        let ptr_ty = self.llvm_context.ptr_type(AddressSpace::default());
+        let function = *self.function_by_name.get(&closure.fn_name).unwrap();
+        let fn_ptr = self
+            .gen_or_get_function(function)
+            .as_global_value()
+            .as_pointer_value();
+
+        // 取り込んだ変数を環境にコピーする。
+        // 関数の外に出ていかないクロージャーの環境は入口のブロックのスタックに置き、ループの中でも増えない。
+        // 出ていくものは値がいつまで使われるか分からないので、dynへの変換と同じくヒープに置いて解放はしない
+        let env_ptr = if closure.captures.is_empty() {
+            ptr_ty.const_null()
+        } else {
+            let env_ty = self
+                .type_to_basic_type_enum(&closure_env_type(&closure.fn_name, &closure.captures))
+                .unwrap();
+            let env_ptr = if closure.escapes {
+                self.llvm_builder
+                    .build_call(
+                        self.get_or_declare_malloc(),
+                        &[env_ty.size_of().unwrap().into()],
+                        "env",
+                    )?
+                    .try_as_basic_value()
+                    .basic()
+                    .unwrap()
+                    .into_pointer_value()
+            } else {
+                self.build_entry_alloca(env_ty, "env")?
+            };
+            for (i, (name, capture_ty)) in closure.captures.iter().enumerate() {
+                let var_ptr = self.get_variable(name);
+                let field_ptr = self
+                    .llvm_builder
+                    .build_struct_gep(env_ty, env_ptr, i as u32, "")?;
+                let value_ty = self.type_to_basic_type_enum(capture_ty).unwrap();
+                if capture_ty.is_struct_type() {
+                    self.llvm_builder.build_memcpy(
+                        field_ptr,
+                        8,
+                        var_ptr,
+                        8,
+                        value_ty.size_of().unwrap(),
+                    )?;
+                } else {
+                    let value = self.llvm_builder.build_load(value_ty, var_ptr, name)?;
+                    self.llvm_builder.build_store(field_ptr, value)?;
+                }
+            }
+            env_ptr
+        };
+
+        let pair_ty = self.type_to_basic_type_enum(ty).unwrap();
+        let pair_ptr = self.build_entry_alloca(pair_ty, "closure")?;
+        let fn_field_ptr = self
+            .llvm_builder
+            .build_struct_gep(pair_ty, pair_ptr, 0, "fn_ptr")?;
+        self.llvm_builder.build_store(fn_field_ptr, fn_ptr)?;
+        let env_field_ptr = self
+            .llvm_builder
+            .build_struct_gep(pair_ty, pair_ptr, 1, "env_ptr")?;
+        self.llvm_builder.build_store(env_field_ptr, env_ptr)?;
+        Ok(pair_ptr.as_basic_value_enum())
+    }
+
+    pub(super) fn eval_closure_call<'a>(
+        &'a self,
+        closure_call: &ClosureCallExpr,
+        ty: &ConcreteType,
+    ) -> Result<Option<BasicValueEnum<'a>>, BuilderError> {
+        let ptr_ty = self.llvm_context.ptr_type(AddressSpace::default());
+        let pair_ty = self
+            .type_to_basic_type_enum(&closure_call.callee.ty)
+            .unwrap();
+        let pair_ptr = self
+            .gen_expression(&closure_call.callee)?
+            .unwrap()
+            .into_pointer_value();
+        let fn_field_ptr = self
+            .llvm_builder
+            .build_struct_gep(pair_ty, pair_ptr, 0, "fn_ptr")?;
+        let fn_ptr = self
+            .llvm_builder
+            .build_load(ptr_ty, fn_field_ptr, "fn")?
+            .into_pointer_value();
+        let env_field_ptr = self
+            .llvm_builder
+            .build_struct_gep(pair_ty, pair_ptr, 1, "env_ptr")?;
+        let env_ptr = self.llvm_builder.build_load(ptr_ty, env_field_ptr, "env")?;
+
+        // 呼び出し先の型は gen_or_get_function と同じ並び (sret, 環境, 引数) にする
+        let returns_struct = ty.is_struct_type();
+        let mut param_types: Vec<BasicMetadataTypeEnum> = Vec::new();
+        let mut args: Vec<BasicMetadataValueEnum> = Vec::new();
+        let ret_ptr = if returns_struct {
+            let return_ty = self.type_to_basic_type_enum(ty).unwrap();
+            let ret_ptr = self.build_entry_alloca(return_ty, "")?;
+            param_types.push(ptr_ty.into());
+            args.push(ret_ptr.into());
+            Some(ret_ptr)
+        } else {
+            None
+        };
+        param_types.push(ptr_ty.into());
+        args.push(env_ptr.into());
+        for arg in &closure_call.args {
+            let value = self.gen_expression(arg)?.unwrap();
+            let arg_ty = self.type_to_basic_type_enum(&arg.ty).unwrap();
+            param_types.push(arg_ty.into());
+            if arg.ty.is_struct_type() {
+                args.push(
+                    self.llvm_builder
+                        .build_load(arg_ty, value.into_pointer_value(), "")?
+                        .into(),
+                );
+            } else {
+                args.push(value.into());
+            }
+        }
+
+        let fn_ty = match self.type_to_basic_type_enum(ty) {
+            Some(return_ty) if !returns_struct => return_ty.fn_type(&param_types, false),
+            _ => self.llvm_context.void_type().fn_type(&param_types, false),
+        };
+        let value = self
+            .llvm_builder
+            .build_indirect_call(fn_ty, fn_ptr, &args, "")?;
+        if let Some(ret_ptr) = ret_ptr {
+            return Ok(Some(ret_ptr.as_basic_value_enum()));
+        }
+        Ok(value.try_as_basic_value().basic())
+    }
+}
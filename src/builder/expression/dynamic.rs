@@ -0,0 +1,243 @@
+use super::*;
+use inkwell::{
+    module::Linkage as LLVMLinkage,
+    types::{BasicMetadataTypeEnum, FunctionType},
+    values::{FunctionValue, PointerValue},
+};
+
+impl LLVMCodeGenerator<'_> {
+    // vtableに格納される関数の型。第一引数(sretの場合は第二引数)にデータへのポインタを受け取り、
+    // その後ろにself以外の引数が続く
+    fn dyn_method_fn_type(
+        &self,
+        return_type: &ConcreteType,
+        arg_types: &[ConcreteType],
+    ) -> FunctionType {
+        let ptr_ty = self.llvm_context.ptr_type(AddressSpace::default());
+        let mut param_types: Vec<BasicMetadataTypeEnum> = Vec::new();
+        if return_type.is_struct_type() {
+            param_types.push(ptr_ty.into());
+        }
+        param_types.push(ptr_ty.into());
+        param_types.extend(
+            arg_types
+                .iter()
+                .filter_map(|ty| self.type_to_basic_metadata_type_enum(ty)),
+        );
+        if return_type.is_struct_type() {
+            return self.llvm_context.void_type().fn_type(&param_types, false);
+        }
+        match self.type_to_basic_type_enum(return_type) {
+            Some(return_ty) => return_ty.fn_type(&param_types, false),
+            None => self.llvm_context.void_type().fn_type(&param_types, false),
+        }
+    }
+
+    // データへのポインタから値を読み出し、実装関数を呼び出すthunkを生成する
+    fn gen_or_get_dyn_thunk(&self, impl_fn_name: &str) -> Result<FunctionValue, BuilderError> {
+        let thunk_name = format!("{}$dyn", impl_fn_name);
+        if let Some(thunk) = self.llvm_module.get_function(&thunk_name) {
+            return Ok(thunk);
+        }
+
+        let impl_function = *self.function_by_name.get(impl_fn_name).unwrap();
+        let impl_fn = self.gen_or_get_function(impl_function);
+        let return_type = &impl_function.decl.return_type;
+        let returns_struct = return_type.is_struct_type();
+        let arg_types = impl_function
+            .decl
+            .args
+            .iter()
+            .map(|arg| match arg {
+                Argument::Normal(ty, _) => ty.clone(),
+                Argument::VarArgs => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        let self_ty = &arg_types[0];
+
+        // thunkとvtableは生成したモジュールの中からしか参照しない
+        let thunk = self.llvm_module.add_function(
+            &thunk_name,
+            self.dyn_method_fn_type(return_type, &arg_types[1..]),
+            Some(LLVMLinkage::Private),
+        );
+        let current_block = self.llvm_builder.get_insert_block();
+        // thunkの命令に、生成中の関数の位置を付けない (verifierが弾く)
+        let current_location = self.llvm_builder.get_current_debug_location();
+        self.llvm_builder.unset_current_debug_location();
+        let entry = self.llvm_context.append_basic_block(thunk, "entry");
+        self.llvm_builder.position_at_end(entry);
+
+        let data_index = if returns_struct { 1 } else { 0 };
+        let data_ptr = thunk
+            .get_nth_param(data_index)
+            .unwrap()
+            .into_pointer_value();
+        let self_value = self.llvm_builder.build_load(
+            self.type_to_basic_type_enum(self_ty).unwrap(),
+            data_ptr,
+            "self",
+        )?;
+        let mut args: Vec<BasicMetadataValueEnum> = Vec::new();
+        if returns_struct {
+            args.push(thunk.get_first_param().unwrap().into());
+        }
+        args.push(self_value.into());
+        // self以外の引数はそのまま実装関数に渡す
+        args.extend(
+            thunk
+                .get_param_iter()
+                .skip(data_index as usize + 1)
+                .map(BasicMetadataValueEnum::from),
+        );
+        let value = self.llvm_builder.build_call(impl_fn, &args, "")?;
+        match value.try_as_basic_value().basic() {
+            Some(value) if !returns_struct => self.llvm_builder.build_return(Some(&value))?,
+            _ => self.llvm_builder.build_return(None)?,
+        };
+
+        if let Some(block) = current_block {
+            self.llvm_builder.position_at_end(block);
+        }
+        if let Some(location) = current_location {
+            self.llvm_builder.set_current_debug_location(location);
+        }
+        Ok(thunk)
+    }
+
+    // vtableは (インターフェース, 具体型) の組、つまり実装関数ごとに一つだけ生成する
+    fn gen_or_get_vtable(&self, impl_fn_name: &str) -> Result<PointerValue, BuilderError> {
+        let vtable_name = format!("vtable.{}", impl_fn_name);
+        if let Some(vtable) = self.llvm_module.get_global(&vtable_name) {
+            return Ok(vtable.as_pointer_value());
+        }
+        let thunk = self.gen_or_get_dyn_thunk(impl_fn_name)?;
+        let ptr_ty = self.llvm_context.ptr_type(AddressSpace::default());
+        let entries = [thunk.as_global_value().as_pointer_value()];
+        let vtable = self.llvm_module.add_global(
+            ptr_ty.array_type(entries.len() as u32),
+            None,
+            &vtable_name,
+        );
+        vtable.set_initializer(&ptr_ty.const_array(&entries));
+        vtable.set_constant(true);
+        vtable.set_linkage(LLVMLinkage::Private);
+        Ok(vtable.as_pointer_value())
+    }
+
+    pub(super) fn eval_dyn_coerce(
+        &self,
+        dyn_coerce: &DynCoerceExpr,
+        ty: &ConcreteType,
+    ) -> Result<BasicValueEnum, BuilderError> {
+        let value = self.gen_expression(&dyn_coerce.target)?.unwrap();
+        let value_ty = self.type_to_basic_type_enum(&dyn_coerce.target.ty).unwrap();
+
+        // 値の寿命を元のスコープから切り離すため、ヒープにコピーする。
+        // dynの値がいつまで使われるか分からないので、コピーは解放しない (リージョンが実装されるまで)。
+        // 変換を繰り返すループでは、その回数だけメモリが増え続ける
+        let data_ptr = self
+            .llvm_builder
+            .build_call(
+                self.get_or_declare_malloc(),
+                &[value_ty.size_of().unwrap().into()],
+                "dyn_data",
+            )?
+            .try_as_basic_value()
+            .basic()
+            .unwrap()
+            .into_pointer_value();
+        if dyn_coerce.target.ty.is_struct_type() {
+            self.llvm_builder.build_memcpy(
+                data_ptr,
+                8,
+                value.into_pointer_value(),
+                8,
+                value_ty.size_of().unwrap(),
+            )?;
+        } else {
+            self.llvm_builder.build_store(data_ptr, value)?;
+        }
+
+        let fat_ty = self.type_to_basic_type_enum(ty).unwrap();
+        let fat_ptr = self.build_entry_alloca(fat_ty, "dyn")?;
+        let data_field_ptr = self
+            .llvm_builder
+            .build_struct_gep(fat_ty, fat_ptr, 0, "data_ptr")?;
+        self.llvm_builder.build_store(data_field_ptr, data_ptr)?;
+        let vtable_field_ptr = self
+            .llvm_builder
+            .build_struct_gep(fat_ty, fat_ptr, 1, "vtable_ptr")?;
+        self.llvm_builder
+            .build_store(vtable_field_ptr, self.gen_or_get_vtable(&dyn_coerce.impl_fn)?)?;
+        Ok(fat_ptr.as_basic_value_enum())
+    }
+
+    pub(super) fn eval_dyn_call<'a>(
+        &'a self,
+        dyn_call: &DynCallExpr,
+        ty: &ConcreteType,
+    ) -> Result<Option<BasicValueEnum<'a>>, BuilderError> {
+        let ptr_ty = self.llvm_context.ptr_type(AddressSpace::default());
+        let fat_ty = self
+            .type_to_basic_type_enum(&dyn_call.receiver.ty)
+            .unwrap();
+        let fat_ptr = self
+            .gen_expression(&dyn_call.receiver)?
+            .unwrap()
+            .into_pointer_value();
+        let data_field_ptr = self
+            .llvm_builder
+            .build_struct_gep(fat_ty, fat_ptr, 0, "data_ptr")?;
+        let data_ptr = self.llvm_builder.build_load(ptr_ty, data_field_ptr, "data")?;
+        let vtable_field_ptr = self
+            .llvm_builder
+            .build_struct_gep(fat_ty, fat_ptr, 1, "vtable_ptr")?;
+        let vtable_ptr = self
+            .llvm_builder
+            .build_load(ptr_ty, vtable_field_ptr, "vtable")?
+            .into_pointer_value();
+        // インターフェースはメソッドを一つだけ持つので、vtableの先頭が呼び出し先になる
+        let method_ptr = self
+            .llvm_builder
+            .build_load(ptr_ty, vtable_ptr, "method")?
+            .into_pointer_value();
+
+        let mut args = dyn_call
+            .args
+            .iter()
+            .map(|arg| -> Result<BasicMetadataValueEnum, BuilderError> {
+                let value = self.gen_expression(arg)?.unwrap();
+                if arg.ty.is_struct_type() {
+                    let ty = self.type_to_basic_type_enum(&arg.ty).unwrap();
+                    Ok(self
+                        .llvm_builder
+                        .build_load(ty, value.into_pointer_value(), "")?
+                        .into())
+                } else {
+                    Ok(value.into())
+                }
+            })
+            .collect::<Result<Vec<BasicMetadataValueEnum>, _>>()?;
+        args.insert(0, data_ptr.into());
+
+        let arg_types = dyn_call
+            .args
+            .iter()
+            .map(|arg| arg.ty.clone())
+            .collect::<Vec<_>>();
+        let method_ty = self.dyn_method_fn_type(ty, &arg_types);
+        if ty.is_struct_type() {
+            let return_ty = self.type_to_basic_type_enum(ty).unwrap();
+            let ret_ptr = self.build_entry_alloca(return_ty, "")?;
+            args.insert(0, ret_ptr.into());
+            self.llvm_builder
+                .build_indirect_call(method_ty, method_ptr, &args, "")?;
+            return Ok(Some(ret_ptr.as_basic_value_enum()));
+        }
+        let value = self
+            .llvm_builder
+            .build_indirect_call(method_ty, method_ptr, &args, "")?;
+        Ok(value.try_as_basic_value().basic())
+    }
+}
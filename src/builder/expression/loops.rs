@@ -0,0 +1,127 @@
+use super::*;
+
+impl LLVMCodeGenerator<'_> {
+    pub(super) fn eval_while_expr<'a>(
+        &'a self,
+        while_expr: &WhileExpr,
+        ty: &ConcreteType,
+    ) -> Result<Option<BasicValueEnum<'a>>, BuilderError> {
+        // Get the current function
+        let function: inkwell::values::FunctionValue<'_> = self
+            .llvm_builder
+            .get_insert_block()
+            .unwrap()
+            .get_parent()
+            .unwrap();
+
+        // `(break value)` で値を返すループでは、値を受け取る領域を用意する
+        let value_type = self.type_to_basic_type_enum(ty);
+        let value_ptr = match value_type {
+            Some(value_type) => Some(self.build_entry_alloca(value_type, "loop_value")?),
+            None => None,
+        };
+
+        // Create basic blocks for the while loop
+        let loop_header = self
+            .llvm_context
+            .append_basic_block(function, "loop_header");
+        let loop_body = self.llvm_context.append_basic_block(function, "loop_body");
+        // ステップの式があれば、本体の後とcontinueの後にそれを実行する
+        let loop_step = match &while_expr.step {
+            Some(_) => Some(self.llvm_context.append_basic_block(function, "loop_step")),
+            None => None,
+        };
+        let loop_end = self.llvm_context.append_basic_block(function, "loop_end");
+
+        // Branch to loop header
+        self.llvm_builder.build_unconditional_branch(loop_header)?;
+
+        // Build loop header: check condition
+        self.llvm_builder.position_at_end(loop_header);
+        let cond = self
+            .gen_expression(&while_expr.cond)?
+            .unwrap()
+            .into_int_value();
+        self.llvm_builder
+            .build_conditional_branch(cond, loop_body, loop_end)?;
+
+        // Build loop body
+        self.llvm_builder.position_at_end(loop_body);
+        self.push_loop(LoopTarget {
+            id: while_expr.loop_id,
+            next: loop_step.unwrap_or(loop_header),
+            exit: loop_end,
+            value_ptr,
+        });
+        let body = self.gen_expression(&while_expr.body);
+        self.pop_loop();
+        body?;
+        self.llvm_builder
+            .build_unconditional_branch(loop_step.unwrap_or(loop_header))?;
+
+        if let (Some(loop_step), Some(step)) = (loop_step, &while_expr.step) {
+            self.llvm_builder.position_at_end(loop_step);
+            self.gen_expression(step)?;
+            self.llvm_builder.build_unconditional_branch(loop_header)?;
+        }
+
+        // Position at loop end for subsequent code
+        self.llvm_builder.position_at_end(loop_end);
+        match (value_type, value_ptr) {
+            (Some(value_type), Some(value_ptr)) => Ok(Some(self.llvm_builder.build_load(
+                value_type,
+                value_ptr,
+                "loop_value",
+            )?)),
+            _ => Ok(None),
+        }
+    }
+    pub(super) fn eval_break_expr(&self, break_expr: &BreakExpr) -> Result<(), BuilderError> {
+        let target = self.get_loop(break_expr.loop_id);
+        if let Some(value) = &break_expr.value {
+            let value = self.gen_expression(value)?.unwrap();
+            self.llvm_builder
+                .build_store(target.value_ptr.unwrap(), value)?;
+        }
+        self.llvm_builder.build_unconditional_branch(target.exit)?;
+        self.position_at_unreachable_block("after_break");
+        Ok(())
+    }
+    pub(super) fn eval_continue_expr(
+        &self,
+        continue_expr: &ContinueExpr,
+    ) -> Result<(), BuilderError> {
+        let target = self.get_loop(continue_expr.loop_id);
+        self.llvm_builder.build_unconditional_branch(target.next)?;
+        self.position_at_unreachable_block("after_continue");
+        Ok(())
+    }
+    // ブロックの中で宣言した変数は、ブロックを抜けると見えなくなる
+    pub(super) fn eval_block_expr<'a>(
+        &'a self,
+        block: &BlockExpr,
+    ) -> Result<Option<BasicValueEnum<'a>>, BuilderError> {
+        self.push_scope(Scope::new());
+        let value = block.expressions.iter().try_fold(None, |_, expression| {
+            let value = self.gen_expression(expression)?;
+            // return の後に続く命令は、到達しないブロックに置く
+            if let ExpressionKind::Return(_) = expression.kind {
+                self.position_at_unreachable_block("after_return");
+            }
+            Ok(value)
+        });
+        self.pop_scope();
+        value
+    }
+    // 分岐した後に続く式は実行されないが、命令を置くためのブロックは必要になる
+    pub(super) fn position_at_unreachable_block(&self, name: &str) {
+        let function = self
+            .llvm_builder
+            .get_insert_block()
+            .unwrap()
+            .get_parent()
+            .unwrap();
+        let block = self.llvm_context.append_basic_block(function, name);
+        self.llvm_builder.position_at_end(block);
+    }
+}
@@ -1,4 +1,8 @@
 mod binary;
+mod cast;
+mod closure;
+mod dynamic;
+mod loops;
 mod multi;
 mod unary;
 
@@ -12,59 +16,35 @@ use inkwell::{
 };
 
 impl LLVMCodeGenerator<'_> {
-    fn eval_u8(&self, value_str: &str) -> BasicValueEnum {
-        let n = value_str.parse::<u8>().unwrap();
-        let int_value = self.llvm_context.i8_type().const_int(n as u64, true);
-        int_value.into()
-    }
-    fn eval_i32(&self, value_str: &str) -> BasicValueEnum {
-        let n = value_str.parse::<i32>().unwrap();
-        let int_value = self.llvm_context.i32_type().const_int(n as u64, true);
-        int_value.into()
-    }
-    fn eval_i64(&self, value_str: &str) -> BasicValueEnum {
-        let n = value_str.parse::<i64>().unwrap();
-        let int_value = self.llvm_context.i64_type().const_int(n as u64, true);
-        int_value.into()
-    }
-    fn eval_u32(&self, value_str: &str) -> BasicValueEnum {
-        let n = value_str.parse::<u32>().unwrap();
-        let int_value = self.llvm_context.i32_type().const_int(n as u64, true);
-        int_value.into()
-    }
-    fn eval_u64(&self, value_str: &str) -> BasicValueEnum {
-        let n = value_str.parse::<u64>().unwrap();
-        let int_value = self.llvm_context.i64_type().const_int(n, true);
-        int_value.into()
-    }
-    fn eval_f32(&self, value_str: &str) -> BasicValueEnum {
-        let n = value_str.parse::<f32>().unwrap();
-        let float_value = self.llvm_context.f32_type().const_float(n as f64);
-        float_value.into()
-    }
-    fn eval_f64(&self, value_str: &str) -> BasicValueEnum {
-        let n = value_str.parse::<f64>().unwrap();
-        let float_value = self.llvm_context.f64_type().const_float(n);
-        float_value.into()
-    }
     fn eval_number_literal(
         &self,
-        integer_literal: &NumberLiteral,
+        number_literal: &NumberLiteral,
         ty: &ConcreteType,
     ) -> Result<BasicValueEnum, BuilderError> {
-        let value_str = &integer_literal.value;
-        Ok(match ty {
-            ConcreteType::U8 => self.eval_u8(value_str),
-            ConcreteType::U32 => self.eval_u32(value_str),
-            ConcreteType::I32 => self.eval_i32(value_str),
-            ConcreteType::I64 => self.eval_i64(value_str),
-            ConcreteType::U64 => self.eval_u64(value_str),
-            ConcreteType::F32 => self.eval_f32(value_str),
-            ConcreteType::F64 => self.eval_f64(value_str),
-            ConcreteType::Ptr(_) => unreachable!(),
-            ConcreteType::Void => unreachable!(),
-            ConcreteType::StructLike(_) => unreachable!(),
-            ConcreteType::Bool => unreachable!(),
+        // 値が型に収まることは resolver で検査済み
+        Ok(match (number_literal.value, ty) {
+            (NumberLiteralValue::Integer(n), ConcreteType::U8) => {
+                self.llvm_context.i8_type().const_int(n, false).into()
+            }
+            (NumberLiteralValue::Integer(n), ConcreteType::I32 | ConcreteType::U32) => {
+                self.llvm_context.i32_type().const_int(n, false).into()
+            }
+            (NumberLiteralValue::Integer(n), ConcreteType::I64 | ConcreteType::U64) => {
+                self.llvm_context.i64_type().const_int(n, false).into()
+            }
+            (NumberLiteralValue::Integer(n), ConcreteType::F32) => {
+                self.llvm_context.f32_type().const_float(n as f64).into()
+            }
+            (NumberLiteralValue::Integer(n), ConcreteType::F64) => {
+                self.llvm_context.f64_type().const_float(n as f64).into()
+            }
+            (NumberLiteralValue::Float(n), ConcreteType::F32) => {
+                self.llvm_context.f32_type().const_float(n as f32 as f64).into()
+            }
+            (NumberLiteralValue::Float(n), ConcreteType::F64) => {
+                self.llvm_context.f64_type().const_float(n).into()
+            }
+            _ => unreachable!(),
         })
     }
     fn eval_bool_literal(
@@ -95,15 +75,39 @@ impl LLVMCodeGenerator<'_> {
         for (i, (_name, field_expr)) in struct_literal.fields.iter().enumerate() {
             let value = self.gen_expression(field_expr)?.unwrap();
             let ptr = self.llvm_builder.build_struct_gep(ty, ptr, i as u32, "")?;
-            self.llvm_builder.build_store(ptr, value)?;
+            // 構造体の値はポインタで受け取るので、中身をコピーする
+            if field_expr.ty.is_struct_type() {
+                let field_ty = self.type_to_basic_type_enum(&field_expr.ty).unwrap();
+                self.llvm_builder.build_memcpy(
+                    ptr,
+                    8,
+                    value.into_pointer_value(),
+                    8,
+                    field_ty.size_of().unwrap(),
+                )?;
+            } else {
+                self.llvm_builder.build_store(ptr, value)?;
+            }
         }
         Ok(ptr.as_basic_value_enum())
     }
+    fn get_or_declare_malloc(&self) -> inkwell::values::FunctionValue {
+        self.llvm_module.get_function("malloc").unwrap_or_else(|| {
+            // Declare malloc if it doesn't exist
+            let ptr_type = self.llvm_context.ptr_type(inkwell::AddressSpace::default());
+            let usize_type = self.llvm_context.i64_type();
+            let malloc_type = ptr_type.fn_type(&[usize_type.into()], false);
+            self.llvm_module.add_function("malloc", malloc_type, None)
+        })
+    }
     fn eval_array_literal(
         &self,
         array_literal: &ArrayLiteral,
         ty: &ConcreteType,
     ) -> Result<BasicValueEnum, BuilderError> {
+        if let ConcreteType::Array(element, _) = ty {
+            return self.eval_fixed_array_literal(array_literal, element, ty);
+        }
         // ty should be Vec<T> which is a struct with { capacity: i32, size: i32, buf: *T }
         if let ConcreteType::StructLike(struct_ty) = ty {
             let vec_ty = self.type_to_basic_type_enum(ty).unwrap();
@@ -130,13 +134,7 @@ impl LLVMCodeGenerator<'_> {
             )?;
 
             // Call malloc to allocate buffer
-            let malloc_fn = self.llvm_module.get_function("malloc").unwrap_or_else(|| {
-                // Declare malloc if it doesn't exist
-                let ptr_type = self.llvm_context.ptr_type(inkwell::AddressSpace::default());
-                let usize_type = self.llvm_context.i64_type();
-                let malloc_type = ptr_type.fn_type(&[usize_type.into()], false);
-                self.llvm_module.add_function("malloc", malloc_type, None)
-            });
+            let malloc_fn = self.get_or_declare_malloc();
             let buf_ptr = self.llvm_builder.build_call(
                 malloc_fn,
                 &[total_size.into()],
@@ -180,6 +178,40 @@ impl LLVMCodeGenerator<'_> {
             unreachable!("Array literal should have Vec<T> type")
         }
     }
+    // 固定長配列はスタックに確保し、要素を順に書き込む
+    fn eval_fixed_array_literal(
+        &self,
+        array_literal: &ArrayLiteral,
+        element: &ConcreteType,
+        ty: &ConcreteType,
+    ) -> Result<BasicValueEnum, BuilderError> {
+        let array_ty = self.type_to_basic_type_enum(ty).unwrap();
+        let element_ty = self.type_to_basic_type_enum(element).unwrap();
+        let array_ptr = self.build_entry_alloca(array_ty, "array")?;
+        for (i, elem) in array_literal.elements.iter().enumerate() {
+            let value = self.gen_expression(elem)?.unwrap();
+            let elem_ptr = unsafe {
+                self.llvm_builder.build_in_bounds_gep(
+                    element_ty,
+                    array_ptr,
+                    &[self.llvm_context.i64_type().const_int(i as u64, false)],
+                    "",
+                )?
+            };
+            if element.is_struct_type() {
+                self.llvm_builder.build_memcpy(
+                    elem_ptr,
+                    8,
+                    value.into_pointer_value(),
+                    8,
+                    element_ty.size_of().unwrap(),
+                )?;
+            } else {
+                self.llvm_builder.build_store(elem_ptr, value)?;
+            }
+        }
+        Ok(array_ptr.as_basic_value_enum())
+    }
     fn eval_variable_ref(
         &self,
         variable_ref: &VariableRefExpr,
@@ -238,6 +270,10 @@ impl LLVMCodeGenerator<'_> {
                 index as u32,
                 "",
             )?;
+            // 構造体の値は他の式と同じくポインタのまま扱う
+            if ty.is_struct_type() {
+                return Ok(field_ptr.as_basic_value_enum());
+            }
             let value = self
                 .llvm_builder
                 .build_load(ty_enum, field_ptr, "")
@@ -282,32 +318,59 @@ impl LLVMCodeGenerator<'_> {
             }
         }
     }
+    // C の可変長引数に渡す値は、既定の実引数拡張に従い f32 を f64 に、
+    // int より小さい整数を int に拡張する
+    pub(super) fn promote_var_arg<'a>(
+        &'a self,
+        value: BasicValueEnum<'a>,
+        ty: &ConcreteType,
+    ) -> Result<BasicValueEnum<'a>, BuilderError> {
+        match ty {
+            ConcreteType::F32 => self.gen_try_cast(value, ty, &ConcreteType::F64),
+            ConcreteType::U8 => self.gen_try_cast(value, ty, &ConcreteType::I32),
+            ConcreteType::Bool => Ok(self
+                .llvm_builder
+                .build_int_z_extend(value.into_int_value(), self.llvm_context.i32_type(), "")?
+                .as_basic_value_enum()),
+            _ => Ok(value),
+        }
+    }
     pub(super) fn eval_call_expr<'a>(
         &'a self,
         call_expr: &CallExpr,
     ) -> Result<Option<BasicValueEnum<'a>>, BuilderError> {
+        let function = *self.function_by_name.get(&call_expr.callee).unwrap();
+        if function.decl.is_extern {
+            return self.eval_extern_call(function, call_expr);
+        }
+        // 可変長引数の位置に渡す値 (インデックスがこれ以降のもの)
+        let var_args_start = match function.decl.args.last() {
+            Some(Argument::VarArgs) => function.decl.args.len() - 1,
+            _ => usize::MAX,
+        };
         let mut args = call_expr
             .args
             .iter()
-            .map(|arg| {
-                self.gen_expression(arg).map(|x| {
-                    if arg.ty.is_struct_type() {
-                        let ty = self.type_to_basic_type_enum(&arg.ty).unwrap();
-                        self.llvm_builder
-                            .build_load(ty, x.unwrap().into_pointer_value(), "")
-                            .unwrap()
-                            .into()
-                    } else {
-                        x.unwrap().into()
-                    }
-                })
+            .enumerate()
+            .map(|(i, arg)| -> Result<BasicMetadataValueEnum, BuilderError> {
+                let value = self.gen_expression(arg)?.unwrap();
+                if arg.ty.is_struct_type() {
+                    let ty = self.type_to_basic_type_enum(&arg.ty).unwrap();
+                    Ok(self
+                        .llvm_builder
+                        .build_load(ty, value.into_pointer_value(), "")?
+                        .into())
+                } else if i >= var_args_start {
+                    self.promote_var_arg(value, &arg.ty).map(Into::into)
+                } else {
+                    Ok(value.into())
+                }
             })
             .collect::<Result<Vec<BasicMetadataValueEnum>, _>>()?;
 
-        let function = *self.function_by_name.get(&call_expr.callee).unwrap();
         let func = self.gen_or_get_function(function);
         // 構造体を返す関数を呼ぶ場合、第一引数にスタックポインタを渡す
-        if let ConcreteType::StructLike(_) = &function.decl.return_type {
+        if function.decl.return_type.is_struct_type() {
             let return_ty = self
                 .type_to_basic_type_enum(&function.decl.return_type)
                 .unwrap();
@@ -388,71 +451,15 @@ impl LLVMCodeGenerator<'_> {
         self.llvm_builder.position_at_end(merge_block);
         Ok(None)
     }
-    pub(super) fn eval_while_expr<'a>(
-        &'a self,
-        while_expr: &WhileExpr,
-    ) -> Result<Option<BasicValueEnum<'a>>, BuilderError> {
-        // Get the current function
-        let function: inkwell::values::FunctionValue<'_> = self
-            .llvm_builder
-            .get_insert_block()
-            .unwrap()
-            .get_parent()
-            .unwrap();
-
-        // Create basic blocks for the while loop
-        let loop_header = self.llvm_context.append_basic_block(function, "loop_header");
-        let loop_body = self.llvm_context.append_basic_block(function, "loop_body");
-        let loop_end = self.llvm_context.append_basic_block(function, "loop_end");
-
-        // Branch to loop header
-        self.llvm_builder.build_unconditional_branch(loop_header)?;
-
-        // Build loop header: check condition
-        self.llvm_builder.position_at_end(loop_header);
-        let cond = self
-            .gen_expression(&while_expr.cond)?
-            .unwrap()
-            .into_int_value();
-        self.llvm_builder
-            .build_conditional_branch(cond, loop_body, loop_end)?;
-
-        // Build loop body
-        self.llvm_builder.position_at_end(loop_body);
-        self.gen_expression(&while_expr.body)?;
-        self.llvm_builder.build_unconditional_branch(loop_header)?;
-
-        // Position at loop end for subsequent code
-        self.llvm_builder.position_at_end(loop_end);
-        Ok(None)
-    }
     pub(super) fn eval_variable_decls(&self, decls: &VariableDecls) -> Result<(), BuilderError> {
         for decl in &decls.decls {
             // Use the declared type of the variable, not the value type
             let var_ty = self.type_to_basic_type_enum(&decl.ty).unwrap();
-            let mut value = self.gen_expression(&decl.value)?.unwrap();
-
-            // If the value type differs from the declared type, insert a cast
-            let value_ty = self.type_to_basic_type_enum(&decl.value.ty).unwrap();
-            if var_ty != value_ty && !var_ty.is_struct_type() && !value_ty.is_struct_type() {
-                // Need to cast value to var_ty
-                if var_ty.is_int_type() && value_ty.is_int_type() {
-                    // Integer type cast
-                    let var_int_ty = var_ty.into_int_type();
-                    let value_int_ty = value_ty.into_int_type();
-                    let value_int = value.into_int_value();
-                    if var_int_ty.get_bit_width() > value_int_ty.get_bit_width() {
-                        // Zero extend for unsigned types or sign extend for signed
-                        value = self.llvm_builder.build_int_z_extend(value_int, var_int_ty, "cast")?.into();
-                    } else if var_int_ty.get_bit_width() < value_int_ty.get_bit_width() {
-                        // Truncate
-                        value = self.llvm_builder.build_int_truncate(value_int, var_int_ty, "cast")?.into();
-                    }
-                }
-            }
+            // 整数型の変換は resolver がキャストの式として挿入している
+            let value = self.gen_expression(&decl.value)?.unwrap();
 
-            if var_ty.is_struct_type() {
-                let ptr = self.llvm_builder.build_alloca(var_ty, "")?;
+            let ptr = self.build_entry_alloca(var_ty, &decl.name)?;
+            if decl.ty.is_struct_type() {
                 self.llvm_builder.build_memcpy(
                     ptr,
                     8,
@@ -460,12 +467,11 @@ impl LLVMCodeGenerator<'_> {
                     8,
                     var_ty.size_of().unwrap(),
                 )?;
-                self.add_variable(&decl.name, ptr);
             } else {
-                let ptr = self.llvm_builder.build_alloca(var_ty, "")?;
                 self.llvm_builder.build_store(ptr, value)?;
-                self.add_variable(&decl.name, ptr);
             }
+            self.declare_debug_variable(&decl.name, &decl.ty, ptr);
+            self.add_variable(&decl.name, ptr);
         }
         Ok(())
     }
@@ -483,15 +489,18 @@ impl LLVMCodeGenerator<'_> {
         }
         if let Some(index_access) = &assignment.index_access {
             let index = self.gen_expression(index_access)?.unwrap();
-            ptr = self
-                .llvm_builder
-                .build_load(self.llvm_context.ptr_type(AddressSpace::default()), ptr, "")
-                .unwrap()
-                .into_pointer_value();
+            // 固定長配列の変数は要素を直接持つので、ポインタを読み出さない
+            if !assignment.target_is_array {
+                ptr = self
+                    .llvm_builder
+                    .build_load(self.llvm_context.ptr_type(AddressSpace::default()), ptr, "")
+                    .unwrap()
+                    .into_pointer_value();
+            }
 
             ptr = unsafe {
                 self.llvm_builder
-                    .build_in_bounds_gep(pointee_type, ptr, &[index.into_int_value()], "")
+                    .build_in_bounds_gep(value_ty, ptr, &[index.into_int_value()], "")
                     .unwrap()
             };
             if assignment.value.ty.is_struct_type() {
@@ -500,7 +509,7 @@ impl LLVMCodeGenerator<'_> {
                     8,
                     value.into_pointer_value(),
                     8,
-                    pointee_type.size_of().unwrap(),
+                    value_ty.size_of().unwrap(),
                 )?;
                 return Ok(());
             }
@@ -522,6 +531,12 @@ impl LLVMCodeGenerator<'_> {
     pub(super) fn gen_expression<'a>(
         &'a self,
         expr: &ConcreteExpression,
+    ) -> Result<Option<BasicValueEnum<'a>>, BuilderError> {
+        self.with_debug_location(expr.location, || self.gen_expression_kind(expr))
+    }
+    fn gen_expression_kind<'a>(
+        &'a self,
+        expr: &ConcreteExpression,
     ) -> Result<Option<BasicValueEnum<'a>>, BuilderError> {
         match &expr.kind {
             ExpressionKind::NumberLiteral(number_literal) => {
@@ -549,6 +564,7 @@ impl LLVMCodeGenerator<'_> {
                 self.eval_array_literal(array_literal, &expr.ty).map(Some)
             }
             ExpressionKind::SizeOf(ty) => Ok(Some(self.eval_sizeof(ty))),
+            ExpressionKind::Cast(cast) => self.eval_cast(cast, &expr.ty).map(Some),
             ExpressionKind::FieldAccess(field_access_expr) => self
                 .eval_field_access(field_access_expr, &expr.ty)
                 .map(Some),
@@ -558,7 +574,16 @@ impl LLVMCodeGenerator<'_> {
             }
             ExpressionKind::If(if_expr) => self.eval_if_expr(if_expr, &expr.ty),
             ExpressionKind::When(when_expr) => self.eval_when_expr(when_expr),
-            ExpressionKind::While(while_expr) => self.eval_while_expr(while_expr),
+            ExpressionKind::While(while_expr) => self.eval_while_expr(while_expr, &expr.ty),
+            ExpressionKind::Break(break_expr) => {
+                self.eval_break_expr(break_expr)?;
+                Ok(None)
+            }
+            ExpressionKind::Continue(continue_expr) => {
+                self.eval_continue_expr(continue_expr)?;
+                Ok(None)
+            }
+            ExpressionKind::Block(block) => self.eval_block_expr(block),
             ExpressionKind::VariableDecls(decls) => {
                 self.eval_variable_decls(decls)?;
                 Ok(None)
@@ -570,6 +595,14 @@ impl LLVMCodeGenerator<'_> {
                 self.gen_return(ret)?;
                 Ok(None)
             }
+            ExpressionKind::DynCoerce(dyn_coerce) => {
+                self.eval_dyn_coerce(dyn_coerce, &expr.ty).map(Some)
+            }
+            ExpressionKind::DynCall(dyn_call) => self.eval_dyn_call(dyn_call, &expr.ty),
+            ExpressionKind::Closure(closure) => self.eval_closure(closure, &expr.ty).map(Some),
+            ExpressionKind::ClosureCall(closure_call) => {
+                self.eval_closure_call(closure_call, &expr.ty)
+            }
         }
     }
 }
@@ -2,28 +2,93 @@ use super::*;
 use crate::ast::MultiOp;
 
 impl LLVMCodeGenerator<'_> {
+    // `and` / `or` は短絡評価する。結果が決まった時点で残りのオペランドは評価しない
     pub(super) fn eval_multi_expr(
         &self,
         multi_expr: &MultiExpr,
     ) -> Result<BasicValueEnum, BuilderError> {
-        let operands = multi_expr
-            .operands
-            .iter()
-            .map(|operand| self.gen_expression(operand))
-            .collect::<Result<Vec<_>, _>>()?;
-
         match multi_expr.op {
             MultiOp::And | MultiOp::Or => {
-                let mut result = self.llvm_context.bool_type().const_int(1, false);
-                for operand in operands {
-                    let operand = operand.unwrap().into_int_value();
-                    result = match multi_expr.op {
-                        MultiOp::And => self.llvm_builder.build_and(result, operand, "and")?,
-                        MultiOp::Or => self.llvm_builder.build_or(result, operand, "or")?,
-                    };
+                let is_and = multi_expr.op == MultiOp::And;
+                let bool_type = self.llvm_context.bool_type();
+                // 途中で結果が決まったときの値。andならfalse、orならtrue
+                let short_circuit_value = bool_type.const_int(!is_and as u64, false);
+
+                let Some((last, rest)) = multi_expr.operands.split_last() else {
+                    // `(and)` はtrue、`(or)` はfalse
+                    return Ok(bool_type.const_int(is_and as u64, false).into());
+                };
+
+                let function = self
+                    .llvm_builder
+                    .get_insert_block()
+                    .unwrap()
+                    .get_parent()
+                    .unwrap();
+                let merge_block = self.llvm_context.append_basic_block(function, "sc_end");
+                let mut incomings = Vec::new();
+
+                for operand in rest {
+                    let value = self.gen_expression(operand)?.unwrap().into_int_value();
+                    let current_block = self.llvm_builder.get_insert_block().unwrap();
+                    let next_block = self.llvm_context.append_basic_block(function, "sc_next");
+                    if is_and {
+                        self.llvm_builder.build_conditional_branch(
+                            value,
+                            next_block,
+                            merge_block,
+                        )?;
+                    } else {
+                        self.llvm_builder.build_conditional_branch(
+                            value,
+                            merge_block,
+                            next_block,
+                        )?;
+                    }
+                    incomings.push((short_circuit_value, current_block));
+                    self.llvm_builder.position_at_end(next_block);
+                }
+
+                // 最後のオペランドの値がそのまま結果になる
+                let value = self.gen_expression(last)?.unwrap().into_int_value();
+                let last_block = self.llvm_builder.get_insert_block().unwrap();
+                self.llvm_builder.build_unconditional_branch(merge_block)?;
+                incomings.push((value, last_block));
+
+                self.llvm_builder.position_at_end(merge_block);
+                let phi = self.llvm_builder.build_phi(bool_type, "sc_result")?;
+                for (value, block) in &incomings {
+                    phi.add_incoming(&[(value, *block)]);
                 }
-                Ok(result.as_basic_value_enum())
+                Ok(phi.as_basic_value())
             }
         }
     }
 }
+
+#[test]
+fn test_short_circuit_multi_expr() {
+    let ir = crate::builder::generate_ir(
+        r#"
+fn check(result: bool): bool {
+  result
+}
+
+fn main(): i32 {
+  (:= x 5)
+  (if (and (check false) (check true)) 1 (if (or (< x 0) (= x 5)) 0 2))
+}
+"#,
+        false,
+    );
+    // 2つ目のオペランドは、1つ目で結果が決まらなかったときに進むブロックで評価する
+    let sc_next = ir
+        .split("\n\n")
+        .find(|block| block.starts_with("sc_next:"))
+        .expect(&ir);
+    assert!(sc_next.contains("call i1"), "{ir}");
+    // 結果は、途中で決まった値と最後のオペランドの値のphiになる
+    assert!(ir.contains("phi i1 [ false, %"), "{ir}");
+    assert!(ir.contains("phi i1 [ true, %"), "{ir}");
+    assert!(ir.contains("%sc_next ]"), "{ir}");
+}
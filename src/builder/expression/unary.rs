@@ -13,6 +13,18 @@ impl LLVMCodeGenerator<'_> {
                 .llvm_builder
                 .build_not(operand.into_int_value(), "not")?
                 .into()),
+            UnaryOp::BitNot => Ok(self
+                .llvm_builder
+                .build_not(operand.into_int_value(), "bitnot")?
+                .into()),
+            UnaryOp::Neg if unary_expr.operand.ty.is_float_type() => Ok(self
+                .llvm_builder
+                .build_float_neg(operand.into_float_value(), "neg")?
+                .into()),
+            UnaryOp::Neg => Ok(self
+                .llvm_builder
+                .build_int_neg(operand.into_int_value(), "neg")?
+                .into()),
         }
     }
 }
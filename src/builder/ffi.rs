@@ -0,0 +1,503 @@
+use inkwell::{
+    attributes::{Attribute, AttributeLoc},
+    builder::BuilderError,
+    types::{AnyType, BasicMetadataTypeEnum, BasicType, BasicTypeEnum},
+    values::{BasicMetadataValueEnum, BasicValue, BasicValueEnum, FunctionValue, PointerValue},
+    AddressSpace,
+};
+
+use super::*;
+use crate::common::target::{Arch, Os};
+use crate::concrete_ast::*;
+
+// Cの呼び出し規約で、1つの値を受け渡す方法
+#[derive(Debug, Clone)]
+enum CAbiPassing<'a> {
+    // 値をそのまま渡す (数値とポインタ)
+    Direct,
+    // 構造体のメモリを、レジスタに載る型の並びとして読み直して渡す
+    Coerce(Vec<BasicTypeEnum<'a>>),
+    // 呼び出し元が作ったコピーへのポインタを渡す。byval ならスタックに積まれる。
+    // 戻り値では sret になる
+    Indirect { byval: bool },
+}
+
+// Cでの大きさと境界 (バイト)
+pub(super) fn c_layout(ty: &ConcreteType, word_size: u64) -> (u64, u64) {
+    match ty {
+        ConcreteType::U8 | ConcreteType::Bool => (1, 1),
+        ConcreteType::I32 | ConcreteType::U32 | ConcreteType::F32 => (4, 4),
+        ConcreteType::I64 | ConcreteType::U64 | ConcreteType::F64 => (8, 8),
+        ConcreteType::Ptr(_) => (word_size, word_size),
+        ConcreteType::Void => (0, 1),
+        ConcreteType::StructLike(struct_ty) => {
+            let mut size = 0;
+            let mut align = 1;
+            for (_, field_ty) in &struct_ty.fields {
+                let (field_size, field_align) = c_layout(field_ty, word_size);
+                size = size.next_multiple_of(field_align) + field_size;
+                align = align.max(field_align);
+            }
+            (size.next_multiple_of(align), align)
+        }
+        ConcreteType::Array(element, len) => {
+            let (size, align) = c_layout(element, word_size);
+            (size * *len as u64, align)
+        }
+    }
+}
+
+// 構造体の中の数値とポインタを、先頭からの位置と一緒に並べる
+fn scalar_fields(
+    ty: &ConcreteType,
+    offset: u64,
+    word_size: u64,
+    out: &mut Vec<(u64, ConcreteType)>,
+) {
+    match ty {
+        ConcreteType::StructLike(struct_ty) => {
+            let mut field_offset = 0;
+            for (_, field_ty) in &struct_ty.fields {
+                let (field_size, field_align) = c_layout(field_ty, word_size);
+                field_offset = field_offset.next_multiple_of(field_align);
+                scalar_fields(field_ty, offset + field_offset, word_size, out);
+                field_offset += field_size;
+            }
+        }
+        ConcreteType::Array(element, len) => {
+            let (size, _) = c_layout(element, word_size);
+            for i in 0..*len as u64 {
+                scalar_fields(element, offset + size * i, word_size, out);
+            }
+        }
+        ConcreteType::Void => {}
+        _ => out.push((offset, ty.clone())),
+    }
+}
+
+impl<'ctx> LLVMCodeGenerator<'ctx> {
+    // 構造体の受け渡し方はターゲットの呼び出し規約で決まる。
+    // 数値とポインタはどのターゲットでもそのまま渡す
+    fn c_abi_passing(&self, ty: &ConcreteType) -> CAbiPassing<'ctx> {
+        if !ty.is_struct_type() {
+            return CAbiPassing::Direct;
+        }
+        let metrics = self.target.metrics();
+        let word_size = metrics.word_size as u64;
+        let (size, _) = c_layout(ty, word_size);
+        let mut fields = Vec::new();
+        scalar_fields(ty, 0, word_size, &mut fields);
+        let context = self.llvm_context;
+        match (metrics.arch, metrics.os) {
+            // Windows x64: 1, 2, 4, 8 バイトの構造体は同じ大きさの整数として、それ以外はコピーへのポインタで渡す
+            (Arch::Amd64, Os::Windows) => match size {
+                1 | 2 | 4 | 8 => {
+                    CAbiPassing::Coerce(vec![context.custom_width_int_type(size as u32 * 8).into()])
+                }
+                _ => CAbiPassing::Indirect { byval: false },
+            },
+            // System V x86-64: 16バイトまでは8バイトごとに、小数だけなら浮動小数点数のレジスタ、
+            // それ以外は整数のレジスタで渡す。それより大きければスタックに積む
+            (Arch::Amd64, _) => {
+                if size > 16 {
+                    return CAbiPassing::Indirect { byval: true };
+                }
+                let types = (0..size.div_ceil(8))
+                    .map(|i| -> BasicTypeEnum<'ctx> {
+                        let bytes = (size - i * 8).min(8);
+                        let in_eightbyte = fields
+                            .iter()
+                            .filter(|(offset, _)| offset / 8 == i)
+                            .map(|(_, ty)| ty)
+                            .collect::<Vec<_>>();
+                        if !in_eightbyte.iter().all(|ty| ty.is_float_type()) {
+                            context.custom_width_int_type(bytes as u32 * 8).into()
+                        } else if in_eightbyte.contains(&&ConcreteType::F64) {
+                            context.f64_type().into()
+                        } else if bytes > 4 {
+                            context.f32_type().vec_type(2).into()
+                        } else {
+                            context.f32_type().into()
+                        }
+                    })
+                    .collect();
+                CAbiPassing::Coerce(types)
+            }
+            // AArch64: 同じ浮動小数点数型が4つまで並ぶ構造体 (HFA) は浮動小数点数のレジスタで、
+            // 16バイトまでは整数のレジスタで、それより大きければコピーへのポインタで渡す
+            (Arch::Arm64, _) => {
+                let is_hfa = (1..=4).contains(&fields.len())
+                    && fields[0].1.is_float_type()
+                    && fields.iter().all(|(_, ty)| *ty == fields[0].1);
+                if is_hfa {
+                    let element_ty: BasicTypeEnum = if fields[0].1 == ConcreteType::F64 {
+                        context.f64_type().into()
+                    } else {
+                        context.f32_type().into()
+                    };
+                    CAbiPassing::Coerce(vec![element_ty.array_type(fields.len() as u32).into()])
+                } else if size > 16 {
+                    CAbiPassing::Indirect { byval: false }
+                } else if size > 8 {
+                    CAbiPassing::Coerce(vec![context.i64_type().array_type(2).into()])
+                } else {
+                    CAbiPassing::Coerce(vec![context.i64_type().into()])
+                }
+            }
+            // その他のターゲットでは、構造体をスタックに積んで渡す
+            _ => CAbiPassing::Indirect { byval: true },
+        }
+    }
+
+    // 読み直す型が複数あれば、それらを並べた構造体として1つの領域に置く
+    fn coerced_type(&self, types: &[BasicTypeEnum<'ctx>]) -> BasicTypeEnum<'ctx> {
+        match types {
+            [ty] => *ty,
+            _ => self.llvm_context.struct_type(types, false).into(),
+        }
+    }
+
+    fn add_type_attribute(
+        &self,
+        function: FunctionValue<'ctx>,
+        index: u32,
+        name: &str,
+        ty: BasicTypeEnum<'ctx>,
+    ) {
+        function.add_attribute(
+            AttributeLoc::Param(index),
+            self.llvm_context.create_type_attribute(
+                Attribute::get_named_enum_kind_id(name),
+                ty.as_any_type_enum(),
+            ),
+        );
+    }
+
+    // bool と u8 は、Cと同じく呼び出し側で拡張されるものとして扱う
+    fn add_zeroext_attribute(
+        &self,
+        function: FunctionValue<'ctx>,
+        loc: AttributeLoc,
+        ty: &ConcreteType,
+    ) {
+        if matches!(ty, ConcreteType::Bool | ConcreteType::U8) {
+            function.add_attribute(
+                loc,
+                self.llvm_context
+                    .create_enum_attribute(Attribute::get_named_enum_kind_id("zeroext"), 0),
+            );
+        }
+    }
+
+    pub(super) fn declare_extern_function(&self, function: &Function) -> FunctionValue<'ctx> {
+        self.declare_c_function(&function.decl.name, &function.decl)
+    }
+
+    // `decl` の引数と戻り値を、Cの呼び出し規約で受け渡す関数として宣言する
+    fn declare_c_function(&self, name: &str, decl: &FunctionDecl) -> FunctionValue<'ctx> {
+        let ptr_ty = self.llvm_context.ptr_type(AddressSpace::default());
+        let return_passing = self.c_abi_passing(&decl.return_type);
+        let mut has_var_args = false;
+        let mut param_types: Vec<BasicMetadataTypeEnum> = Vec::new();
+        // 引数の位置ごとに付ける属性
+        let mut byval_params = Vec::new();
+        let mut zeroext_params = Vec::new();
+        if let CAbiPassing::Indirect { .. } = return_passing {
+            param_types.push(ptr_ty.into());
+        }
+        for arg in &decl.args {
+            match arg {
+                Argument::VarArgs => {
+                    has_var_args = true;
+                }
+                Argument::Normal(ty, _) => match self.c_abi_passing(ty) {
+                    CAbiPassing::Direct => {
+                        zeroext_params.push((param_types.len() as u32, ty));
+                        if let Some(ty) = self.type_to_basic_metadata_type_enum(ty) {
+                            param_types.push(ty);
+                        }
+                    }
+                    CAbiPassing::Coerce(types) => {
+                        param_types.extend(types.into_iter().map(BasicMetadataTypeEnum::from));
+                    }
+                    CAbiPassing::Indirect { byval } => {
+                        if byval {
+                            byval_params.push((
+                                param_types.len() as u32,
+                                self.type_to_basic_type_enum(ty).unwrap(),
+                            ));
+                        }
+                        param_types.push(ptr_ty.into());
+                    }
+                },
+            }
+        }
+
+        let fn_type = match (
+            &return_passing,
+            self.type_to_basic_type_enum(&decl.return_type),
+        ) {
+            (CAbiPassing::Coerce(types), _) => {
+                self.coerced_type(types).fn_type(&param_types, has_var_args)
+            }
+            (CAbiPassing::Direct, Some(return_ty)) => return_ty.fn_type(&param_types, has_var_args),
+            _ => self
+                .llvm_context
+                .void_type()
+                .fn_type(&param_types, has_var_args),
+        };
+        let function_value = self.llvm_module.add_function(name, fn_type, None);
+
+        if let CAbiPassing::Indirect { .. } = return_passing {
+            let return_ty = self.type_to_basic_type_enum(&decl.return_type).unwrap();
+            self.add_type_attribute(function_value, 0, "sret", return_ty);
+        }
+        for (index, ty) in byval_params {
+            self.add_type_attribute(function_value, index, "byval", ty);
+        }
+        for (index, ty) in zeroext_params {
+            self.add_zeroext_attribute(function_value, AttributeLoc::Param(index), ty);
+        }
+        self.add_zeroext_attribute(function_value, AttributeLoc::Return, &decl.return_type);
+        function_value
+    }
+
+    // 構造体の値 (を指すポインタ) を、Cの呼び出し規約に合わせた引数に変換する
+    fn lower_struct_arg<'a>(
+        &'a self,
+        value: PointerValue<'a>,
+        ty: &ConcreteType,
+        passing: CAbiPassing<'ctx>,
+        args: &mut Vec<BasicMetadataValueEnum<'a>>,
+    ) -> Result<(), BuilderError> {
+        let struct_ty = self.type_to_basic_type_enum(ty).unwrap();
+        let (_, align) = c_layout(ty, self.target.metrics().word_size as u64);
+        match passing {
+            CAbiPassing::Direct => unreachable!(),
+            // 読み直す型の大きさは構造体以上なので、一度その型の領域にコピーしてから読む
+            CAbiPassing::Coerce(types) => {
+                let coerced_ty = self.coerced_type(&types);
+                let coerced_ptr = self.build_entry_alloca(coerced_ty, "coerce")?;
+                self.llvm_builder.build_memcpy(
+                    coerced_ptr,
+                    align as u32,
+                    value,
+                    align as u32,
+                    struct_ty.size_of().unwrap(),
+                )?;
+                if let [ty] = types.as_slice() {
+                    args.push(self.llvm_builder.build_load(*ty, coerced_ptr, "")?.into());
+                } else {
+                    for (i, ty) in types.iter().enumerate() {
+                        let field_ptr = self.llvm_builder.build_struct_gep(
+                            coerced_ty,
+                            coerced_ptr,
+                            i as u32,
+                            "",
+                        )?;
+                        args.push(self.llvm_builder.build_load(*ty, field_ptr, "")?.into());
+                    }
+                }
+            }
+            // 呼び出し先が書き換えても元の値が変わらないように、コピーを渡す
+            CAbiPassing::Indirect { .. } => {
+                let copy_ptr = self.build_entry_alloca(struct_ty, "arg")?;
+                self.llvm_builder.build_memcpy(
+                    copy_ptr,
+                    align as u32,
+                    value,
+                    align as u32,
+                    struct_ty.size_of().unwrap(),
+                )?;
+                args.push(copy_ptr.into());
+            }
+        }
+        Ok(())
+    }
+
+    // `#[export]` した関数の入口。Cの呼び出し規約で受け取った引数を組み立て直して本体を呼ぶ
+    pub(super) fn gen_export_wrapper(
+        &self,
+        function: &Function,
+        export_name: &str,
+    ) -> Result<(), BuilderError> {
+        let wrapper = self.declare_c_function(export_name, &function.decl);
+        let callee = self.gen_or_get_function(function);
+        let entry_block = self.llvm_context.append_basic_block(wrapper, "entry");
+        self.llvm_builder.position_at_end(entry_block);
+
+        let return_ty = &function.decl.return_type;
+        let return_passing = self.c_abi_passing(return_ty);
+        let mut params = wrapper.get_param_iter();
+        let mut args: Vec<BasicMetadataValueEnum> = Vec::new();
+        // 本体が構造体を返すときは、sret で受け取った領域にそのまま書き込ませる
+        let result_ptr = if return_ty.is_struct_type() {
+            let ptr = match return_passing {
+                CAbiPassing::Indirect { .. } => params.next().unwrap().into_pointer_value(),
+                _ => self
+                    .llvm_builder
+                    .build_alloca(self.type_to_basic_type_enum(return_ty).unwrap(), "result")?,
+            };
+            args.push(ptr.into());
+            Some(ptr)
+        } else {
+            None
+        };
+        for arg in &function.decl.args {
+            let Argument::Normal(ty, name) = arg else {
+                continue;
+            };
+            match self.c_abi_passing(ty) {
+                CAbiPassing::Direct => args.push(params.next().unwrap().into()),
+                // レジスタで受け取った値を一度書き出し、構造体として読み直す
+                CAbiPassing::Coerce(types) => {
+                    let struct_ty = self.type_to_basic_type_enum(ty).unwrap();
+                    let coerced_ty = self.coerced_type(&types);
+                    let coerced_ptr = self.llvm_builder.build_alloca(coerced_ty, name)?;
+                    if types.len() == 1 {
+                        self.llvm_builder
+                            .build_store(coerced_ptr, params.next().unwrap())?;
+                    } else {
+                        for i in 0..types.len() {
+                            let field_ptr = self.llvm_builder.build_struct_gep(
+                                coerced_ty,
+                                coerced_ptr,
+                                i as u32,
+                                "",
+                            )?;
+                            self.llvm_builder
+                                .build_store(field_ptr, params.next().unwrap())?;
+                        }
+                    }
+                    args.push(
+                        self.llvm_builder
+                            .build_load(struct_ty, coerced_ptr, name)?
+                            .into(),
+                    );
+                }
+                CAbiPassing::Indirect { .. } => {
+                    let struct_ty = self.type_to_basic_type_enum(ty).unwrap();
+                    let ptr = params.next().unwrap().into_pointer_value();
+                    args.push(self.llvm_builder.build_load(struct_ty, ptr, name)?.into());
+                }
+            }
+        }
+
+        let value = self.llvm_builder.build_call(callee, &args, "")?;
+        match (return_passing, result_ptr) {
+            (CAbiPassing::Coerce(types), Some(result_ptr)) => {
+                let struct_ty = self.type_to_basic_type_enum(return_ty).unwrap();
+                let (_, align) = c_layout(return_ty, self.target.metrics().word_size as u64);
+                let coerced_ty = self.coerced_type(&types);
+                let coerced_ptr = self.llvm_builder.build_alloca(coerced_ty, "coerce")?;
+                self.llvm_builder.build_memcpy(
+                    coerced_ptr,
+                    align as u32,
+                    result_ptr,
+                    align as u32,
+                    struct_ty.size_of().unwrap(),
+                )?;
+                let result = self.llvm_builder.build_load(coerced_ty, coerced_ptr, "")?;
+                self.llvm_builder.build_return(Some(&result))?;
+            }
+            (CAbiPassing::Indirect { .. }, _) => {
+                self.llvm_builder.build_return(None)?;
+            }
+            _ => match value.try_as_basic_value().basic() {
+                Some(result) => {
+                    self.llvm_builder.build_return(Some(&result))?;
+                }
+                None => {
+                    self.llvm_builder.build_return(None)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    pub(super) fn eval_extern_call<'a>(
+        &'a self,
+        function: &Function,
+        call_expr: &CallExpr,
+    ) -> Result<Option<BasicValueEnum<'a>>, BuilderError> {
+        let func = self.gen_or_get_function(function);
+        let return_ty = &function.decl.return_type;
+        let return_passing = self.c_abi_passing(return_ty);
+        let mut args: Vec<BasicMetadataValueEnum> = Vec::new();
+        let sret_ptr = if let CAbiPassing::Indirect { .. } = return_passing {
+            let ptr =
+                self.build_entry_alloca(self.type_to_basic_type_enum(return_ty).unwrap(), "")?;
+            args.push(ptr.into());
+            Some(ptr)
+        } else {
+            None
+        };
+        // 可変長引数の位置に渡す値 (インデックスがこれ以降のもの)
+        let var_args_start = match function.decl.args.last() {
+            Some(Argument::VarArgs) => function.decl.args.len() - 1,
+            _ => usize::MAX,
+        };
+        for (i, arg) in call_expr.args.iter().enumerate() {
+            let value = self.gen_expression(arg)?.unwrap();
+            if arg.ty.is_struct_type() {
+                let passing = self.c_abi_passing(&arg.ty);
+                self.lower_struct_arg(value.into_pointer_value(), &arg.ty, passing, &mut args)?;
+            } else if i >= var_args_start {
+                args.push(self.promote_var_arg(value, &arg.ty)?.into());
+            } else {
+                args.push(value.into());
+            }
+        }
+
+        let value = self.llvm_builder.build_call(func, &args, "")?;
+        match return_passing {
+            CAbiPassing::Direct => Ok(value.try_as_basic_value().basic()),
+            CAbiPassing::Indirect { .. } => Ok(sret_ptr.map(|ptr| ptr.as_basic_value_enum())),
+            // レジスタで返された値を一度書き出してから、構造体の領域にコピーする
+            CAbiPassing::Coerce(types) => {
+                let struct_ty = self.type_to_basic_type_enum(return_ty).unwrap();
+                let (_, align) = c_layout(return_ty, self.target.metrics().word_size as u64);
+                let coerced_ptr = self.build_entry_alloca(self.coerced_type(&types), "coerce")?;
+                self.llvm_builder
+                    .build_store(coerced_ptr, value.try_as_basic_value().basic().unwrap())?;
+                let result_ptr = self.build_entry_alloca(struct_ty, "")?;
+                self.llvm_builder.build_memcpy(
+                    result_ptr,
+                    align as u32,
+                    coerced_ptr,
+                    align as u32,
+                    struct_ty.size_of().unwrap(),
+                )?;
+                Ok(Some(result_ptr.as_basic_value_enum()))
+            }
+        }
+    }
+}
+
+#[test]
+fn test_c_layout() {
+    let struct_type = |fields: &[ConcreteType]| {
+        ConcreteType::StructLike(ConcreteStructType {
+            name: "S".into(),
+            non_generic_name: "S".into(),
+            fields: fields
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| (i.to_string(), ty.clone()))
+                .collect(),
+        })
+    };
+    // { u8, i32, u8 } はCと同じく詰め物が入って12バイトになる
+    let ty = struct_type(&[ConcreteType::U8, ConcreteType::I32, ConcreteType::U8]);
+    assert_eq!(c_layout(&ty, 8), (12, 4));
+    let ty = struct_type(&[
+        ConcreteType::I32,
+        ConcreteType::Ptr(Box::new(ConcreteType::U8)),
+    ]);
+    assert_eq!(c_layout(&ty, 8), (16, 8));
+    assert_eq!(c_layout(&ty, 4), (8, 4));
+
+    let mut fields = Vec::new();
+    scalar_fields(&ty, 0, 8, &mut fields);
+    assert_eq!(fields[1].0, 8);
+}
@@ -1,4 +1,6 @@
+mod debug_info;
 mod expression;
+mod ffi;
 mod statement;
 mod toplevel;
 mod ty;
@@ -6,11 +8,15 @@ mod ty;
 use inkwell::OptimizationLevel;
 
 use crate::common::target::TargetPlatform;
+use crate::common::Linkage;
 use crate::concrete_ast::*;
+use inkwell::basic_block::BasicBlock;
 use inkwell::builder::Builder as LLVMBuilder;
 use inkwell::context::Context as LLVMContext;
 use inkwell::module::Module as LLVMModule;
+use inkwell::builder::BuilderError;
 use inkwell::targets::{InitializationConfig, Target};
+use inkwell::types::BasicTypeEnum;
 use inkwell::values::PointerValue;
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -28,18 +34,36 @@ impl Scope<'_> {
     }
 }
 
+// break / continue の飛び先になるループ
+#[derive(Debug, Clone, Copy)]
+pub struct LoopTarget<'a> {
+    pub id: usize,
+    // continue の飛び先。ステップの式があればそれを実行してから、条件の評価からやり直す
+    pub next: BasicBlock<'a>,
+    // break の飛び先
+    pub exit: BasicBlock<'a>,
+    // `(break value)` の値を書き込む領域。値を返さないループではNone
+    pub value_ptr: Option<PointerValue<'a>>,
+}
+
 pub struct LLVMCodeGenerator<'a> {
     llvm_module: LLVMModule<'a>,
     llvm_builder: LLVMBuilder<'a>,
     llvm_context: &'a LLVMContext,
-    scopes: Vec<RefCell<Scope<'a>>>,
+    // 内側のスコープが末尾
+    scopes: RefCell<Vec<Scope<'a>>>,
+    loops: RefCell<Vec<LoopTarget<'a>>>,
     function_by_name: HashMap<String, &'a Function>,
+    // extern 関数に構造体を渡すときの呼び出し規約を決める
+    target: TargetPlatform,
+    // `-g` のときだけ Some
+    debug_info: Option<debug_info::DebugInfo<'a>>,
 }
 
 impl<'a> LLVMCodeGenerator<'a> {
     pub fn new(
         llvm_context: &'a LLVMContext,
-        _target: TargetPlatform,
+        target: TargetPlatform,
         _optimization_level: OptimizationLevel,
         module: &'a ConcreteModule,
     ) -> Self {
@@ -83,12 +107,15 @@ impl<'a> LLVMCodeGenerator<'a> {
             llvm_module,
             llvm_builder,
             llvm_context,
-            scopes: Vec::new(),
+            scopes: RefCell::new(Vec::new()),
+            loops: RefCell::new(Vec::new()),
             function_by_name,
+            target,
+            debug_info: None,
         }
     }
     pub fn gen_module(&mut self, module: &'a ConcreteModule) {
-        self.scopes.push(RefCell::new(Scope::new()));
+        self.push_scope(Scope::new());
 
         // self.gen_intrinsic_functions_on_llvm();
         for top in &module.toplevels {
@@ -101,33 +128,120 @@ impl<'a> LLVMCodeGenerator<'a> {
             }
         }
 
-        self.scopes.pop();
+        for top in &module.toplevels {
+            match top {
+                // Cの呼び出し規約の入口は、本体と一緒にライブラリが持っている
+                TopLevel::Function(func) if func.decl.linkage == Linkage::Imported => {}
+                TopLevel::Function(func) => {
+                    if let Some(export_name) = &func.decl.export_name {
+                        self.gen_export_wrapper(func, export_name).unwrap();
+                    }
+                }
+            }
+        }
+
+        self.finalize_debug_info();
+        self.pop_scope();
     }
     pub fn get_module(self) -> LLVMModule<'a> {
         self.llvm_module
     }
     fn add_variable(&self, name: &str, value: PointerValue<'a>) {
         self.scopes
-            .last()
-            .unwrap()
             .borrow_mut()
+            .last_mut()
+            .unwrap()
             .values
             .insert(name.into(), value);
     }
+    // 内側のスコープから順に探す。
+    // 他の変数を隠す変数にはresolverが一意な名前を付けているので、名前から宣言が1つに決まる
     fn get_variable(&self, name: &str) -> PointerValue<'a> {
         *self
             .scopes
-            .last()
+            .borrow()
+            .iter()
+            .rev()
+            .find_map(|scope| scope.values.get(name))
+            .unwrap()
+    }
+    // allocaは関数の入口のブロックに置く。ループの中で宣言した変数でもスタックが伸び続けない
+    fn build_entry_alloca(
+        &self,
+        ty: BasicTypeEnum<'a>,
+        name: &str,
+    ) -> Result<PointerValue<'a>, BuilderError> {
+        let entry_block = self
+            .llvm_builder
+            .get_insert_block()
+            .unwrap()
+            .get_parent()
             .unwrap()
+            .get_first_basic_block()
+            .unwrap();
+        let builder = self.llvm_context.create_builder();
+        match entry_block.get_first_instruction() {
+            Some(instruction) => builder.position_before(&instruction),
+            None => builder.position_at_end(entry_block),
+        }
+        builder.build_alloca(ty, name)
+    }
+    fn push_loop(&self, target: LoopTarget<'a>) {
+        self.loops.borrow_mut().push(target);
+    }
+    fn pop_loop(&self) {
+        self.loops.borrow_mut().pop();
+    }
+    fn get_loop(&self, id: usize) -> LoopTarget<'a> {
+        *self
+            .loops
             .borrow()
-            .values
-            .get(name)
+            .iter()
+            .rev()
+            .find(|target| target.id == id)
             .unwrap()
     }
-    fn pop_scope(&mut self) {
-        self.scopes.pop();
+    fn pop_scope(&self) {
+        self.scopes.borrow_mut().pop();
+    }
+    fn push_scope(&self, scope: Scope<'a>) {
+        self.scopes.borrow_mut().push(scope);
     }
-    fn push_scope(&mut self, scope: Scope<'a>) {
-        self.scopes.push(RefCell::new(scope));
+}
+
+// テスト用に、ソースからLLVM IRを生成して文字列にする
+#[cfg(test)]
+fn generate_ir(source: &str, debug: bool) -> String {
+    use crate::common::target::PointerSizedIntWidth;
+    use crate::resolver::{resolve_module, ResolverContext};
+    use nom::Finish;
+
+    let (_, module) = crate::parser::parse_module(source.into()).finish().unwrap();
+    let resolver_context = ResolverContext::new(PointerSizedIntWidth::SixtyFour);
+    let resolved_module = resolve_module(&resolver_context, &module, true).unwrap();
+    assert!(
+        resolver_context.errors.borrow().is_empty(),
+        "{:?}",
+        resolver_context.errors
+    );
+    let concrete_module = crate::concretizer::concretize_module(
+        resolved_module,
+        PointerSizedIntWidth::SixtyFour,
+        None,
+    );
+
+    let llvm_context = LLVMContext::create();
+    let mut codegen = LLVMCodeGenerator::new(
+        &llvm_context,
+        TargetPlatform::current().unwrap_or(TargetPlatform::DarwinArm64),
+        OptimizationLevel::None,
+        &concrete_module,
+    );
+    if debug {
+        codegen.enable_debug_info(std::path::Path::new("test.hr"));
     }
+    codegen.gen_module(&concrete_module);
+    let llvm_module = codegen.get_module();
+    llvm_module.verify().unwrap();
+    llvm_module.print_to_string().to_string()
 }
@@ -11,6 +11,27 @@ impl LLVMCodeGenerator<'_> {
     pub(super) fn gen_return(&self, ret: &Return) -> Result<InstructionValue, BuilderError> {
         if let Some(expression) = &ret.expression {
             let value = self.gen_expression(expression)?.unwrap();
+            // 構造体を返す関数では、呼び出し元が用意した領域 (最初の引数) にコピーしてから return void する
+            if expression.ty.is_struct_type() {
+                let sret_ptr = self
+                    .llvm_builder
+                    .get_insert_block()
+                    .unwrap()
+                    .get_parent()
+                    .unwrap()
+                    .get_first_param()
+                    .unwrap()
+                    .into_pointer_value();
+                let struct_ty = self.type_to_basic_type_enum(&expression.ty).unwrap();
+                self.llvm_builder.build_memcpy(
+                    sret_ptr,
+                    8,
+                    value.into_pointer_value(),
+                    8,
+                    struct_ty.size_of().unwrap(),
+                )?;
+                return self.llvm_builder.build_return(None);
+            }
             let ptr = self.llvm_builder.build_alloca(value.get_type(), "")?;
             if value.is_struct_value() {
                 self.llvm_builder.build_call(
@@ -1,11 +1,13 @@
 use inkwell::{
     builder::BuilderError,
+    module::Linkage as LLVMLinkage,
     types::{AnyType, BasicMetadataTypeEnum, BasicType},
     values::FunctionValue,
     AddressSpace,
 };
 
 use super::*;
+use crate::common::Linkage;
 use crate::concrete_ast::*;
 
 impl<'a> LLVMCodeGenerator<'a> {
@@ -13,11 +15,11 @@ impl<'a> LLVMCodeGenerator<'a> {
         if let Some(ret) = self.llvm_module.get_function(&function.decl.name) {
             return ret;
         }
+        if function.decl.is_extern {
+            return self.declare_extern_function(function);
+        }
 
-        let returns_struct = match function.decl.return_type {
-            ConcreteType::StructLike(_) => true,
-            _ => false,
-        };
+        let returns_struct = function.decl.return_type.is_struct_type();
 
         let mut has_var_args = false;
         let mut param_types: Vec<BasicMetadataTypeEnum> = Vec::new();
@@ -26,6 +28,11 @@ impl<'a> LLVMCodeGenerator<'a> {
             let param = self.llvm_context.ptr_type(AddressSpace::default());
             param_types.push(param.into());
         }
+        // `fn` 式から変換した関数は、環境へのポインタを受け取る
+        if function.decl.captures.is_some() {
+            let param = self.llvm_context.ptr_type(AddressSpace::default());
+            param_types.push(param.into());
+        }
 
         for arg in &function.decl.args {
             match arg {
@@ -40,6 +47,12 @@ impl<'a> LLVMCodeGenerator<'a> {
             }
         }
 
+        // ライブラリと重複し得るインスタンスはリンク時に一つにまとめ、`fn` 式から変換した関数は外に見せない
+        let linkage = match function.decl.linkage {
+            Linkage::Instance => Some(LLVMLinkage::WeakODR),
+            Linkage::Internal => Some(LLVMLinkage::Private),
+            Linkage::Unique | Linkage::Imported => None,
+        };
         let return_ty = self.type_to_basic_type_enum(&function.decl.return_type);
         let function = self.llvm_module.add_function(
             &function.decl.name,
@@ -56,7 +69,7 @@ impl<'a> LLVMCodeGenerator<'a> {
                     .void_type()
                     .fn_type(&param_types, has_var_args)
             },
-            None,
+            linkage,
         );
 
         if returns_struct {
@@ -82,13 +95,11 @@ impl<'a> LLVMCodeGenerator<'a> {
     }
 
     pub(super) fn gen_function_body(&mut self, function: &'a Function) -> Result<(), BuilderError> {
-        if function.body.is_empty() {
+        // ライブラリから読み込んだ関数は宣言だけする
+        if function.body.is_empty() || function.decl.linkage == Linkage::Imported {
             return Ok(());
         }
-        let returns_struct = match function.decl.return_type {
-            ConcreteType::StructLike(_) => true,
-            _ => false,
-        };
+        let returns_struct = function.decl.return_type.is_struct_type();
         let function_value = self.llvm_module.get_function(&function.decl.name).unwrap();
         let entry_basic_block = self
             .llvm_context
@@ -98,6 +109,7 @@ impl<'a> LLVMCodeGenerator<'a> {
         self.push_scope(scope);
         {
             self.llvm_builder.position_at_end(entry_basic_block);
+            self.begin_function_debug_info(function, function_value);
 
             // Set parameters in function body
             // Generate function body
@@ -106,6 +118,28 @@ impl<'a> LLVMCodeGenerator<'a> {
                 let parameter = function_value.get_first_param().unwrap();
                 parameter.set_name("sret_ptr");
             }
+            let mut first_arg = if returns_struct { 1 } else { 0 };
+            if let Some(captures) = &function.decl.captures {
+                let env_ptr = function_value
+                    .get_nth_param(first_arg)
+                    .unwrap()
+                    .into_pointer_value();
+                env_ptr.set_name("env");
+                first_arg += 1;
+                // 取り込んだ変数は環境の中のフィールドをそのまま指す。
+                // 取り込んだ変数への代入はresolverがエラーにしている
+                if !captures.is_empty() {
+                    let env_ty = self
+                        .type_to_basic_type_enum(&closure_env_type(&function.decl.name, captures))
+                        .unwrap();
+                    for (i, (name, _)) in captures.iter().enumerate() {
+                        let field_ptr = self
+                            .llvm_builder
+                            .build_struct_gep(env_ty, env_ptr, i as u32, name)?;
+                        self.add_variable(name, field_ptr);
+                    }
+                }
+            }
 
             for (i, (_ty, name)) in function
                 .decl
@@ -117,8 +151,7 @@ impl<'a> LLVMCodeGenerator<'a> {
                 })
                 .enumerate()
             {
-                let i = if returns_struct { i + 1 } else { i };
-                let parameter = function_value.get_nth_param(i as u32).unwrap();
+                let parameter = function_value.get_nth_param(first_arg + i as u32).unwrap();
                 parameter.set_name(name.as_str());
                 let allocated_pointer = self
                     .llvm_builder
@@ -131,43 +164,12 @@ impl<'a> LLVMCodeGenerator<'a> {
             }
 
             // Generate function body
-            for (i, expr) in function.body.iter().enumerate() {
-                if i == function.body.len() - 1 {
-                    // 構造体を返す場合、最後のreturn文はreturn voidにする
-                    if returns_struct {
-                        match &expr.kind {
-                            ExpressionKind::Return(return_val) => {
-                                let value: inkwell::values::BasicValueEnum = self
-                                    .gen_expression(return_val.expression.as_ref().unwrap())?
-                                    .unwrap();
-                                let first_param_ptr = function_value
-                                    .get_first_param()
-                                    .unwrap()
-                                    .into_pointer_value();
-                                let struct_ptr = value.into_pointer_value();
-                                let struct_ty = self
-                                    .type_to_basic_type_enum(
-                                        &return_val.expression.as_ref().unwrap().ty,
-                                    )
-                                    .unwrap()
-                                    .into_struct_type();
-                                self.llvm_builder.build_memcpy(
-                                    first_param_ptr,
-                                    8,
-                                    struct_ptr,
-                                    8,
-                                    struct_ty.size_of().unwrap(),
-                                )?;
-                                self.llvm_builder.build_return(None)?;
-                                continue;
-                            }
-                            _ => {}
-                        }
-                    }
-                }
+            // 構造体を返すreturnは gen_return が return void にする
+            for expr in &function.body {
                 self.gen_expression(expr)?;
             }
         }
+        self.end_function_debug_info();
         self.pop_scope();
         Ok(())
     }
@@ -178,3 +180,13 @@ impl<'a> LLVMCodeGenerator<'a> {
         };
     }
 }
+
+// `fn` 式が取り込んだ変数を並べた環境の型
+pub(super) fn closure_env_type(fn_name: &str, captures: &[(String, ConcreteType)]) -> ConcreteType {
+    let name = format!("{}.env", fn_name);
+    ConcreteType::StructLike(ConcreteStructType {
+        name: name.clone(),
+        non_generic_name: name,
+        fields: captures.to_vec(),
+    })
+}
@@ -1,5 +1,5 @@
 use inkwell::{
-    types::{BasicMetadataTypeEnum, BasicTypeEnum},
+    types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum},
     AddressSpace,
 };
 
@@ -50,6 +50,10 @@ impl<'a> LLVMCodeGenerator<'a> {
                 struct_type.set_body(&field_types, false);
                 struct_type.into()
             }
+            ConcreteType::Array(element, len) => self
+                .type_to_basic_type_enum(element)?
+                .array_type(*len as u32)
+                .into(),
         })
     }
 }
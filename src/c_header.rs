@@ -0,0 +1,224 @@
+use std::collections::HashSet;
+
+use crate::resolved_ast::{Argument, ResolvedModule, ResolvedStructType, ResolvedType, TopLevel};
+
+// `Vec<i32>` のような名前を、Cの識別子として使える `Vec_i32` にする
+fn c_identifier(name: &str) -> String {
+    let mut identifier = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            identifier.push(c);
+        } else if !identifier.ends_with('_') {
+            identifier.push('_');
+        }
+    }
+    identifier.trim_end_matches('_').to_string()
+}
+
+// Cの型として書けるか。ポインタはresolverが中身を問わず通すので、書けない型を指すものは `void *` にする
+fn is_c_representable(ty: &ResolvedType) -> bool {
+    match ty {
+        ResolvedType::StructLike(struct_ty) => struct_ty
+            .fields
+            .iter()
+            .all(|(_, ty)| is_c_representable(ty)),
+        ResolvedType::Dyn(_)
+        | ResolvedType::Function(_)
+        | ResolvedType::Tuple(_)
+        | ResolvedType::Array(_, _)
+        | ResolvedType::Generics(_)
+        | ResolvedType::Var(_)
+        | ResolvedType::Unknown => false,
+        _ => true,
+    }
+}
+
+// `#[export]` の関数の引数と戻り値はresolverの `check_ffi_safe` を通っているので、Cで表せる型だけが来る
+fn c_type(ty: &ResolvedType) -> String {
+    match ty {
+        ResolvedType::I32 => "int32_t".into(),
+        ResolvedType::I64 => "int64_t".into(),
+        ResolvedType::U32 => "uint32_t".into(),
+        ResolvedType::U64 => "uint64_t".into(),
+        ResolvedType::USize => "size_t".into(),
+        ResolvedType::U8 => "uint8_t".into(),
+        ResolvedType::F32 => "float".into(),
+        ResolvedType::F64 => "double".into(),
+        ResolvedType::Bool => "bool".into(),
+        ResolvedType::Ptr(inner) if !is_c_representable(inner) => "void *".into(),
+        ResolvedType::Ptr(inner) => {
+            let inner = c_type(inner);
+            if inner.ends_with('*') {
+                format!("{}*", inner)
+            } else {
+                format!("{} *", inner)
+            }
+        }
+        ResolvedType::Void => "void".into(),
+        ResolvedType::StructLike(struct_ty) => c_identifier(&struct_ty.name),
+        ResolvedType::Dyn(_)
+        | ResolvedType::Function(_)
+        | ResolvedType::Tuple(_)
+        | ResolvedType::Array(_, _)
+        | ResolvedType::Generics(_)
+        | ResolvedType::Var(_)
+        | ResolvedType::Unknown => unreachable!(
+            "check_ffi_safe should have rejected `{:?}` in an exported signature",
+            ty
+        ),
+    }
+}
+
+// `int32_t x` や `uint8_t *s` のような宣言
+fn c_declaration(ty: &ResolvedType, name: &str) -> String {
+    let ty = c_type(ty);
+    if ty.ends_with('*') {
+        format!("{}{}", ty, c_identifier(name))
+    } else {
+        format!("{} {}", ty, c_identifier(name))
+    }
+}
+
+// 値として含む構造体が先に定義されるように並べる。ポインタの先の構造体は前方宣言で足りる
+fn collect_structs(
+    ty: &ResolvedType,
+    seen: &mut HashSet<String>,
+    structs: &mut Vec<ResolvedStructType>,
+) {
+    match ty {
+        ResolvedType::Ptr(inner) if is_c_representable(inner) => {
+            collect_structs(inner, seen, structs)
+        }
+        ResolvedType::StructLike(struct_ty) => {
+            if !seen.insert(struct_ty.name.clone()) {
+                return;
+            }
+            for (_, field_ty) in &struct_ty.fields {
+                collect_structs(field_ty, seen, structs);
+            }
+            structs.push(struct_ty.clone());
+        }
+        _ => {}
+    }
+}
+
+// `#[export]` した関数のプロトタイプと、それらが使う構造体の定義を並べたCのヘッダー
+pub fn generate_c_header(module: &ResolvedModule, guard: &str) -> String {
+    let mut functions = module
+        .toplevels
+        .iter()
+        .filter_map(|toplevel| match toplevel {
+            TopLevel::Function(function) => Some((function.decl.export_name.clone()?, function)),
+        })
+        .collect::<Vec<_>>();
+    functions.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut seen = HashSet::new();
+    let mut structs = Vec::new();
+    for (_, function) in &functions {
+        for arg in &function.decl.args {
+            if let Argument::Normal(ty, _) = arg {
+                collect_structs(ty, &mut seen, &mut structs);
+            }
+        }
+        collect_structs(&function.decl.return_type, &mut seen, &mut structs);
+    }
+
+    let guard = c_identifier(guard).to_uppercase();
+    let mut header = String::new();
+    header.push_str("// Generated by the Hirou compiler. Do not edit.\n");
+    header.push_str(&format!("#ifndef {}\n#define {}\n\n", guard, guard));
+    header.push_str("#include <stdbool.h>\n#include <stddef.h>\n#include <stdint.h>\n\n");
+    for struct_ty in &structs {
+        let name = c_identifier(&struct_ty.name);
+        header.push_str(&format!("typedef struct {} {};\n", name, name));
+    }
+    for struct_ty in &structs {
+        header.push_str(&format!("\nstruct {} {{\n", c_identifier(&struct_ty.name)));
+        for (field_name, field_ty) in &struct_ty.fields {
+            header.push_str(&format!("    {};\n", c_declaration(field_ty, field_name)));
+        }
+        header.push_str("};\n");
+    }
+    header.push('\n');
+    for (export_name, function) in &functions {
+        let args = function
+            .decl
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                Argument::Normal(ty, name) => Some(c_declaration(ty, name)),
+                Argument::VarArgs => None,
+            })
+            .collect::<Vec<_>>();
+        let args = if args.is_empty() {
+            "void".to_string()
+        } else {
+            args.join(", ")
+        };
+        header.push_str(&format!(
+            "{}({});\n",
+            c_declaration(&function.decl.return_type, export_name),
+            args
+        ));
+    }
+    header.push_str(&format!("\n#endif // {}\n", guard));
+    header
+}
+
+#[test]
+fn test_generate_c_header() {
+    use crate::common::target::PointerSizedIntWidth;
+    use crate::parser::parse_module;
+    use crate::resolver::{resolve_module, ResolverContext};
+    use nom::Finish;
+
+    let source = r#"
+struct Point {
+  x: f64,
+  y: f64,
+}
+
+struct Line {
+  start: Point,
+  end: *Point,
+}
+
+#[export("hirou_midpoint")]
+fn midpoint(a: Point, b: Point): Point {
+  Point { x: (/ (+ a.x b.x) 2.0), y: (/ (+ a.y b.y) 2.0) }
+}
+
+#[export]
+fn line_start(line: *Line, name: *u8): Point {
+  Point { x: 0.0, y: 0.0 }
+}
+
+#[export]
+fn pair_sum(pair: *(i32, i32)): i32 {
+  0
+}
+
+fn main(): i32 {
+  0
+}
+"#;
+    let (_, module) = parse_module(source.into()).finish().unwrap();
+    let context = ResolverContext::new(PointerSizedIntWidth::SixtyFour);
+    let resolved = resolve_module(&context, &module, true).unwrap();
+    assert!(context.errors.borrow().is_empty());
+
+    let header = generate_c_header(&resolved, "points.h");
+    assert!(header.contains("#ifndef POINTS_H"));
+    assert!(header.contains("typedef struct Point Point;"));
+    // Line は値として Point を持つので、Point の定義が先に来る
+    let point = header.find("struct Point {").unwrap();
+    let line = header.find("struct Line {").unwrap();
+    assert!(point < line);
+    assert!(header.contains("    Point *end;"));
+    assert!(header.contains("Point line_start(Line *line, uint8_t *name);"));
+    assert!(header.contains("Point hirou_midpoint(Point a, Point b);"));
+    // Cで書けない型へのポインタは中身を見せない
+    assert!(header.contains("int32_t pair_sum(void *pair);"));
+    assert!(!header.contains("main"));
+}
@@ -13,6 +13,8 @@ pub(crate) fn get_cast_type(
             ConcreteType::U32 => (None, Some(ConcreteType::I32)),
             ConcreteType::U64 => (Some(ConcreteType::I64), Some(ConcreteType::I64)),
             ConcreteType::U8 => (None, Some(ConcreteType::I32)),
+            // 整数と浮動小数点数の演算では、整数の側を浮動小数点数に変換する
+            ConcreteType::F32 | ConcreteType::F64 => (Some(rhs.clone()), None),
             _ => panic!("Invalid type for binary expression"),
         },
         ConcreteType::I64 => match rhs {
@@ -21,6 +23,7 @@ pub(crate) fn get_cast_type(
             ConcreteType::U32 => (None, Some(ConcreteType::I64)),
             ConcreteType::U64 => (None, Some(ConcreteType::I64)),
             ConcreteType::U8 => (None, Some(ConcreteType::I64)),
+            ConcreteType::F32 | ConcreteType::F64 => (Some(rhs.clone()), None),
             _ => panic!("Invalid type for binary expression"),
         },
         ConcreteType::U32 => match rhs {
@@ -29,6 +32,7 @@ pub(crate) fn get_cast_type(
             ConcreteType::U32 => (None, None),
             ConcreteType::U64 => (Some(ConcreteType::U64), None),
             ConcreteType::U8 => (Some(ConcreteType::U32), None),
+            ConcreteType::F32 | ConcreteType::F64 => (Some(rhs.clone()), None),
             _ => panic!("Invalid type for binary expression"),
         },
         ConcreteType::U64 => match rhs {
@@ -36,6 +40,8 @@ pub(crate) fn get_cast_type(
             ConcreteType::I64 => (Some(ConcreteType::I64), None),
             ConcreteType::U32 => (None, Some(ConcreteType::U64)),
             ConcreteType::U64 => (None, None),
+            ConcreteType::U8 => (None, Some(ConcreteType::U64)),
+            ConcreteType::F32 | ConcreteType::F64 => (Some(rhs.clone()), None),
             _ => panic!("Invalid type for binary expression"),
         },
         ConcreteType::U8 => match rhs {
@@ -44,17 +50,20 @@ pub(crate) fn get_cast_type(
             ConcreteType::U32 => (Some(ConcreteType::U32), None),
             ConcreteType::U64 => (Some(ConcreteType::U64), None),
             ConcreteType::U8 => (None, None),
+            ConcreteType::F32 | ConcreteType::F64 => (Some(rhs.clone()), None),
             _ => panic!("Invalid type for binary expression"),
         },
         ConcreteType::F32 => match rhs {
             ConcreteType::F32 => (None, None),
             ConcreteType::F64 => (Some(ConcreteType::F64), None),
-            _ => panic!("Invalid type for binary expression: cannot mix float with non-float"),
+            rhs if rhs.is_integer_type() => (None, Some(ConcreteType::F32)),
+            _ => panic!("Invalid type for binary expression"),
         },
         ConcreteType::F64 => match rhs {
             ConcreteType::F32 => (None, Some(ConcreteType::F64)),
             ConcreteType::F64 => (None, None),
-            _ => panic!("Invalid type for binary expression: cannot mix float with non-float"),
+            rhs if rhs.is_integer_type() => (None, Some(ConcreteType::F64)),
+            _ => panic!("Invalid type for binary expression"),
         },
         ConcreteType::Bool => match rhs {
             ConcreteType::Bool => (None, None),
@@ -62,6 +71,8 @@ pub(crate) fn get_cast_type(
         },
         ConcreteType::Ptr(_) => panic!("Invalid type for binary expression"),
         ConcreteType::Void => panic!("Invalid type for binary expression"),
-        ConcreteType::StructLike(_) => panic!("Invalid type for binary expression"),
+        ConcreteType::StructLike(_) | ConcreteType::Array(_, _) => {
+            panic!("Invalid type for binary expression")
+        }
     }
 }
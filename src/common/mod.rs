@@ -2,13 +2,29 @@ pub mod binary;
 pub mod target;
 pub mod typename;
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum StructKind {
     Struct,
     Record,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+// 関数の本体をどのオブジェクトファイルが持つか
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum Linkage {
+    // このモジュールだけが本体を持つ
+    Unique,
+    // ジェネリック関数や標準ライブラリの関数のインスタンス。ライブラリと利用する側の両方に
+    // 同じ本体ができることがあるので、リンクするときに一つにまとめる
+    Instance,
+    // ライブラリが本体を持ち、このモジュールでは宣言だけする
+    Imported,
+    // このモジュールの中からしか参照されない (`fn` 式から変換した関数)
+    Internal,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum AllocMode {
     Stack,
 }
@@ -42,6 +42,22 @@ pub enum TargetPlatform {
     WasiWasm32,
 }
 
+const ALL_TARGET_PLATFORMS: [TargetPlatform; 13] = [
+    TargetPlatform::Windows386,
+    TargetPlatform::WindowsAmd64,
+    TargetPlatform::Linux386,
+    TargetPlatform::LinuxAmd64,
+    TargetPlatform::LinuxArm64,
+    TargetPlatform::DarwinAmd64,
+    TargetPlatform::DarwinArm64,
+    TargetPlatform::FreeBSD386,
+    TargetPlatform::FreeBSDAmd64,
+    TargetPlatform::EssenceAmd64,
+    TargetPlatform::FreestandingWasm32,
+    TargetPlatform::JsWasm32,
+    TargetPlatform::WasiWasm32,
+];
+
 impl TargetPlatform {
     #[allow(unused)]
     pub fn is_windows(&self) -> bool {
@@ -199,12 +215,27 @@ impl TargetPlatform {
         }
     }
 
-    #[allow(unused)]
+    // `hirou.toml` のプロファイルでターゲットを指定するときの名前 (`linux-amd64` など)
+    pub fn name(&self) -> String {
+        let metrics = self.metrics();
+        format!("{}-{}", metrics.os.name(), metrics.arch.name())
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        ALL_TARGET_PLATFORMS
+            .into_iter()
+            .find(|target| target.name() == name)
+    }
+
+    // コンパイラを動かしているマシン。JITで実行するコードはこのターゲットに合わせる
     pub fn current() -> Result<Self, &'static str> {
-        match env::consts::OS {
-            "linux" => Ok(Self::LinuxAmd64),
-            "windows" => Ok(Self::WindowsAmd64),
-            os => Err(os),
+        match (env::consts::OS, env::consts::ARCH) {
+            ("linux", "x86_64") => Ok(Self::LinuxAmd64),
+            ("linux", "aarch64") => Ok(Self::LinuxArm64),
+            ("macos", "x86_64") => Ok(Self::DarwinAmd64),
+            ("macos", "aarch64") => Ok(Self::DarwinArm64),
+            ("windows", "x86_64") => Ok(Self::WindowsAmd64),
+            (os, _) => Err(os),
         }
     }
 }
@@ -1,19 +1,23 @@
 use std::fmt::{Display, Write};
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
-    ast::{BinaryOp, MultiOp, UnaryOp},
-    common::typename::*,
+    ast::{BinaryOp, MultiOp, SourceLocation, UnaryOp},
+    common::{typename::*, Linkage},
     resolved_ast::ResolvedType,
 };
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub use crate::resolved_ast::NumberLiteralValue;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct ConcreteStructType {
     pub name: String,
     pub non_generic_name: String,
     pub fields: Vec<(String, ConcreteType)>,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub enum ConcreteType {
     I32,
     I64,
@@ -26,6 +30,7 @@ pub enum ConcreteType {
     Ptr(Box<ConcreteType>),
     Void,
     StructLike(ConcreteStructType),
+    Array(Box<ConcreteType>, usize),
 }
 
 impl ConcreteType {
@@ -39,6 +44,7 @@ impl ConcreteType {
             ConcreteType::Ptr(_) => false,
             ConcreteType::Void => false,
             ConcreteType::StructLike(_) => false,
+            ConcreteType::Array(_, _) => false,
             ConcreteType::Bool => false,
             ConcreteType::F32 => false,
             ConcreteType::F64 => false,
@@ -54,12 +60,12 @@ impl ConcreteType {
             _ => false,
         }
     }
+    // 構造体と固定長配列は、値をポインタで受け渡して memcpy でコピーし、関数からは sret で返す
     pub fn is_struct_type(&self) -> bool {
-        if let ConcreteType::StructLike(_) = self {
-            true
-        } else {
-            false
-        }
+        matches!(
+            self,
+            ConcreteType::StructLike(_) | ConcreteType::Array(_, _)
+        )
     }
     pub fn is_pointer_type(&self) -> bool {
         if let ConcreteType::Ptr(_) = self {
@@ -123,112 +129,172 @@ impl Display for ConcreteType {
                     }) => {
                         name
                     }
+                    ConcreteType::Array(element, len) => {
+                        return write!(f, "[{}; {}]", element, len);
+                    }
                 }
             )
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CallExpr {
     pub callee: String,
     pub args: Vec<ConcreteExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VariableRefExpr {
     pub name: String,
 }
 
 // TODO: type毎に細かく分ける
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NumberLiteral {
-    pub value: String,
+    pub value: NumberLiteralValue,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StringLiteral {
     pub value: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BoolLiteral {
     pub value: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StructLiteral {
     pub fields: Vec<(String, ConcreteExpression)>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ArrayLiteral {
     pub elements: Vec<ConcreteExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BinaryExpr {
     pub op: BinaryOp,
     pub lhs: Box<ConcreteExpression>,
     pub rhs: Box<ConcreteExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UnaryExpr {
     pub op: UnaryOp,
     pub operand: Box<ConcreteExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MultiExpr {
     pub op: MultiOp,
     pub operands: Vec<ConcreteExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DerefExpr {
     pub target: Box<ConcreteExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AddressOfExpr {
     pub target: Box<ConcreteExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IndexAccessExpr {
     pub target: Box<ConcreteExpression>,
     pub index: Box<ConcreteExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FieldAccessExpr {
     pub target: Box<ConcreteExpression>,
     pub field_name: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IfExpr {
     pub cond: Box<ConcreteExpression>,
     pub then: Box<ConcreteExpression>,
     pub els: Box<ConcreteExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WhenExpr {
     pub cond: Box<ConcreteExpression>,
     pub then: Box<ConcreteExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WhileExpr {
+    // break / continue の飛び先を特定するためのループの識別子
+    pub loop_id: usize,
     pub cond: Box<ConcreteExpression>,
     pub body: Box<ConcreteExpression>,
+    // 各回の最後に実行する式。continue してもこの式は実行される
+    pub step: Option<Box<ConcreteExpression>>,
+}
+
+// 順に評価し、最後の式の値を返す。ブロックの中で宣言した変数はブロックの外からは見えない
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockExpr {
+    pub expressions: Vec<ConcreteExpression>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakExpr {
+    pub loop_id: usize,
+    pub value: Option<Box<ConcreteExpression>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContinueExpr {
+    pub loop_id: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CastExpr {
+    pub target: Box<ConcreteExpression>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DynCoerceExpr {
+    pub target: Box<ConcreteExpression>,
+    pub interface: String,
+    pub impl_fn: String,
+}
+
+// 関数へのポインタと、取り込んだ変数をコピーした環境の組を作る
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClosureExpr {
+    pub fn_name: String,
+    pub captures: Vec<(String, ConcreteType)>,
+    // 関数の外に出ていく可能性があれば環境をヒープに、なければスタックに置く
+    pub escapes: bool,
 }
 
-#[derive(Debug, Clone)]
+// 組から関数へのポインタと環境を取り出し、環境を第一引数にして呼び出す
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClosureCallExpr {
+    pub callee: Box<ConcreteExpression>,
+    pub args: Vec<ConcreteExpression>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DynCallExpr {
+    pub interface: String,
+    pub receiver: Box<ConcreteExpression>,
+    pub args: Vec<ConcreteExpression>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ExpressionKind {
     SizeOf(ConcreteType),
+    Cast(CastExpr),
     VariableRef(VariableRefExpr),
     NumberLiteral(NumberLiteral),
     StringLiteral(StringLiteral),
@@ -246,63 +312,82 @@ pub enum ExpressionKind {
     If(IfExpr),
     When(WhenExpr),
     While(WhileExpr),
+    Break(BreakExpr),
+    Continue(ContinueExpr),
+    Block(BlockExpr),
+    Closure(ClosureExpr),
+    ClosureCall(ClosureCallExpr),
     VariableDecls(VariableDecls),
     Assignment(Assignment),
     Return(Return),
+    DynCoerce(DynCoerceExpr),
+    DynCall(DynCallExpr),
     Unknown,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConcreteExpression {
     pub ty: ConcreteType,
     pub kind: ExpressionKind,
+    // 元になった式のソース上の位置。デバッグ情報の行番号になる
+    pub location: SourceLocation,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Assignment {
     pub name: String,
     pub value: Box<ConcreteExpression>,
     pub deref_count: usize,
     pub index_access: Option<Box<ConcreteExpression>>,
+    pub target_is_array: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VariableDecl {
     pub name: String,
     pub ty: ConcreteType,
     pub value: Box<ConcreteExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VariableDecls {
     pub decls: Vec<VariableDecl>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Return {
     pub expression: Option<Box<ConcreteExpression>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Argument {
     VarArgs,
     Normal(ConcreteType, String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionDecl {
     pub name: String,
     pub args: Vec<Argument>,
     pub return_type: ConcreteType,
-}
-
-#[derive(Debug, Clone)]
+    // `fn` 式から変換した関数では、環境へのポインタを引数の前に受け取り、
+    // そこから取り込んだ変数を読み出す
+    pub captures: Option<Vec<(String, ConcreteType)>>,
+    // Cの呼び出し規約で呼ぶ、本体を持たない関数。構造体の受け渡し方がターゲットごとに変わる
+    pub is_extern: bool,
+    // Cに公開する名前。この名前でCの呼び出し規約の入口を作る
+    pub export_name: Option<String>,
+    pub linkage: Linkage,
+    pub location: SourceLocation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Function {
     pub decl: FunctionDecl,
     pub body: Vec<ConcreteExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TopLevel {
     Function(Function),
 }
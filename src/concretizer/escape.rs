@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use crate::concrete_ast::{ConcreteExpression, ExpressionKind, Function};
+
+// 関数の外に出ていかないクロージャーを探し、環境をスタックに置けるようにする。
+// `:=` で束縛した変数が、呼び出し先としてしか使われないものに限る。
+// 引数に渡したり、返したり、別の `fn` 式に取り込んだりした値は、どこまで使われるか分からないのでヒープに置く
+pub(super) fn mark_stack_closures(function: &mut Function) {
+    // 変数ごとの (全ての参照の数, 呼び出し先としての参照の数)
+    let mut uses: HashMap<String, (usize, usize)> = HashMap::new();
+    for expr in &mut function.body {
+        walk(expr, &mut |expr| match &expr.kind {
+            ExpressionKind::VariableRef(variable_ref) => {
+                uses.entry(variable_ref.name.clone()).or_default().0 += 1;
+            }
+            ExpressionKind::ClosureCall(closure_call) => {
+                if let ExpressionKind::VariableRef(variable_ref) = &closure_call.callee.kind {
+                    uses.entry(variable_ref.name.clone()).or_default().1 += 1;
+                }
+            }
+            ExpressionKind::Closure(closure) => {
+                for (name, _) in &closure.captures {
+                    uses.entry(name.clone()).or_default().0 += 1;
+                }
+            }
+            ExpressionKind::Assignment(assignment) => {
+                uses.entry(assignment.name.clone()).or_default().0 += 1;
+            }
+            _ => {}
+        });
+    }
+
+    // 同じ名前の変数が複数あれば、全ての参照をまとめて数えるので安全な側に倒れる
+    let only_called = |name: &str| uses.get(name).is_none_or(|(all, called)| all == called);
+    for expr in &mut function.body {
+        walk(expr, &mut |expr| {
+            if let ExpressionKind::VariableDecls(variable_decls) = &mut expr.kind {
+                for decl in &mut variable_decls.decls {
+                    if let ExpressionKind::Closure(closure) = &mut decl.value.kind {
+                        closure.escapes = !only_called(&decl.name);
+                    }
+                }
+            }
+        });
+    }
+}
+
+// 式とその中の全ての式を、外側から順に訪れる
+fn walk(expr: &mut ConcreteExpression, visit: &mut impl FnMut(&mut ConcreteExpression)) {
+    visit(expr);
+    match &mut expr.kind {
+        ExpressionKind::SizeOf(_)
+        | ExpressionKind::VariableRef(_)
+        | ExpressionKind::NumberLiteral(_)
+        | ExpressionKind::StringLiteral(_)
+        | ExpressionKind::BoolLiteral(_)
+        | ExpressionKind::Continue(_)
+        | ExpressionKind::Closure(_)
+        | ExpressionKind::Unknown => {}
+        ExpressionKind::Cast(cast) => walk(&mut cast.target, visit),
+        ExpressionKind::StructLiteral(struct_literal) => {
+            for (_, field) in &mut struct_literal.fields {
+                walk(field, visit);
+            }
+        }
+        ExpressionKind::ArrayLiteral(array_literal) => {
+            for element in &mut array_literal.elements {
+                walk(element, visit);
+            }
+        }
+        ExpressionKind::Binary(binary) => {
+            walk(&mut binary.lhs, visit);
+            walk(&mut binary.rhs, visit);
+        }
+        ExpressionKind::Unary(unary) => walk(&mut unary.operand, visit),
+        ExpressionKind::Multi(multi) => {
+            for operand in &mut multi.operands {
+                walk(operand, visit);
+            }
+        }
+        ExpressionKind::CallExpr(call) => {
+            for arg in &mut call.args {
+                walk(arg, visit);
+            }
+        }
+        ExpressionKind::Deref(deref) => walk(&mut deref.target, visit),
+        ExpressionKind::AddressOf(address_of) => walk(&mut address_of.target, visit),
+        ExpressionKind::IndexAccess(index_access) => {
+            walk(&mut index_access.target, visit);
+            walk(&mut index_access.index, visit);
+        }
+        ExpressionKind::FieldAccess(field_access) => walk(&mut field_access.target, visit),
+        ExpressionKind::If(if_expr) => {
+            walk(&mut if_expr.cond, visit);
+            walk(&mut if_expr.then, visit);
+            walk(&mut if_expr.els, visit);
+        }
+        ExpressionKind::When(when_expr) => {
+            walk(&mut when_expr.cond, visit);
+            walk(&mut when_expr.then, visit);
+        }
+        ExpressionKind::While(while_expr) => {
+            walk(&mut while_expr.cond, visit);
+            walk(&mut while_expr.body, visit);
+            if let Some(step) = &mut while_expr.step {
+                walk(step, visit);
+            }
+        }
+        ExpressionKind::Break(break_expr) => {
+            if let Some(value) = &mut break_expr.value {
+                walk(value, visit);
+            }
+        }
+        ExpressionKind::Block(block) => {
+            for expression in &mut block.expressions {
+                walk(expression, visit);
+            }
+        }
+        ExpressionKind::ClosureCall(closure_call) => {
+            walk(&mut closure_call.callee, visit);
+            for arg in &mut closure_call.args {
+                walk(arg, visit);
+            }
+        }
+        ExpressionKind::VariableDecls(variable_decls) => {
+            for decl in &mut variable_decls.decls {
+                walk(&mut decl.value, visit);
+            }
+        }
+        ExpressionKind::Assignment(assignment) => {
+            walk(&mut assignment.value, visit);
+            if let Some(index) = &mut assignment.index_access {
+                walk(index, visit);
+            }
+        }
+        ExpressionKind::Return(ret) => {
+            if let Some(expression) = &mut ret.expression {
+                walk(expression, visit);
+            }
+        }
+        ExpressionKind::DynCoerce(dyn_coerce) => walk(&mut dyn_coerce.target, visit),
+        ExpressionKind::DynCall(dyn_call) => {
+            walk(&mut dyn_call.receiver, visit);
+            for arg in &mut dyn_call.args {
+                walk(arg, visit);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::target::PointerSizedIntWidth,
+        concrete_ast::TopLevel,
+        concretizer::concretize_module,
+        parser::parse_module,
+        resolver::{resolve_module, ResolverContext},
+    };
+    use nom::Finish;
+
+    fn closure_escapes(function: &mut Function) -> Vec<bool> {
+        let mut escapes = Vec::new();
+        for expr in &mut function.body {
+            walk(expr, &mut |expr| {
+                if let ExpressionKind::Closure(closure) = &expr.kind {
+                    escapes.push(closure.escapes);
+                }
+            });
+        }
+        escapes
+    }
+
+    #[test]
+    fn test_mark_stack_closures() {
+        let source = r#"
+fn apply(f: fn(i32): i32, x: i32): i32 {
+  (f x)
+}
+
+fn make_adder(n: i32): fn(i32): i32 {
+  (fn (x: i32) (+ x n))
+}
+
+fn main(): i32 {
+  (:= offset 10)
+  (:= add_offset (fn (x: i32) (+ x offset)))
+  (:= passed (fn (x: i32) (* x offset)))
+  (:= outer (fn (x: i32)
+    (:= inner (fn (y: i32) (+ y x)))
+    (inner 2)))
+  (+ (+ (add_offset 1) (apply passed 2)) (outer 3))
+}
+"#;
+        let (_, module) = parse_module(source.into()).finish().unwrap();
+        let context = ResolverContext::new(PointerSizedIntWidth::SixtyFour);
+        let resolved = resolve_module(&context, &module, false).unwrap();
+        assert!(context.errors.borrow().is_empty(), "{:?}", context.errors);
+        let concrete = concretize_module(resolved, PointerSizedIntWidth::SixtyFour, None);
+
+        let results = concrete
+            .toplevels
+            .into_iter()
+            .map(|TopLevel::Function(mut function)| {
+                let escapes = closure_escapes(&mut function);
+                (
+                    function.decl.name,
+                    function.decl.captures.is_some(),
+                    escapes,
+                )
+            })
+            .filter(|(_, _, escapes)| !escapes.is_empty())
+            .collect::<Vec<_>>();
+
+        // 返すものと引数に渡すものだけがヒープに残る
+        let main = results.iter().find(|(name, _, _)| name == "main").unwrap();
+        assert_eq!(main.2, vec![false, true, false]);
+        let make_adder = results
+            .iter()
+            .find(|(name, lifted, _)| !lifted && name != "main")
+            .unwrap();
+        assert_eq!(make_adder.2, vec![true]);
+        // `fn` 式から作った関数の中の `fn` 式も調べる
+        let outer = results.iter().find(|(_, lifted, _)| *lifted).unwrap();
+        assert_eq!(outer.2, vec![false]);
+    }
+}
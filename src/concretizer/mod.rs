@@ -1,13 +1,19 @@
+mod escape;
 mod toplevel;
 
+use std::cell::RefCell;
+
 use crate::{
     common::target::PointerSizedIntWidth,
-    concrete_ast::ConcreteModule,
-    resolved_ast::ResolvedModule,
+    concrete_ast::{self, ConcreteModule},
+    resolved_ast::{self, ResolvedModule},
+    resolver::cache::{concrete_key, IncrementalCache},
 };
 
 pub struct ConcretizerContext {
     pub ptr_sized_int_type: PointerSizedIntWidth,
+    // `fn` 式の本体から作った関数。モジュールの最後に追加する
+    pub lifted_functions: RefCell<Vec<concrete_ast::Function>>,
 }
 
 impl ConcretizerContext {
@@ -16,19 +22,57 @@ impl ConcretizerContext {
     }
 }
 
+// キャッシュがあれば、前回と同じ関数は前回作ったものを使い、今回作ったものを記録する
 pub fn concretize_module(
     resolved_module: ResolvedModule,
     ptr_sized_int_type: PointerSizedIntWidth,
+    cache: Option<&RefCell<IncrementalCache>>,
 ) -> ConcreteModule {
-    let context = ConcretizerContext { ptr_sized_int_type };
+    let context = ConcretizerContext {
+        ptr_sized_int_type,
+        lifted_functions: Default::default(),
+    };
 
     let mut toplevels = Vec::new();
 
     for toplevel in resolved_module.toplevels {
+        let resolved_ast::TopLevel::Function(function) = &toplevel;
+        let symbol = &function.decl.name;
+        let key = cache.map(|_| concrete_key(function, context.is_64_bit()));
+        if let (Some(cache), Some(key)) = (cache, key) {
+            if let Some((concrete_toplevels, lifted_functions)) =
+                cache.borrow_mut().reuse_concrete(symbol, key)
+            {
+                toplevels.extend(concrete_toplevels);
+                context
+                    .lifted_functions
+                    .borrow_mut()
+                    .extend(lifted_functions);
+                continue;
+            }
+        }
+        let lifted_before = context.lifted_functions.borrow().len();
         if let Some(concrete_toplevels) = toplevel::concretize_toplevel(&context, &toplevel) {
+            if let (Some(cache), Some(key)) = (cache, key) {
+                let lifted_functions = context.lifted_functions.borrow()[lifted_before..].to_vec();
+                cache.borrow_mut().store_concrete(
+                    symbol,
+                    key,
+                    concrete_toplevels.clone(),
+                    lifted_functions,
+                );
+            }
             toplevels.extend(concrete_toplevels);
         }
     }
 
+    toplevels.extend(
+        context
+            .lifted_functions
+            .into_inner()
+            .into_iter()
+            .map(concrete_ast::TopLevel::Function),
+    );
+
     ConcreteModule { toplevels }
 }
@@ -1,9 +1,10 @@
 use crate::{
+    common::Linkage,
     concrete_ast::{self, ConcreteExpression, ConcreteType, Function},
     resolved_ast::{self, ResolvedType},
 };
 
-use super::ConcretizerContext;
+use super::{escape::mark_stack_closures, ConcretizerContext};
 
 fn concretize_type(ctx: &ConcretizerContext, ty: &ResolvedType) -> ConcreteType {
     match ty {
@@ -25,6 +26,7 @@ fn concretize_type(ctx: &ConcretizerContext, ty: &ResolvedType) -> ConcreteType
         ResolvedType::Ptr(inner) => ConcreteType::Ptr(Box::new(concretize_type(ctx, inner))),
         ResolvedType::Void => ConcreteType::Void,
         ResolvedType::Unknown => panic!("Unknown type should not reach concretizer"),
+        ResolvedType::Var(_) => panic!("Type variable should be resolved before concretizer"),
         ResolvedType::StructLike(struct_ty) => {
             let fields = struct_ty
                 .fields
@@ -40,9 +42,92 @@ fn concretize_type(ctx: &ConcretizerContext, ty: &ResolvedType) -> ConcreteType
         ResolvedType::Generics(_) => {
             panic!("Generic type should be resolved before concretizer")
         }
+        // dyn型はデータへのポインタとvtableへのポインタの組 (fat pointer) として表現する
+        ResolvedType::Dyn(interface_name) => {
+            let name = format!("dyn {}", interface_name);
+            ConcreteType::StructLike(concrete_ast::ConcreteStructType {
+                name: name.clone(),
+                non_generic_name: name,
+                fields: vec![
+                    ("data".to_string(), ConcreteType::Ptr(Box::new(ConcreteType::U8))),
+                    ("vtable".to_string(), ConcreteType::Ptr(Box::new(ConcreteType::U8))),
+                ],
+            })
+        }
+        // 関数の値は関数へのポインタと環境へのポインタの組として表現する
+        ResolvedType::Function(_) => {
+            let name = ty.to_string();
+            ConcreteType::StructLike(concrete_ast::ConcreteStructType {
+                name: name.clone(),
+                non_generic_name: name,
+                fields: vec![
+                    ("fn".to_string(), ConcreteType::Ptr(Box::new(ConcreteType::U8))),
+                    ("env".to_string(), ConcreteType::Ptr(Box::new(ConcreteType::U8))),
+                ],
+            })
+        }
+        ResolvedType::Array(element, len) => {
+            ConcreteType::Array(Box::new(concretize_type(ctx, element)), *len)
+        }
+        // タプルは要素の番号をフィールド名にした構造体になる。
+        // 構造体と同じく、関数から返すときは sret を使う
+        ResolvedType::Tuple(elements) => {
+            let name = ty.to_string();
+            ConcreteType::StructLike(concrete_ast::ConcreteStructType {
+                name: name.clone(),
+                non_generic_name: name,
+                fields: elements
+                    .iter()
+                    .enumerate()
+                    .map(|(i, element)| (i.to_string(), concretize_type(ctx, element)))
+                    .collect(),
+            })
+        }
     }
 }
 
+// `fn` 式の本体を、環境を受け取る独立した関数にする (closure conversion)。
+// 式の位置には関数と取り込んだ変数の組を作る式が残る
+fn lift_lambda(
+    ctx: &ConcretizerContext,
+    lambda: &resolved_ast::LambdaExpr,
+) -> concrete_ast::ExpressionKind {
+    let captures = lambda
+        .captures
+        .iter()
+        .map(|(name, ty)| (name.clone(), concretize_type(ctx, ty)))
+        .collect::<Vec<_>>();
+    let mut function = Function {
+        decl: concrete_ast::FunctionDecl {
+            name: lambda.fn_name.clone(),
+            args: lambda
+                .args
+                .iter()
+                .map(|a| concretize_argument(ctx, a))
+                .collect(),
+            return_type: concretize_type(ctx, &lambda.return_type),
+            captures: Some(captures.clone()),
+            is_extern: false,
+            export_name: None,
+            linkage: Linkage::Internal,
+            location: lambda.location,
+        },
+        body: lambda
+            .body
+            .iter()
+            .map(|stmt| concretize_statement(ctx, stmt))
+            .collect(),
+    };
+    mark_stack_closures(&mut function);
+    ctx.lifted_functions.borrow_mut().push(function);
+    // 逃げないと分かった場合は、使う側の関数を調べるときに書き換える
+    concrete_ast::ExpressionKind::Closure(concrete_ast::ClosureExpr {
+        fn_name: lambda.fn_name.clone(),
+        captures,
+        escapes: true,
+    })
+}
+
 fn concretize_expression(
     ctx: &ConcretizerContext,
     expr: &resolved_ast::ResolvedExpression,
@@ -59,7 +144,7 @@ fn concretize_expression(
         }
         resolved_ast::ExpressionKind::NumberLiteral(num) => {
             concrete_ast::ExpressionKind::NumberLiteral(concrete_ast::NumberLiteral {
-                value: num.value.clone(),
+                value: num.value,
             })
         }
         resolved_ast::ExpressionKind::StringLiteral(str_lit) => {
@@ -158,8 +243,47 @@ fn concretize_expression(
         }
         resolved_ast::ExpressionKind::While(while_expr) => {
             concrete_ast::ExpressionKind::While(concrete_ast::WhileExpr {
+                loop_id: while_expr.loop_id,
                 cond: Box::new(concretize_expression(ctx, &while_expr.cond)),
                 body: Box::new(concretize_expression(ctx, &while_expr.body)),
+                step: while_expr
+                    .step
+                    .as_ref()
+                    .map(|e| Box::new(concretize_expression(ctx, e))),
+            })
+        }
+        resolved_ast::ExpressionKind::Block(block) => {
+            concrete_ast::ExpressionKind::Block(concrete_ast::BlockExpr {
+                expressions: block
+                    .statements
+                    .iter()
+                    .map(|stmt| concretize_statement(ctx, stmt))
+                    .collect(),
+            })
+        }
+        resolved_ast::ExpressionKind::Lambda(lambda) => lift_lambda(ctx, lambda),
+        resolved_ast::ExpressionKind::ClosureCall(closure_call) => {
+            concrete_ast::ExpressionKind::ClosureCall(concrete_ast::ClosureCallExpr {
+                callee: Box::new(concretize_expression(ctx, &closure_call.callee)),
+                args: closure_call
+                    .args
+                    .iter()
+                    .map(|e| concretize_expression(ctx, e))
+                    .collect(),
+            })
+        }
+        resolved_ast::ExpressionKind::Break(break_expr) => {
+            concrete_ast::ExpressionKind::Break(concrete_ast::BreakExpr {
+                loop_id: break_expr.loop_id,
+                value: break_expr
+                    .value
+                    .as_ref()
+                    .map(|e| Box::new(concretize_expression(ctx, e))),
+            })
+        }
+        resolved_ast::ExpressionKind::Continue(continue_expr) => {
+            concrete_ast::ExpressionKind::Continue(concrete_ast::ContinueExpr {
+                loop_id: continue_expr.loop_id,
             })
         }
         resolved_ast::ExpressionKind::VariableDecls(decls) => {
@@ -184,11 +308,39 @@ fn concretize_expression(
                     .index_access
                     .as_ref()
                     .map(|e| Box::new(concretize_expression(ctx, e))),
+                target_is_array: assign.target_is_array,
+            })
+        }
+        resolved_ast::ExpressionKind::Cast(cast) => {
+            concrete_ast::ExpressionKind::Cast(concrete_ast::CastExpr {
+                target: Box::new(concretize_expression(ctx, &cast.target)),
+            })
+        }
+        resolved_ast::ExpressionKind::DynCoerce(dyn_coerce) => {
+            concrete_ast::ExpressionKind::DynCoerce(concrete_ast::DynCoerceExpr {
+                target: Box::new(concretize_expression(ctx, &dyn_coerce.target)),
+                interface: dyn_coerce.interface.clone(),
+                impl_fn: dyn_coerce.impl_fn.clone(),
+            })
+        }
+        resolved_ast::ExpressionKind::DynCall(dyn_call) => {
+            concrete_ast::ExpressionKind::DynCall(concrete_ast::DynCallExpr {
+                interface: dyn_call.interface.clone(),
+                receiver: Box::new(concretize_expression(ctx, &dyn_call.receiver)),
+                args: dyn_call
+                    .args
+                    .iter()
+                    .map(|e| concretize_expression(ctx, e))
+                    .collect(),
             })
         }
         resolved_ast::ExpressionKind::Unknown => concrete_ast::ExpressionKind::Unknown,
     };
-    ConcreteExpression { ty, kind }
+    ConcreteExpression {
+        ty,
+        kind,
+        location: expr.location,
+    }
 }
 
 fn concretize_statement(
@@ -210,6 +362,7 @@ fn concretize_statement(
                         .as_ref()
                         .map(|e| Box::new(concretize_expression(ctx, e))),
                 }),
+                location: ret.location,
             }
         }
         resolved_ast::Statement::Effect(effect) => concretize_expression(ctx, &effect.expression),
@@ -242,14 +395,21 @@ fn concretize_function(ctx: &ConcretizerContext, func: &resolved_ast::Function)
         .map(|stmt| concretize_statement(ctx, stmt))
         .collect();
 
-    Function {
+    let mut function = Function {
         decl: concrete_ast::FunctionDecl {
             name: func.decl.name.clone(),
             args,
             return_type,
+            captures: None,
+            is_extern: func.decl.is_extern,
+            export_name: func.decl.export_name.clone(),
+            linkage: func.decl.linkage,
+            location: func.decl.location,
         },
         body,
-    }
+    };
+    mark_stack_closures(&mut function);
+    function
 }
 
 pub fn concretize_toplevel(
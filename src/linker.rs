@@ -0,0 +1,174 @@
+use std::{path::Path, process::Command};
+
+use inkwell::{
+    module::Module as LLVMModule,
+    passes::PassBuilderOptions,
+    support::load_library_permanently,
+    targets::{CodeModel, FileType, RelocMode, Target, TargetMachine, TargetTriple},
+    OptimizationLevel,
+};
+
+use crate::common::target::TargetPlatform;
+
+// libc と libm はコンパイラ自身がリンクしているので、JITでは読み込まなくても使える
+const PROCESS_LIBRARIES: [&str; 2] = ["c", "m"];
+
+// `-l m` や `#[link("m")]` で指定されたライブラリ。`/` を含むか拡張子があればファイルのパスとして扱う
+fn is_library_path(library: &str) -> bool {
+    library.contains('/')
+        || library.ends_with(".so")
+        || library.ends_with(".dylib")
+        || library.ends_with(".a")
+}
+
+fn shared_library_file_name(library: &str, target: TargetPlatform) -> String {
+    if is_library_path(library) {
+        library.to_string()
+    } else if target.is_darwin() {
+        format!("lib{}.dylib", library)
+    } else if target.is_windows() {
+        format!("{}.dll", library)
+    } else {
+        format!("lib{}.so", library)
+    }
+}
+
+// JITで実行する前にライブラリを読み込み、extern 関数のシンボルを解決できるようにする
+pub fn load_libraries_for_jit(libraries: &[String], target: TargetPlatform) -> Result<(), String> {
+    for library in libraries {
+        if PROCESS_LIBRARIES.contains(&library.as_str()) {
+            continue;
+        }
+        let file_name = shared_library_file_name(library, target);
+        // 失敗したときに true が返る
+        if load_library_permanently(Path::new(&file_name)) {
+            return Err(format!(
+                "could not load library `{}` ({})",
+                library, file_name
+            ));
+        }
+    }
+    Ok(())
+}
+
+// モジュールをターゲット向けに設定する。ホスト向けならホストのCPUの機能を使い、それ以外は汎用のCPU向けにする
+fn create_target_machine(
+    module: &LLVMModule,
+    target: TargetPlatform,
+    opt_level: OptimizationLevel,
+) -> Result<TargetMachine, String> {
+    let (triple, cpu, features) = if TargetPlatform::current() == Ok(target) {
+        (
+            TargetMachine::get_default_triple(),
+            TargetMachine::get_host_cpu_name().to_string(),
+            TargetMachine::get_host_cpu_features().to_string(),
+        )
+    } else {
+        (
+            TargetTriple::create(target.metrics().target_triplet),
+            "generic".to_string(),
+            String::new(),
+        )
+    };
+    let llvm_target = Target::from_triple(&triple).map_err(|err| err.to_string())?;
+    let target_machine = llvm_target
+        .create_target_machine(
+            &triple,
+            &cpu,
+            &features,
+            opt_level,
+            RelocMode::PIC,
+            CodeModel::Default,
+        )
+        .ok_or_else(|| format!("unsupported target `{}`", triple.as_str().to_string_lossy()))?;
+    module.set_triple(&triple);
+    module.set_data_layout(&target_machine.get_target_data().get_data_layout());
+    Ok(target_machine)
+}
+
+// プロファイルの `opt-level` に合わせて、LLVMの最適化パスをかける
+pub fn optimize_module(
+    module: &LLVMModule,
+    target: TargetPlatform,
+    opt_level: OptimizationLevel,
+) -> Result<(), String> {
+    let passes = match opt_level {
+        OptimizationLevel::None => return Ok(()),
+        OptimizationLevel::Less => "default<O1>",
+        OptimizationLevel::Default => "default<O2>",
+        OptimizationLevel::Aggressive => "default<O3>",
+    };
+    let target_machine = create_target_machine(module, target, opt_level)?;
+    module
+        .run_passes(passes, &target_machine, PassBuilderOptions::create())
+        .map_err(|err| err.to_string())
+}
+
+fn write_object_file(
+    module: &LLVMModule,
+    object_path: &Path,
+    target: TargetPlatform,
+    opt_level: OptimizationLevel,
+) -> Result<(), String> {
+    let target_machine = create_target_machine(module, target, opt_level)?;
+    target_machine
+        .write_to_file(module, FileType::Object, object_path)
+        .map_err(|err| err.to_string())
+}
+
+// オブジェクトファイルを書き出し、Cコンパイラを介してライブラリと一緒に実行ファイルへリンクする
+pub fn build_executable(
+    module: &LLVMModule,
+    output: &Path,
+    libraries: &[String],
+    target: TargetPlatform,
+    opt_level: OptimizationLevel,
+) -> Result<(), String> {
+    let object_path = output.with_extension("o");
+    write_object_file(module, &object_path, target, opt_level)?;
+
+    let mut command = Command::new("cc");
+    command.arg(&object_path).arg("-o").arg(output);
+    for library in libraries {
+        if is_library_path(library) {
+            command.arg(library);
+        } else {
+            command.arg(format!("-l{}", library));
+        }
+    }
+    let status = command
+        .status()
+        .map_err(|err| format!("failed to run the linker: {}", err))?;
+    if !status.success() {
+        return Err(format!("linker exited with {}", status));
+    }
+    Ok(())
+}
+
+// `--crate-type lib` の出力。`.o` ならオブジェクトファイルをそのまま、それ以外は `ar` で静的ライブラリにまとめる
+pub fn build_library(
+    module: &LLVMModule,
+    output: &Path,
+    target: TargetPlatform,
+    opt_level: OptimizationLevel,
+) -> Result<(), String> {
+    if output.extension().is_some_and(|extension| extension == "o") {
+        return write_object_file(module, output, target, opt_level);
+    }
+    let object_path = output.with_extension("o");
+    write_object_file(module, &object_path, target, opt_level)?;
+    // 古いアーカイブに残ったメンバーを混ぜないように作り直す
+    if output.exists() {
+        std::fs::remove_file(output).map_err(|err| err.to_string())?;
+    }
+    let status = Command::new("ar")
+        .arg("rcs")
+        .arg(output)
+        .arg(&object_path)
+        .status()
+        .map_err(|err| format!("failed to run the archiver: {}", err))?;
+    if !status.success() {
+        return Err(format!("archiver exited with {}", status));
+    }
+    Ok(())
+}
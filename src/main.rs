@@ -1,40 +1,145 @@
-use std::{fs::read_to_string, path::Path};
+use std::{
+    cell::RefCell,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
 mod ast;
 mod builder;
+mod c_header;
 mod common;
 mod concrete_ast;
 mod concretizer;
+mod linker;
+mod metadata;
+mod package;
 mod parser;
 mod resolved_ast;
 mod resolver;
 
-use clap::{command, Parser};
+use clap::{command, Parser, ValueEnum};
 use common::target::{PointerSizedIntWidth, TargetPlatform};
-use inkwell::{context::Context as LLVMContext, OptimizationLevel};
+use inkwell::{context::Context as LLVMContext, module::Module as LLVMModule, OptimizationLevel};
 use nom::{
     error::{convert_error, VerboseError},
     Finish,
 };
-use resolver::ResolverContext;
+use package::{PackageGraph, SourceFile};
+use resolver::{cache::IncrementalCache, ResolverContext};
 
 #[derive(clap::Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    #[clap(index = 1)]
-    target: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[clap(index = 1, required = true)]
+    target: Option<String>,
+    // 指定すると実行せず、実行ファイルを書き出す
     #[clap(short, long)]
     output: Option<String>,
+    // リンクするライブラリ (`-l m`)。`#[link("m")]` で指定したものに加える
+    #[clap(short = 'l', long = "link")]
+    link: Vec<String>,
     #[clap(long)]
     parse: bool,
+    // 実行せずに、指定したものを書き出す
+    #[clap(long, value_enum)]
+    emit: Option<Emit>,
+    // 値が変わり得る暗黙の整数変換をエラーにする
+    #[clap(long)]
+    strict: bool,
+    // デバッガで行と変数を辿れるように、DWARFのデバッグ情報を付ける
+    #[clap(short = 'g')]
+    debug: bool,
+    // `lib` にすると、mainを持たないファイルを静的ライブラリとして書き出す
+    #[clap(long = "crate-type", value_enum, default_value = "bin")]
+    crate_type: CrateType,
+    // `--crate-type lib` で作ったライブラリ。隣の `.hri` からメタデータを読み込み、ライブラリと一緒にリンクする
+    #[clap(long = "extern")]
+    extern_libraries: Vec<String>,
+    // 関数ごとの解決結果を `<DIR>/<ファイル名>.json` に保存し、次のコンパイルで変わっていない関数に使う
+    #[clap(long, value_name = "DIR")]
+    incremental: Option<String>,
 }
 
-fn main() {
-    let args = Args::parse();
-    let path = Path::new(&args.target);
-    let input = read_to_string(path).unwrap();
-    let input = input.as_str().into();
-    let module = match parser::parse_module(input).finish() {
-        Ok((_, module)) => module,
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    // シンボル名を読める形に戻す。指定がなければ標準入力の各行に含まれるシンボル名を置き換える
+    Demangle {
+        symbols: Vec<String>,
+    },
+    // コードを生成せずに、mainから呼ばれない関数やジェネリック関数も含めて型検査だけを行う。
+    // ファイルを省略すると `hirou.toml` のパッケージを依存パッケージごと検査する
+    Check {
+        target: Option<String>,
+        // 値が変わり得る暗黙の整数変換をエラーにする
+        #[clap(long)]
+        strict: bool,
+        #[clap(long = "manifest-path")]
+        manifest_path: Option<String>,
+    },
+    // `hirou.toml` のパッケージをビルドし、`target/<プロファイル>/` に実行ファイルを書き出す。
+    // エントリーのないパッケージは静的ライブラリとメタデータを書き出す
+    Build(PackageArgs),
+    // パッケージをビルドして、mainをJITで実行する
+    Run(PackageArgs),
+    // ルートのパッケージの `#[test]` を付けた関数をJITで実行する
+    Test(PackageArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct PackageArgs {
+    // `hirou.toml` の `[profile.<name>]`。`dev` と `release` は書かなくても使える
+    #[clap(long, default_value = "dev")]
+    profile: String,
+    // `--profile release` と同じ
+    #[clap(long, conflicts_with = "profile")]
+    release: bool,
+    // 省略すると、カレントディレクトリから親へ向かって `hirou.toml` を探す
+    #[clap(long = "manifest-path")]
+    manifest_path: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PackageCommand {
+    Build,
+    Run,
+    Test,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum CrateType {
+    // mainから実行する
+    Bin,
+    // `pub fn` で宣言したジェネリックでない関数を起点に、静的ライブラリ (`-o` が `.o` ならオブジェクトファイル) を書き出す。
+    // 公開した関数の宣言とジェネリックな関数の本体などは、同じ名前の `.hri` ファイルにメタデータとして書き出す
+    Lib,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Emit {
+    // `#[export]` した関数のプロトタイプを並べたCのヘッダー
+    CHeader,
+    // 生成したLLVM IR
+    LlvmIr,
+}
+
+fn demangle(symbols: &[String]) {
+    if symbols.is_empty() {
+        for line in std::io::stdin().lines() {
+            println!("{}", resolver::mangle::demangle_text(&line.unwrap()));
+        }
+    } else {
+        for symbol in symbols {
+            println!("{}", resolver::mangle::demangle_text(symbol));
+        }
+    }
+}
+
+// 構文エラーがあれば表示してNoneを返す
+fn parse(input: parser::Span) -> Option<ast::Module> {
+    match parser::parse_module(input).finish() {
+        Ok((_, module)) => Some(module),
         Err(err) => {
             // using workaround to convert Span -> &str
             // ref: https://github.com/fflorent/nom_locate/issues/36#issuecomment-1013469728
@@ -46,59 +151,666 @@ fn main() {
 
             let error_message = convert_error(*input, VerboseError { errors });
             println!("{}", error_message);
+            None
+        }
+    }
+}
+
+// カレントディレクトリからの相対パス。
+// カレントディレクトリの外にある依存パッケージのファイルは、絶対パスのまま表示する
+fn display_path(path: &Path) -> PathBuf {
+    let absolute_path = path.canonicalize().unwrap();
+    match absolute_path.strip_prefix(std::env::current_dir().unwrap()) {
+        Ok(relative_path) => relative_path.to_path_buf(),
+        Err(_) => absolute_path,
+    }
+}
+
+// 型検査で見つかったエラーを、それが見つかったファイルのソースの該当箇所と一緒に表示する
+fn print_errors(resolver_context: &ResolverContext, sources: &[SourceFile]) {
+    let mut stdout = std::io::stdout();
+    for error in resolver_context.errors.borrow().iter() {
+        let source_file = sources
+            .iter()
+            .find(|source_file| source_file.file == error.file())
+            .unwrap_or(&sources[0]);
+        error
+            .fmt_with_source(
+                &mut stdout,
+                display_path(&source_file.path).to_str().unwrap(),
+                &source_file.source,
+            )
+            .unwrap();
+    }
+}
+
+// 解決を続けられなかったエラー (mainがないなど) を表示する。
+// ソースの位置を持たないので、エントリーのファイルを添える
+fn print_faital_error(message: &str, sources: &[SourceFile]) {
+    eprintln!(
+        "error: {}: {}",
+        display_path(&sources[0].path).display(),
+        message
+    );
+}
+
+// 失敗していればエラーを表示してNoneを返す
+fn report<T>(result: Result<T, String>) -> Option<T> {
+    result.map_err(|err| println!("error: {}", err)).ok()
+}
+
+// エラーがあれば終了コード1で終わる
+fn check(target: &str, strict: bool) {
+    let path = Path::new(target);
+    let input = read_to_string(path).unwrap();
+    let Some(module) = parse(input.as_str().into()) else {
+        std::process::exit(1);
+    };
+    let target_platform = TargetPlatform::current().unwrap_or(TargetPlatform::DarwinArm64);
+    let mut resolver_context = ResolverContext::new(PointerSizedIntWidth::from(target_platform));
+    resolver_context.strict_conversions = strict;
+    let sources = [SourceFile::single(path, input)];
+    if let Err(err) = resolver::check_module(&resolver_context, &module) {
+        print_faital_error(&err.0, &sources);
+        std::process::exit(1);
+    }
+    if !resolver_context.errors.borrow().is_empty() {
+        print_errors(&resolver_context, &sources);
+        std::process::exit(1);
+    }
+}
+
+// `hirou.toml` を読み込み、依存パッケージも含めた全てのソースファイルをパースして一つのモジュールにまとめる
+fn load_package(
+    manifest_path: Option<&str>,
+) -> Option<(PackageGraph, Vec<SourceFile>, ast::Module)> {
+    let manifest_path = match manifest_path {
+        Some(manifest_path) => PathBuf::from(manifest_path),
+        None => {
+            let Some(manifest_path) = package::find_manifest(&std::env::current_dir().unwrap())
+            else {
+                println!(
+                    "error: could not find `{}` in the current directory or any parent directory",
+                    package::MANIFEST_FILE_NAME
+                );
+                return None;
+            };
+            manifest_path
+        }
+    };
+    let graph = report(PackageGraph::load(&manifest_path))?;
+    let sources = report(graph.read_sources())?;
+    if sources.is_empty() {
+        println!(
+            "error: package `{}` has no source files",
+            graph.root().name()
+        );
+        return None;
+    }
+    let mut modules = Vec::new();
+    for source_file in &sources {
+        let Some(module) = parse(source_file.source.as_str().into()) else {
+            println!("error: could not parse `{}`", source_file.path.display());
+            return None;
+        };
+        modules.push((source_file, module));
+    }
+    let module = report(graph.merge_modules(modules))?;
+    Some((graph, sources, module))
+}
+
+// 依存パッケージのファイルの関数からは、同じパッケージの関数を名前だけで呼べるようにする
+fn package_resolver_context(
+    sources: &[SourceFile],
+    target_platform: TargetPlatform,
+    strict: bool,
+) -> ResolverContext {
+    let mut resolver_context = ResolverContext::new(PointerSizedIntWidth::from(target_platform));
+    resolver_context.strict_conversions = strict;
+    for source_file in sources {
+        if let Some(namespace) = &source_file.namespace {
+            resolver_context
+                .namespace_by_file
+                .insert(source_file.file, namespace.clone());
+        }
+    }
+    resolver_context
+}
+
+// 前回までの解決結果を読み込んで、解決に使わせる
+fn load_cache(resolver_context: &mut ResolverContext, cache_path: &Path) {
+    resolver_context.cache = Some(RefCell::new(IncrementalCache::read(
+        cache_path,
+        resolver::cache::compiler_id(),
+    )));
+}
+
+// 解決にエラーがなかったときに、具体的な関数も作るならそのあとに呼ぶ。書き出せなくてもコンパイルは続ける
+fn save_cache(resolver_context: &ResolverContext, cache_path: &Path) {
+    if let Some(cache) = &resolver_context.cache {
+        if let Err(err) = cache.borrow().write(cache_path) {
+            println!("warning: {}", err);
+        }
+    }
+}
+
+// エラーがあれば終了コード1で終わる
+fn check_package(manifest_path: Option<&str>, strict: bool) {
+    let Some((graph, sources, module)) = load_package(manifest_path) else {
+        std::process::exit(1);
+    };
+    let target_platform = TargetPlatform::current().unwrap_or(TargetPlatform::DarwinArm64);
+    let Some(profile) = report(graph.root().manifest.profile("dev", target_platform)) else {
+        std::process::exit(1);
+    };
+    let resolver_context =
+        package_resolver_context(&sources, profile.target, strict || profile.strict);
+    if let Err(err) = resolver::check_module(&resolver_context, &module) {
+        print_faital_error(&err.0, &sources);
+        std::process::exit(1);
+    }
+    if !resolver_context.errors.borrow().is_empty() {
+        print_errors(&resolver_context, &sources);
+        std::process::exit(1);
+    }
+}
+
+fn optimization_level(opt_level: u8) -> OptimizationLevel {
+    match opt_level {
+        0 => OptimizationLevel::None,
+        1 => OptimizationLevel::Less,
+        2 => OptimizationLevel::Default,
+        _ => OptimizationLevel::Aggressive,
+    }
+}
+
+// LLVMのモジュールを作る。`-g` のときは、ライブラリとパッケージのソースファイルもデバッグ情報に載せる
+fn generate_module<'a>(
+    llvm_context: &'a LLVMContext,
+    concrete_module: &'a concrete_ast::ConcreteModule,
+    resolver_context: &ResolverContext,
+    sources: &[SourceFile],
+    target_platform: TargetPlatform,
+    optimization_level: OptimizationLevel,
+    debug: bool,
+) -> LLVMModule<'a> {
+    let mut llvm_codegenerator = builder::LLVMCodeGenerator::new(
+        llvm_context,
+        target_platform,
+        optimization_level,
+        concrete_module,
+    );
+    if debug {
+        let main_source = sources
+            .iter()
+            .find(|source_file| source_file.file == ast::FileId::MAIN)
+            .unwrap_or(&sources[0]);
+        llvm_codegenerator.enable_debug_info(&main_source.path);
+        for (index, library) in resolver_context.libraries.iter().enumerate() {
+            llvm_codegenerator.add_debug_source_file(
+                ast::FileId::library(index),
+                Path::new(&library.source_path),
+            );
+        }
+        for source_file in sources {
+            if source_file.file != ast::FileId::MAIN {
+                llvm_codegenerator.add_debug_source_file(source_file.file, &source_file.path);
+            }
+        }
+    }
+    llvm_codegenerator.gen_module(concrete_module);
+    llvm_codegenerator.get_module()
+}
+
+fn run_main(
+    module: &LLVMModule,
+    libraries: &[String],
+    target_platform: TargetPlatform,
+    optimization_level: OptimizationLevel,
+) {
+    if let Err(err) = linker::load_libraries_for_jit(libraries, target_platform) {
+        println!("error: {}", err);
+        return;
+    }
+    let execution_engine = &module
+        .create_jit_execution_engine(optimization_level)
+        .unwrap();
+    unsafe {
+        execution_engine
+            .get_function::<unsafe extern "C" fn()>("main")
+            .unwrap()
+            .call();
+    }
+}
+
+// テストの関数を1つずつ子プロセスで呼び出して結果を表示する。失敗したものがあれば終了コード1で終わる
+fn run_tests(
+    module: &LLVMModule,
+    tests: &[(String, String)],
+    libraries: &[String],
+    target_platform: TargetPlatform,
+    optimization_level: OptimizationLevel,
+) {
+    if let Err(err) = linker::load_libraries_for_jit(libraries, target_platform) {
+        println!("error: {}", err);
+        std::process::exit(1);
+    }
+    let execution_engine = &module
+        .create_jit_execution_engine(optimization_level)
+        .unwrap();
+    println!("running {} tests", tests.len());
+    let mut failed = 0;
+    for (name, symbol) in tests {
+        let test_fn = unsafe {
+            execution_engine
+                .get_function::<unsafe extern "C" fn() -> u8>(symbol)
+                .unwrap()
+        };
+        match run_test(|| unsafe { test_fn.call() }) {
+            Ok(true) => println!("test {} ... ok", name),
+            Ok(false) => {
+                println!("test {} ... FAILED", name);
+                failed += 1;
+            }
+            Err(reason) => {
+                println!("test {} ... FAILED ({})", name, reason);
+                failed += 1;
+            }
+        }
+    }
+    println!();
+    println!(
+        "test result: {}. {} passed; {} failed",
+        if failed == 0 { "ok" } else { "FAILED" },
+        tests.len() - failed,
+        failed
+    );
+    if failed != 0 {
+        std::process::exit(1);
+    }
+}
+
+// テストを子プロセスで動かし、返した値をパイプで受け取る。
+// テストが exit や abort を呼んだり不正なメモリアクセスで止まったりしても、残りのテストを続けられる。
+// 値を受け取れなかったときは、子プロセスがどう終わったかを返す
+#[cfg(unix)]
+fn run_test(test_fn: impl FnOnce() -> u8) -> Result<bool, String> {
+    use std::io::{Read, Write};
+
+    let (mut reader, mut writer) = std::io::pipe().map_err(|err| err.to_string())?;
+    // 書き出していない出力が子プロセスにも複製されないよう、forkの前に書き出す
+    std::io::stdout().flush().unwrap();
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    if pid == 0 {
+        // bool は i1 で返るので、最下位のビットだけを見る
+        let result = test_fn() & 1;
+        let _ = writer.write_all(&[result]);
+        // 親プロセスから受け継いだ終了処理は動かさず、テストが書いた出力だけを書き出して終わる
+        unsafe {
+            libc::fflush(std::ptr::null_mut());
+            libc::_exit(0);
+        }
+    }
+    drop(writer);
+
+    let mut status = 0;
+    if unsafe { libc::waitpid(pid, &mut status, 0) } < 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    let mut result = [0u8];
+    if libc::WIFEXITED(status)
+        && libc::WEXITSTATUS(status) == 0
+        && reader.read(&mut result).ok() == Some(1)
+    {
+        Ok(result[0] == 1)
+    } else if libc::WIFSIGNALED(status) {
+        Err(format!("killed by signal {}", libc::WTERMSIG(status)))
+    } else {
+        Err(format!("exited with code {}", libc::WEXITSTATUS(status)))
+    }
+}
+
+// forkできない環境では、同じプロセスで順に呼び出す
+#[cfg(not(unix))]
+fn run_test(test_fn: impl FnOnce() -> u8) -> Result<bool, String> {
+    Ok(test_fn() & 1 == 1)
+}
+
+// `hirou build` / `hirou run` / `hirou test`。失敗したら終了コード1で終わる
+fn build_package(command: PackageCommand, args: &PackageArgs) {
+    let Some((graph, sources, module)) = load_package(args.manifest_path.as_deref()) else {
+        std::process::exit(1);
+    };
+    let root = graph.root();
+    let profile_name = if args.release {
+        "release"
+    } else {
+        args.profile.as_str()
+    };
+    let host = TargetPlatform::current().unwrap_or(TargetPlatform::DarwinArm64);
+    let Some(profile) = report(root.manifest.profile(profile_name, host)) else {
+        std::process::exit(1);
+    };
+    // JITで実行できるのはホスト向けのコードだけ
+    if command != PackageCommand::Build && TargetPlatform::current() != Ok(profile.target) {
+        println!(
+            "error: code built for `{}` cannot be run on this machine",
+            profile.target.name()
+        );
+        std::process::exit(1);
+    }
+    let is_library = root.entry().is_none();
+    if command == PackageCommand::Run && is_library {
+        println!("error: package `{}` has no entry to run", root.name());
+        std::process::exit(1);
+    }
+
+    let mut resolver_context = package_resolver_context(&sources, profile.target, profile.strict);
+    // テストとそれ以外では起点になる関数が違うので、互いの結果を捨て合わないように別のファイルにする
+    let cache_name = match command {
+        PackageCommand::Test => format!("{}-test.json", root.name()),
+        _ => format!("{}.json", root.name()),
+    };
+    let cache_path = root
+        .root
+        .join("target")
+        .join(profile_name)
+        .join("incremental")
+        .join(cache_name);
+    load_cache(&mut resolver_context, &cache_path);
+    let resolved_module = match command {
+        PackageCommand::Test => resolver::resolve_tests(&resolver_context, &module),
+        _ if is_library => resolver::resolve_library(&resolver_context, &module),
+        _ => resolver::resolve_module(&resolver_context, &module, true),
+    };
+    let resolved_module = match resolved_module {
+        Ok(module) => module,
+        Err(err) => {
+            print_faital_error(&err.0, &sources);
+            std::process::exit(1);
+        }
+    };
+    if !resolver_context.errors.borrow().is_empty() {
+        print_errors(&resolver_context, &sources);
+        std::process::exit(1);
+    }
+    // 依存パッケージのテストは実行しない
+    let tests = module
+        .toplevels
+        .iter()
+        .filter_map(|toplevel| match &toplevel.value {
+            ast::TopLevel::Function(function)
+                if function.decl.is_test()
+                    && !resolver_context
+                        .namespace_by_file
+                        .contains_key(&function.file) =>
+            {
+                Some((
+                    function.decl.name.clone(),
+                    resolver::mangle::function_symbol(&function.decl, &[]),
+                ))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let libraries = module.link_libraries();
+    let library_metadata = (command == PackageCommand::Build && is_library).then(|| {
+        let source_path = sources
+            .iter()
+            .find(|source_file| source_file.namespace.is_none())
+            .map_or(root.root.as_path(), |source_file| {
+                source_file.path.as_path()
+            });
+        metadata::LibraryMetadata::new(&module, source_path)
+    });
+
+    let llvm_context: LLVMContext = LLVMContext::create();
+    let optimization_level = optimization_level(profile.opt_level);
+    let concrete_module = concretizer::concretize_module(
+        resolved_module,
+        PointerSizedIntWidth::from(profile.target),
+        resolver_context.cache.as_ref(),
+    );
+    save_cache(&resolver_context, &cache_path);
+    let module = generate_module(
+        &llvm_context,
+        &concrete_module,
+        &resolver_context,
+        &sources,
+        profile.target,
+        optimization_level,
+        profile.debug,
+    );
+    if let Err(err) = linker::optimize_module(&module, profile.target, optimization_level) {
+        println!("error: {}", err);
+        std::process::exit(1);
+    }
+
+    match command {
+        PackageCommand::Build => {
+            let output_dir = root.root.join("target").join(profile_name);
+            if let Err(err) = std::fs::create_dir_all(&output_dir) {
+                println!(
+                    "error: could not create `{}`: {}",
+                    output_dir.display(),
+                    err
+                );
+                std::process::exit(1);
+            }
+            let result = match library_metadata {
+                Some(library_metadata) => {
+                    let output = output_dir.join(format!("lib{}.a", root.name()));
+                    linker::build_library(&module, &output, profile.target, optimization_level)
+                        .and_then(|()| library_metadata.write(&output.with_extension("hri")))
+                }
+                None => linker::build_executable(
+                    &module,
+                    &output_dir.join(root.name()),
+                    &libraries,
+                    profile.target,
+                    optimization_level,
+                ),
+            };
+            if report(result).is_none() {
+                std::process::exit(1);
+            }
+        }
+        PackageCommand::Run => {
+            run_main(&module, &libraries, profile.target, optimization_level);
+        }
+        PackageCommand::Test => {
+            run_tests(
+                &module,
+                &tests,
+                &libraries,
+                profile.target,
+                optimization_level,
+            );
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    match &args.command {
+        Some(Command::Demangle { symbols }) => {
+            demangle(symbols);
             return;
         }
+        Some(Command::Check {
+            target: Some(target),
+            strict,
+            ..
+        }) => {
+            check(target, *strict);
+            return;
+        }
+        Some(Command::Check {
+            target: None,
+            strict,
+            manifest_path,
+        }) => {
+            check_package(manifest_path.as_deref(), *strict);
+            return;
+        }
+        Some(Command::Build(package_args)) => {
+            build_package(PackageCommand::Build, package_args);
+            return;
+        }
+        Some(Command::Run(package_args)) => {
+            build_package(PackageCommand::Run, package_args);
+            return;
+        }
+        Some(Command::Test(package_args)) => {
+            build_package(PackageCommand::Test, package_args);
+            return;
+        }
+        None => {}
+    }
+    // サブコマンドがなければ、clap が target を必須にしている
+    let target = args.target.as_deref().unwrap();
+    let path = Path::new(target);
+    let input = read_to_string(path).unwrap();
+    let Some(module) = parse(input.as_str().into()) else {
+        return;
     };
     if args.parse {
         dbg!(module);
         return;
     }
+    let sources = [SourceFile::single(path, input)];
 
     let llvm_context: LLVMContext = LLVMContext::create();
-    let target_platform = TargetPlatform::DarwinArm64;
-    let resolver_context = ResolverContext::new(PointerSizedIntWidth::from(target_platform));
-    let resolved_module = match resolver::resolve_module(&resolver_context, &module, true) {
+    let target_platform = TargetPlatform::current().unwrap_or(TargetPlatform::DarwinArm64);
+    let mut resolver_context = ResolverContext::new(PointerSizedIntWidth::from(target_platform));
+    resolver_context.strict_conversions = args.strict;
+    for extern_library in &args.extern_libraries {
+        let metadata_path = Path::new(extern_library).with_extension("hri");
+        match metadata::LibraryMetadata::read(&metadata_path) {
+            Ok(library) => resolver_context.libraries.push(library),
+            Err(err) => {
+                println!("error: {}", err);
+                return;
+            }
+        }
+    }
+    let cache_path = args.incremental.as_ref().map(|dir| {
+        Path::new(dir).join(format!(
+            "{}.json",
+            path.file_stem().unwrap().to_string_lossy()
+        ))
+    });
+    if let Some(cache_path) = &cache_path {
+        load_cache(&mut resolver_context, cache_path);
+    }
+    let resolved_module = match args.crate_type {
+        CrateType::Bin => resolver::resolve_module(&resolver_context, &module, true),
+        CrateType::Lib => resolver::resolve_library(&resolver_context, &module),
+    };
+    let resolved_module = match resolved_module {
         Ok(module) => module,
         Err(err) => {
-            dbg!(err);
+            print_faital_error(&err.0, &sources);
             return;
         }
     };
     if !resolver_context.errors.borrow().is_empty() {
-        let absolute_path = path.canonicalize().unwrap();
-        let current_dir = std::env::current_dir().unwrap();
-        let relative_path = absolute_path.strip_prefix(current_dir).unwrap();
-        let mut stdout = std::io::stdout();
-        for error in resolver_context.errors.borrow().iter() {
-            error
-                .fmt_with_source(
-                    &mut stdout,
-                    relative_path.to_str().unwrap(),
-                    input.fragment(),
-                )
-                .unwrap();
+        print_errors(&resolver_context, &sources);
+        return;
+    }
+    if let Some(Emit::CHeader) = args.emit {
+        let header_path = Path::new(args.output.as_deref().unwrap_or(target)).with_extension("h");
+        let guard = header_path.file_name().unwrap().to_str().unwrap();
+        let header = c_header::generate_c_header(&resolved_module, guard);
+        std::fs::write(&header_path, header).unwrap();
+        if let Some(cache_path) = &cache_path {
+            save_cache(&resolver_context, cache_path);
         }
         return;
     }
+    let library_metadata =
+        (args.crate_type == CrateType::Lib).then(|| metadata::LibraryMetadata::new(&module, path));
+    let mut libraries = module.link_libraries();
+    // ライブラリの extern 関数が使うライブラリも、利用する側でリンクする
+    let extern_link_libraries = resolver_context
+        .libraries
+        .iter()
+        .flat_map(|library| &library.link_libraries);
+    for library in args
+        .link
+        .iter()
+        .chain(&args.extern_libraries)
+        .chain(extern_link_libraries)
+    {
+        if !libraries.contains(library) {
+            libraries.push(library.clone());
+        }
+    }
     let ptr_sized_int_type = PointerSizedIntWidth::from(target_platform);
-    let concrete_module = concretizer::concretize_module(resolved_module, ptr_sized_int_type);
-    let mut llvm_codegenerator = builder::LLVMCodeGenerator::new(
+    let concrete_module = concretizer::concretize_module(
+        resolved_module,
+        ptr_sized_int_type,
+        resolver_context.cache.as_ref(),
+    );
+    if let Some(cache_path) = &cache_path {
+        save_cache(&resolver_context, cache_path);
+    }
+    let module = generate_module(
         &llvm_context,
+        &concrete_module,
+        &resolver_context,
+        &sources,
         target_platform,
         OptimizationLevel::None,
-        &concrete_module,
+        args.debug,
     );
-    llvm_codegenerator.gen_module(&concrete_module);
-    let module = llvm_codegenerator.get_module();
+    if let Some(Emit::LlvmIr) = args.emit {
+        let ir_path = Path::new(args.output.as_deref().unwrap_or(target)).with_extension("ll");
+        if let Err(err) = module.print_to_file(&ir_path) {
+            println!("error: {}", err);
+        }
+        return;
+    }
 
-    module.print_to_file(Path::new("out.ll")).unwrap();
-    let execution_engine = &module
-        .create_jit_execution_engine(inkwell::OptimizationLevel::None)
-        .unwrap();
-    unsafe {
-        execution_engine
-            .get_function::<unsafe extern "C" fn()>("main")
-            .unwrap()
-            .call();
+    if let Some(library_metadata) = library_metadata {
+        // 出力先が指定されていなければ、`foo.hr` の隣に `libfoo.a` を作る
+        let output = match &args.output {
+            Some(output) => PathBuf::from(output),
+            None => path.with_file_name(format!(
+                "lib{}.a",
+                path.file_stem().unwrap().to_string_lossy()
+            )),
+        };
+        if let Err(err) =
+            linker::build_library(&module, &output, target_platform, OptimizationLevel::None)
+        {
+            println!("error: {}", err);
+            return;
+        }
+        if let Err(err) = library_metadata.write(&output.with_extension("hri")) {
+            println!("error: {}", err);
+        }
+        return;
     }
+    if let Some(output) = &args.output {
+        if let Err(err) = linker::build_executable(
+            &module,
+            Path::new(output),
+            &libraries,
+            target_platform,
+            OptimizationLevel::None,
+        ) {
+            println!("error: {}", err);
+        }
+        return;
+    }
+    run_main(
+        &module,
+        &libraries,
+        target_platform,
+        OptimizationLevel::None,
+    );
 }
@@ -0,0 +1,154 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{
+    Function, FunctionDecl, Implementation, ImplementationDecl, Interface, Module, TopLevel,
+    TypeDef,
+};
+
+// `--crate-type lib` でライブラリと一緒に書き出し、`--extern` で読み込む。
+// 利用する側はライブラリのソースをパースせずに、これだけで関数を呼び出し、ジェネリックな関数をインスタンス化できる
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibraryMetadata {
+    // ライブラリのソースファイル。インスタンス化した関数のデバッグ情報に使う
+    pub source_path: String,
+    // ジェネリックでない関数。本体はライブラリのオブジェクトファイルにある。
+    // `pub` でないものは、残したジェネリックな関数から呼び出すためだけに含める
+    pub function_decls: Vec<FunctionDecl>,
+    // ジェネリックな関数は、利用する側でインスタンス化するので本体ごと残す
+    pub generic_functions: Vec<Function>,
+    pub type_defs: Vec<TypeDef>,
+    pub interfaces: Vec<Interface>,
+    // ジェネリックでない実装。本体はライブラリのオブジェクトファイルにある
+    pub implementation_decls: Vec<ImplementationDecl>,
+    pub generic_implementations: Vec<Implementation>,
+    // ライブラリの extern 関数のために、利用する側でもリンクするライブラリ
+    pub link_libraries: Vec<String>,
+}
+
+impl LibraryMetadata {
+    pub fn new(module: &Module, source_path: &Path) -> Self {
+        let mut metadata = LibraryMetadata {
+            source_path: source_path.to_string_lossy().into_owned(),
+            function_decls: Vec::new(),
+            generic_functions: Vec::new(),
+            type_defs: Vec::new(),
+            interfaces: Vec::new(),
+            implementation_decls: Vec::new(),
+            generic_implementations: Vec::new(),
+            link_libraries: module.link_libraries(),
+        };
+        for toplevel in &module.toplevels {
+            match &toplevel.value {
+                // 非公開のジェネリック関数も、公開したジェネリック関数から呼ばれることがあるので残す
+                TopLevel::Function(function) if function.decl.generic_args.is_some() => {
+                    metadata.generic_functions.push(function.clone());
+                }
+                TopLevel::Function(function) => {
+                    metadata.function_decls.push(function.decl.clone());
+                }
+                TopLevel::Implemantation(implementation)
+                    if implementation.decl.generic_args.is_some() =>
+                {
+                    metadata
+                        .generic_implementations
+                        .push(implementation.clone());
+                }
+                TopLevel::Implemantation(implementation) => {
+                    metadata
+                        .implementation_decls
+                        .push(implementation.decl.clone());
+                }
+                TopLevel::TypeDef(typedef) => metadata.type_defs.push(typedef.clone()),
+                TopLevel::Interface(interface) => metadata.interfaces.push(interface.clone()),
+                TopLevel::Use(_) => {}
+            }
+        }
+        metadata
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|err| err.to_string())?;
+        std::fs::write(path, json)
+            .map_err(|err| format!("could not write `{}`: {}", path.display(), err))
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|err| format!("could not read `{}`: {}", path.display(), err))?;
+        serde_json::from_str(&json)
+            .map_err(|err| format!("invalid library metadata `{}`: {}", path.display(), err))
+    }
+}
+
+#[test]
+fn test_library_metadata_roundtrip() {
+    use crate::parser::parse_module;
+    use nom::Finish;
+
+    let source = r#"
+interface ->bool(self) : bool
+
+impl ->bool for i32 (self) {
+  (> self 0)
+}
+
+struct Pair<T> {
+  first: T,
+  second: T,
+}
+
+impl ->bool<T: ->bool> for Pair<T> (self) {
+  (and (->bool self.first) (->bool self.second))
+}
+
+fn swap<T>(p: Pair<T>): Pair<T> {
+  Pair<T> { first: p.second, second: p.first }
+}
+
+pub fn both<T: ->bool>(a: T, b: T): bool {
+  (->bool Pair<T> { first: a, second: b })
+}
+
+fn helper(): i32 {
+  42
+}
+
+pub fn answer(): i32 {
+  (helper)
+}
+
+#[link("m")]
+extern "C" fn sqrt(x: f64): f64;
+"#;
+    let (_, module) = parse_module(source.into()).finish().unwrap();
+    let metadata = LibraryMetadata::new(&module, Path::new("pair.hr"));
+
+    // 非公開の関数も、公開されているかどうかと一緒に含める
+    let function_decls = metadata
+        .function_decls
+        .iter()
+        .map(|decl| (decl.name.as_str(), decl.is_public))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        function_decls,
+        vec![("helper", false), ("answer", true), ("sqrt", false)]
+    );
+    let generic_functions = metadata
+        .generic_functions
+        .iter()
+        .map(|function| function.decl.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(generic_functions, vec!["swap", "both"]);
+    assert_eq!(metadata.implementation_decls.len(), 1);
+    assert_eq!(metadata.generic_implementations.len(), 1);
+    assert_eq!(metadata.link_libraries, vec!["m".to_string()]);
+
+    // 書き出したものを読み込むと、本体も含めて元に戻る
+    let path = std::env::temp_dir().join(format!("hirou-metadata-{}.hri", std::process::id()));
+    metadata.write(&path).unwrap();
+    let loaded = LibraryMetadata::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, metadata);
+}
@@ -0,0 +1,539 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    ast::{Attribute, FileId, Module, TopLevel},
+    common::target::TargetPlatform,
+};
+
+pub const MANIFEST_FILE_NAME: &str = "hirou.toml";
+
+// パッケージのルートに置く `hirou.toml`
+//
+// [package]
+// name = "app"
+// entry = "src/main.hr"   # mainを持つファイル。省略するとライブラリのパッケージになる
+// sources = ["src"]       # `.hr` ファイルを探すディレクトリ
+//
+// [dependencies]
+// util = { path = "../util" }
+//
+// [profile.release]
+// opt-level = 3
+// target = "linux-amd64"
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub package: PackageSection,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+    #[serde(default)]
+    pub profile: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackageSection {
+    pub name: String,
+    pub entry: Option<String>,
+    #[serde(default = "default_sources")]
+    pub sources: Vec<String>,
+}
+
+fn default_sources() -> Vec<String> {
+    vec!["src".to_string()]
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Dependency {
+    // マニフェストのあるディレクトリからの相対パス
+    pub path: String,
+}
+
+// `[profile.<name>]` に書く設定。書かなかった項目は `dev` の値になる
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+    pub opt_level: Option<u8>,
+    pub debug: Option<bool>,
+    pub strict: Option<bool>,
+    pub target: Option<String>,
+}
+
+// 組み込みの設定とマニフェストの設定を重ねた、ビルドに使う設定
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuildProfile {
+    // 0から3。LLVMの最適化パスの強さ
+    pub opt_level: u8,
+    // DWARFのデバッグ情報を付けるかどうか
+    pub debug: bool,
+    // 値が変わり得る暗黙の整数変換をエラーにするかどうか
+    pub strict: bool,
+    pub target: TargetPlatform,
+}
+
+impl Manifest {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("could not read `{}`: {}", path.display(), err))?;
+        toml::from_str(&text)
+            .map_err(|err| format!("invalid manifest `{}`: {}", path.display(), err))
+    }
+
+    // `dev` と `release` はマニフェストに書かなくても使える。ターゲットを省略するとホストになる
+    pub fn profile(&self, name: &str, host: TargetPlatform) -> Result<BuildProfile, String> {
+        let mut profile = BuildProfile {
+            opt_level: 0,
+            debug: true,
+            strict: false,
+            target: host,
+        };
+        match name {
+            "dev" => {}
+            "release" => {
+                profile.opt_level = 3;
+                profile.debug = false;
+            }
+            _ if self.profile.contains_key(name) => {}
+            _ => return Err(format!("profile `{}` is not defined", name)),
+        }
+        let Some(overrides) = self.profile.get(name) else {
+            return Ok(profile);
+        };
+        if let Some(opt_level) = overrides.opt_level {
+            if opt_level > 3 {
+                return Err(format!(
+                    "`opt-level` of profile `{}` must be between 0 and 3",
+                    name
+                ));
+            }
+            profile.opt_level = opt_level;
+        }
+        if let Some(debug) = overrides.debug {
+            profile.debug = debug;
+        }
+        if let Some(strict) = overrides.strict {
+            profile.strict = strict;
+        }
+        if let Some(target) = &overrides.target {
+            profile.target = TargetPlatform::from_name(target)
+                .ok_or_else(|| format!("unknown target `{}` in profile `{}`", target, name))?;
+        }
+        Ok(profile)
+    }
+}
+
+// カレントディレクトリから親へ向かって `hirou.toml` を探す
+pub fn find_manifest(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(MANIFEST_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+#[derive(Debug)]
+pub struct Package {
+    pub manifest: Manifest,
+    // マニフェストがあるディレクトリ
+    pub root: PathBuf,
+}
+
+impl Package {
+    pub fn name(&self) -> &str {
+        &self.manifest.package.name
+    }
+
+    pub fn entry(&self) -> Option<PathBuf> {
+        let entry = self.manifest.package.entry.as_ref()?;
+        Some(self.root.join(entry))
+    }
+
+    // ソースのディレクトリにある `.hr` ファイルを名前順に返す。エントリーは先頭にする
+    pub fn source_files(&self) -> Result<Vec<PathBuf>, String> {
+        let mut files = Vec::new();
+        for source in &self.manifest.package.sources {
+            let dir = self.root.join(source);
+            if !dir.is_dir() {
+                return Err(format!(
+                    "source directory `{}` of package `{}` is not found",
+                    dir.display(),
+                    self.name()
+                ));
+            }
+            collect_source_files(&dir, &mut files)?;
+        }
+        if let Some(entry) = self.entry() {
+            let entry = entry
+                .canonicalize()
+                .map_err(|err| format!("could not read entry `{}`: {}", entry.display(), err))?;
+            files.retain(|file| *file != entry);
+            files.insert(0, entry);
+        }
+        Ok(files)
+    }
+}
+
+fn collect_source_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|err| format!("could not read `{}`: {}", dir.display(), err))?;
+    let mut paths = entries
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("could not read `{}`: {}", dir.display(), err))?;
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            collect_source_files(&path, files)?;
+        } else if path.extension().is_some_and(|extension| extension == "hr") {
+            let path = path
+                .canonicalize()
+                .map_err(|err| format!("could not read `{}`: {}", path.display(), err))?;
+            // ソースのディレクトリが重なっていても、同じファイルを二度読まない
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+// パッケージのソースファイル。依存パッケージのものは、そのパッケージの名前を namespace に持つ
+#[derive(Debug)]
+pub struct SourceFile {
+    pub file: FileId,
+    pub path: PathBuf,
+    pub source: String,
+    pub package: String,
+    pub namespace: Option<String>,
+}
+
+impl SourceFile {
+    // パッケージを使わずに `hirou file.hr` で渡されたファイル
+    pub fn single(path: &Path, source: String) -> Self {
+        SourceFile {
+            file: FileId::MAIN,
+            path: path.to_path_buf(),
+            source,
+            package: String::new(),
+            namespace: None,
+        }
+    }
+}
+
+// ルートのパッケージと、そこから辿れる依存パッケージ。依存されるパッケージほど前に並び、ルートは末尾にある
+#[derive(Debug)]
+pub struct PackageGraph {
+    pub packages: Vec<Package>,
+}
+
+impl PackageGraph {
+    pub fn load(manifest_path: &Path) -> Result<Self, String> {
+        let root = manifest_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .canonicalize()
+            .map_err(|err| format!("could not read `{}`: {}", manifest_path.display(), err))?;
+        let mut packages = Vec::new();
+        load_package(root, &mut Vec::new(), &mut packages)?;
+        Ok(PackageGraph { packages })
+    }
+
+    pub fn root(&self) -> &Package {
+        self.packages.last().unwrap()
+    }
+
+    // 全てのパッケージのソースファイルを読み込む。ルートのパッケージのエントリーを MAIN にする
+    pub fn read_sources(&self) -> Result<Vec<SourceFile>, String> {
+        let root_entry = self
+            .root()
+            .entry()
+            .and_then(|entry| entry.canonicalize().ok());
+        let mut sources = Vec::new();
+        for package in &self.packages {
+            let is_root = std::ptr::eq(package, self.root());
+            for path in package.source_files()? {
+                let source = std::fs::read_to_string(&path)
+                    .map_err(|err| format!("could not read `{}`: {}", path.display(), err))?;
+                let file = if is_root && root_entry.as_ref() == Some(&path) {
+                    FileId::MAIN
+                } else {
+                    FileId::package_source(sources.len())
+                };
+                sources.push(SourceFile {
+                    file,
+                    path,
+                    source,
+                    package: package.name().to_string(),
+                    namespace: (!is_root).then(|| package.name().to_string()),
+                });
+            }
+        }
+        Ok(sources)
+    }
+
+    // 各ファイルのモジュールを一つにまとめる。依存パッケージの関数には `util::add` のようにパッケージの名前を付け、
+    // ほかのパッケージからは名前を付けて、同じパッケージの中からは名前だけで呼べるようにする。
+    // extern 関数はCの名前のままリンクするので、名前を変えない
+    pub fn merge_modules(&self, modules: Vec<(&SourceFile, Module)>) -> Result<Module, String> {
+        let mut toplevels = Vec::new();
+        for (source_file, module) in modules {
+            for mut toplevel in module.toplevels {
+                match &mut toplevel.value {
+                    TopLevel::Function(function) => {
+                        function.file = source_file.file;
+                        if let Some(namespace) = &source_file.namespace {
+                            if !function.decl.is_extern {
+                                // 名前を省略した `#[export]` は、パッケージの名前を付ける前の名前で公開する
+                                for attribute in &mut function.decl.attributes {
+                                    if attribute.value == Attribute::Export(None) {
+                                        attribute.value =
+                                            Attribute::Export(Some(function.decl.name.clone()));
+                                    }
+                                }
+                                function.decl.name =
+                                    format!("{}::{}", namespace, function.decl.name);
+                            }
+                        }
+                    }
+                    TopLevel::Implemantation(implementation) => {
+                        implementation.file = source_file.file;
+                    }
+                    TopLevel::Use(use_stmt) => {
+                        use_stmt.file = source_file.file;
+                        self.check_use(source_file, &use_stmt.path.segments[0])?;
+                    }
+                    TopLevel::TypeDef(_) | TopLevel::Interface(_) => {}
+                }
+                toplevels.push(toplevel);
+            }
+        }
+        Ok(Module { toplevels })
+    }
+
+    // `use` でほかのパッケージを参照できるのは、`[dependencies]` に書いたときだけ
+    fn check_use(&self, source_file: &SourceFile, first_segment: &str) -> Result<(), String> {
+        let Some(package) = self
+            .packages
+            .iter()
+            .find(|package| package.name() == source_file.package)
+        else {
+            return Ok(());
+        };
+        let refers_to_package = self
+            .packages
+            .iter()
+            .any(|other| other.name() == first_segment);
+        if refers_to_package
+            && first_segment != package.name()
+            && !package.manifest.dependencies.contains_key(first_segment)
+        {
+            return Err(format!(
+                "`{}`: package `{}` is not a dependency of `{}`",
+                source_file.path.display(),
+                first_segment,
+                package.name()
+            ));
+        }
+        Ok(())
+    }
+}
+
+// 依存パッケージを先に読み込んでから、パッケージを末尾に加える。
+// stack は読み込み中のパッケージで、ここに戻ってくる依存は循環している
+fn load_package(
+    root: PathBuf,
+    stack: &mut Vec<(PathBuf, String)>,
+    packages: &mut Vec<Package>,
+) -> Result<(), String> {
+    if packages.iter().any(|package| package.root == root) {
+        return Ok(());
+    }
+    let manifest = Manifest::read(&root.join(MANIFEST_FILE_NAME))?;
+    if let Some(position) = stack.iter().position(|(path, _)| *path == root) {
+        let cycle = stack[position..]
+            .iter()
+            .map(|(_, name)| format!("`{}`", name))
+            .chain(std::iter::once(format!("`{}`", manifest.package.name)))
+            .collect::<Vec<_>>();
+        return Err(format!("cyclic package dependency: {}", cycle.join(" -> ")));
+    }
+
+    stack.push((root.clone(), manifest.package.name.clone()));
+    for (name, dependency) in &manifest.dependencies {
+        let dependency_root = root.join(&dependency.path).canonicalize().map_err(|err| {
+            format!(
+                "could not find dependency `{}` of `{}` at `{}`: {}",
+                name, manifest.package.name, dependency.path, err
+            )
+        })?;
+        load_package(dependency_root.clone(), stack, packages)?;
+        let dependency_package = packages
+            .iter()
+            .find(|package| package.root == dependency_root)
+            .unwrap();
+        // 依存パッケージの関数はパッケージの名前で呼ぶので、別の名前を付けることはできない
+        if dependency_package.name() != name {
+            return Err(format!(
+                "dependency `{}` of `{}` is a package named `{}`",
+                name,
+                manifest.package.name,
+                dependency_package.name()
+            ));
+        }
+    }
+    stack.pop();
+
+    if packages
+        .iter()
+        .any(|package| package.name() == manifest.package.name)
+    {
+        return Err(format!(
+            "two different packages are named `{}`",
+            manifest.package.name
+        ));
+    }
+    packages.push(Package { manifest, root });
+    Ok(())
+}
+
+#[test]
+fn test_manifest_profiles() {
+    let manifest: Manifest = toml::from_str(
+        r#"
+[package]
+name = "app"
+entry = "src/main.hr"
+
+[dependencies]
+util = { path = "../util" }
+
+[profile.release]
+target = "linux-arm64"
+
+[profile.bench]
+opt-level = 2
+debug = false
+"#,
+    )
+    .unwrap();
+    assert_eq!(manifest.package.sources, vec!["src".to_string()]);
+    assert_eq!(manifest.dependencies["util"].path, "../util");
+
+    let host = TargetPlatform::LinuxAmd64;
+    assert_eq!(
+        manifest.profile("dev", host),
+        Ok(BuildProfile {
+            opt_level: 0,
+            debug: true,
+            strict: false,
+            target: host,
+        })
+    );
+    // 組み込みの設定に、マニフェストの設定を重ねる
+    let release = manifest.profile("release", host).unwrap();
+    assert_eq!(release.opt_level, 3);
+    assert_eq!(release.target, TargetPlatform::LinuxArm64);
+    let bench = manifest.profile("bench", host).unwrap();
+    assert_eq!((bench.opt_level, bench.debug), (2, false));
+    assert!(manifest.profile("fast", host).is_err());
+
+    assert!(toml::from_str::<Manifest>("[package]\nname = \"app\"\nentyr = \"main.hr\"").is_err());
+}
+
+#[test]
+fn test_package_graph_resolves_namespaced_functions() {
+    use crate::{parser::parse_module, resolver};
+    use nom::Finish;
+
+    let dir = std::env::temp_dir().join(format!("hirou-package-{}", std::process::id()));
+    let write = |path: &str, content: &str| {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+    write(
+        "app/hirou.toml",
+        "[package]\nname = \"app\"\nentry = \"src/main.hr\"\n\n[dependencies]\nutil = { path = \"../util\" }\n",
+    );
+    write(
+        "app/src/main.hr",
+        "use util::*\n\nfn main(): i32 {\n  (+ (double 2) (util::triple 1))\n}\n",
+    );
+    write("util/hirou.toml", "[package]\nname = \"util\"\n");
+    // 同じパッケージの関数は名前だけで呼べる
+    write(
+        "util/src/lib.hr",
+        "pub fn double(x: i32): i32 {\n  (add x x)\n}\n\npub fn triple(x: i32): i32 {\n  (add x (double x))\n}\n",
+    );
+    write(
+        "util/src/math.hr",
+        "fn add(a: i32, b: i32): i32 {\n  (+ a b)\n}\n",
+    );
+
+    let graph = PackageGraph::load(&dir.join("app/hirou.toml")).unwrap();
+    let names = graph
+        .packages
+        .iter()
+        .map(|package| package.name())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["util", "app"]);
+    let sources = graph.read_sources().unwrap();
+    assert_eq!(sources.len(), 3);
+    assert_eq!(sources[2].file, FileId::MAIN);
+
+    let modules = sources
+        .iter()
+        .map(|source_file| {
+            let (_, module) = parse_module(source_file.source.as_str().into())
+                .finish()
+                .unwrap();
+            (source_file, module)
+        })
+        .collect();
+    let module = graph.merge_modules(modules).unwrap();
+
+    let mut context =
+        resolver::ResolverContext::new(crate::common::target::PointerSizedIntWidth::SixtyFour);
+    for source_file in &sources {
+        if let Some(namespace) = &source_file.namespace {
+            context
+                .namespace_by_file
+                .insert(source_file.file, namespace.clone());
+        }
+    }
+    let resolved = resolver::resolve_module(&context, &module, true).unwrap();
+    assert!(
+        context.errors.borrow().is_empty(),
+        "{:?}",
+        context.errors.borrow()
+    );
+    let mut functions = resolved
+        .toplevels
+        .iter()
+        .map(|toplevel| {
+            let crate::resolved_ast::TopLevel::Function(function) = toplevel;
+            resolver::mangle::demangle(&function.decl.name)
+                .unwrap_or_else(|| function.decl.name.clone())
+        })
+        .collect::<Vec<_>>();
+    functions.sort();
+    assert_eq!(
+        functions,
+        vec!["main", "util::add", "util::double", "util::triple"]
+    );
+
+    // 依存パッケージが自分に依存していると循環する
+    write(
+        "util/hirou.toml",
+        "[package]\nname = \"util\"\n\n[dependencies]\napp = { path = \"../app\" }\n",
+    );
+    let err = PackageGraph::load(&dir.join("app/hirou.toml")).unwrap_err();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(err, "cyclic package dependency: `app` -> `util` -> `app`");
+}
@@ -2,7 +2,7 @@ use nom::{
     branch::alt,
     bytes::complete::{tag, take},
     character::complete::none_of,
-    combinator::{cut, opt},
+    combinator::{cut, opt, peek, verify},
     error::context,
     multi::many0,
     sequence::{pair, preceded, terminated, tuple},
@@ -11,6 +11,7 @@ use nom::{
 use crate::ast::*;
 
 use super::{
+    statement::parse_statement,
     token::*,
     ty::{parse_generic_arguments, parse_type},
     util::*,
@@ -19,34 +20,51 @@ use super::{
 
 fn parse_number_literal(input: Span) -> NotLocatedParseResult<Expression> {
     // Parse integer or float literal
-    // Supports: 123, 123.456, .456
+    // Supports: 123, 123.456, .456, 1_000, 0xff, 0b1010, 0o17 and suffixes like 10u8, 2.5f32
+    // 値の解釈と接尾辞の検査は resolver で行う
     let (rest, _) = skip0(input)?;
     let mut take_count: usize = 0;
     let mut has_dot = false;
     let chars: Vec<char> = rest.fragment().chars().collect();
 
-    while take_count < chars.len() {
-        let c = chars[take_count];
-        match c {
-            '0'..='9' => take_count += 1,
-            '.' => {
-                // Check if this is a float or field access
-                if has_dot {
-                    break; // Already have a dot, stop
-                }
-                // Check next char is a digit (to distinguish from field access like "1.field")
-                if take_count + 1 < chars.len() && chars[take_count + 1].is_ascii_digit() {
-                    has_dot = true;
-                    take_count += 1;
-                } else if take_count == 0 {
-                    // .456 style
-                    has_dot = true;
-                    take_count += 1;
-                } else {
-                    break;
+    let has_radix_prefix =
+        chars.len() >= 2 && chars[0] == '0' && matches!(chars[1], 'x' | 'b' | 'o');
+    if has_radix_prefix {
+        take_count = 2;
+    } else {
+        while take_count < chars.len() {
+            let c = chars[take_count];
+            match c {
+                '0'..='9' => take_count += 1,
+                // `_` は桁の区切りとしてのみ使える (`_foo` は識別子)
+                '_' if take_count > 0 && chars[take_count - 1] != '.' => take_count += 1,
+                '.' => {
+                    // Check if this is a float or field access
+                    if has_dot {
+                        break; // Already have a dot, stop
+                    }
+                    // Check next char is a digit (to distinguish from field access like "1.field")
+                    if take_count + 1 < chars.len() && chars[take_count + 1].is_ascii_digit() {
+                        has_dot = true;
+                        take_count += 1;
+                    } else if take_count == 0 {
+                        // .456 style
+                        has_dot = true;
+                        take_count += 1;
+                    } else {
+                        break;
+                    }
                 }
+                _ => break,
             }
-            _ => break,
+        }
+    }
+    // 基数付きの桁や型の接尾辞 (`0xff`, `10u8` など)
+    if take_count > 0 && !(take_count == 1 && has_dot) {
+        while take_count < chars.len()
+            && (chars[take_count].is_ascii_alphanumeric() || chars[take_count] == '_')
+        {
+            take_count += 1;
         }
     }
 
@@ -96,6 +114,13 @@ pub(super) fn parse_intrinsic_binop_expression(input: Span) -> NotLocatedParseRe
                         map(minus, |_| BinaryOp::Sub),
                         map(asterisk, |_| BinaryOp::Mul),
                         map(slash, |_| BinaryOp::Div),
+                        map(percent, |_| BinaryOp::Rem),
+                        map(ampersand, |_| BinaryOp::BitAnd),
+                        map(pipe, |_| BinaryOp::BitOr),
+                        map(caret, |_| BinaryOp::BitXor),
+                        // `<` や `>` より先に試す
+                        map(shl_token, |_| BinaryOp::Shl),
+                        map(shr_token, |_| BinaryOp::Shr),
                         map(eq_token, |_| BinaryOp::Equals),
                         map(neq_token, |_| BinaryOp::NotEquals),
                         map(lte_token, |_| BinaryOp::LessThanOrEquals),
@@ -125,12 +150,22 @@ pub(super) fn parse_intrinsic_unary_op_expression(
     map(
         preceded(
             lparen,
-            tuple((
-                alt((map(not_token, |_| {
-                    // panic!();
-                    UnaryOp::Not
-                }),)),
-                cut(terminated(parse_boxed_expression, rparen)),
+            alt((
+                tuple((
+                    map(not_token, |_| {
+                        // panic!();
+                        UnaryOp::Not
+                    }),
+                    cut(terminated(parse_boxed_expression, rparen)),
+                )),
+                // `(- a b)` は二項演算なので、ここではcutしない
+                tuple((
+                    alt((
+                        map(minus, |_| UnaryOp::Neg),
+                        map(tilde, |_| UnaryOp::BitNot),
+                    )),
+                    terminated(parse_boxed_expression, rparen),
+                )),
             )),
         ),
         |(op, target)| {
@@ -146,6 +181,35 @@ pub(super) fn parse_intrinsic_unary_op_expression(
 fn parse_test_intrinsic_unary_op_expression() {
     assert!(parse_boxed_expression("(not a b)".into()).is_err());
     assert!(parse_boxed_expression("(not a)".into()).is_ok());
+    for (input, op) in [("(- a)", UnaryOp::Neg), ("(~ a)", UnaryOp::BitNot)] {
+        let (_, expr) = parse_boxed_expression(input.into()).unwrap();
+        assert!(matches!(*expr.value, Expression::Unary(UnaryExpr { op: actual, .. }) if actual == op));
+    }
+    let (_, expr) = parse_boxed_expression("(- a b)".into()).unwrap();
+    assert!(matches!(
+        *expr.value,
+        Expression::Binary(BinaryExpr {
+            op: BinaryOp::Sub,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_parse_bitwise_binop_expression() {
+    for (input, op) in [
+        ("(% a b)", BinaryOp::Rem),
+        ("(& a b)", BinaryOp::BitAnd),
+        ("(| a b)", BinaryOp::BitOr),
+        ("(^ a b)", BinaryOp::BitXor),
+        ("(<< a 2)", BinaryOp::Shl),
+        ("(>> a 2)", BinaryOp::Shr),
+        ("(< a 2)", BinaryOp::LessThan),
+    ] {
+        let (rest, expr) = parse_intrinsic_binop_expression(input.into()).unwrap();
+        assert_eq!(rest.to_string().as_str(), "");
+        assert!(matches!(expr, Expression::Binary(BinaryExpr { op: actual, .. }) if actual == op));
+    }
 }
 
 pub(super) fn parse_intrinsic_multi_op_expression(
@@ -243,17 +307,228 @@ fn parse_when_expression(input: Span) -> NotLocatedParseResult<Expression> {
     )(input)
 }
 
+// ループのラベル `'outer`
+fn parse_label(input: Span) -> NotLocatedParseResult<String> {
+    preceded(quote, parse_identifier)(input)
+}
+
 fn parse_while_expression(input: Span) -> NotLocatedParseResult<Expression> {
     map(
         delimited(
             lparen,
-            tuple((while_token, parse_boxed_expression, parse_boxed_expression)),
+            tuple((
+                while_token,
+                opt(parse_label),
+                parse_boxed_expression,
+                parse_boxed_expression,
+            )),
+            rparen,
+        ),
+        |(_, label, cond, body)| Expression::While(WhileExpr { label, cond, body }),
+    )(input)
+}
+
+fn parse_do_expression(input: Span) -> NotLocatedParseResult<Expression> {
+    map(
+        delimited(
+            lparen,
+            preceded(
+                // `(done x)` などの関数呼び出しと区別する
+                terminated(do_token, alt((skip1, peek(rparen)))),
+                cut(many0(parse_statement)),
+            ),
+            rparen,
+        ),
+        |statements| Expression::Do(DoExpr { statements }),
+    )(input)
+}
+
+fn parse_for_expression(input: Span) -> NotLocatedParseResult<Expression> {
+    map(
+        delimited(
+            lparen,
+            preceded(
+                // `(format ...)` などの関数呼び出しと区別する
+                terminated(for_token, skip1),
+                cut(tuple((
+                    opt(parse_label),
+                    parse_identifier,
+                    parse_boxed_expression,
+                    parse_boxed_expression,
+                    opt(parse_boxed_expression),
+                ))),
+            ),
             rparen,
         ),
-        |(_, cond, body)| Expression::While(WhileExpr { cond, body }),
+        |(label, var, first, second, third)| {
+            // 式が3つなら範囲、2つならコレクションに対するループ
+            let (iter, body) = match third {
+                Some(body) => (
+                    ForIter::Range {
+                        start: first,
+                        end: second,
+                    },
+                    body,
+                ),
+                None => (ForIter::Collection(first), second),
+            };
+            Expression::For(ForExpr {
+                label,
+                var,
+                iter,
+                body,
+            })
+        },
     )(input)
 }
 
+// `(fn (x: i32, y: i32): i32 body...)`
+fn parse_lambda_expression(input: Span) -> NotLocatedParseResult<Expression> {
+    map(
+        delimited(
+            lparen,
+            preceded(
+                // `(fnord x)` などの関数呼び出しと区別する
+                terminated(fn_token, alt((skip1, peek(lparen)))),
+                cut(tuple((
+                    delimited(
+                        lparen,
+                        many0(pair(parse_identifier, preceded(colon, parse_type))),
+                        rparen,
+                    ),
+                    opt(preceded(colon, parse_type)),
+                    many0(parse_statement),
+                ))),
+            ),
+            rparen,
+        ),
+        |(args, return_type, body)| {
+            Expression::Lambda(LambdaExpr {
+                args,
+                return_type,
+                body,
+            })
+        },
+    )(input)
+}
+
+fn parse_break_expression(input: Span) -> NotLocatedParseResult<Expression> {
+    map(
+        delimited(
+            lparen,
+            preceded(
+                // `(breaker)` などの関数呼び出しと区別する
+                terminated(break_token, alt((skip1, peek(rparen)))),
+                cut(pair(opt(parse_label), opt(parse_boxed_expression))),
+            ),
+            rparen,
+        ),
+        |(label, value)| Expression::Break(BreakExpr { label, value }),
+    )(input)
+}
+
+fn parse_continue_expression(input: Span) -> NotLocatedParseResult<Expression> {
+    map(
+        delimited(
+            lparen,
+            preceded(
+                terminated(continue_token, alt((skip1, peek(rparen)))),
+                cut(opt(parse_label)),
+            ),
+            rparen,
+        ),
+        |label| Expression::Continue(ContinueExpr { label }),
+    )(input)
+}
+
+#[test]
+fn test_parse_loop_control_expression() {
+    let (rest, expr) = parse_while_expression(Span::new("(while 'outer true (break 'outer))")).unwrap();
+    assert_eq!(rest.to_string().as_str(), "");
+    let Expression::While(while_expr) = expr else {
+        panic!("unexpected expression type");
+    };
+    assert_eq!(while_expr.label.as_deref(), Some("outer"));
+    let Expression::Break(break_expr) = *while_expr.body.value else {
+        panic!("unexpected expression type");
+    };
+    assert_eq!(break_expr.label.as_deref(), Some("outer"));
+    assert!(break_expr.value.is_none());
+
+    let (_, expr) = parse_break_expression(Span::new("(break 1)")).unwrap();
+    assert!(matches!(expr, Expression::Break(BreakExpr { label: None, value: Some(_) })));
+    let (_, expr) = parse_continue_expression(Span::new("(continue)")).unwrap();
+    assert_eq!(expr, Expression::Continue(ContinueExpr { label: None }));
+
+    // 関数呼び出しとして解釈される
+    assert!(parse_break_expression(Span::new("(breaker)")).is_err());
+    assert!(parse_boxed_expression(Span::new("(continued x)")).is_ok());
+}
+
+#[test]
+fn test_parse_do_expression() {
+    let (rest, expr) = parse_do_expression(Span::new("(do (:= x 1) (f x) return x)")).unwrap();
+    assert_eq!(rest.to_string().as_str(), "");
+    let Expression::Do(do_expr) = expr else {
+        panic!("unexpected expression type");
+    };
+    assert_eq!(do_expr.statements.len(), 3);
+    assert!(matches!(do_expr.statements[2].value, Statement::Return(_)));
+
+    let (_, expr) = parse_do_expression(Span::new("(do)")).unwrap();
+    assert_eq!(expr, Expression::Do(DoExpr { statements: vec![] }));
+
+    // 関数呼び出しとして解釈される
+    assert!(parse_do_expression(Span::new("(done x)")).is_err());
+}
+
+#[test]
+fn test_parse_for_expression() {
+    let (rest, expr) = parse_for_expression(Span::new("(for i 0 n (f i))")).unwrap();
+    assert_eq!(rest.to_string().as_str(), "");
+    let Expression::For(for_expr) = expr else {
+        panic!("unexpected expression type");
+    };
+    assert_eq!(for_expr.var, "i");
+    assert!(matches!(for_expr.iter, ForIter::Range { .. }));
+
+    let (_, expr) = parse_for_expression(Span::new("(for 'outer x v (f x))")).unwrap();
+    let Expression::For(for_expr) = expr else {
+        panic!("unexpected expression type");
+    };
+    assert_eq!(for_expr.label.as_deref(), Some("outer"));
+    assert!(matches!(for_expr.iter, ForIter::Collection(_)));
+
+    // 関数呼び出しとして解釈される
+    assert!(parse_for_expression(Span::new("(format x)")).is_err());
+    assert!(parse_boxed_expression(Span::new("(format x)")).is_ok());
+}
+
+#[test]
+fn test_parse_lambda_expression() {
+    let (rest, expr) =
+        parse_lambda_expression(Span::new("(fn (x: i32, y: i32): i32 (+ x y))")).unwrap();
+    assert_eq!(rest.to_string().as_str(), "");
+    let Expression::Lambda(lambda) = expr else {
+        panic!("unexpected expression type");
+    };
+    assert_eq!(lambda.args.len(), 2);
+    assert_eq!(lambda.args[1].0, "y");
+    assert!(lambda.return_type.is_some());
+    assert_eq!(lambda.body.len(), 1);
+
+    let (_, expr) = parse_lambda_expression(Span::new("(fn() (printf \"hi\") return)")).unwrap();
+    let Expression::Lambda(lambda) = expr else {
+        panic!("unexpected expression type");
+    };
+    assert!(lambda.args.is_empty() && lambda.return_type.is_none());
+    assert_eq!(lambda.body.len(), 2);
+
+    // 関数呼び出しとして解釈される
+    assert!(parse_lambda_expression(Span::new("(fnord x)")).is_err());
+    assert!(parse_boxed_expression(Span::new("(fnord x)")).is_ok());
+}
+
 #[test]
 fn test_parse_if_expression() {
     let result = parse_if_expression(Span::new("(if a b c)"));
@@ -384,6 +659,29 @@ fn parse_struct_literal(input: Span) -> NotLocatedParseResult<Expression> {
     )(input)
 }
 
+#[test]
+fn test_parse_number_literal() {
+    for (input, expected, rest) in [
+        ("123", "123", ""),
+        ("1_000_000i64", "1_000_000i64", ""),
+        ("0xFFu8)", "0xFFu8", ")"),
+        ("0b1010 x", "0b1010", " x"),
+        ("2.5f32", "2.5f32", ""),
+        (".5", ".5", ""),
+        ("1.field", "1", ".field"),
+    ] {
+        let (actual_rest, expr) = parse_number_literal(Span::new(input)).unwrap();
+        assert_eq!(actual_rest.to_string().as_str(), rest);
+        assert_eq!(
+            expr,
+            Expression::NumberLiteral(NumberLiteralExpr {
+                value: expected.to_string()
+            })
+        );
+    }
+    assert!(parse_number_literal(Span::new("_1")).is_err());
+}
+
 #[test]
 fn test_parse_struct_literal() {
     let result = parse_boxed_expression(Span::new("Point { x: 1, y: 2, }"));
@@ -440,6 +738,38 @@ fn parse_sizeof(input: Span) -> NotLocatedParseResult<Expression> {
     )(input)
 }
 
+fn parse_cast(input: Span) -> NotLocatedParseResult<Expression> {
+    map(
+        delimited(
+            lparen,
+            preceded(
+                // `(assert ...)` などの関数呼び出しと区別するため、空白が続く場合のみ
+                terminated(as_token, skip1),
+                cut(pair(parse_type, parse_boxed_expression)),
+            ),
+            rparen,
+        ),
+        |(ty, target)| Expression::Cast(CastExpr { ty, target }),
+    )(input)
+}
+
+#[test]
+fn test_parse_cast() {
+    let (rest, expr) = parse_cast(Span::new("(as u8 x)")).unwrap();
+    assert_eq!(rest.to_string().as_str(), "");
+    if let Expression::Cast(cast_expr) = expr {
+        assert_eq!(
+            *cast_expr.target.value,
+            Expression::VariableRef(VariableRefExpr {
+                name: "x".to_string()
+            })
+        );
+    } else {
+        panic!();
+    }
+    assert!(parse_cast(Span::new("(assert x)")).is_err());
+}
+
 fn parse_asignment(input: Span) -> NotLocatedParseResult<Expression> {
     map(
         delimited(
@@ -470,6 +800,16 @@ fn test_parse_assignment() {
     assert!(parse_asignment(Span::new("(:=< buf[index] value)")).is_ok());
 }
 
+fn parse_variable_pattern(input: Span) -> NotLocatedParseResult<VariablePattern> {
+    alt((
+        map(parse_identifier, VariablePattern::Name),
+        map(
+            delimited(lparen, cut(many1(parse_identifier)), rparen),
+            VariablePattern::Tuple,
+        ),
+    ))(input)
+}
+
 fn parse_variable_decl(input: Span) -> NotLocatedParseResult<Expression> {
     delimited(
         lparen,
@@ -478,7 +818,7 @@ fn parse_variable_decl(input: Span) -> NotLocatedParseResult<Expression> {
             cut(map(
                 many1(located(map(
                     tuple((
-                        parse_identifier,
+                        parse_variable_pattern,
                         opt(context(
                             "type_annotation",
                             map(
@@ -488,9 +828,9 @@ fn parse_variable_decl(input: Span) -> NotLocatedParseResult<Expression> {
                         )),
                         preceded(skip0, parse_boxed_expression),
                     )),
-                    |(name, ty, expression)| VariableDecl {
+                    |(pattern, ty, expression)| VariableDecl {
                         ty,
-                        name,
+                        pattern,
                         value: expression,
                     },
                 ))),
@@ -525,10 +865,59 @@ fn parse_array_literal(input: Span) -> NotLocatedParseResult<Expression> {
     Ok((rest, Expression::ArrayLiteral(ArrayLiteralExpr { elements })))
 }
 
+// `(tuple a b ...)`。要素は2つ以上
+fn parse_tuple_literal(input: Span) -> NotLocatedParseResult<Expression> {
+    map(
+        delimited(
+            lparen,
+            preceded(
+                terminated(tuple_token, skip1),
+                cut(verify(parse_arguments, |elements: &Vec<_>| {
+                    elements.len() >= 2
+                })),
+            ),
+            rparen,
+        ),
+        |elements| Expression::TupleLiteral(TupleLiteralExpr { elements }),
+    )(input)
+}
+
+#[test]
+fn test_parse_tuple_literal() {
+    let (rest, expr) = parse_tuple_literal(Span::new("(tuple 1, (f x))")).unwrap();
+    assert_eq!(rest.to_string().as_str(), "");
+    let Expression::TupleLiteral(tuple_literal) = expr else {
+        panic!("unexpected expression");
+    };
+    assert_eq!(tuple_literal.elements.len(), 2);
+    assert!(parse_tuple_literal(Span::new("(tuple 1)")).is_err());
+    assert!(parse_tuple_literal(Span::new("(tuples 1 2)")).is_err());
+
+    let (_, expr) = parse_boxed_expression(Span::new("t.1")).unwrap();
+    let Expression::FieldAccess(field_access) = &*expr.value else {
+        panic!("unexpected expression");
+    };
+    assert_eq!(field_access.field_name, "1");
+
+    let (_, expr) = parse_variable_decl(Span::new("(:= (a b) (tuple 1 2) c 3)")).unwrap();
+    let Expression::VariableDecl(decls) = expr else {
+        panic!("unexpected expression");
+    };
+    assert_eq!(
+        decls.decls[0].value.pattern,
+        VariablePattern::Tuple(vec!["a".to_string(), "b".to_string()])
+    );
+    assert_eq!(
+        decls.decls[1].value.pattern,
+        VariablePattern::Name("c".to_string())
+    );
+}
+
 pub(super) fn parse_boxed_expression(input: Span) -> ParseResult<Box<Expression>> {
     let (rest, expr) = located(map(
         alt((
             context("sizeof", parse_sizeof),
+            context("cast", parse_cast),
             context("deref", parse_deref_expression),
             context("address_of", parse_address_of_expression),
             context("string_literal", parse_string_literal),
@@ -536,9 +925,17 @@ pub(super) fn parse_boxed_expression(input: Span) -> ParseResult<Box<Expression>
             context("number_literal", parse_number_literal),
             context("bool_literal", parse_bool_literal),
             context("struct_literal", parse_struct_literal),
-            context("if", parse_if_expression),
-            context("when", parse_when_expression),
-            context("while", parse_while_expression),
+            context("tuple_literal", parse_tuple_literal),
+            alt((
+                context("if", parse_if_expression),
+                context("when", parse_when_expression),
+                context("do", parse_do_expression),
+                context("while", parse_while_expression),
+                context("for", parse_for_expression),
+                context("break", parse_break_expression),
+                context("continue", parse_continue_expression),
+                context("lambda", parse_lambda_expression),
+            )),
             context("assignment", parse_asignment),
             context("variable_decl", parse_variable_decl),
             context("unary_op", parse_intrinsic_unary_op_expression),
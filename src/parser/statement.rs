@@ -1,6 +1,6 @@
 use nom::{
     branch::alt,
-    combinator::{map, opt},
+    combinator::{map, opt, peek},
     error::context,
     sequence::tuple,
 };
@@ -13,7 +13,12 @@ use super::{
 
 fn parse_return_statement(input: Span) -> NotLocatedParseResult<Statement> {
     map(
-        tuple((return_token, skip1, opt(parse_boxed_expression))),
+        // `(do ... return)` のように、値のないreturnの直後に閉じ括弧が来ることもある
+        tuple((
+            return_token,
+            alt((skip1, peek(rparen))),
+            opt(parse_boxed_expression),
+        )),
         |(_, _, opt_expr)| {
             Statement::Return(ReturnStatement {
                 expression: opt_expr.map(|expr| expr.unbox()),
@@ -49,6 +49,7 @@ token_char!(langlebracket, '<');
 token_char!(ranglebracket, '>');
 token_char!(comma, ',');
 token_char!(colon, ':');
+token_char!(semicolon, ';');
 token_char!(plus, '+');
 token_char!(minus, '-');
 token_char!(asterisk, '*');
@@ -56,6 +57,12 @@ token_char!(slash, '/');
 token_char!(dot, '.');
 token_char!(underscore, '_');
 token_char!(ampersand, '&');
+token_char!(percent, '%');
+token_char!(pipe, '|');
+token_char!(caret, '^');
+token_char!(tilde, '~');
+token_char!(quote, '\'');
+token_char!(hash, '#');
 token_tag!(fn_token, "fn");
 token_tag!(struct_token, "struct");
 token_tag!(record_token, "record");
@@ -63,6 +70,7 @@ token_tag!(return_token, "return");
 token_tag!(doublequote, "\"");
 token_tag!(threedots, "...");
 token_tag!(sizeof_token, "sizeof");
+token_tag!(as_token, "as");
 token_tag!(if_token, "if");
 token_tag!(when_token, "when");
 token_tag!(var_decl_token, ":=");
@@ -72,6 +80,8 @@ token_tag!(or_token, "or");
 token_tag!(not_token, "not");
 token_tag!(eq_token, "=");
 token_tag!(neq_token, "!=");
+token_tag!(shl_token, "<<");
+token_tag!(shr_token, ">>");
 token_tag!(gte_token, ">=");
 token_tag!(lte_token, "<=");
 token_tag!(gt_token, ">");
@@ -85,6 +95,17 @@ token_tag!(self_token, "self");
 token_tag!(use_token, "use");
 token_tag!(double_colon, "::");
 token_tag!(while_token, "while");
+token_tag!(break_token, "break");
+token_tag!(continue_token, "continue");
+token_tag!(do_token, "do");
+token_tag!(dyn_token, "dyn");
+token_tag!(where_token, "where");
+token_tag!(tuple_token, "tuple");
+token_tag!(extern_token, "extern");
+token_tag!(link_token, "link");
+token_tag!(export_token, "export");
+token_tag!(test_token, "test");
+token_tag!(pub_token, "pub");
 
 pub(super) fn parse_identifier(input: Span) -> NotLocatedParseResult<String> {
     let (first_skipped, _) = skip0(input)?;
@@ -1,15 +1,17 @@
 use crate::{
     ast::{self, *},
     common::{AllocMode, StructKind},
-    parser::ty::{parse_generic_argument_decls, parse_type},
+    parser::ty::{parse_generic_argument_decls, parse_type, parse_where_clauses},
 };
 
 use super::{statement::parse_statement, token::*, util::*, *};
 
 use nom::{
     branch::alt,
-    combinator::{cut, opt, peek},
+    character::complete::none_of,
+    combinator::{cut, opt, peek, verify},
     error::context,
+    multi::many0,
     sequence::{preceded, tuple},
 };
 
@@ -140,6 +142,7 @@ fn parse_function_decl(input: Span) -> ParseResult<FunctionDecl> {
         "function_decl",
         located(map(
             tuple((
+                opt(pub_token),
                 opt(parse_alloc_mode),
                 fn_token,
                 parse_namespace_path,
@@ -147,19 +150,57 @@ fn parse_function_decl(input: Span) -> ParseResult<FunctionDecl> {
                 // params
                 parse_arguments,
                 map(tuple((colon, parse_type)), |(_, ty)| ty),
+                opt(parse_where_clauses),
             )),
-            |(alloc_mode, _, name_path, generic_args, params, ty)| FunctionDecl {
-                alloc_mode,
-                name: name_path.to_string(),
-                generic_args,
-                args: params,
-                return_type: ty,
-                is_intrinsic: false,
+            |(is_public, alloc_mode, _, name_path, generic_args, params, ty, where_clauses)| {
+                FunctionDecl {
+                    alloc_mode,
+                    name: name_path.to_string(),
+                    generic_args,
+                    args: params,
+                    return_type: ty,
+                    where_clauses: where_clauses.unwrap_or_default(),
+                    is_extern: false,
+                    attributes: Vec::new(),
+                    is_public: is_public.is_some(),
+                    is_imported: false,
+                }
             },
         )),
     )(input)
 }
 
+#[test]
+fn test_parse_function_with_where_clauses() {
+    let (_, decl) = parse_function_decl(
+        "fn show-all<T>(v: Vec<T>): void where T: ->str, Vec<T>: Show + ->bool {".into(),
+    )
+    .unwrap();
+    assert_eq!(decl.value.where_clauses.len(), 2);
+    assert_eq!(
+        decl.value.where_clauses[0].value.restrictions,
+        vec![Restriction::Interface("->str".into())]
+    );
+    assert_eq!(decl.value.where_clauses[1].value.restrictions.len(), 2);
+    assert_eq!(
+        decl.value.where_clauses[1].value.to_string(),
+        "Vec<T>: Show + ->bool"
+    );
+
+    let (_, decl) = parse_function_decl("fn f(): void {".into()).unwrap();
+    assert!(decl.value.where_clauses.is_empty());
+}
+
+#[test]
+fn test_parse_public_function_decl() {
+    let (_, decl) = parse_function_decl("pub alloc fn f(): void {".into()).unwrap();
+    assert!(decl.value.is_public);
+    assert_eq!(decl.value.alloc_mode, Some(AllocMode::Stack));
+
+    let (_, decl) = parse_function_decl("fn f(): void {".into()).unwrap();
+    assert!(!decl.value.is_public);
+}
+
 pub fn parse_block(input: Span) -> NotLocatedParseResult<Vec<Located<Statement>>> {
     let (s, _) = skip0(input)?;
     let (s, _) = lbracket(s)?;
@@ -175,21 +216,154 @@ pub fn parse_block(input: Span) -> NotLocatedParseResult<Vec<Located<Statement>>
     Ok((s, statements))
 }
 
+fn parse_quoted_string(input: Span) -> NotLocatedParseResult<String> {
+    map(
+        delimited(doublequote, many0(none_of("\"")), doublequote),
+        |chars| chars.into_iter().collect(),
+    )(input)
+}
+
+fn parse_attribute_argument(input: Span) -> NotLocatedParseResult<String> {
+    delimited(lparen, preceded(skip0, parse_quoted_string), rparen)(input)
+}
+
+// `#[link("m")]`, `#[export]`, `#[export("name")]`, `#[test]`
+fn parse_attribute(input: Span) -> ParseResult<Attribute> {
+    let (s, _) = peek(hash)(input)?;
+    cut(located(context(
+        "attribute",
+        delimited(
+            tuple((hash, lsqrbracket)),
+            alt((
+                map(
+                    preceded(link_token, parse_attribute_argument),
+                    Attribute::Link,
+                ),
+                map(
+                    preceded(export_token, opt(parse_attribute_argument)),
+                    Attribute::Export,
+                ),
+                map(test_token, |_| Attribute::Test),
+            )),
+            rsqrbracket,
+        ),
+    )))(s)
+}
+
 fn parse_function(input: Span) -> ParseResult<TopLevel> {
     located(context(
         "function",
         map(
-            tuple((parse_function_decl, skip0, cut(parse_block))),
-            |(decl, _, body)| {
+            tuple((
+                many0(parse_attribute),
+                parse_function_decl,
+                skip0,
+                cut(parse_block),
+            )),
+            |(attributes, decl, _, body)| {
                 TopLevel::Function(Function {
-                    decl: decl.value,
+                    decl: FunctionDecl {
+                        attributes,
+                        ..decl.value
+                    },
+                    decl_range: decl.range,
                     body,
+                    file: FileId::MAIN,
+                })
+            },
+        ),
+    ))(input)
+}
+
+// `extern "C" fn sqrt(x: f64): f64;` 本体は持たず、リンクするライブラリなどにある関数を呼ぶ
+fn parse_extern_function(input: Span) -> ParseResult<TopLevel> {
+    located(context(
+        "extern_function",
+        map(
+            tuple((
+                many0(parse_attribute),
+                extern_token,
+                cut(tuple((
+                    preceded(
+                        skip0,
+                        context("abi", verify(parse_quoted_string, |abi: &str| abi == "C")),
+                    ),
+                    parse_function_decl,
+                    semicolon,
+                ))),
+            )),
+            |(attributes, _, (_, decl, _))| {
+                TopLevel::Function(Function {
+                    decl: FunctionDecl {
+                        is_extern: true,
+                        attributes,
+                        ..decl.value
+                    },
+                    decl_range: decl.range,
+                    body: Vec::new(),
+                    file: FileId::MAIN,
                 })
             },
         ),
     ))(input)
 }
 
+#[test]
+fn test_parse_extern_function() {
+    let (_, toplevel) = parse_toplevel(
+        r#"
+#[link("m")]
+extern "C" fn pow(x: f64, y: f64): f64;
+"#
+        .into(),
+    )
+    .unwrap();
+    let TopLevel::Function(function) = toplevel.value else {
+        panic!("expected function");
+    };
+    assert!(function.decl.is_extern);
+    assert_eq!(function.decl.name, "pow");
+    assert_eq!(function.decl.args.len(), 2);
+    assert!(function.body.is_empty());
+    assert_eq!(
+        function.decl.attributes[0].value,
+        Attribute::Link("m".into())
+    );
+
+    // C以外の呼び出し規約は受け付けない
+    assert!(parse_toplevel(r#"extern "Rust" fn f(): void;"#.into()).is_err());
+    assert!(parse_toplevel(r#"extern "C" fn f(): void {}"#.into()).is_err());
+}
+
+#[test]
+fn test_parse_export_attribute() {
+    let attributes = |input: &str| {
+        let (_, toplevel) = parse_toplevel(input.into()).unwrap();
+        let TopLevel::Function(function) = toplevel.value else {
+            panic!("expected function");
+        };
+        function
+            .decl
+            .attributes
+            .into_iter()
+            .map(|attribute| attribute.value)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        attributes("#[export] fn add(a: i32, b: i32): i32 { (+ a b) }"),
+        vec![Attribute::Export(None)]
+    );
+    assert_eq!(
+        attributes(r#"#[export("hirou_add")] fn add(a: i32, b: i32): i32 { (+ a b) }"#),
+        vec![Attribute::Export(Some("hirou_add".into()))]
+    );
+    assert_eq!(
+        attributes("#[test] fn adds(): bool { (= (+ 1 2) 3) }"),
+        vec![Attribute::Test]
+    );
+    assert!(parse_toplevel("#[inline] fn f(): void {}".into()).is_err());
+}
+
 fn parse_interface(input: Span) -> ParseResult<TopLevel> {
     let (s, _) = peek(interface_token)(input)?;
     cut(located(context(
@@ -247,8 +421,10 @@ fn parse_impl(input: Span) -> ParseResult<TopLevel> {
                         target_ty,
                         args,
                         return_type,
+                        is_imported: false,
                     },
                     body,
+                    file: FileId::MAIN,
                 })
             },
         ),
@@ -336,6 +512,7 @@ fn parse_use_statement(input: Span) -> ParseResult<TopLevel> {
                 TopLevel::Use(ast::UseStatement {
                     path,
                     wildcard: wildcard_opt.is_some(),
+                    file: FileId::MAIN,
                 })
             },
         ),
@@ -345,7 +522,14 @@ fn parse_use_statement(input: Span) -> ParseResult<TopLevel> {
 pub(crate) fn parse_toplevel(input: Span) -> ParseResult<TopLevel> {
     context(
         "toplevel",
-        alt((parse_use_statement, parse_function, parse_struct, parse_interface, parse_impl)),
+        alt((
+            parse_use_statement,
+            parse_extern_function,
+            parse_function,
+            parse_struct,
+            parse_interface,
+            parse_impl,
+        )),
     )(input)
 }
 
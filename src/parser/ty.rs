@@ -1,16 +1,20 @@
 use itertools::Itertools;
 use nom::{
     branch::alt,
-    combinator::opt,
+    character::complete::digit1,
+    combinator::{cut, map_res, opt, peek, verify},
     error::context,
-    multi::separated_list1,
-    sequence::{pair, preceded},
+    multi::{many0, many1, separated_list1},
+    sequence::{pair, preceded, tuple},
 };
 
 use crate::ast::*;
 
 use super::*;
-use super::{token::*, util::located};
+use super::{
+    token::*,
+    util::{located, skip1},
+};
 
 pub(super) fn parse_generic_argument_decls(
     input: Span<'_>,
@@ -19,17 +23,10 @@ pub(super) fn parse_generic_argument_decls(
         located(context(
             "generic_argument",
             map(
-                pair(
-                    parse_identifier,
-                    opt(preceded(colon, separated_list1(plus, parse_identifier))),
-                ),
+                pair(parse_identifier, opt(preceded(colon, parse_restrictions))),
                 |(name, restrictions)| GenericArgument {
                     name,
-                    restrictions: restrictions
-                        .unwrap_or(vec![])
-                        .into_iter()
-                        .map(|x| Restriction::Interface(x))
-                        .collect_vec(),
+                    restrictions: restrictions.unwrap_or_default(),
                 },
             ),
         ))(input)
@@ -60,6 +57,27 @@ fn test_parse_generic_argument_decls() {
     assert_eq!(args[0].value.restrictions.len(), 2);
 }
 
+fn parse_restrictions(input: Span) -> NotLocatedParseResult<Vec<Restriction>> {
+    map(separated_list1(plus, parse_identifier), |names| {
+        names.into_iter().map(Restriction::Interface).collect_vec()
+    })(input)
+}
+
+pub(super) fn parse_where_clauses(
+    input: Span,
+) -> NotLocatedParseResult<Vec<Located<WhereClause>>> {
+    preceded(
+        where_token,
+        cut(many1(located(context(
+            "where_clause",
+            map(
+                tuple((parse_type, colon, parse_restrictions)),
+                |(ty, _, restrictions)| WhereClause { ty, restrictions },
+            ),
+        )))),
+    )(input)
+}
+
 pub(super) fn parse_generic_arguments(
     input: Span,
 ) -> NotLocatedParseResult<Vec<Located<UnresolvedType>>> {
@@ -109,6 +127,63 @@ fn parse_ptr(input: Span) -> ParseResult<UnresolvedType> {
     }))(input)
 }
 
+fn parse_dyn(input: Span) -> ParseResult<UnresolvedType> {
+    located(map(
+        preceded(pair(dyn_token, skip1), parse_identifier),
+        UnresolvedType::Dyn,
+    ))(input)
+}
+
+// `fn(i32, i32): i32`
+fn parse_function_type(input: Span) -> ParseResult<UnresolvedType> {
+    located(map(
+        preceded(
+            // `fnord` などの型名と区別する
+            pair(fn_token, peek(lparen)),
+            cut(tuple((
+                delimited(lparen, many0(parse_type), rparen),
+                preceded(colon, parse_type),
+            ))),
+        ),
+        |(args, return_type)| {
+            UnresolvedType::Function(FunctionType {
+                args,
+                return_type: Box::new(return_type),
+            })
+        },
+    ))(input)
+}
+
+// `(i32, bool)`。要素は2つ以上
+fn parse_tuple_type(input: Span) -> ParseResult<UnresolvedType> {
+    located(map(
+        delimited(
+            lparen,
+            verify(many1(parse_type), |elements: &Vec<_>| elements.len() >= 2),
+            rparen,
+        ),
+        UnresolvedType::Tuple,
+    ))(input)
+}
+
+// `[i32; 4]`。長さは整数のリテラルで書く
+fn parse_array_type(input: Span) -> ParseResult<UnresolvedType> {
+    located(map(
+        delimited(
+            lsqrbracket,
+            pair(
+                parse_type,
+                preceded(
+                    semicolon,
+                    preceded(skip0, map_res(digit1, |len: Span| len.parse::<usize>())),
+                ),
+            ),
+            rsqrbracket,
+        ),
+        |(element, len)| UnresolvedType::Array(Box::new(element), len),
+    ))(input)
+}
+
 fn parse_typeref(input: Span) -> ParseResult<UnresolvedType> {
     located(map(
         pair(parse_identifier, opt(parse_generic_arguments)),
@@ -122,7 +197,18 @@ fn parse_typeref(input: Span) -> ParseResult<UnresolvedType> {
 }
 
 pub(super) fn parse_type(input: Span) -> ParseResult<UnresolvedType> {
-    context("type", alt((parse_infer, parse_ptr, parse_typeref)))(input)
+    context(
+        "type",
+        alt((
+            parse_infer,
+            parse_ptr,
+            parse_dyn,
+            parse_function_type,
+            parse_tuple_type,
+            parse_array_type,
+            parse_typeref,
+        )),
+    )(input)
 }
 
 #[test]
@@ -164,3 +250,62 @@ fn test_parse_type() {
     });
     assert_eq!(rest.to_string().as_str(), ",");
 }
+
+#[test]
+fn test_parse_dyn_type() {
+    let (rest, ty) = parse_type(Span::new("dyn ->bool,")).unwrap();
+    assert_eq!(ty.value, UnresolvedType::Dyn("->bool".to_string()));
+    assert_eq!(rest.to_string().as_str(), ",");
+
+    // `dyn` で始まるだけの型名は通常の型として扱う
+    let (_, ty) = parse_type(Span::new("dynamic")).unwrap();
+    assert!(matches!(ty.value, UnresolvedType::TypeRef(TypeRef { name, .. }) if name == "dynamic"));
+
+    let (_, ty) = parse_type(Span::new("Vec<dyn ->bool>")).unwrap();
+    assert!(match ty.value {
+        UnresolvedType::TypeRef(TypeRef { name, generic_args }) => {
+            name == "Vec"
+                && generic_args.unwrap()[0].value == UnresolvedType::Dyn("->bool".to_string())
+        }
+        _ => false,
+    });
+}
+
+#[test]
+fn test_parse_function_type() {
+    let (rest, ty) = parse_type(Span::new("fn(i32, *u8): bool,")).unwrap();
+    assert_eq!(rest.to_string().as_str(), ",");
+    assert_eq!(ty.value.to_string(), "fn(i32, [u8]): bool");
+    let UnresolvedType::Function(function_ty) = ty.value else {
+        panic!("unexpected type");
+    };
+    assert_eq!(function_ty.args.len(), 2);
+
+    let (_, ty) = parse_type(Span::new("Vec<fn(): void>")).unwrap();
+    assert_eq!(ty.value.to_string(), "Vec<fn(): void>");
+
+    // `fn` で始まるだけの型名は通常の型として扱う
+    let (_, ty) = parse_type(Span::new("fnord")).unwrap();
+    assert!(matches!(ty.value, UnresolvedType::TypeRef(TypeRef { name, .. }) if name == "fnord"));
+}
+
+#[test]
+fn test_parse_tuple_type() {
+    let (rest, ty) = parse_type(Span::new("(i32, Vec<*u8>) x")).unwrap();
+    assert_eq!(rest.to_string().as_str(), " x");
+    assert_eq!(ty.value.to_string(), "(i32, Vec<[u8]>)");
+
+    let (_, ty) = parse_type(Span::new("fn(): (i32 (bool, u8))")).unwrap();
+    assert_eq!(ty.value.to_string(), "fn(): (i32, (bool, u8))");
+
+    // 要素が1つだけのものはタプルではない
+    assert!(parse_type(Span::new("(i32)")).is_err());
+}
+
+#[test]
+fn test_parse_array_type() {
+    let (rest, ty) = parse_type(Span::new("[Vec<i32>; 16] x")).unwrap();
+    assert_eq!(rest.to_string().as_str(), " x");
+    assert_eq!(ty.value.to_string(), "[Vec<i32>; 16]");
+    assert!(parse_type(Span::new("[i32; n]")).is_err());
+}
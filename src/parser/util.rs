@@ -4,7 +4,7 @@ use super::{expression::parse_boxed_expression, token::*, *};
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_till},
-    character::complete::{line_ending, multispace1},
+    character::complete::{digit1, line_ending, multispace1},
     combinator::eof,
     multi::many0,
     sequence::{preceded, tuple},
@@ -73,6 +73,13 @@ pub(super) fn index_access(input: Span<'_>) -> ParseResult<Box<Expression>> {
     delimited(lsqrbracket, parse_boxed_expression, rsqrbracket)(input)
 }
 
+// `.name`、タプルの要素は `.0`
 pub(super) fn field_access(input: Span<'_>) -> NotLocatedParseResult<String> {
-    preceded(dot, parse_identifier)(input)
+    preceded(
+        dot,
+        alt((
+            parse_identifier,
+            map(digit1, |index: Span| index.to_string()),
+        )),
+    )(input)
 }
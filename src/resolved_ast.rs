@@ -1,12 +1,14 @@
 use std::fmt::{Display, Write};
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
-    ast::{BinaryOp, MultiOp, UnaryOp},
-    common::{typename::*, AllocMode},
+    ast::{BinaryOp, MultiOp, SourceLocation, UnaryOp},
+    common::{typename::*, AllocMode, Linkage},
     concrete_ast::ConcreteType,
 };
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct ResolvedStructType {
     pub name: String,
     pub non_generic_name: String,
@@ -14,23 +16,29 @@ pub struct ResolvedStructType {
     pub generic_args: Option<Vec<ResolvedType>>,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct InterfaceRestriction {
     pub name: String,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub enum Restriction {
     Interface(InterfaceRestriction),
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct ResolvedGenericType {
     pub name: String,
     pub restrictions: Vec<Restriction>,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+pub struct ResolvedFunctionType {
+    pub args: Vec<ResolvedType>,
+    pub return_type: Box<ResolvedType>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub enum ResolvedType {
     I32,
     I64,
@@ -46,6 +54,16 @@ pub enum ResolvedType {
     Unknown,
     StructLike(ResolvedStructType),
     Generics(ResolvedGenericType),
+    // インターフェースを実装した任意の型の値 (vtableを介して呼び出す)
+    Dyn(String),
+    // 関数の値 (クロージャー)。関数へのポインタと、取り込んだ変数の環境の組として表現する
+    Function(ResolvedFunctionType),
+    // タプル。要素を `0`, `1`, ... というフィールドに持つ名前のない構造体として扱う
+    Tuple(Vec<ResolvedType>),
+    // 固定長配列 `[T; N]`。構造体と同じく値としてコピーされる
+    Array(Box<ResolvedType>, usize),
+    // 型推論中の型変数。関数の解決が終わるまでに具体的な型に置き換えられる
+    Var(usize),
 }
 
 impl ResolvedType {
@@ -65,6 +83,11 @@ impl ResolvedType {
             ResolvedType::Generics(_) => false,
             ResolvedType::F32 => false,
             ResolvedType::F64 => false,
+            ResolvedType::Dyn(_) => false,
+            ResolvedType::Function(_) => false,
+            ResolvedType::Tuple(_) => false,
+            ResolvedType::Array(_, _) => false,
+            ResolvedType::Var(_) => false,
         }
     }
     pub fn is_float_type(&self) -> bool {
@@ -171,112 +194,212 @@ impl Display for ResolvedType {
                     }) => {
                         name
                     }
+                    ResolvedType::Dyn(interface_name) => {
+                        return write!(f, "dyn {}", interface_name);
+                    }
+                    ResolvedType::Function(function_ty) => {
+                        f.write_str("fn(")?;
+                        for (i, arg) in function_ty.args.iter().enumerate() {
+                            if i != 0 {
+                                f.write_str(", ")?;
+                            }
+                            write!(f, "{}", arg)?;
+                        }
+                        return write!(f, "): {}", function_ty.return_type);
+                    }
+                    ResolvedType::Tuple(elements) => {
+                        f.write_char('(')?;
+                        for (i, element) in elements.iter().enumerate() {
+                            if i != 0 {
+                                f.write_str(", ")?;
+                            }
+                            write!(f, "{}", element)?;
+                        }
+                        return f.write_char(')');
+                    }
+                    ResolvedType::Array(element, len) => {
+                        return write!(f, "[{}; {}]", element, len);
+                    }
+                    ResolvedType::Var(id) => {
+                        return write!(f, "?{}", id);
+                    }
                 }
             )
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CallExpr {
     pub callee: String,
     pub args: Vec<ResolvedExpression>,
+    // ジェネリック関数の呼び出しは型推論が終わってからインスタンス化されるので、
+    // それまでの間は保留中のインスタンス化の番号を持つ
+    pub pending_instantiation: Option<usize>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VariableRefExpr {
     pub name: String,
 }
 
-// TODO: type毎に細かく分ける
-#[derive(Debug, Clone)]
+// 数値リテラルの値。どの型として扱うかは式の型で決まる
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NumberLiteralValue {
+    Integer(u64),
+    Float(f64),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NumberLiteral {
-    pub value: String,
+    pub value: NumberLiteralValue,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StringLiteral {
     pub value: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BoolLiteral {
     pub value: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StructLiteral {
     pub fields: Vec<(String, ResolvedExpression)>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ArrayLiteral {
     pub elements: Vec<ResolvedExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BinaryExpr {
     pub op: BinaryOp,
     pub lhs: Box<ResolvedExpression>,
     pub rhs: Box<ResolvedExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UnaryExpr {
     pub op: UnaryOp,
     pub operand: Box<ResolvedExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MultiExpr {
     pub op: MultiOp,
     pub operands: Vec<ResolvedExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DerefExpr {
     pub target: Box<ResolvedExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AddressOfExpr {
     pub target: Box<ResolvedExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IndexAccessExpr {
     pub target: Box<ResolvedExpression>,
     pub index: Box<ResolvedExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FieldAccessExpr {
     pub target: Box<ResolvedExpression>,
     pub field_name: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IfExpr {
     pub cond: Box<ResolvedExpression>,
     pub then: Box<ResolvedExpression>,
     pub els: Box<ResolvedExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WhenExpr {
     pub cond: Box<ResolvedExpression>,
     pub then: Box<ResolvedExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WhileExpr {
+    // break / continue の飛び先を特定するためのループの識別子
+    pub loop_id: usize,
     pub cond: Box<ResolvedExpression>,
     pub body: Box<ResolvedExpression>,
+    // 各回の最後に実行する式。continue してもこの式は実行される
+    pub step: Option<Box<ResolvedExpression>>,
+}
+
+// 順に評価し、最後の式の値を返す。ブロックの中で宣言した変数はブロックの外からは見えない
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockExpr {
+    pub statements: Vec<Statement>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakExpr {
+    pub loop_id: usize,
+    pub value: Option<Box<ResolvedExpression>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContinueExpr {
+    pub loop_id: usize,
+}
+
+// 式の型への変換。変換先の型は式自体の型になる
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CastExpr {
+    pub target: Box<ResolvedExpression>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DynCoerceExpr {
+    pub target: Box<ResolvedExpression>,
+    pub interface: String,
+    pub impl_fn: String,
+}
+
+// `fn` 式。本体はconcretizerで独立した関数に変換される
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LambdaExpr {
+    // 変換後の関数の名前
+    pub fn_name: String,
+    pub args: Vec<Argument>,
+    pub return_type: ResolvedType,
+    // 値としてコピーして取り込む外側の変数
+    pub captures: Vec<(String, ResolvedType)>,
+    pub body: Vec<Statement>,
+    pub location: SourceLocation,
+}
+
+// 関数の値を介した呼び出し
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClosureCallExpr {
+    pub callee: Box<ResolvedExpression>,
+    pub args: Vec<ResolvedExpression>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DynCallExpr {
+    pub interface: String,
+    pub receiver: Box<ResolvedExpression>,
+    pub args: Vec<ResolvedExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ExpressionKind {
     SizeOf(ResolvedType),
+    Cast(CastExpr),
     VariableRef(VariableRefExpr),
     NumberLiteral(NumberLiteral),
     StringLiteral(StringLiteral),
@@ -294,73 +417,91 @@ pub enum ExpressionKind {
     If(IfExpr),
     When(WhenExpr),
     While(WhileExpr),
+    Break(BreakExpr),
+    Continue(ContinueExpr),
+    Block(BlockExpr),
+    Lambda(LambdaExpr),
+    ClosureCall(ClosureCallExpr),
     VariableDecls(VariableDecls),
     Assignment(Assignment),
+    DynCoerce(DynCoerceExpr),
+    DynCall(DynCallExpr),
     Unknown,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResolvedExpression {
     pub ty: ResolvedType,
     pub kind: ExpressionKind,
+    pub location: SourceLocation,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Assignment {
     pub name: String,
     pub value: Box<ResolvedExpression>,
     pub deref_count: usize,
     pub index_access: Option<Box<ResolvedExpression>>,
+    // 添字の対象が固定長配列なら、ポインタを読み出さずに配列の中を直接指す
+    pub target_is_array: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VariableDecl {
     pub name: String,
     pub ty: ResolvedType,
     pub value: Box<ResolvedExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VariableDecls {
     pub decls: Vec<VariableDecl>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Return {
     pub expression: Option<ResolvedExpression>,
+    pub location: SourceLocation,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Effect {
     pub expression: ResolvedExpression,
+    pub location: SourceLocation,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Statement {
     Return(Return),
     Effect(Effect),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Argument {
     VarArgs,
     Normal(ResolvedType, String),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FunctionDecl {
     pub name: String,
     pub args: Vec<Argument>,
     pub return_type: ResolvedType,
+    // Cの呼び出し規約で呼ぶ、本体を持たない関数
+    pub is_extern: bool,
+    // `#[export]` でCに公開する名前
+    pub export_name: Option<String>,
+    pub linkage: Linkage,
+    pub location: SourceLocation,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Function {
     pub decl: FunctionDecl,
     pub body: Vec<Statement>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ImplementationDecl {
     pub alloc_mode: Option<AllocMode>,
     pub name: String,
@@ -370,7 +511,7 @@ pub struct ImplementationDecl {
     pub return_type: ResolvedType,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TopLevel {
     Function(Function),
 }
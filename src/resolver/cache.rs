@@ -0,0 +1,701 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ast::{FileId, Position},
+    concrete_ast, in_global_scope, in_new_scope,
+    resolved_ast::{self, ResolvedType},
+};
+
+use super::{
+    error::FaitalError,
+    generics::find_implementation,
+    resolve_function, resolve_implementation,
+    stdlib::{parse_stdlib, StdlibRegistration, STDLIB_DEFINITIONS},
+    ResolverContext,
+};
+
+// 前回までのコンパイルで解決した関数と、パースした標準ライブラリ。
+// 関数はシンボル名ごとに、ジェネリック関数はインスタンスごとに持つ。
+// 関数のASTとモジュール全体の宣言が位置を除いて同じで、本体から呼んでいた名前が同じシグネチャの関数を指していれば、
+// 本体を解決し直さずに前回の結果を使う。行がずれただけの関数は、前回の結果の位置を今の位置に置き換える。
+// 解決した関数が位置も含めて前回と同じなら、具体的な関数も作り直さない
+pub struct IncrementalCache {
+    compiler: String,
+    stdlib: Option<CachedStdlib>,
+    previous: HashMap<String, CachedFunction>,
+    // 今回のコンパイルで解決した、または前回の結果を使った関数
+    functions: HashMap<String, CachedFunction>,
+    previous_concrete: HashMap<String, CachedConcrete>,
+    // 今回のコンパイルで作った、または前回の結果を使った具体的な関数
+    concrete: HashMap<String, CachedConcrete>,
+    // 型定義、インターフェース、実装の宣言などのハッシュ。最初に関数を解決するときに求める
+    environment: Option<u64>,
+    // 解決中の関数が引いた名前と解決した関数 (内側が末尾)
+    recordings: Vec<Recording>,
+    // 前回の結果を使った関数の数
+    reused_count: usize,
+    // 前回の結果を使った具体的な関数の数
+    reused_concrete_count: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    compiler: String,
+    stdlib: Option<CachedStdlib>,
+    functions: HashMap<String, CachedFunction>,
+    concrete: HashMap<String, CachedConcrete>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedStdlib {
+    source_hash: u64,
+    registration: StdlibRegistration,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedFunction {
+    key: u64,
+    // キーを作ったときのASTの位置。キーには含めず、デバッグ情報などに残る位置を置き換えるのに使う
+    positions: Vec<Position>,
+    lookups: Vec<NameLookup>,
+    requests: Vec<ResolveRequest>,
+    function: resolved_ast::Function,
+}
+
+// 解決した関数から作ったトップレベルの関数と、その中の `fn` 式から作った関数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedConcrete {
+    key: u64,
+    toplevels: Vec<concrete_ast::TopLevel>,
+    lifted_functions: Vec<concrete_ast::Function>,
+}
+
+// 本体で呼び出した名前と、それが指していた関数の名前とシグネチャのハッシュ
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct NameLookup {
+    name: String,
+    target: Option<(String, u64)>,
+}
+
+// 本体の解決中に解決した関数と実装。前回の結果を使うときは、これを同じ順に解決する
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) enum ResolveRequest {
+    Function {
+        name: String,
+        generic_args: Vec<ResolvedType>,
+    },
+    Implementation {
+        interface: String,
+        target_ty: ResolvedType,
+    },
+}
+
+#[derive(Default)]
+struct Recording {
+    lookups: Vec<NameLookup>,
+    requests: Vec<ResolveRequest>,
+}
+
+impl IncrementalCache {
+    pub fn new(compiler: String) -> Self {
+        Self {
+            compiler,
+            stdlib: None,
+            previous: HashMap::new(),
+            functions: HashMap::new(),
+            previous_concrete: HashMap::new(),
+            concrete: HashMap::new(),
+            environment: None,
+            recordings: Vec::new(),
+            reused_count: 0,
+            reused_concrete_count: 0,
+        }
+    }
+
+    // 読めないファイルや、別のコンパイラが書いたファイルは空のキャッシュとして扱う
+    pub fn read(path: &Path, compiler: String) -> Self {
+        let cache_file = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<CacheFile>(&json).ok())
+            .filter(|cache_file| cache_file.compiler == compiler);
+        let mut cache = Self::new(compiler);
+        if let Some(cache_file) = cache_file {
+            cache.stdlib = cache_file.stdlib;
+            cache.previous = cache_file.functions;
+            cache.previous_concrete = cache_file.concrete;
+        }
+        cache
+    }
+
+    // 今回解決したか前回の結果を使った関数だけを書き出す。消した関数やインスタンスは次のコンパイルに持ち越さない
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let cache_file = CacheFile {
+            compiler: self.compiler.clone(),
+            stdlib: self.stdlib.clone(),
+            functions: self.functions.clone(),
+            concrete: self.concrete.clone(),
+        };
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)
+                .map_err(|err| format!("could not create `{}`: {}", parent.display(), err))?;
+        }
+        let json = serde_json::to_string(&cache_file).map_err(|err| err.to_string())?;
+        std::fs::write(path, json)
+            .map_err(|err| format!("could not write `{}`: {}", path.display(), err))
+    }
+
+    // 前回同じキーで作った具体的な関数と、`fn` 式から作った関数を返す
+    pub fn reuse_concrete(
+        &mut self,
+        symbol: &str,
+        key: u64,
+    ) -> Option<(Vec<concrete_ast::TopLevel>, Vec<concrete_ast::Function>)> {
+        let entry = self
+            .previous_concrete
+            .get(symbol)
+            .filter(|entry| entry.key == key)?
+            .clone();
+        self.reused_concrete_count += 1;
+        self.concrete.insert(symbol.to_string(), entry.clone());
+        Some((entry.toplevels, entry.lifted_functions))
+    }
+
+    pub fn store_concrete(
+        &mut self,
+        symbol: &str,
+        key: u64,
+        toplevels: Vec<concrete_ast::TopLevel>,
+        lifted_functions: Vec<concrete_ast::Function>,
+    ) {
+        self.concrete.insert(
+            symbol.to_string(),
+            CachedConcrete {
+                key,
+                toplevels,
+                lifted_functions,
+            },
+        );
+    }
+}
+
+// コンパイラが変わると同じASTでも結果が変わり得る。開発中はバージョンが同じまま実行ファイルが変わるので、更新日時も含める
+pub fn compiler_id() -> String {
+    let modified = std::env::current_exe()
+        .and_then(|exe| exe.metadata())
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs());
+    format!("{} {}", env!("CARGO_PKG_VERSION"), modified)
+}
+
+// FNV-1a。実行するたびに同じ値になるハッシュが要る
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn content_hash<T: Serialize>(value: &T) -> u64 {
+    fnv1a(&serde_json::to_vec(value).expect("AST should be serializable"))
+}
+
+fn strip_ranges(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.remove("range");
+            map.remove("decl_range");
+            map.values_mut().for_each(strip_ranges);
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(strip_ranges),
+        _ => {}
+    }
+}
+
+// 位置を除いた内容のハッシュ。行がずれただけの宣言は変わっていないものとして扱う
+fn signature_hash<T: Serialize>(value: &T) -> u64 {
+    let mut value = serde_json::to_value(value).expect("AST should be serializable");
+    strip_ranges(&mut value);
+    fnv1a(value.to_string().as_bytes())
+}
+
+fn as_position(value: &serde_json::Value) -> Option<Position> {
+    let map = value.as_object()?;
+    if map.len() != 2 || !map.contains_key("line") || !map.contains_key("col") {
+        return None;
+    }
+    serde_json::from_value(value.clone()).ok()
+}
+
+// 出てくる順に並べた全ての位置
+fn collect_positions(value: &serde_json::Value, positions: &mut Vec<Position>) {
+    if let Some(position) = as_position(value) {
+        positions.push(position);
+        return;
+    }
+    match value {
+        serde_json::Value::Object(map) => {
+            for value in map.values() {
+                collect_positions(value, positions);
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                collect_positions(value, positions);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn move_positions(
+    value: &mut serde_json::Value,
+    moves: &HashMap<Position, Position>,
+) -> Option<()> {
+    if let Some(position) = as_position(value) {
+        *value = serde_json::to_value(moves.get(&position)?).ok()?;
+        return Some(());
+    }
+    match value {
+        serde_json::Value::Object(map) => {
+            for value in map.values_mut() {
+                move_positions(value, moves)?;
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                move_positions(value, moves)?;
+            }
+        }
+        _ => {}
+    }
+    Some(())
+}
+
+// 前回のASTの位置から今のASTの位置への対応で、解決した関数の位置を置き換える。
+// 位置を除いたASTは同じなので、出てくる順に対応する。
+// 対応が一つに決まらない位置や、ASTの外から来た位置があればNone
+fn relocate(
+    function: &resolved_ast::Function,
+    from: &[Position],
+    to: &[Position],
+) -> Option<resolved_ast::Function> {
+    if from.len() != to.len() {
+        return None;
+    }
+    let mut moves = HashMap::new();
+    for (from, to) in from.iter().zip(to) {
+        if moves.insert(*from, *to).is_some_and(|moved| moved != *to) {
+            return None;
+        }
+    }
+    let mut value = serde_json::to_value(function).ok()?;
+    move_positions(&mut value, &moves)?;
+    serde_json::from_value(value).ok()
+}
+
+// 具体的な関数は、解決した関数とポインタの幅だけから決まる。位置もデバッグ情報に残るので含める
+pub fn concrete_key(function: &resolved_ast::Function, is_64_bit: bool) -> u64 {
+    content_hash(&(is_64_bit, function))
+}
+
+// 本体の解決に関わる、関数以外の宣言とコンパイルの設定
+fn environment_hash(context: &ResolverContext) -> u64 {
+    let type_defs = context.type_defs.borrow();
+    let interfaces = context.interface_by_name.borrow();
+    let impls_by_name = context.impls_by_name.borrow();
+    // 実装は先に登録したものから探すので、順番も含める
+    let impls = impls_by_name
+        .iter()
+        .map(|(name, implementations)| {
+            let decls = implementations
+                .iter()
+                .map(|implementation| &implementation.decl)
+                .collect::<Vec<_>>();
+            (name, decls)
+        })
+        .collect::<BTreeMap<_, _>>();
+    let mut namespaces = context.namespace_by_file.iter().collect::<Vec<_>>();
+    namespaces.sort_by_key(|(file, _)| file.0);
+    signature_hash(&(
+        context.is_64_bit(),
+        context.strict_conversions,
+        type_defs.iter().collect::<BTreeMap<_, _>>(),
+        interfaces.iter().collect::<BTreeMap<_, _>>(),
+        impls,
+        namespaces,
+    ))
+}
+
+// 標準ライブラリは埋め込みのソースが同じ間、パースした結果を使い回す
+pub(super) fn load_stdlib(context: &ResolverContext) -> Result<StdlibRegistration, String> {
+    let Some(cache) = &context.cache else {
+        return parse_stdlib();
+    };
+    let source_hash = fnv1a(STDLIB_DEFINITIONS.as_bytes());
+    if let Some(stdlib) = cache
+        .borrow()
+        .stdlib
+        .as_ref()
+        .filter(|stdlib| stdlib.source_hash == source_hash)
+    {
+        return Ok(stdlib.registration.clone());
+    }
+    let registration = parse_stdlib()?;
+    cache.borrow_mut().stdlib = Some(CachedStdlib {
+        source_hash,
+        registration: registration.clone(),
+    });
+    Ok(registration)
+}
+
+// 関数 (または実装) のキャッシュのキー
+pub(super) struct FunctionKey {
+    // 位置を除いたAST、シンボル名、モジュール全体の宣言のハッシュ
+    hash: u64,
+    // ASTの位置。前回から変わっていれば、前回の結果の位置を置き換える
+    positions: Vec<Position>,
+}
+
+// キャッシュを使わなければNone
+pub(super) fn function_key<T: Serialize>(
+    context: &ResolverContext,
+    symbol: &str,
+    function: &T,
+) -> Option<FunctionKey> {
+    let cache = context.cache.as_ref()?;
+    let environment = cache.borrow().environment;
+    let environment = environment.unwrap_or_else(|| {
+        let environment = environment_hash(context);
+        cache.borrow_mut().environment = Some(environment);
+        environment
+    });
+    let mut positions = Vec::new();
+    let function = serde_json::to_value(function).expect("AST should be serializable");
+    collect_positions(&function, &mut positions);
+    Some(FunctionKey {
+        hash: signature_hash(&(environment, symbol, function)),
+        positions,
+    })
+}
+
+fn lookup_target(context: &ResolverContext, qualified: Option<&str>) -> Option<(String, u64)> {
+    let name = qualified?;
+    let signature = signature_hash(&context.function_by_name.borrow().get(name)?.decl);
+    Some((name.to_string(), signature))
+}
+
+// 解決中の関数が名前で関数を引いたことを記録する
+pub(super) fn record_lookup(context: &ResolverContext, name: &str, qualified: Option<&str>) {
+    let Some(cache) = &context.cache else {
+        return;
+    };
+    let mut cache = cache.borrow_mut();
+    let Some(recording) = cache.recordings.last_mut() else {
+        return;
+    };
+    if recording.lookups.iter().any(|lookup| lookup.name == name) {
+        return;
+    }
+    recording.lookups.push(NameLookup {
+        name: name.to_string(),
+        target: lookup_target(context, qualified),
+    });
+}
+
+// 解決中の関数の本体から、関数や実装を解決したことを記録する。解決済みのものも記録する
+pub(super) fn record_request(context: &ResolverContext, request: ResolveRequest) {
+    let Some(cache) = &context.cache else {
+        return;
+    };
+    if let Some(recording) = cache.borrow_mut().recordings.last_mut() {
+        if !recording.requests.contains(&request) {
+            recording.requests.push(request);
+        }
+    }
+}
+
+pub(super) fn start_recording(context: &ResolverContext) {
+    if let Some(cache) = &context.cache {
+        cache.borrow_mut().recordings.push(Recording::default());
+    }
+}
+
+// 本体の解決が終わった関数を記録する。エラーがあった関数は次回も解決し直す
+pub(super) fn store_function(
+    context: &ResolverContext,
+    symbol: &str,
+    key: Option<FunctionKey>,
+    errors_before: usize,
+    function: &resolved_ast::Function,
+) {
+    let Some(cache) = &context.cache else {
+        return;
+    };
+    let recording = cache.borrow_mut().recordings.pop().unwrap_or_default();
+    let Some(key) = key else {
+        return;
+    };
+    if context.errors.borrow().len() > errors_before {
+        return;
+    }
+    cache.borrow_mut().functions.insert(
+        symbol.to_string(),
+        CachedFunction {
+            key: key.hash,
+            positions: key.positions,
+            lookups: recording.lookups,
+            requests: recording.requests,
+            function: function.clone(),
+        },
+    );
+}
+
+// 前回の結果が使えれば解決済みの関数に加え、本体から解決していた関数と実装を解決してtrueを返す。
+// それらも前回の結果が使えるものは使い、変わったものだけ解決し直す
+pub(super) fn reuse_function(
+    context: &ResolverContext,
+    symbol: &str,
+    key: Option<&FunctionKey>,
+    file: FileId,
+) -> Result<bool, FaitalError> {
+    let (Some(cache), Some(key)) = (&context.cache, key) else {
+        return Ok(false);
+    };
+    let Some(mut entry) = cache
+        .borrow()
+        .previous
+        .get(symbol)
+        .filter(|entry| entry.key == key.hash)
+        .cloned()
+    else {
+        return Ok(false);
+    };
+    if entry.positions != key.positions {
+        let Some(function) = relocate(&entry.function, &entry.positions, &key.positions) else {
+            return Ok(false);
+        };
+        entry.function = function;
+        entry.positions = key.positions.clone();
+    }
+    // 名前は関数が書かれたファイルから引く
+    let outer_file = context.file.replace(file);
+    let is_valid = entry.lookups.iter().all(|lookup| {
+        let qualified = context.lookup_function_name(&lookup.name);
+        lookup_target(context, qualified.as_deref()) == lookup.target
+    });
+    context.file.replace(outer_file);
+    if !is_valid {
+        return Ok(false);
+    }
+
+    context
+        .resolved_functions
+        .borrow_mut()
+        .insert(symbol.to_string(), entry.function.clone());
+    cache.borrow_mut().reused_count += 1;
+    // 解決し直す関数の記録が、呼び出し元の記録に混ざらないようにする
+    start_recording(context);
+    for request in &entry.requests {
+        resolve_request(context, request)?;
+    }
+    cache.borrow_mut().recordings.pop();
+    cache
+        .borrow_mut()
+        .functions
+        .insert(symbol.to_string(), entry);
+    Ok(true)
+}
+
+fn resolve_request(context: &ResolverContext, request: &ResolveRequest) -> Result<(), FaitalError> {
+    match request {
+        ResolveRequest::Function { name, generic_args } => {
+            let Some(function) = context.function_by_name.borrow().get(name).cloned() else {
+                return Ok(());
+            };
+            let function_generic_args = function.decl.generic_args.iter().flatten();
+            in_global_scope!(context.scopes, {
+                in_global_scope!(context.types, {
+                    in_new_scope!(context.types, {
+                        for (generic_arg, ty) in function_generic_args.zip(generic_args) {
+                            context
+                                .types
+                                .borrow_mut()
+                                .add(generic_arg.name.clone(), ty.clone());
+                        }
+                        resolve_function(context, &function)
+                    })
+                })
+            })
+        }
+        ResolveRequest::Implementation {
+            interface,
+            target_ty,
+        } => {
+            let Some((implementation, fn_name, generic_args)) =
+                find_implementation(context, interface, target_ty)
+            else {
+                return Ok(());
+            };
+            resolve_implementation(context, &implementation, &fn_name, &generic_args).map(|_| ())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::target::PointerSizedIntWidth, concretizer::concretize_module, parser::parse_module,
+        resolver::resolve_module,
+    };
+    use nom::Finish;
+
+    fn resolve_with_cache(source: &str, path: &Path) -> (Vec<String>, usize) {
+        let (_, module) = parse_module(source.into()).finish().unwrap();
+        let mut context = ResolverContext::new(PointerSizedIntWidth::SixtyFour);
+        context.cache = Some(std::cell::RefCell::new(IncrementalCache::read(
+            path,
+            "test".to_string(),
+        )));
+        let resolved = resolve_module(&context, &module, true).unwrap();
+        assert!(context.errors.borrow().is_empty());
+        let cache = context.cache.unwrap().into_inner();
+        cache.write(path).unwrap();
+        let mut names = resolved
+            .toplevels
+            .iter()
+            .map(|resolved_ast::TopLevel::Function(function)| function.decl.name.clone())
+            .collect::<Vec<_>>();
+        names.sort();
+        (names, cache.reused_count)
+    }
+
+    #[test]
+    fn test_reuse_unchanged_functions() {
+        let source = r#"
+interface ->bool(self) : bool
+
+impl ->bool for i32 (self) {
+  (> self 0)
+}
+
+fn double(x: i32): i32 {
+  (* x 2)
+}
+
+fn main(): i32 {
+  (:= v [1, 2, 3])
+  (:= f (fn (x: i32) (double x)))
+  (if (->bool (Vec::len v)) (f 1) 0)
+}
+"#;
+        let path = std::env::temp_dir().join(format!("hirou-cache-{}.json", std::process::id()));
+        let (names, reused) = resolve_with_cache(source, &path);
+        assert_eq!(reused, 0);
+
+        // 何も変えなければ全ての関数で前回の結果を使う
+        assert_eq!(
+            resolve_with_cache(source, &path),
+            (names.clone(), names.len())
+        );
+
+        // 本体だけを変えた関数は、それだけを解決し直す
+        let changed_body = source.replace("(* x 2)", "(* x 3)");
+        assert_eq!(
+            resolve_with_cache(&changed_body, &path),
+            (names.clone(), names.len() - 1)
+        );
+
+        // シグネチャを変えると、呼び出し元も解決し直す
+        let changed_signature =
+            changed_body.replace("fn double(x: i32): i32", "fn double(x: i64): i32");
+        assert_eq!(
+            resolve_with_cache(&changed_signature, &path),
+            (names.clone(), names.len() - 2)
+        );
+
+        // 使わなくなった関数は次のコンパイルに持ち越さない
+        let unused_double = changed_signature.replace("(double x)", "x");
+        let (names, _) = resolve_with_cache(&unused_double, &path);
+        let mut cached = IncrementalCache::read(&path, "test".to_string())
+            .previous
+            .into_keys()
+            .collect::<Vec<_>>();
+        cached.sort();
+        assert_eq!(cached, names);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // 位置も比べられるようにJSONにした解決済みの関数と、書き出したキャッシュ
+    fn compile_with_cache(source: &str, path: &Path) -> (Vec<serde_json::Value>, IncrementalCache) {
+        let (_, module) = parse_module(source.into()).finish().unwrap();
+        let mut context = ResolverContext::new(PointerSizedIntWidth::SixtyFour);
+        context.cache = Some(std::cell::RefCell::new(IncrementalCache::read(
+            path,
+            "test".to_string(),
+        )));
+        let resolved = resolve_module(&context, &module, true).unwrap();
+        assert!(context.errors.borrow().is_empty());
+        let mut functions = resolved
+            .toplevels
+            .iter()
+            .map(|resolved_ast::TopLevel::Function(function)| {
+                serde_json::to_value(function).unwrap()
+            })
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|function| function.to_string());
+        concretize_module(
+            resolved,
+            PointerSizedIntWidth::SixtyFour,
+            context.cache.as_ref(),
+        );
+        let cache = context.cache.unwrap().into_inner();
+        cache.write(path).unwrap();
+        (functions, cache)
+    }
+
+    #[test]
+    fn test_reuse_moved_functions() {
+        let source = r#"
+fn double(x: i32): i32 {
+  (* x 2)
+}
+
+fn main(): i32 {
+  (:= f (fn (x: i32) (double x)))
+  (f 1)
+}
+"#;
+        let path =
+            std::env::temp_dir().join(format!("hirou-cache-moved-{}.json", std::process::id()));
+        let (functions, cache) = compile_with_cache(source, &path);
+        assert_eq!(cache.reused_concrete_count, 0);
+
+        // 何も変えなければ具体的な関数も作り直さない
+        let (_, cache) = compile_with_cache(source, &path);
+        assert_eq!(cache.reused_count, functions.len());
+        assert_eq!(cache.reused_concrete_count, functions.len());
+
+        // 行がずれただけなら解決し直さず、位置だけを今の位置にする
+        let moved = format!("\n\n{}", source.replace("  (* x 2)", "    (* x 2)"));
+        let (moved_functions, cache) = compile_with_cache(&moved, &path);
+        assert_eq!(cache.reused_count, functions.len());
+        assert_eq!(cache.reused_concrete_count, 0);
+        std::fs::remove_file(&path).unwrap();
+        let (fresh_functions, cache) = compile_with_cache(&moved, &path);
+        assert_eq!(cache.reused_count, 0);
+        assert_eq!(moved_functions, fresh_functions);
+        assert_ne!(moved_functions, functions);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -4,7 +4,7 @@ use std::fmt::Display;
 use thiserror::Error;
 
 use crate::{
-    ast::{Range, UnresolvedType},
+    ast::{FileId, Range, UnresolvedType},
     resolved_ast::ResolvedType,
 };
 
@@ -14,18 +14,34 @@ pub enum CompileErrorKind {
     VariableNotFound { name: String },
     #[error("Function `{name:?}` is not found.")]
     FunctionNotFound { name: String },
+    #[error("Function `{name}` is private to its library")]
+    PrivateFunction { name: String },
     #[error("Interface `{name:?}` is not found.")]
     InterfaceNotFound { name: String },
     #[error("`{name:?}` is not implemented for `{ty:?}`")]
     InterfaceNotImplemented { name: String, ty: ResolvedType },
     #[error("`{name:?}` is not a function")]
     IsNotFunction { name: String },
+    #[error("`{name}` cannot be used as a value, because it is generic or takes variable arguments")]
+    FunctionNotValue { name: String },
+    #[error("Cannot assign to `{name}`, because it is captured by copy in this `fn` expression")]
+    AssignToCapturedVariable { name: String },
     #[error("`{name:?}` is not a typename")]
     IsNotType { name: String },
     #[error("`{name:?}` is not a variable")]
     IsNotVariable { name: String },
     #[error("Invalid operand. Expected numeric value, but got `{actual:?}`")]
     InvalidNumericOperand { actual: ResolvedType },
+    #[error("Invalid operand. Expected integer value, but got `{actual}`")]
+    InvalidIntegerOperand { actual: ResolvedType },
+    #[error("Cannot negate unsigned integer `{ty}`")]
+    CannotNegateUnsigned { ty: ResolvedType },
+    #[error("`{keyword}` outside of a loop")]
+    OutsideOfLoop { keyword: String },
+    #[error("Use of undeclared label `'{label}`")]
+    UndeclaredLabel { label: String },
+    #[error("`break` with a value is only allowed in `(while true ...)`, because the loop can also end when its condition becomes false")]
+    BreakValueInConditionalLoop,
     #[error("Invalid argument.")]
     InvalidArgument,
     #[error("Type does not match. expected `{expected}`, but got `{actual}`")]
@@ -79,14 +95,73 @@ pub enum CompileErrorKind {
     ImplForPointerIsInvalid,
     #[error("Cannot implement interface for inference type")]
     ImplForInferenceIsInvalid,
+    #[error("Cannot implement interface for dyn type")]
+    ImplForDynIsInvalid,
+    #[error("Cannot implement interface for function type")]
+    ImplForFunctionIsInvalid,
+    #[error("Cannot implement interface for tuple type")]
+    ImplForTupleIsInvalid,
+    #[error("Cannot implement interface for array type")]
+    ImplForArrayIsInvalid,
+    #[error("Array literal has {actual} elements, but `{ty}` requires {expected}")]
+    ArrayLengthMismatch {
+        ty: ResolvedType,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("Index {index} is out of bounds for `{ty}`")]
+    IndexOutOfBounds { index: u64, ty: ResolvedType },
+    #[error("extern function `{name}` cannot have generic arguments")]
+    GenericExternFunction { name: String },
+    #[error("Type `{ty}` cannot be used in the C signature of `{name}`")]
+    NotFfiSafe { name: String, ty: ResolvedType },
+    #[error("`#[link]` can only be used on extern functions")]
+    LinkAttributeOnNonExternFunction,
+    #[error(
+        "`#[export]` can only be used on non-generic functions with a body, but `{name}` is not"
+    )]
+    InvalidExportTarget { name: String },
+    #[error("`{name}` is not a valid C identifier")]
+    InvalidExportName { name: String },
+    #[error("`#[test]` can only be used on non-generic functions without arguments that return `bool`, but `{name}` is not")]
+    InvalidTestFunction { name: String },
+    #[error("`use {path}` does not match any function")]
+    UnresolvedImport { path: String },
+    #[error("Cannot destructure a value of type `{ty}` into {count} variables")]
+    InvalidDestructuring { ty: ResolvedType, count: usize },
     #[error("Invalid number literal: `{value}`")]
     InvalidNumberLiteral { value: String },
+    #[error("Number literal `{value}` is out of range for `{ty}`. The range of `{ty}` is {min}..={max}")]
+    NumberLiteralOutOfRange {
+        value: String,
+        ty: ResolvedType,
+        min: String,
+        max: String,
+    },
+    #[error("Cannot cast `{from}` to `{to}`")]
+    InvalidCast { from: ResolvedType, to: ResolvedType },
+    #[error("Implicit conversion from `{from}` to `{to}` may change the value. Use `(as {to} ...)` to convert explicitly.")]
+    ImplicitConversion { from: ResolvedType, to: ResolvedType },
+    #[error("Cannot infer the type of {description}. Consider adding a type annotation.")]
+    AmbiguousType { description: String },
+    #[error("The type of this expression must be known here. Consider adding a type annotation.")]
+    TypeAnnotationRequired,
+    #[error("`{ty}` does not implement `{interface}`, which is required by the bound `{bound}`")]
+    UnsatisfiedBound {
+        ty: ResolvedType,
+        interface: String,
+        bound: String,
+    },
 }
 
 #[derive(Debug, Error, PartialEq)]
 pub struct CompileError {
     range: Range,
     kind: CompileErrorKind,
+    // エラーに関連する別の位置 (境界の宣言位置など)
+    notes: Vec<(Range, String)>,
+    // エラーが見つかった関数のファイル。関数の解決が終わったときに記録する
+    file: Option<FileId>,
 }
 
 #[derive(Debug)]
@@ -94,7 +169,32 @@ pub struct FaitalError(pub String);
 
 impl CompileError {
     pub fn new(range: Range, kind: CompileErrorKind) -> Self {
-        CompileError { kind, range }
+        CompileError {
+            kind,
+            range,
+            notes: Vec::new(),
+            file: None,
+        }
+    }
+
+    pub fn with_note(mut self, range: Range, message: impl Into<String>) -> Self {
+        self.notes.push((range, message.into()));
+        self
+    }
+
+    #[cfg(test)]
+    pub fn kind(&self) -> &CompileErrorKind {
+        &self.kind
+    }
+
+    // 関数の外で見つかったエラーは、MAIN のものとして扱う
+    pub fn file(&self) -> FileId {
+        self.file.unwrap_or(FileId::MAIN)
+    }
+
+    // 内側で解決した関数のエラーには、既にその関数のファイルが記録されている
+    pub(crate) fn set_file_if_unset(&mut self, file: FileId) {
+        self.file.get_or_insert(file);
     }
 }
 
@@ -149,6 +249,20 @@ impl CompileError {
                 .collect::<Vec<_>>()
                 .join("\n")
         )?;
+        for (range, message) in &self.notes {
+            match source.lines().nth(range.from.line.saturating_sub(1) as usize) {
+                Some(line) => writeln!(
+                    f,
+                    "note: {}\n  in {}:{}:{}\n{:4} |{}\n",
+                    message, path, range.from.line, range.from.col, range.from.line, line
+                )?,
+                None => writeln!(
+                    f,
+                    "note: {}\n  at line {}:{}\n",
+                    message, range.from.line, range.from.col
+                )?,
+            }
+        }
         Ok(())
     }
 }
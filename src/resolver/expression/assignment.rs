@@ -1,5 +1,7 @@
 use super::*;
 
+use super::lambda::capture_variable;
+
 use crate::resolver::{AssignExpr, ResolverContext};
 
 //上記を参考にして、Statementではなく、Effectとして扱うことにする
@@ -7,22 +9,120 @@ pub(super) fn resolve_assignment(
     context: &ResolverContext,
     assignment_expr: &Located<&AssignExpr>,
 ) -> Result<ResolvedExpression, FaitalError> {
-    let resolved_expr =
-        resolve_expression(context, assignment_expr.value.value.as_inner_deref(), None)?;
+    let variable = context
+        .scopes
+        .borrow()
+        .lookup(&assignment_expr.name)
+        .map(|(depth, variable)| (depth, variable.clone()));
+    let Some((depth, variable)) = variable else {
+        context.errors.borrow_mut().push(CompileError::new(
+            assignment_expr.range,
+            CompileErrorKind::VariableNotFound {
+                name: assignment_expr.name.clone(),
+            },
+        ));
+        return Ok(ResolvedExpression {
+            ty: ResolvedType::Void,
+            kind: ExpressionKind::Unknown,
+            location: context.location(assignment_expr.range),
+        });
+    };
+    // `fn` 式に取り込んだ変数はコピーなので、変数そのものへの代入は外側の変数に反映されない。
+    // ポインタを介した代入はできる
+    if capture_variable(context, depth, &variable)
+        && assignment_expr.deref_count == 0
+        && assignment_expr.index_access.is_none()
+    {
+        context.errors.borrow_mut().push(CompileError::new(
+            assignment_expr.range,
+            CompileErrorKind::AssignToCapturedVariable {
+                name: assignment_expr.name.clone(),
+            },
+        ));
+    }
+    // 固定長配列の要素への代入では、添字を適用する前の型が配列になる
+    let array_ty = match &assignment_expr.index_access {
+        Some(_) => {
+            let mut target_ty = context.zonk(&variable.ty);
+            for _ in 0..assignment_expr.deref_count {
+                target_ty = match context.structurally_resolve(&target_ty) {
+                    ResolvedType::Ptr(inner) => context.zonk(&inner),
+                    _ => ResolvedType::Unknown,
+                };
+            }
+            match target_ty {
+                ResolvedType::Array(_, _) => Some(target_ty),
+                _ => None,
+            }
+        }
+        None => None,
+    };
+    // 変数そのもの、または配列の要素への代入であれば、その型を型推論の手がかりにする
+    let variable_ty = match &array_ty {
+        Some(ResolvedType::Array(element, _)) => Some((**element).clone()),
+        _ if assignment_expr.deref_count == 0 && assignment_expr.index_access.is_none() => {
+            Some(variable.ty)
+        }
+        _ => None,
+    };
+    let resolved_expr = resolve_expression(
+        context,
+        assignment_expr.value.value.as_inner_deref(),
+        variable_ty.as_ref().and_then(value_annotation),
+    )?;
+    let resolved_expr = match &variable_ty {
+        Some(variable_ty) => coerce_to_dyn(
+            context,
+            variable_ty,
+            resolved_expr,
+            assignment_expr.value.value.range,
+        )?,
+        None => resolved_expr,
+    };
+    let resolved_expr = match &variable_ty {
+        Some(variable_ty) => {
+            if !context.can_insert(variable_ty, &resolved_expr.ty) {
+                context.errors.borrow_mut().push(CompileError::new(
+                    assignment_expr.value.value.range,
+                    CompileErrorKind::TypeMismatch {
+                        expected: context.zonk_for_error(variable_ty),
+                        actual: context.zonk_for_error(&resolved_expr.ty),
+                    },
+                ));
+            }
+            coerce_integer(
+                context,
+                variable_ty,
+                resolved_expr,
+                assignment_expr.value.value.range,
+            )
+        }
+        None => resolved_expr,
+    };
     Ok(ResolvedExpression {
         ty: ResolvedType::Void,
         kind: ExpressionKind::Assignment(resolved_ast::Assignment {
-            name: assignment_expr.name.clone(),
+            name: variable.resolved_name,
             value: Box::new(resolved_expr),
             deref_count: assignment_expr.deref_count as usize,
             index_access: assignment_expr
                 .index_access
                 .as_ref()
-                .map(|x| {
-                    resolve_expression(context, x.as_inner_deref(), Some(&ResolvedType::USize))
+                .map(|x| -> Result<_, FaitalError> {
+                    let index = resolve_expression(
+                        context,
+                        x.as_inner_deref(),
+                        Some(&ResolvedType::USize),
+                    )?;
+                    if let Some(array_ty) = &array_ty {
+                        check_array_index(context, array_ty, &index, x.range);
+                    }
+                    Ok(index)
                 })
                 .transpose()?
                 .map(Box::new),
+            target_is_array: array_ty.is_some(),
         }),
+        location: context.location(assignment_expr.range),
     })
 }
@@ -11,37 +11,95 @@ pub(super) fn resolve_binary_expression(
     let lhs = resolve_expression(context, bin_expr.lhs.as_deref(), None)?;
     let rhs = resolve_expression(context, bin_expr.rhs.as_deref(), None)?;
     match bin_expr.op {
-        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => {
-            if !lhs.ty.is_integer_type() {
-                context.errors.borrow_mut().push(CompileError::new(
-                    bin_expr.range,
-                    CompileErrorKind::InvalidNumericOperand {
-                        actual: lhs.ty.clone(),
-                    },
-                ));
-            }
-            if !rhs.ty.is_integer_type() {
-                context.errors.borrow_mut().push(CompileError::new(
-                    bin_expr.range,
-                    CompileErrorKind::InvalidNumericOperand {
-                        actual: rhs.ty.clone(),
-                    },
-                ));
-            }
-            let ty: ResolvedType = match get_cast_type(
-                &lhs.ty
-                    .unwrap_primitive_into_concrete_type(context.is_64_bit()),
-                &rhs.ty
-                    .unwrap_primitive_into_concrete_type(context.is_64_bit()),
-            ) {
-                (None, None) => lhs
-                    .ty
-                    .unwrap_primitive_into_concrete_type(context.is_64_bit()),
-                (None, Some(t)) => t,
-                (Some(t), None) => t,
-                (Some(_), Some(t)) => t,
+        BinaryOp::Add
+        | BinaryOp::Sub
+        | BinaryOp::Mul
+        | BinaryOp::Div
+        | BinaryOp::Rem
+        | BinaryOp::BitAnd
+        | BinaryOp::BitOr
+        | BinaryOp::BitXor => {
+            let is_bitwise = matches!(
+                bin_expr.op,
+                BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor
+            );
+            // どちらかの型が決まっていなければ、もう一方の型に合わせる
+            let lhs_ty = context.shallow_resolve(&lhs.ty);
+            let rhs_ty = context.shallow_resolve(&rhs.ty);
+            if matches!(lhs_ty, ResolvedType::Var(_)) || matches!(rhs_ty, ResolvedType::Var(_)) {
+                let unified = if is_bitwise {
+                    context.unify(&lhs_ty, &rhs_ty)
+                } else {
+                    context.unify_numeric(&lhs_ty, &rhs_ty)
+                };
+                if !unified {
+                    context.errors.borrow_mut().push(CompileError::new(
+                        bin_expr.range,
+                        CompileErrorKind::TypeMismatch {
+                            expected: context.zonk_for_error(&lhs_ty),
+                            actual: context.zonk_for_error(&rhs_ty),
+                        },
+                    ));
+                }
             }
-            .unwrap_primitive_into_resolved_type();
+            let (lhs_ty, rhs_ty) = if is_bitwise {
+                // ビット演算は整数にしか使えないので、ここで型を確定させる
+                (
+                    context.structurally_resolve(&lhs.ty),
+                    context.structurally_resolve(&rhs.ty),
+                )
+            } else {
+                (
+                    context.shallow_resolve(&lhs.ty),
+                    context.shallow_resolve(&rhs.ty),
+                )
+            };
+            let ty = if let ResolvedType::Var(_) = lhs_ty {
+                // 両辺ともリテラルなどで型が決まっていない
+                lhs_ty
+            } else {
+                let mut is_valid = true;
+                for operand_ty in [&lhs_ty, &rhs_ty] {
+                    if is_bitwise && !operand_ty.is_integer_type() {
+                        is_valid = false;
+                        context.errors.borrow_mut().push(CompileError::new(
+                            bin_expr.range,
+                            CompileErrorKind::InvalidIntegerOperand {
+                                actual: operand_ty.clone(),
+                            },
+                        ));
+                    } else if !operand_ty.is_integer_type() && !operand_ty.is_float_type() {
+                        is_valid = false;
+                        context.errors.borrow_mut().push(CompileError::new(
+                            bin_expr.range,
+                            CompileErrorKind::InvalidNumericOperand {
+                                actual: operand_ty.clone(),
+                            },
+                        ));
+                    }
+                }
+                if is_valid {
+                    let ty = match get_cast_type(
+                        &lhs_ty.unwrap_primitive_into_concrete_type(context.is_64_bit()),
+                        &rhs_ty.unwrap_primitive_into_concrete_type(context.is_64_bit()),
+                    ) {
+                        (None, None) => lhs_ty.unwrap_primitive_into_concrete_type(context.is_64_bit()),
+                        (None, Some(t)) => t,
+                        (Some(t), None) => t,
+                        (Some(_), Some(t)) => t,
+                    }
+                    .unwrap_primitive_into_resolved_type();
+                    // 型の異なる数値同士の演算では、両辺を結果の型に変換する
+                    for (operand_ty, operand) in [(&lhs_ty, &bin_expr.lhs), (&rhs_ty, &bin_expr.rhs)] {
+                        if *operand_ty != ty {
+                            check_implicit_conversion(context, operand_ty, &ty, operand.range);
+                        }
+                    }
+                    ty
+                } else {
+                    ResolvedType::Unknown
+                }
+            };
             Ok(resolved_ast::ResolvedExpression {
                 kind: resolved_ast::ExpressionKind::Binary(resolved_ast::BinaryExpr {
                     op: bin_expr.op,
@@ -49,6 +107,37 @@ pub(super) fn resolve_binary_expression(
                     rhs: Box::new(rhs),
                 }),
                 ty,
+                location: context.location(bin_expr.range),
+            })
+        }
+        BinaryOp::Shl | BinaryOp::Shr => {
+            // シフト量の型は左辺と揃える必要はなく、結果は左辺の型になる
+            let lhs_ty = context.structurally_resolve(&lhs.ty);
+            let rhs_ty = context.structurally_resolve(&rhs.ty);
+            let mut is_valid = true;
+            for operand_ty in [&lhs_ty, &rhs_ty] {
+                if !operand_ty.is_integer_type() && !matches!(operand_ty, ResolvedType::Var(_)) {
+                    is_valid = false;
+                    context.errors.borrow_mut().push(CompileError::new(
+                        bin_expr.range,
+                        CompileErrorKind::InvalidIntegerOperand {
+                            actual: operand_ty.clone(),
+                        },
+                    ));
+                }
+            }
+            Ok(resolved_ast::ResolvedExpression {
+                kind: resolved_ast::ExpressionKind::Binary(resolved_ast::BinaryExpr {
+                    op: bin_expr.op,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                }),
+                ty: if is_valid {
+                    lhs_ty
+                } else {
+                    ResolvedType::Unknown
+                },
+                location: context.location(bin_expr.range),
             })
         }
         BinaryOp::Equals
@@ -57,12 +146,12 @@ pub(super) fn resolve_binary_expression(
         | BinaryOp::LessThanOrEquals
         | BinaryOp::GreaterThan
         | BinaryOp::GreaterThanOrEquals => {
-            if lhs.ty != rhs.ty {
+            if !context.unify_numeric(&lhs.ty, &rhs.ty) {
                 context.errors.borrow_mut().push(CompileError::new(
                     bin_expr.range,
                     CompileErrorKind::TypeMismatch {
-                        expected: lhs.ty.clone(),
-                        actual: rhs.ty.clone(),
+                        expected: context.zonk_for_error(&lhs.ty),
+                        actual: context.zonk_for_error(&rhs.ty),
                     },
                 ));
             }
@@ -73,6 +162,7 @@ pub(super) fn resolve_binary_expression(
                     rhs: Box::new(rhs),
                 }),
                 ty: ResolvedType::Bool,
+                location: context.location(bin_expr.range),
             })
         }
     }
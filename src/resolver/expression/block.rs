@@ -0,0 +1,50 @@
+use super::*;
+
+use crate::ast::{DoExpr, Statement};
+use crate::resolver::statement::resolve_return_in_block;
+use crate::resolver::ResolverContext;
+
+pub(super) fn resolve_do_expr(
+    context: &ResolverContext,
+    do_expr: &Located<&DoExpr>,
+    annotation: Option<&ResolvedType>,
+) -> Result<ResolvedExpression, FaitalError> {
+    // ブロックの中で宣言した変数は、ブロックの外からは見えない
+    in_new_scope!(context.scopes, {
+        resolve_do_expr_in_scope(context, do_expr, annotation)
+    })
+}
+
+fn resolve_do_expr_in_scope(
+    context: &ResolverContext,
+    do_expr: &Located<&DoExpr>,
+    annotation: Option<&ResolvedType>,
+) -> Result<ResolvedExpression, FaitalError> {
+    let mut statements = Vec::new();
+    for (i, statement) in do_expr.statements.iter().enumerate() {
+        let is_last = i + 1 == do_expr.statements.len();
+        statements.push(match &statement.value {
+            Statement::Return(ret) => resolve_return_in_block(context, ret, statement.range)?,
+            Statement::Effect(effect) => resolved_ast::Statement::Effect(resolved_ast::Effect {
+                // ブロックの値になる最後の式にだけ、型の注釈を伝える
+                expression: resolve_expression(
+                    context,
+                    effect.expression.as_ref(),
+                    annotation.filter(|_| is_last),
+                )?,
+                location: context.location(statement.range),
+            }),
+        });
+    }
+
+    // 最後がreturnのブロックは値を返さない
+    let ty = match statements.last() {
+        Some(resolved_ast::Statement::Effect(effect)) => effect.expression.ty.clone(),
+        _ => ResolvedType::Void,
+    };
+    Ok(ResolvedExpression {
+        ty,
+        kind: ExpressionKind::Block(resolved_ast::BlockExpr { statements }),
+        location: context.location(do_expr.range),
+    })
+}
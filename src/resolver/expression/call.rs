@@ -1,25 +1,57 @@
-use itertools::Itertools;
-use std::collections::HashMap;
-
-use crate::{
-    ast::UnresolvedType,
-    resolver::{generics::check_generic_bounds, resolve_implementation, ResolverContext},
+use crate::resolver::{
+    generics::{find_implementation, implements_interface},
+    infer::{DeferredInstantiation, TypeVarKind},
+    mangle::{function_symbol, impl_symbol},
+    resolve_implementation, ResolverContext,
 };
 
 use super::*;
 
-/// Resolves a function call with explicit generic arguments.
-/// Returns true if resolution was successful, false if generic args were missing or mismatched.
-pub fn resolve_call_with_generic_args(
+// ジェネリック引数を持たない関数の解決を試みる関数
+pub fn resolve_non_generic_function(
     context: &ResolverContext,
-    call_expr: &Located<&ast::CallExpr>,
     callee: &ast::Function,
+    // 推論に成功した場合のみtrueを返す
 ) -> Result<bool, FaitalError> {
-    let Some(callee_generic_args) = &callee.decl.generic_args else {
+    // ジェネリック引数が存在する場合は、解決を行わない
+    if callee.decl.generic_args.is_some() {
         return Ok(false);
+    }
+    // グローバルスコープで関数の解決を試みる
+    in_global_scope!(context.scopes, {
+        in_global_scope!(context.types, {
+            resolve_function(context, callee)?;
+        });
+    });
+    Ok(true)
+}
+
+// 呼び出しごとのジェネリック引数を用意する。
+// 明示されていなければ型変数を割り当て、引数やアノテーションとの単一化で決める
+fn instantiate_generic_args(
+    context: &ResolverContext,
+    call_expr: &Located<&ast::CallExpr>,
+    callee: &ast::Function,
+) -> Result<Option<Vec<ResolvedType>>, FaitalError> {
+    let Some(callee_generic_args) = &callee.decl.generic_args else {
+        return Ok(Some(Vec::new()));
     };
     let Some(call_generic_args) = &call_expr.generic_args else {
-        return Ok(false);
+        return Ok(Some(
+            callee_generic_args
+                .iter()
+                .map(|generic_arg| {
+                    context.fresh_type_var(
+                        TypeVarKind::General,
+                        call_expr.range,
+                        format!(
+                            "generic argument `{}` of `{}`",
+                            generic_arg.name, callee.decl.name
+                        ),
+                    )
+                })
+                .collect(),
+        ));
     };
 
     // Check generic argument count matches
@@ -32,286 +64,32 @@ pub fn resolve_call_with_generic_args(
                 actual: call_generic_args.len(),
             },
         ));
-        return Ok(false);
+        return Ok(None);
     }
-
-    // Resolve and register generic arguments
     let mut resolved_generic_args = Vec::new();
-    for (i, call_generic_arg) in call_generic_args.iter().enumerate() {
-        let resolved_generic_arg = resolve_type(context, call_generic_arg)?;
-        resolved_generic_args.push(resolved_generic_arg.clone());
-        context.types.borrow_mut().add(
-            callee_generic_args[i].name.clone(),
-            resolved_generic_arg,
-        );
-    }
-
-    // Check interface bounds for generic arguments
-    if let Err(e) = check_generic_bounds(
-        context,
-        callee_generic_args,
-        &resolved_generic_args,
-        &resolved_generic_args,
-    ) {
-        context.errors.borrow_mut().push(e);
+    for call_generic_arg in call_generic_args {
+        resolved_generic_args.push(resolve_type(context, call_generic_arg)?);
     }
-
-    // Resolve the function with concrete type parameters
-    resolve_function(context, callee)?;
-    Ok(true)
-}
-
-fn infer_generic_args_recursively(
-    tmp_errors: &mut Vec<CompileError>,
-    context: &ResolverContext,
-    callee: &ast::Function,
-    current_callee_return_ty: &UnresolvedType,
-    current_annotation: &ResolvedType,
-) -> Result<bool, FaitalError> {
-    let callee_generic_args = callee.decl.generic_args.as_ref().unwrap();
-    match current_callee_return_ty {
-        UnresolvedType::TypeRef(return_ty_typeref) => {
-            if return_ty_typeref.generic_args.is_none() {
-                if let Some(generic_arg) = callee_generic_args
-                    .iter()
-                    .find(|x| x.value.name == return_ty_typeref.name)
-                {
-                    context
-                        .types
-                        .borrow_mut()
-                        .add(generic_arg.value.name.clone(), current_annotation.clone());
-                    return Ok(true);
-                } else {
-                    return Ok(false);
-                }
-            }
-            if let Some(generic_args) = &return_ty_typeref.generic_args {
-                match current_annotation {
-                    ResolvedType::StructLike(resolved_struct) => {
-                        if resolved_struct.non_generic_name == return_ty_typeref.name {
-                            let mut generic_arg_inferred = false;
-                            if let Some(resolved_generic_args) = &resolved_struct.generic_args {
-                                for (i, resolved_generic_ty) in
-                                    resolved_generic_args.iter().enumerate()
-                                {
-                                    if infer_generic_args_recursively(
-                                        tmp_errors,
-                                        context,
-                                        callee,
-                                        &generic_args[i].value,
-                                        resolved_generic_ty,
-                                    )? {
-                                        generic_arg_inferred = true;
-                                    }
-                                }
-                            }
-                            if generic_arg_inferred {
-                                return Ok(true);
-                            }
-                        } else {
-                            return Ok(false);
-                        }
-                    }
-                    _ => {
-                        return Ok(false);
-                    }
-                }
-            }
-        }
-        UnresolvedType::Ptr(return_ty_pointer_ty) => {
-            if let ResolvedType::Ptr(inner) = current_annotation {
-                if infer_generic_args_recursively(
-                    tmp_errors,
-                    context,
-                    callee,
-                    return_ty_pointer_ty,
-                    inner,
-                )? {
-                    return Ok(true);
-                }
-            }
-        }
-        UnresolvedType::Infer => {
-            return Ok(false);
-        }
-    }
-    Ok(false)
+    Ok(Some(resolved_generic_args))
 }
 
-// Helper function to recursively infer generic types from argument types
-fn infer_generic_type_from_match(
+// 関数の引数と戻り値の型を解決する。可変長引数の位置はNoneになる
+pub(super) fn resolve_signature(
     context: &ResolverContext,
-    callee_generic_args: &[Located<ast::GenericArgument>],
-    param_ty: &UnresolvedType,
-    arg_ty: &ResolvedType,
-    inferred_indices: &mut Vec<usize>,
-    inferred_types: &mut HashMap<String, ResolvedType>,
-) -> Result<(), FaitalError> {
-    match param_ty {
-        UnresolvedType::TypeRef(typeref) => {
-            // Check if this is a direct generic parameter (e.g., T)
-            if typeref.generic_args.is_none() {
-                for (gen_idx, gen_arg) in callee_generic_args.iter().enumerate() {
-                    if typeref.name == gen_arg.name {
-                        // Check if this generic type has already been inferred in THIS call
-                        if let Some(existing_ty) = inferred_types.get(&gen_arg.name) {
-                            // Already inferred, verify it matches
-                            if existing_ty != arg_ty {
-                                // Type mismatch in inference - this is an error but we'll let
-                                // the later type checking catch it
-                            }
-                        } else {
-                            // Direct match: parameter is T, infer from argument type
-                            inferred_types.insert(gen_arg.name.clone(), arg_ty.clone());
-                            if !inferred_indices.contains(&gen_idx) {
-                                inferred_indices.push(gen_idx);
-                            }
-                        }
-                        return Ok(());
-                    }
-                }
-            } else {
-                // Parameter has generic arguments (e.g., Vec<T>)
-                // Check if argument type is a struct with matching name
-                if let ResolvedType::StructLike(resolved_struct) = arg_ty {
-                    if resolved_struct.non_generic_name == typeref.name {
-                        // Names match, now match generic arguments recursively
-                        if let Some(param_generic_args) = &typeref.generic_args {
-                            if let Some(arg_generic_args) = &resolved_struct.generic_args {
-                                // Match each generic argument
-                                for (param_gen_ty, arg_gen_ty) in param_generic_args.iter().zip(arg_generic_args.iter()) {
-                                    infer_generic_type_from_match(
-                                        context,
-                                        callee_generic_args,
-                                        &param_gen_ty.value,
-                                        arg_gen_ty,
-                                        inferred_indices,
-                                        inferred_types,
-                                    )?;
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        UnresolvedType::Ptr(inner_param_ty) => {
-            // Parameter is a pointer type
-            if let ResolvedType::Ptr(inner_arg_ty) = arg_ty {
-                infer_generic_type_from_match(
-                    context,
-                    callee_generic_args,
-                    &inner_param_ty.value,
-                    inner_arg_ty,
-                    inferred_indices,
-                    inferred_types,
-                )?;
-            }
-        }
-        UnresolvedType::Infer => {
-            // Cannot infer from infer type
-        }
-    }
-    Ok(())
-}
-
-// 関数のジェネリック引数を引数から推論する関数
-pub fn resolve_infer_generic_from_arguments(
-    context: &ResolverContext,
-    call_expr: &Located<&ast::CallExpr>,
-    callee: &ast::Function,
-    resolved_args: &[ResolvedExpression],
-) -> Result<(Vec<usize>, HashMap<String, ResolvedType>), FaitalError> {
-    // ジェネリック引数が既に指定されている場合、または宣言されていない場合は推論を行わない
-    if call_expr.generic_args.is_some() {
-        return Ok((vec![], HashMap::new()));
-    }
-    let Some(callee_generic_args) = &callee.decl.generic_args else {
-        return Ok((vec![], HashMap::new()));
-    };
-    // 可変長引数を持つ関数は、推論を行わない
-    if callee.decl.args.iter().any(|x| matches!(x, ast::Argument::VarArgs)) {
-        return Ok((vec![], HashMap::new()));
-    }
-
-    let mut inferred_indices = Vec::new();
-    let mut inferred_types = HashMap::new();
-
-    // 各引数からジェネリック型を推論
-    for (i, callee_arg) in callee.decl.args.iter().enumerate() {
-        if let ast::Argument::Normal(arg_ty, _) = callee_arg {
-            if let Some(resolved_arg) = resolved_args.get(i) {
-                infer_generic_type_from_match(
-                    context,
-                    callee_generic_args,
-                    &arg_ty.value,
-                    &resolved_arg.ty,
-                    &mut inferred_indices,
-                    &mut inferred_types,
-                )?;
+    decl: &ast::FunctionDecl,
+) -> Result<(Vec<Option<ResolvedType>>, ResolvedType), FaitalError> {
+    let mut param_types = Vec::new();
+    for arg in &decl.args {
+        match arg {
+            ast::Argument::VarArgs => param_types.push(None),
+            ast::Argument::SelfArg => {
+                // SelfArg is only valid in interface implementations
+                unreachable!("SelfArg is not allowed in regular function calls")
             }
+            ast::Argument::Normal(ty, _name) => param_types.push(Some(resolve_type(context, ty)?)),
         }
     }
-
-    Ok((inferred_indices, inferred_types))
-}
-
-// 関数のジェネリック引数をアノテーションから推論する関数
-pub fn resolve_infer_generic_from_annotation(
-    context: &ResolverContext,
-    call_expr: &Located<&ast::CallExpr>,
-    callee: &ast::Function,
-    annotation: Option<&ResolvedType>,
-    // 推論に成功したジェネリクス引数のインデックスを返す
-) -> Result<Vec<usize>, FaitalError> {
-    // ジェネリック引数が存在しない場合は、推論を行わない
-    if call_expr.generic_args.is_some() || callee.decl.generic_args.is_none() {
-        return Ok(vec![]);
-    }
-    // アノテーションが存在する場合、推論を試みる
-    if let Some(annotation) = &annotation {
-        in_global_scope!(context.scopes, {
-            in_global_scope!(context.types, {
-                let mut temp_errors = Vec::new();
-                let inferred = infer_generic_args_recursively(
-                    &mut temp_errors,
-                    context,
-                    callee,
-                    &callee.decl.return_type.value,
-                    annotation,
-                )?;
-                // 推論が成功した場合、一時エラーを追加し、関数の解決を試みる
-                if inferred {
-                    context.errors.borrow_mut().extend(temp_errors);
-                    resolve_function(context, callee)?;
-                    Ok((0..callee.decl.generic_args.as_ref().unwrap().len()).collect_vec())
-                } else {
-                    Ok(vec![])
-                }
-            })
-        })
-    } else {
-        Ok(vec![])
-    }
-}
-
-// ジェネリック引数を持たない関数の解決を試みる関数
-pub fn resolve_non_generic_function(
-    context: &ResolverContext,
-    callee: &ast::Function,
-    // 推論に成功した場合のみtrueを返す
-) -> Result<bool, FaitalError> {
-    // ジェネリック引数が存在する場合は、解決を行わない
-    if callee.decl.generic_args.is_some() {
-        return Ok(false);
-    }
-    // グローバルスコープで関数の解決を試みる
-    in_global_scope!(context.scopes, {
-        in_global_scope!(context.types, {
-            resolve_function(context, callee)?;
-        });
-    });
-    Ok(true)
+    Ok((param_types, resolve_type(context, &decl.return_type)?))
 }
 
 fn resolve_function_call_expr(
@@ -323,6 +101,17 @@ fn resolve_function_call_expr(
     let mut resolved_args = Vec::new();
     let has_var_args = callee.decl.args.last() == Some(&ast::Argument::VarArgs);
 
+    // ライブラリの非公開の関数は、そのライブラリの関数からしか呼び出せない
+    let from_library = (0..context.libraries.len()).any(|i| callee.file == ast::FileId::library(i));
+    if from_library && !callee.decl.is_public && callee.file != *context.file.borrow() {
+        context.errors.borrow_mut().push(CompileError::new(
+            call_expr.range,
+            CompileErrorKind::PrivateFunction {
+                name: call_expr.name.to_string(),
+            },
+        ));
+    }
+
     // 可変長引数を持たない場合、引数の数が一致しなければエラーを返す
     if !has_var_args && callee.decl.args.len() != call_expr.args.len() {
         context.errors.borrow_mut().push(CompileError::new(
@@ -336,136 +125,191 @@ fn resolve_function_call_expr(
         return Ok(ResolvedExpression {
             ty: ResolvedType::Unknown,
             kind: ExpressionKind::Unknown,
+            location: context.location(call_expr.range),
         });
     }
 
-    // ジェネリック関数の解決
-    if callee.decl.generic_args.is_some() {
-        // 1. 明示的なジェネリック引数がある場合
-        let explicit_resolved = resolve_call_with_generic_args(context, call_expr, callee)?;
-
-        // 2. アノテーションからの推論を試みる
-        let annotation_inferred = if !explicit_resolved {
-            !resolve_infer_generic_from_annotation(context, call_expr, callee, annotation)?
-                .is_empty()
-        } else {
-            false
-        };
-
-        // 3. 引数からの推論を試みる
-        if !explicit_resolved && !annotation_inferred {
-            // まず引数をアノテーションなしで解決（ジェネリック推論のため）
-            let mut pre_resolved_args = Vec::new();
-            for arg in &call_expr.args {
-                pre_resolved_args.push(resolve_expression(context, arg.as_inner_deref(), None)?);
-            }
-
-            let (inferred_indices, inferred_types) = resolve_infer_generic_from_arguments(
-                context,
-                call_expr,
-                callee,
-                &pre_resolved_args,
-            )?;
-
-            // 推論されたジェネリクスの境界をチェック
-            if !inferred_indices.is_empty() {
-                if let Some(callee_generic_args) = &callee.decl.generic_args {
-                    // Add inferred types to root scope temporarily
-                    for (name, ty) in &inferred_types {
-                        context.types.borrow_mut().add_to_root(name.clone(), ty.clone());
-                    }
-
-                    let resolved_generic_args: Vec<_> = callee_generic_args
-                        .iter()
-                        .map(|g| inferred_types.get(&g.name).cloned().unwrap_or(ResolvedType::Unknown))
-                        .collect();
-
-                    if let Err(e) = check_generic_bounds(
-                        context,
-                        callee_generic_args,
-                        &resolved_generic_args,
-                        &resolved_generic_args,
-                    ) {
-                        context.errors.borrow_mut().push(e);
-                    }
+    let Some(generic_args) = instantiate_generic_args(context, call_expr, callee)? else {
+        return Ok(ResolvedExpression {
+            ty: ResolvedType::Unknown,
+            kind: ExpressionKind::Unknown,
+            location: context.location(call_expr.range),
+        });
+    };
 
-                    // 推論した型で関数を解決（グローバルスコープで実行）
-                    in_global_scope!(context.scopes, {
-                        in_global_scope!(context.types, {
-                            resolve_function(context, callee)?;
-                        });
-                    });
-                }
+    // ジェネリック引数を登録した上でシグネチャを解決する
+    let (param_types, return_ty) = if let Some(callee_generic_args) = &callee.decl.generic_args {
+        in_new_scope!(context.types, {
+            for (generic_arg, ty) in callee_generic_args.iter().zip(&generic_args) {
+                context
+                    .types
+                    .borrow_mut()
+                    .add(generic_arg.name.clone(), ty.clone());
             }
-        }
+            resolve_signature(context, &callee.decl)
+        })?
     } else {
         // 非ジェネリック関数の解決
         resolve_non_generic_function(context, callee)?;
+        resolve_signature(context, &callee.decl)?
+    };
+
+    let is_void_ptr_return = matches!(&return_ty, ResolvedType::Ptr(inner) if **inner == ResolvedType::Void);
+
+    // 戻り値の型とアノテーションを単一化する。
+    // 失敗しても暗黙の型変換で代入できる場合があるので、ここではエラーにしない
+    if let Some(annotation) = annotation {
+        if !is_void_ptr_return {
+            context.unify(&return_ty, annotation);
+        }
     }
 
-    // 各引数を解決し、型の不一致があればエラーを返す
+    // 各引数を解決し、仮引数の型と単一化する
     for (i, arg) in call_expr.args.iter().enumerate() {
-        let callee_arg = if has_var_args && i >= callee.decl.args.len() {
-            &callee.decl.args[callee.decl.args.len() - 1]
+        let param_ty = if has_var_args && i >= param_types.len() {
+            &param_types[param_types.len() - 1]
         } else {
-            &callee.decl.args[i]
+            &param_types[i]
         };
-        match callee_arg {
-            ast::Argument::VarArgs => {
+        match param_ty {
+            None => {
                 resolved_args.push(resolve_expression(context, arg.as_inner_deref(), None)?);
             }
-            ast::Argument::SelfArg => {
-                // SelfArg is only valid in interface implementations
-                unreachable!("SelfArg is not allowed in regular function calls")
-            }
-            ast::Argument::Normal(ty, _name) => {
-                let resolved_ty = resolve_type(context, ty)?;
-                let resolved_arg =
-                    resolve_expression(context, arg.as_inner_deref(), Some(&resolved_ty))?;
-                if !resolved_ty.can_insert(&resolved_arg.ty) {
-                    context.errors.borrow_mut().push(CompileError::new(
-                        arg.range,
-                        CompileErrorKind::TypeMismatch {
-                            expected: resolved_ty.clone(),
-                            actual: resolved_arg.ty.clone(),
-                        },
-                    ));
-                }
-                resolved_args.push(resolved_arg);
+            Some(param_ty) => {
+                resolved_args.push(resolve_argument(context, arg, param_ty)?);
             }
         }
     }
 
-    // 戻り値の型を解決する
-    let mut resolved_return_ty = resolve_type(context, &callee.decl.return_type)?;
     // void* はアノテーションがあればその型として扱う
-    if let Some(annotation) = annotation {
-        if let ResolvedType::Ptr(inner) = &resolved_return_ty {
-            if let ResolvedType::Void = **inner {
-                resolved_return_ty = annotation.clone();
-            }
-        }
-    }
+    let resolved_return_ty = match annotation {
+        Some(annotation) if is_void_ptr_return => annotation.clone(),
+        _ => return_ty.clone(),
+    };
+
+    // ジェネリック関数は、型推論が終わって引数の型が確定してからインスタンス化する
+    let pending_instantiation = if callee.decl.generic_args.is_some() {
+        Some(
+            context
+                .inference
+                .borrow_mut()
+                .defer_instantiation(DeferredInstantiation {
+                    callee: callee.clone(),
+                    generic_args,
+                    call_range: call_expr.range,
+                }),
+        )
+    } else {
+        None
+    };
 
-    // 解決された式を返す
+    // 解決された式を返す。ジェネリック関数の名前は、インスタンス化した後に置き換えられる
     Ok(resolved_ast::ResolvedExpression {
         kind: resolved_ast::ExpressionKind::CallExpr(resolved_ast::CallExpr {
-            callee: if callee.decl.generic_args.is_some() {
-                mangle_fn_name(
-                    &callee.decl.name,
-                    resolved_args
-                        .iter()
-                        .map(|x| &x.ty)
-                        .collect::<Vec<_>>()
-                        .as_slice(),
-                    &resolved_return_ty,
-                )
-            } else {
-                callee.decl.name.clone()
-            },
+            callee: function_symbol(&callee.decl, &[]),
             args: resolved_args,
+            pending_instantiation,
         }),
         ty: resolved_return_ty,
+        location: context.location(call_expr.range),
+    })
+}
+
+// 引数を解決し、仮引数の型に合わせる
+pub(super) fn resolve_argument(
+    context: &ResolverContext,
+    arg: &ast::LocatedExpr,
+    param_ty: &ResolvedType,
+) -> Result<ResolvedExpression, FaitalError> {
+    let expected_ty = context.shallow_resolve(param_ty);
+    let resolved_arg = resolve_expression(
+        context,
+        arg.as_inner_deref(),
+        value_annotation(&expected_ty),
+    )?;
+    let resolved_arg = coerce_to_dyn(context, &expected_ty, resolved_arg, arg.range)?;
+    if !context.can_insert(&expected_ty, &resolved_arg.ty) {
+        context.errors.borrow_mut().push(CompileError::new(
+            arg.range,
+            CompileErrorKind::TypeMismatch {
+                expected: context.zonk_for_error(&expected_ty),
+                actual: context.zonk_for_error(&resolved_arg.ty),
+            },
+        ));
+    }
+    Ok(coerce_integer(
+        context,
+        &expected_ty,
+        resolved_arg,
+        arg.range,
+    ))
+}
+
+// 変数に入った関数の値を呼び出す
+fn resolve_closure_call_expr(
+    context: &ResolverContext,
+    call_expr: &Located<&ast::CallExpr>,
+    annotation: Option<&ResolvedType>,
+) -> Result<ResolvedExpression, FaitalError> {
+    let name = call_expr.name.to_string();
+    let callee_expr = ast::Expression::VariableRef(ast::VariableRefExpr { name: name.clone() });
+    let callee = resolve_expression(
+        context,
+        Located {
+            range: call_expr.range,
+            value: &callee_expr,
+        },
+        None,
+    )?;
+    let unknown = ResolvedExpression {
+        ty: ResolvedType::Unknown,
+        kind: ExpressionKind::Unknown,
+        location: context.location(call_expr.range),
+    };
+    let function_ty = match context.zonk(&context.structurally_resolve(&callee.ty)) {
+        ResolvedType::Function(function_ty) => function_ty,
+        ResolvedType::Unknown => return Ok(unknown),
+        _ => {
+            context.errors.borrow_mut().push(CompileError::new(
+                call_expr.range,
+                CompileErrorKind::IsNotFunction { name },
+            ));
+            return Ok(unknown);
+        }
+    };
+    if call_expr.generic_args.is_some() {
+        context.errors.borrow_mut().push(CompileError::new(
+            call_expr.range,
+            CompileErrorKind::UnnecessaryGenericArgs { name },
+        ));
+        return Ok(unknown);
+    }
+    if function_ty.args.len() != call_expr.args.len() {
+        context.errors.borrow_mut().push(CompileError::new(
+            call_expr.range,
+            CompileErrorKind::MismatchFunctionArgCount {
+                name,
+                expected: function_ty.args.len(),
+                actual: call_expr.args.len(),
+            },
+        ));
+        return Ok(unknown);
+    }
+
+    if let Some(annotation) = annotation {
+        context.unify(&function_ty.return_type, annotation);
+    }
+    let mut args = Vec::new();
+    for (arg, param_ty) in call_expr.args.iter().zip(&function_ty.args) {
+        args.push(resolve_argument(context, arg, param_ty)?);
+    }
+    Ok(ResolvedExpression {
+        ty: *function_ty.return_type,
+        kind: ExpressionKind::ClosureCall(resolved_ast::ClosureCallExpr {
+            callee: Box::new(callee),
+            args,
+        }),
+        location: context.location(call_expr.range),
     })
 }
 
@@ -478,13 +322,20 @@ pub fn resolve_call_expr(
     // Convert namespace path to string for lookup
     let function_name = call_expr.name.to_string();
 
+    // 同じ名前の変数があれば、関数よりも変数を優先する
+    if !call_expr.name.is_namespaced() && context.scopes.borrow().get(&function_name).is_some() {
+        return resolve_closure_call_expr(context, call_expr, annotation);
+    }
+
     // 関数名から関数を取得し、見つからない場合はエラーを返す
     let function_by_name = context.function_by_name.borrow();
     let interface_by_name = context.interface_by_name.borrow();
-    let impls_by_name = context.impls_by_name.borrow();
 
-    // First try direct lookup
-    if let Some(callee) = function_by_name.get(&function_name) {
+    // 依存パッケージの中の関数、書かれた名前、`use` で取り込んだ名前の順に探す
+    if let Some(callee) = context
+        .qualify_function_name(&function_name)
+        .and_then(|name| function_by_name.get(&name))
+    {
         resolve_function_call_expr(context, call_expr, callee, annotation)
     } else {
         // If not found, check if it's an imported name
@@ -500,88 +351,150 @@ pub fn resolve_call_expr(
         if let Some(callee) = function_by_name.get(&resolved_name) {
             resolve_function_call_expr(context, call_expr, callee, annotation)
         } else if let Some(interface) = interface_by_name.get(&resolved_name) {
-            let mut resolved_arg_types = vec![];
+            let mut resolved_args = Vec::new();
             for arg in &call_expr.args {
-                resolved_arg_types.push(resolve_expression(context, arg.as_inner_deref(), None)?.ty);
+                resolved_args.push(resolve_expression(context, arg.as_inner_deref(), None)?);
             }
-            let mut generic_annotations: Vec<ResolvedType> = vec![];
-            if let Some(generic_args) = &call_expr.generic_args {
-                for generic_arg in generic_args {
-                    generic_annotations.push(resolve_type(context, generic_arg)?);
-                }
-            } else {
-                let required_generic_args_len = interface.generic_args.len();
-                for _ in 0..required_generic_args_len {
-                    generic_annotations.push(ResolvedType::Unknown);
+            // 実装を選ぶために、レシーバの型はここで確定させる
+            let resolved_arg_types = resolved_args
+                .iter()
+                .map(|arg| context.zonk(&context.structurally_resolve(&arg.ty)))
+                .collect::<Vec<_>>();
+            // `dyn` 型の値に対する呼び出しはvtableを介して行う
+            if let Some(ResolvedType::Dyn(dyn_interface_name)) = resolved_arg_types.first() {
+                if *dyn_interface_name == interface.name {
+                    let resolved_return_ty = resolve_type(context, &interface.return_type)?;
+                    if interface.args.len() != call_expr.args.len() {
+                        context.errors.borrow_mut().push(CompileError::new(
+                            call_expr.range,
+                            CompileErrorKind::MismatchFunctionArgCount {
+                                name: resolved_name.clone(),
+                                expected: interface.args.len(),
+                                actual: call_expr.args.len(),
+                            },
+                        ));
+                        return Ok(ResolvedExpression {
+                            ty: ResolvedType::Unknown,
+                            kind: ExpressionKind::Unknown,
+                            location: context.location(call_expr.range),
+                        });
+                    }
+                    // self以外の引数は、インターフェースの引数の型に合わせてvtable経由で渡す
+                    let receiver = resolved_args.remove(0);
+                    let mut args = Vec::new();
+                    for (i, arg) in resolved_args.into_iter().enumerate() {
+                        let expected = match &interface.args[i + 1] {
+                            ast::Argument::Normal(ty, _) => resolve_type(context, ty)?,
+                            ast::Argument::SelfArg | ast::Argument::VarArgs => {
+                                ResolvedType::Unknown
+                            }
+                        };
+                        let range = call_expr.args[i + 1].range;
+                        if !context.can_insert(&expected, &arg.ty) {
+                            context.errors.borrow_mut().push(CompileError::new(
+                                range,
+                                CompileErrorKind::TypeMismatch {
+                                    expected: expected.clone(),
+                                    actual: context.zonk_for_error(&arg.ty),
+                                },
+                            ));
+                        }
+                        args.push(coerce_integer(context, &expected, arg, range));
+                    }
+                    return Ok(ResolvedExpression {
+                        kind: ExpressionKind::DynCall(resolved_ast::DynCallExpr {
+                            interface: interface.name.clone(),
+                            receiver: Box::new(receiver),
+                            args,
+                        }),
+                        ty: resolved_return_ty,
+                        location: context.location(call_expr.range),
+                    });
                 }
             }
-            if let Some(impls) = impls_by_name.get(&interface.name) {
-                // Find the implementation that matches the argument type
-                if let Some(implementation) = impls.iter().find(|implementation| {
-                    let resolved_target_ty =
-                        resolve_type(context, &implementation.decl.target_ty).unwrap();
-                    // Check if the first argument type matches the implementation's target type
-                    if let Some(first_arg_ty) = resolved_arg_types.first() {
-                        *first_arg_ty == resolved_target_ty
+            let Some((implementation, impl_fn_name, impl_generic_args)) = resolved_arg_types
+                .first()
+                .and_then(|first_arg_ty| find_implementation(context, &interface.name, first_arg_ty))
+            else {
+                // 境界やwhere句でインターフェースを要求された型の値は、実装が決まる前でも呼び出せる。
+                // ジェネリック関数をインスタンス化せずに検査するときだけ通る
+                if let Some(receiver_ty) = resolved_arg_types
+                    .first()
+                    .filter(|ty| implements_interface(context, ty, &interface.name))
+                {
+                    // ジェネリックなインターフェースの戻り値の型は、実装が決まるまで分からない
+                    let resolved_return_ty = if interface.generic_args.is_empty() {
+                        resolve_type(context, &interface.return_type)?
                     } else {
-                        false
-                    }
-                }) {
-                    // Resolve the implementation and generate a function call
-                    let resolved_target_ty =
-                        resolve_type(context, &implementation.decl.target_ty).unwrap();
-                    let impl_fn_name = format!(
-                        "impl_{}_for_{}",
-                        interface.name.replace("->", "to_"),
-                        resolved_target_ty.to_string()
-                    );
-
-                    // Resolve implementation body as a function
-                    resolve_implementation(context, implementation, &impl_fn_name)?;
-
-                    // Resolve the return type from interface
-                    let resolved_return_ty = resolve_type(context, &interface.return_type)?;
-
-                    // Generate call expression to the implementation function
-                    let resolved_args: Vec<ResolvedExpression> = call_expr
-                        .args
-                        .iter()
-                        .map(|arg| resolve_expression(context, arg.as_inner_deref(), None))
-                        .collect::<Result<Vec<_>, _>>()?;
-
-                    Ok(ResolvedExpression {
+                        ResolvedType::Unknown
+                    };
+                    return Ok(ResolvedExpression {
                         kind: ExpressionKind::CallExpr(resolved_ast::CallExpr {
-                            callee: impl_fn_name,
+                            callee: impl_symbol(&interface.name, receiver_ty),
                             args: resolved_args,
+                            pending_instantiation: None,
                         }),
                         ty: resolved_return_ty,
-                    })
-                } else {
-                    context.errors.borrow_mut().push(CompileError::new(
-                        call_expr.range,
-                        CompileErrorKind::InterfaceNotImplemented {
-                            name: resolved_name.clone(),
-                            ty: resolved_arg_types.first().cloned().unwrap_or(ResolvedType::Unknown),
-                        },
-                    ));
-                    Ok(ResolvedExpression {
-                        ty: ResolvedType::Unknown,
-                        kind: ExpressionKind::Unknown,
-                    })
+                        location: context.location(call_expr.range),
+                    });
                 }
-            } else {
                 context.errors.borrow_mut().push(CompileError::new(
                     call_expr.range,
                     CompileErrorKind::InterfaceNotImplemented {
                         name: resolved_name.clone(),
-                        ty: ResolvedType::Unknown,
+                        ty: resolved_arg_types.first().cloned().unwrap_or(ResolvedType::Unknown),
                     },
                 ));
-                Ok(ResolvedExpression {
+                return Ok(ResolvedExpression {
                     ty: ResolvedType::Unknown,
                     kind: ExpressionKind::Unknown,
+                    location: context.location(call_expr.range),
+                });
+            };
+
+            // Resolve implementation body as a function
+            let resolved_return_ty =
+                resolve_implementation(context, &implementation, &impl_fn_name, &impl_generic_args)?;
+
+            // self以外の引数は、実装の引数の型に合わせる
+            let impl_arg_types = context.resolved_functions.borrow()[&impl_fn_name]
+                .decl
+                .args
+                .iter()
+                .map(|arg| match arg {
+                    resolved_ast::Argument::Normal(ty, _) => ty.clone(),
+                    resolved_ast::Argument::VarArgs => ResolvedType::Unknown,
                 })
+                .collect::<Vec<_>>();
+            let mut args = Vec::new();
+            for (i, arg) in resolved_args.into_iter().enumerate() {
+                let Some(expected) = impl_arg_types.get(i).filter(|_| i > 0) else {
+                    args.push(arg);
+                    continue;
+                };
+                let range = call_expr.args[i].range;
+                if !context.can_insert(expected, &arg.ty) {
+                    context.errors.borrow_mut().push(CompileError::new(
+                        range,
+                        CompileErrorKind::TypeMismatch {
+                            expected: expected.clone(),
+                            actual: context.zonk_for_error(&arg.ty),
+                        },
+                    ));
+                }
+                args.push(coerce_integer(context, expected, arg, range));
             }
+
+            // Generate call expression to the implementation function
+            Ok(ResolvedExpression {
+                kind: ExpressionKind::CallExpr(resolved_ast::CallExpr {
+                    callee: impl_fn_name,
+                    args,
+                    pending_instantiation: None,
+                }),
+                ty: resolved_return_ty,
+                location: context.location(call_expr.range),
+            })
         } else {
             context.errors.borrow_mut().push(CompileError::new(
                 call_expr.range,
@@ -592,6 +505,7 @@ pub fn resolve_call_expr(
             Ok(ResolvedExpression {
                 ty: ResolvedType::Unknown,
                 kind: ExpressionKind::Unknown,
+                location: context.location(call_expr.range),
             })
         }
     }
@@ -0,0 +1,114 @@
+use crate::{
+    ast::{CastExpr, Range},
+    resolved_ast,
+    resolver::{literal::integer_range, ResolverContext},
+};
+
+use super::*;
+
+// `(as T expr)` で許される変換: 整数同士、整数と浮動小数点数、浮動小数点数同士、ポインタとusize
+fn is_valid_cast(from: &ResolvedType, to: &ResolvedType) -> bool {
+    let is_numeric = |ty: &ResolvedType| ty.is_integer_type() || ty.is_float_type();
+    from == to
+        || is_numeric(from) && is_numeric(to)
+        || from.is_pointer_type() && *to == ResolvedType::USize
+        || *from == ResolvedType::USize && to.is_pointer_type()
+}
+
+// 浮動小数点数型が誤差なく表現できる整数の絶対値の上限 (仮数部のビット数で決まる)
+fn exact_integer_limit(ty: &ResolvedType) -> Option<i128> {
+    match ty {
+        ResolvedType::F32 => Some(1 << f32::MANTISSA_DIGITS),
+        ResolvedType::F64 => Some(1 << f64::MANTISSA_DIGITS),
+        _ => None,
+    }
+}
+
+// 変換先の型が変換元の型の値を全て表現できるか
+fn is_lossless_conversion(
+    context: &ResolverContext,
+    from: &ResolvedType,
+    to: &ResolvedType,
+) -> bool {
+    match (
+        integer_range(from, context.ptr_sized_int_type),
+        integer_range(to, context.ptr_sized_int_type),
+    ) {
+        (Some((from_min, from_max)), Some((to_min, to_max))) => {
+            to_min <= from_min && from_max <= to_max
+        }
+        (Some((from_min, from_max)), None) => exact_integer_limit(to)
+            .is_some_and(|limit| -limit <= from_min && from_max <= limit),
+        (None, None) => from == to || *from == ResolvedType::F32 && *to == ResolvedType::F64,
+        (None, Some(_)) => false,
+    }
+}
+
+// strictモードでは、値が変わり得る暗黙の数値の変換をエラーにする
+pub(crate) fn check_implicit_conversion(
+    context: &ResolverContext,
+    from: &ResolvedType,
+    to: &ResolvedType,
+    range: Range,
+) {
+    if context.strict_conversions && !is_lossless_conversion(context, from, to) {
+        context.errors.borrow_mut().push(CompileError::new(
+            range,
+            CompileErrorKind::ImplicitConversion {
+                from: from.clone(),
+                to: to.clone(),
+            },
+        ));
+    }
+}
+
+// 整数型同士の暗黙の変換を、明示的なキャストの式に置き換える
+pub(crate) fn coerce_integer(
+    context: &ResolverContext,
+    expected: &ResolvedType,
+    expr: ResolvedExpression,
+    range: Range,
+) -> ResolvedExpression {
+    let expected = context.zonk(expected);
+    let actual = context.zonk(&expr.ty);
+    if expected == actual || !expected.is_integer_type() || !actual.is_integer_type() {
+        return expr;
+    }
+    check_implicit_conversion(context, &actual, &expected, range);
+    let location = expr.location;
+    ResolvedExpression {
+        ty: expected,
+        kind: ExpressionKind::Cast(resolved_ast::CastExpr {
+            target: Box::new(expr),
+        }),
+        location,
+    }
+}
+
+pub(super) fn resolve_cast_expr(
+    context: &ResolverContext,
+    cast_expr: &Located<&CastExpr>,
+) -> Result<ResolvedExpression, FaitalError> {
+    let target_ty = resolve_type(context, &cast_expr.ty)?;
+    let target = resolve_expression(context, cast_expr.target.as_deref(), None)?;
+    // 変換元の型はここで確定させる
+    let from_ty = context.zonk(&context.structurally_resolve(&target.ty));
+    if !matches!(from_ty, ResolvedType::Unknown | ResolvedType::Var(_))
+        && !is_valid_cast(&from_ty, &target_ty)
+    {
+        context.errors.borrow_mut().push(CompileError::new(
+            cast_expr.range,
+            CompileErrorKind::InvalidCast {
+                from: from_ty,
+                to: target_ty.clone(),
+            },
+        ));
+    }
+    Ok(ResolvedExpression {
+        ty: target_ty,
+        kind: ExpressionKind::Cast(resolved_ast::CastExpr {
+            target: Box::new(target),
+        }),
+        location: context.location(cast_expr.range),
+    })
+}
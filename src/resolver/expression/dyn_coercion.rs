@@ -0,0 +1,60 @@
+use crate::{
+    ast::Range,
+    resolved_ast::DynCoerceExpr,
+    resolver::{generics::find_implementation, resolve_implementation, ResolverContext},
+};
+
+use super::*;
+
+// `dyn` 型は値側の型推論の手がかりにならないので、アノテーションとしては渡さない
+pub(crate) fn value_annotation(expected: &ResolvedType) -> Option<&ResolvedType> {
+    if matches!(expected, ResolvedType::Dyn(_)) {
+        None
+    } else {
+        Some(expected)
+    }
+}
+
+// `dyn Interface` が期待される位置に具体的な型の値が渡された場合、
+// インターフェースの実装を解決し、vtable付きの値への変換式で包む
+pub(crate) fn coerce_to_dyn(
+    context: &ResolverContext,
+    expected: &ResolvedType,
+    expr: ResolvedExpression,
+    range: Range,
+) -> Result<ResolvedExpression, FaitalError> {
+    let ResolvedType::Dyn(interface_name) = expected else {
+        return Ok(expr);
+    };
+    // 実装を選ぶために、値の型はここで確定させる
+    let value_ty = context.zonk(&context.structurally_resolve(&expr.ty));
+    if matches!(
+        value_ty,
+        ResolvedType::Dyn(_) | ResolvedType::Unknown | ResolvedType::Var(_)
+    ) {
+        return Ok(expr);
+    }
+    let Some((implementation, impl_fn_name, impl_generic_args)) =
+        find_implementation(context, interface_name, &value_ty)
+    else {
+        context.errors.borrow_mut().push(CompileError::new(
+            range,
+            CompileErrorKind::InterfaceNotImplemented {
+                name: interface_name.clone(),
+                ty: value_ty,
+            },
+        ));
+        return Ok(expr);
+    };
+    resolve_implementation(context, &implementation, &impl_fn_name, &impl_generic_args)?;
+    let location = expr.location;
+    Ok(ResolvedExpression {
+        ty: expected.clone(),
+        kind: ExpressionKind::DynCoerce(DynCoerceExpr {
+            target: Box::new(expr),
+            interface: interface_name.clone(),
+            impl_fn: impl_fn_name,
+        }),
+        location,
+    })
+}
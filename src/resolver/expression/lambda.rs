@@ -0,0 +1,289 @@
+use super::*;
+
+use crate::ast::{LambdaExpr, Range, Statement};
+use crate::resolved_ast::{Argument, ResolvedFunctionType};
+use crate::resolver::mangle::function_symbol;
+use crate::resolver::statement::resolve_return_in_block;
+use crate::resolver::{LambdaScope, LocalVariable, ResolverContext};
+
+use super::call::{resolve_non_generic_function, resolve_signature};
+
+pub(super) fn resolve_lambda_expr(
+    context: &ResolverContext,
+    lambda: &Located<&LambdaExpr>,
+    annotation: Option<&ResolvedType>,
+) -> Result<ResolvedExpression, FaitalError> {
+    // 関数の型が期待されていれば、`_` の引数の型や省略された戻り値の型をそこから決める
+    let expected = match annotation {
+        Some(ResolvedType::Function(function_ty)) => Some(function_ty),
+        _ => None,
+    };
+    let return_type = match (&lambda.return_type, expected) {
+        (Some(return_type), _) => resolve_type(context, return_type)?,
+        (None, Some(expected)) => (*expected.return_type).clone(),
+        (None, None) => context.fresh_type_var(
+            TypeVarKind::General,
+            lambda.range,
+            "the return value of this `fn`",
+        ),
+    };
+
+    // 本体のbreak / continue / return は `fn` 式の外側には届かない
+    let depth = context.scopes.borrow().len();
+    context.lambdas.borrow_mut().push(LambdaScope {
+        depth,
+        captures: Vec::new(),
+    });
+    let outer_loops = std::mem::take(&mut *context.loops.borrow_mut());
+    let outer_return_type = context.return_type.replace(return_type.clone());
+    let result = in_new_scope!(context.scopes, {
+        resolve_lambda_in_scope(context, lambda, expected, &return_type)
+    });
+    *context.loops.borrow_mut() = outer_loops;
+    context.return_type.replace(outer_return_type);
+    let captures = context.lambdas.borrow_mut().pop().unwrap().captures;
+    let (args, body) = result?;
+
+    let ty = ResolvedType::Function(ResolvedFunctionType {
+        args: args
+            .iter()
+            .map(|arg| match arg {
+                Argument::Normal(ty, _) => ty.clone(),
+                Argument::VarArgs => unreachable!(),
+            })
+            .collect(),
+        return_type: Box::new(return_type.clone()),
+    });
+    Ok(ResolvedExpression {
+        ty,
+        kind: ExpressionKind::Lambda(resolved_ast::LambdaExpr {
+            fn_name: next_lambda_name(context),
+            args,
+            return_type,
+            captures,
+            body,
+            location: context.location(lambda.range),
+        }),
+        location: context.location(lambda.range),
+    })
+}
+
+fn resolve_lambda_in_scope(
+    context: &ResolverContext,
+    lambda: &Located<&LambdaExpr>,
+    expected: Option<&ResolvedFunctionType>,
+    return_type: &ResolvedType,
+) -> Result<(Vec<Argument>, Vec<resolved_ast::Statement>), FaitalError> {
+    let expected_args = expected
+        .map(|expected| expected.args.as_slice())
+        .filter(|args| args.len() == lambda.args.len());
+    let mut args = Vec::new();
+    for (i, (name, ty)) in lambda.args.iter().enumerate() {
+        let ty = resolve_type(context, ty)?;
+        if let Some(expected_args) = expected_args {
+            context.unify(&ty, &expected_args[i]);
+        }
+        let resolved_name = context.scopes.borrow_mut().add(name.clone(), ty.clone());
+        args.push(Argument::Normal(ty, resolved_name));
+    }
+
+    let mut body = Vec::new();
+    for (i, statement) in lambda.body.iter().enumerate() {
+        let is_last = i + 1 == lambda.body.len();
+        match &statement.value {
+            Statement::Return(ret) => {
+                body.push(resolve_return_in_block(context, ret, statement.range)?);
+            }
+            Statement::Effect(effect) if is_last => {
+                let value = resolve_expression(
+                    context,
+                    effect.expression.as_ref(),
+                    value_annotation(return_type),
+                )?;
+                body.extend(return_last_value(
+                    context,
+                    return_type,
+                    value,
+                    statement.range,
+                )?);
+            }
+            Statement::Effect(effect) => {
+                body.push(resolved_ast::Statement::Effect(resolved_ast::Effect {
+                    expression: resolve_expression(context, effect.expression.as_ref(), None)?,
+                    location: context.location(statement.range),
+                }));
+            }
+        }
+    }
+    if !matches!(body.last(), Some(resolved_ast::Statement::Return(_))) {
+        // 値を返さずに本体が終わる
+        if !context.unify(return_type, &ResolvedType::Void) {
+            context.errors.borrow_mut().push(CompileError::new(
+                lambda.range,
+                CompileErrorKind::TypeMismatch {
+                    expected: context.zonk_for_error(return_type),
+                    actual: ResolvedType::Void,
+                },
+            ));
+        }
+        body.push(resolved_ast::Statement::Return(resolved_ast::Return {
+            expression: None,
+            location: context.location(lambda.range),
+        }));
+    }
+    Ok((args, body))
+}
+
+// 本体の最後の式を `fn` 式の戻り値にする。戻り値の型がvoidなら値は捨てる
+fn return_last_value(
+    context: &ResolverContext,
+    return_type: &ResolvedType,
+    value: ResolvedExpression,
+    range: Range,
+) -> Result<Vec<resolved_ast::Statement>, FaitalError> {
+    if matches!(context.shallow_resolve(return_type), ResolvedType::Var(_)) {
+        context.unify(return_type, &value.ty);
+    }
+    let location = context.location(range);
+    if context.shallow_resolve(return_type) == ResolvedType::Void {
+        return Ok(vec![
+            resolved_ast::Statement::Effect(resolved_ast::Effect {
+                expression: value,
+                location,
+            }),
+            resolved_ast::Statement::Return(resolved_ast::Return {
+                expression: None,
+                location,
+            }),
+        ]);
+    }
+    let value = coerce_to_dyn(context, return_type, value, range)?;
+    if !context.can_insert(return_type, &value.ty) {
+        context.errors.borrow_mut().push(CompileError::new(
+            range,
+            CompileErrorKind::TypeMismatch {
+                expected: context.zonk_for_error(return_type),
+                actual: context.zonk_for_error(&value.ty),
+            },
+        ));
+    }
+    Ok(vec![resolved_ast::Statement::Return(
+        resolved_ast::Return {
+            expression: Some(coerce_integer(context, return_type, value, range)),
+            location,
+        },
+    )])
+}
+
+// 関数ごとに数えるので、解決し直さなかった関数の `fn` 式と名前が重ならない
+fn next_lambda_name(context: &ResolverContext) -> String {
+    let mut lambda_count = context.lambda_count.borrow_mut();
+    *lambda_count += 1;
+    format!("{}.lambda.{}", context.symbol.borrow(), *lambda_count)
+}
+
+// `depth` 番目のスコープで宣言された変数を参照したことを記録する。
+// その変数より内側にある `fn` 式は、全てその変数を取り込む。
+// 解決中の `fn` 式の外側の変数であればtrueを返す
+pub(in crate::resolver) fn capture_variable(
+    context: &ResolverContext,
+    depth: usize,
+    variable: &LocalVariable,
+) -> bool {
+    let mut lambdas = context.lambdas.borrow_mut();
+    for lambda in lambdas.iter_mut().filter(|lambda| depth < lambda.depth) {
+        if !lambda
+            .captures
+            .iter()
+            .any(|(name, _)| *name == variable.resolved_name)
+        {
+            lambda
+                .captures
+                .push((variable.resolved_name.clone(), variable.ty.clone()));
+        }
+    }
+    lambdas.last().is_some_and(|lambda| depth < lambda.depth)
+}
+
+// トップレベルの関数を値として使う。受け取った引数をそのまま渡して呼び出す `fn` 式として扱う
+pub(super) fn resolve_function_ref(
+    context: &ResolverContext,
+    range: Range,
+    callee: &ast::Function,
+) -> Result<ResolvedExpression, FaitalError> {
+    if callee.decl.generic_args.is_some() || callee.decl.args.contains(&ast::Argument::VarArgs) {
+        context.errors.borrow_mut().push(CompileError::new(
+            range,
+            CompileErrorKind::FunctionNotValue {
+                name: callee.decl.name.clone(),
+            },
+        ));
+        return Ok(ResolvedExpression {
+            ty: ResolvedType::Unknown,
+            kind: ExpressionKind::Unknown,
+            location: context.location(range),
+        });
+    }
+    let location = context.location(range);
+    resolve_non_generic_function(context, callee)?;
+    let (param_types, return_type) = resolve_signature(context, &callee.decl)?;
+    let param_types = param_types.into_iter().flatten().collect::<Vec<_>>();
+
+    let args = param_types
+        .iter()
+        .enumerate()
+        .map(|(i, ty)| Argument::Normal(ty.clone(), format!("arg.{}", i)))
+        .collect::<Vec<_>>();
+    let call = ResolvedExpression {
+        ty: return_type.clone(),
+        kind: ExpressionKind::CallExpr(resolved_ast::CallExpr {
+            callee: function_symbol(&callee.decl, &[]),
+            args: param_types
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| ResolvedExpression {
+                    ty: ty.clone(),
+                    kind: ExpressionKind::VariableRef(resolved_ast::VariableRefExpr {
+                        name: format!("arg.{}", i),
+                    }),
+                    location,
+                })
+                .collect(),
+            pending_instantiation: None,
+        }),
+        location,
+    };
+    let body = if return_type == ResolvedType::Void {
+        vec![
+            resolved_ast::Statement::Effect(resolved_ast::Effect {
+                expression: call,
+                location,
+            }),
+            resolved_ast::Statement::Return(resolved_ast::Return {
+                expression: None,
+                location,
+            }),
+        ]
+    } else {
+        vec![resolved_ast::Statement::Return(resolved_ast::Return {
+            expression: Some(call),
+            location,
+        })]
+    };
+
+    Ok(ResolvedExpression {
+        ty: ResolvedType::Function(ResolvedFunctionType {
+            args: param_types,
+            return_type: Box::new(return_type.clone()),
+        }),
+        kind: ExpressionKind::Lambda(resolved_ast::LambdaExpr {
+            fn_name: next_lambda_name(context),
+            args,
+            return_type,
+            captures: Vec::new(),
+            body,
+            location,
+        }),
+        location,
+    })
+}
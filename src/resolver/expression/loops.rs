@@ -0,0 +1,602 @@
+use super::*;
+
+use crate::ast::{
+    BreakExpr, ContinueExpr, ForExpr, ForIter, LocatedExpr, Range, SourceLocation, WhileExpr,
+};
+use crate::resolver::{
+    generics::find_implementation, resolve_implementation, LoopScope, ResolverContext,
+};
+
+pub(super) fn resolve_while_expr(
+    context: &ResolverContext,
+    while_expr: &Located<&WhileExpr>,
+    annotation: Option<&ResolvedType>,
+) -> Result<ResolvedExpression, FaitalError> {
+    let condition_expr = resolve_expression(
+        context,
+        while_expr.cond.as_deref(),
+        Some(&ResolvedType::Bool),
+    )?;
+    if !context.unify(&ResolvedType::Bool, &condition_expr.ty) {
+        context.errors.borrow_mut().push(CompileError::new(
+            while_expr.range,
+            CompileErrorKind::TypeMismatch {
+                expected: ResolvedType::Bool,
+                actual: context.zonk_for_error(&condition_expr.ty),
+            },
+        ));
+    }
+
+    let loop_id = next_loop_id(context);
+    push_loop_scope(
+        context,
+        loop_id,
+        while_expr.label.clone(),
+        matches!(
+            condition_expr.kind,
+            ExpressionKind::BoolLiteral(resolved_ast::BoolLiteral { value: true })
+        ),
+        annotation,
+    );
+    let body_expr = in_new_scope!(context.scopes, {
+        resolve_expression(context, while_expr.body.as_deref(), None)
+    });
+    let ty = pop_loop_scope(context);
+    let body_expr = body_expr?;
+
+    Ok(resolved_ast::ResolvedExpression {
+        ty,
+        kind: resolved_ast::ExpressionKind::While(resolved_ast::WhileExpr {
+            loop_id,
+            cond: Box::new(condition_expr),
+            body: Box::new(body_expr),
+            step: None,
+        }),
+        location: context.location(while_expr.range),
+    })
+}
+
+fn next_loop_id(context: &ResolverContext) -> usize {
+    let mut loop_count = context.loop_count.borrow_mut();
+    *loop_count += 1;
+    *loop_count
+}
+
+fn push_loop_scope(
+    context: &ResolverContext,
+    id: usize,
+    label: Option<String>,
+    is_infinite: bool,
+    annotation: Option<&ResolvedType>,
+) {
+    context.loops.borrow_mut().push(LoopScope {
+        id,
+        label,
+        is_infinite,
+        annotation: annotation.cloned(),
+        value_ty: None,
+        plain_breaks: Vec::new(),
+    });
+}
+
+// ループの登録を外し、ループの式の型を返す
+fn pop_loop_scope(context: &ResolverContext) -> ResolvedType {
+    let loop_scope = context.loops.borrow_mut().pop().unwrap();
+    // 値付きのbreakがあれば、ループはその値を返す
+    match loop_scope.value_ty {
+        Some(value_ty) => {
+            for range in loop_scope.plain_breaks {
+                context.errors.borrow_mut().push(CompileError::new(
+                    range,
+                    CompileErrorKind::TypeMismatch {
+                        expected: context.zonk_for_error(&value_ty),
+                        actual: ResolvedType::Void,
+                    },
+                ));
+            }
+            value_ty
+        }
+        None => ResolvedType::Void,
+    }
+}
+
+pub(super) fn resolve_for_expr(
+    context: &ResolverContext,
+    for_expr: &Located<&ForExpr>,
+) -> Result<ResolvedExpression, FaitalError> {
+    // ループ変数はforの外からは見えない
+    in_new_scope!(context.scopes, {
+        match &for_expr.iter {
+            ForIter::Range { start, end } => resolve_range_for_expr(context, for_expr, start, end),
+            ForIter::Collection(collection) => {
+                resolve_collection_for_expr(context, for_expr, collection)
+            }
+        }
+    })
+}
+
+fn resolve_range_for_expr(
+    context: &ResolverContext,
+    for_expr: &Located<&ForExpr>,
+    start: &LocatedExpr,
+    end: &LocatedExpr,
+) -> Result<ResolvedExpression, FaitalError> {
+    let start_expr = resolve_expression(context, start.as_deref(), None)?;
+    let end_expr = resolve_expression(context, end.as_deref(), Some(&start_expr.ty))?;
+    if !context.unify(&start_expr.ty, &end_expr.ty) {
+        context.errors.borrow_mut().push(CompileError::new(
+            end.range,
+            CompileErrorKind::TypeMismatch {
+                expected: context.zonk_for_error(&start_expr.ty),
+                actual: context.zonk_for_error(&end_expr.ty),
+            },
+        ));
+    }
+    let index_ty = context.structurally_resolve(&start_expr.ty);
+    if !matches!(index_ty, ResolvedType::Var(_) | ResolvedType::Unknown)
+        && !index_ty.is_integer_type()
+    {
+        context.errors.borrow_mut().push(CompileError::new(
+            start.range,
+            CompileErrorKind::InvalidIntegerOperand {
+                actual: index_ty.clone(),
+            },
+        ));
+    }
+
+    let loop_id = next_loop_id(context);
+    let location = context.location(for_expr.range);
+    let decls = vec![
+        resolved_ast::VariableDecl {
+            name: for_var_name("index", loop_id),
+            ty: index_ty.clone(),
+            value: Box::new(start_expr),
+        },
+        resolved_ast::VariableDecl {
+            name: for_var_name("end", loop_id),
+            ty: index_ty.clone(),
+            value: Box::new(end_expr),
+        },
+    ];
+    let element = variable_ref(for_var_name("index", loop_id), index_ty.clone(), location);
+    desugar_for_expr(context, for_expr, loop_id, decls, index_ty, element)
+}
+
+fn resolve_collection_for_expr(
+    context: &ResolverContext,
+    for_expr: &Located<&ForExpr>,
+    collection: &LocatedExpr,
+) -> Result<ResolvedExpression, FaitalError> {
+    let location = context.location(for_expr.range);
+    let collection_expr = resolve_expression(context, collection.as_deref(), None)?;
+    // 実装を選ぶために、値の型はここで確定させる
+    let collection_ty = context.zonk(&context.structurally_resolve(&collection_expr.ty));
+    if collection_ty == ResolvedType::Unknown {
+        return Ok(ResolvedExpression {
+            ty: ResolvedType::Void,
+            kind: ExpressionKind::Unknown,
+            location,
+        });
+    }
+
+    if let ResolvedType::Array(element_ty, len) = &collection_ty {
+        return resolve_array_for_expr(
+            context,
+            for_expr,
+            collection_expr.clone(),
+            (**element_ty).clone(),
+            *len,
+        );
+    }
+
+    // 要素の数を返す iter-len と、添字の位置の要素を返す iter-get の実装を使う
+    let mut impl_fns = Vec::new();
+    for interface_name in ["iter-len", "iter-get"] {
+        let Some((implementation, impl_fn_name, impl_generic_args)) =
+            find_implementation(context, interface_name, &collection_ty)
+        else {
+            context.errors.borrow_mut().push(CompileError::new(
+                collection.range,
+                CompileErrorKind::InterfaceNotImplemented {
+                    name: interface_name.to_string(),
+                    ty: collection_ty,
+                },
+            ));
+            return Ok(ResolvedExpression {
+                ty: ResolvedType::Void,
+                kind: ExpressionKind::Unknown,
+                location,
+            });
+        };
+        let return_ty =
+            resolve_implementation(context, &implementation, &impl_fn_name, &impl_generic_args)?;
+        impl_fns.push((impl_fn_name, return_ty));
+    }
+    let (get_fn, element_ty) = impl_fns.pop().unwrap();
+    let (len_fn, len_ty) = impl_fns.pop().unwrap();
+    // 添字は iter-len の戻り値と比べ、そのまま iter-get に渡す。
+    // どちらもi32であることは、実装をインターフェースの宣言と比べて確かめている
+    let index_ty = ResolvedType::I32;
+
+    let loop_id = next_loop_id(context);
+    let collection_ref = variable_ref(
+        for_var_name("collection", loop_id),
+        collection_ty.clone(),
+        location,
+    );
+    let decls = vec![
+        resolved_ast::VariableDecl {
+            name: for_var_name("collection", loop_id),
+            ty: collection_ty,
+            value: Box::new(collection_expr),
+        },
+        resolved_ast::VariableDecl {
+            name: for_var_name("index", loop_id),
+            ty: index_ty.clone(),
+            value: Box::new(ResolvedExpression {
+                ty: index_ty.clone(),
+                kind: ExpressionKind::NumberLiteral(resolved_ast::NumberLiteral {
+                    value: resolved_ast::NumberLiteralValue::Integer(0),
+                }),
+                location,
+            }),
+        },
+        resolved_ast::VariableDecl {
+            name: for_var_name("end", loop_id),
+            ty: len_ty.clone(),
+            value: Box::new(ResolvedExpression {
+                ty: len_ty,
+                kind: ExpressionKind::CallExpr(resolved_ast::CallExpr {
+                    callee: len_fn,
+                    args: vec![collection_ref.clone()],
+                    pending_instantiation: None,
+                }),
+                location,
+            }),
+        },
+    ];
+    let element = ResolvedExpression {
+        ty: element_ty,
+        kind: ExpressionKind::CallExpr(resolved_ast::CallExpr {
+            callee: get_fn,
+            args: vec![
+                collection_ref,
+                variable_ref(for_var_name("index", loop_id), index_ty.clone(), location),
+            ],
+            pending_instantiation: None,
+        }),
+        location,
+    };
+    desugar_for_expr(context, for_expr, loop_id, decls, index_ty, element)
+}
+
+// 固定長配列は長さが定数なので、添字で直接要素を読み出す
+fn resolve_array_for_expr(
+    context: &ResolverContext,
+    for_expr: &Located<&ForExpr>,
+    collection_expr: ResolvedExpression,
+    element_ty: ResolvedType,
+    len: usize,
+) -> Result<ResolvedExpression, FaitalError> {
+    let location = context.location(for_expr.range);
+    let loop_id = next_loop_id(context);
+    let collection_ty = collection_expr.ty.clone();
+    let index_ty = ResolvedType::I32;
+    let decls = vec![
+        resolved_ast::VariableDecl {
+            name: for_var_name("collection", loop_id),
+            ty: collection_ty.clone(),
+            value: Box::new(collection_expr),
+        },
+        resolved_ast::VariableDecl {
+            name: for_var_name("index", loop_id),
+            ty: index_ty.clone(),
+            value: Box::new(ResolvedExpression {
+                ty: index_ty.clone(),
+                kind: ExpressionKind::NumberLiteral(resolved_ast::NumberLiteral {
+                    value: resolved_ast::NumberLiteralValue::Integer(0),
+                }),
+                location,
+            }),
+        },
+        resolved_ast::VariableDecl {
+            name: for_var_name("end", loop_id),
+            ty: index_ty.clone(),
+            value: Box::new(ResolvedExpression {
+                ty: index_ty.clone(),
+                kind: ExpressionKind::NumberLiteral(resolved_ast::NumberLiteral {
+                    value: resolved_ast::NumberLiteralValue::Integer(len as u64),
+                }),
+                location,
+            }),
+        },
+    ];
+    let element = ResolvedExpression {
+        ty: element_ty,
+        kind: ExpressionKind::IndexAccess(resolved_ast::IndexAccessExpr {
+            target: Box::new(variable_ref(
+                for_var_name("collection", loop_id),
+                collection_ty,
+                location,
+            )),
+            index: Box::new(variable_ref(
+                for_var_name("index", loop_id),
+                index_ty.clone(),
+                location,
+            )),
+        }),
+        location,
+    };
+    desugar_for_expr(context, for_expr, loop_id, decls, index_ty, element)
+}
+
+// forの中で使う、ソースコードからは参照できない変数の名前
+fn for_var_name(kind: &str, loop_id: usize) -> String {
+    format!("for.{}.{}", kind, loop_id)
+}
+
+fn variable_ref(name: String, ty: ResolvedType, location: SourceLocation) -> ResolvedExpression {
+    ResolvedExpression {
+        ty,
+        kind: ExpressionKind::VariableRef(resolved_ast::VariableRefExpr { name }),
+        location,
+    }
+}
+
+// forを、添字 `for.index.N` を `for.end.N` の手前まで1ずつ進めるwhileループに書き換える。
+// decls で添字と終端を宣言しておき、各回の最初に element の値をループ変数に入れる
+fn desugar_for_expr(
+    context: &ResolverContext,
+    for_expr: &Located<&ForExpr>,
+    loop_id: usize,
+    decls: Vec<resolved_ast::VariableDecl>,
+    index_ty: ResolvedType,
+    element: ResolvedExpression,
+) -> Result<ResolvedExpression, FaitalError> {
+    let location = context.location(for_expr.range);
+    let index_ref = variable_ref(for_var_name("index", loop_id), index_ty.clone(), location);
+    let cond = ResolvedExpression {
+        ty: ResolvedType::Bool,
+        kind: ExpressionKind::Binary(resolved_ast::BinaryExpr {
+            op: BinaryOp::LessThan,
+            lhs: Box::new(index_ref.clone()),
+            rhs: Box::new(variable_ref(
+                for_var_name("end", loop_id),
+                index_ty.clone(),
+                location,
+            )),
+        }),
+        location,
+    };
+    let step = ResolvedExpression {
+        ty: ResolvedType::Void,
+        kind: ExpressionKind::Assignment(resolved_ast::Assignment {
+            name: for_var_name("index", loop_id),
+            value: Box::new(ResolvedExpression {
+                ty: index_ty.clone(),
+                kind: ExpressionKind::Binary(resolved_ast::BinaryExpr {
+                    op: BinaryOp::Add,
+                    lhs: Box::new(index_ref),
+                    rhs: Box::new(ResolvedExpression {
+                        ty: index_ty,
+                        kind: ExpressionKind::NumberLiteral(resolved_ast::NumberLiteral {
+                            value: resolved_ast::NumberLiteralValue::Integer(1),
+                        }),
+                        location,
+                    }),
+                }),
+                location,
+            }),
+            deref_count: 0,
+            index_access: None,
+            target_is_array: false,
+        }),
+        location,
+    };
+
+    let var_name = context
+        .scopes
+        .borrow_mut()
+        .add(for_expr.var.clone(), element.ty.clone());
+    push_loop_scope(context, loop_id, for_expr.label.clone(), false, None);
+    let body_expr = resolve_expression(context, for_expr.body.as_deref(), None);
+    let ty = pop_loop_scope(context);
+    let body_expr = body_expr?;
+
+    let element_decl = ResolvedExpression {
+        ty: ResolvedType::Void,
+        kind: ExpressionKind::VariableDecls(resolved_ast::VariableDecls {
+            decls: vec![resolved_ast::VariableDecl {
+                name: var_name,
+                ty: element.ty.clone(),
+                value: Box::new(element),
+            }],
+        }),
+        location,
+    };
+    let while_expr = ResolvedExpression {
+        ty,
+        kind: ExpressionKind::While(resolved_ast::WhileExpr {
+            loop_id,
+            cond: Box::new(cond),
+            body: Box::new(ResolvedExpression {
+                ty: body_expr.ty.clone(),
+                kind: ExpressionKind::Block(resolved_ast::BlockExpr {
+                    statements: vec![
+                        resolved_ast::Statement::Effect(resolved_ast::Effect {
+                            expression: element_decl,
+                            location,
+                        }),
+                        resolved_ast::Statement::Effect(resolved_ast::Effect {
+                            expression: body_expr,
+                            location: context.location(for_expr.body.range),
+                        }),
+                    ],
+                }),
+                location: context.location(for_expr.body.range),
+            }),
+            step: Some(Box::new(step)),
+        }),
+        location,
+    };
+    Ok(ResolvedExpression {
+        ty: while_expr.ty.clone(),
+        kind: ExpressionKind::Block(resolved_ast::BlockExpr {
+            statements: vec![
+                resolved_ast::Statement::Effect(resolved_ast::Effect {
+                    expression: ResolvedExpression {
+                        ty: ResolvedType::Void,
+                        kind: ExpressionKind::VariableDecls(resolved_ast::VariableDecls { decls }),
+                        location,
+                    },
+                    location,
+                }),
+                resolved_ast::Statement::Effect(resolved_ast::Effect {
+                    expression: while_expr,
+                    location,
+                }),
+            ],
+        }),
+        location,
+    })
+}
+
+// break / continue の飛び先のループを探す。ラベルがなければ一番内側のループになる
+fn find_target_loop(
+    context: &ResolverContext,
+    label: &Option<String>,
+    keyword: &str,
+    range: Range,
+) -> Option<usize> {
+    let loops = context.loops.borrow();
+    let index = match label {
+        Some(label) => loops
+            .iter()
+            .rposition(|loop_scope| loop_scope.label.as_ref() == Some(label)),
+        None => loops.len().checked_sub(1),
+    };
+    if index.is_none() {
+        let kind = match label {
+            Some(label) if !loops.is_empty() => CompileErrorKind::UndeclaredLabel {
+                label: label.clone(),
+            },
+            _ => CompileErrorKind::OutsideOfLoop {
+                keyword: keyword.to_string(),
+            },
+        };
+        context
+            .errors
+            .borrow_mut()
+            .push(CompileError::new(range, kind));
+    }
+    index
+}
+
+pub(super) fn resolve_break_expr(
+    context: &ResolverContext,
+    break_expr: &Located<&BreakExpr>,
+) -> Result<ResolvedExpression, FaitalError> {
+    let target = find_target_loop(context, &break_expr.label, "break", break_expr.range);
+
+    let Some(loc_value) = &break_expr.value.value else {
+        let Some(index) = target else {
+            return Ok(ResolvedExpression {
+                ty: ResolvedType::Void,
+                kind: ExpressionKind::Unknown,
+                location: context.location(break_expr.range),
+            });
+        };
+        let mut loops = context.loops.borrow_mut();
+        loops[index].plain_breaks.push(break_expr.range);
+        return Ok(ResolvedExpression {
+            ty: ResolvedType::Void,
+            kind: ExpressionKind::Break(resolved_ast::BreakExpr {
+                loop_id: loops[index].id,
+                value: None,
+            }),
+            location: context.location(break_expr.range),
+        });
+    };
+
+    let Some(index) = target else {
+        resolve_expression(context, loc_value.as_deref(), None)?;
+        return Ok(ResolvedExpression {
+            ty: ResolvedType::Void,
+            kind: ExpressionKind::Unknown,
+            location: context.location(break_expr.range),
+        });
+    };
+    // 値の式の中で別のループを解決することがあるので、借用は保持しない
+    let (loop_id, is_infinite, value_ty, annotation) = {
+        let loop_scope = &context.loops.borrow()[index];
+        (
+            loop_scope.id,
+            loop_scope.is_infinite,
+            loop_scope.value_ty.clone(),
+            loop_scope.annotation.clone(),
+        )
+    };
+    if !is_infinite {
+        context.errors.borrow_mut().push(CompileError::new(
+            break_expr.range,
+            CompileErrorKind::BreakValueInConditionalLoop,
+        ));
+    }
+
+    let value = resolve_expression(
+        context,
+        loc_value.as_deref(),
+        value_ty.as_ref().or(annotation.as_ref()),
+    )?;
+    let value = match value_ty {
+        // 2つ目以降のbreakの値は、最初のbreakの値の型に合わせる
+        Some(value_ty) => {
+            if !context.can_insert(&value_ty, &value.ty) {
+                context.errors.borrow_mut().push(CompileError::new(
+                    loc_value.range,
+                    CompileErrorKind::TypeMismatch {
+                        expected: context.zonk_for_error(&value_ty),
+                        actual: context.zonk_for_error(&value.ty),
+                    },
+                ));
+            }
+            coerce_integer(context, &value_ty, value, loc_value.range)
+        }
+        None => {
+            context.loops.borrow_mut()[index].value_ty = Some(value.ty.clone());
+            value
+        }
+    };
+
+    Ok(ResolvedExpression {
+        ty: ResolvedType::Void,
+        kind: ExpressionKind::Break(resolved_ast::BreakExpr {
+            loop_id,
+            value: Some(Box::new(value)),
+        }),
+        location: context.location(break_expr.range),
+    })
+}
+
+pub(super) fn resolve_continue_expr(
+    context: &ResolverContext,
+    continue_expr: &Located<&ContinueExpr>,
+) -> Result<ResolvedExpression, FaitalError> {
+    let target = find_target_loop(
+        context,
+        &continue_expr.label,
+        "continue",
+        continue_expr.range,
+    );
+    let kind = match target {
+        Some(index) => ExpressionKind::Continue(resolved_ast::ContinueExpr {
+            loop_id: context.loops.borrow()[index].id,
+        }),
+        None => ExpressionKind::Unknown,
+    };
+    Ok(ResolvedExpression {
+        ty: ResolvedType::Void,
+        kind,
+        location: context.location(continue_expr.range),
+    })
+}
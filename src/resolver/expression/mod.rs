@@ -1,23 +1,41 @@
 mod assignment;
 mod binary;
+mod block;
 mod call;
+mod cast;
+mod dyn_coercion;
+mod lambda;
+mod loops;
+mod tuple;
+mod unary;
 mod variable_decl;
 
 use crate::ast::{Expression, Located, TypeDefKind};
 use crate::resolved_ast::{
-    ExpressionKind, IndexAccessExpr, ResolvedExpression, ResolvedStructType, ResolvedType,
+    ExpressionKind, IndexAccessExpr, NumberLiteralValue, ResolvedExpression, ResolvedStructType,
+    ResolvedType,
 };
+use crate::resolver::infer::{PendingLiteral, TypeVarKind};
+use crate::resolver::literal::parse_number_literal;
 use crate::resolver::ty::resolve_type;
 use crate::{ast, in_global_scope, in_new_scope, resolved_ast};
 
 use self::assignment::resolve_assignment;
 use self::binary::resolve_binary_expression;
+use self::block::resolve_do_expr;
 use self::call::resolve_call_expr;
+use self::cast::resolve_cast_expr;
+use self::lambda::{capture_variable, resolve_function_ref, resolve_lambda_expr};
+use self::loops::{resolve_break_expr, resolve_continue_expr, resolve_for_expr, resolve_while_expr};
+use self::tuple::resolve_tuple_literal;
+use self::unary::resolve_unary_expression;
+pub(crate) use self::cast::{check_implicit_conversion, coerce_integer};
+pub(crate) use self::dyn_coercion::{coerce_to_dyn, value_annotation};
 use self::variable_decl::resolve_variable_decl;
 
 use super::ty::get_resolved_struct_name;
 use super::{
-    error::*, mangle_fn_name, resolve_function, BinaryOp, MultiOp, ResolverContext, UnaryOp,
+    check_literal, error::*, resolve_function, BinaryOp, MultiOp, ResolverContext,
 };
 
 pub(crate) fn resolve_expression(
@@ -25,20 +43,31 @@ pub(crate) fn resolve_expression(
     loc_expr: Located<&ast::Expression>,
     annotation: Option<&ResolvedType>,
 ) -> Result<resolved_ast::ResolvedExpression, FaitalError> {
+    let annotation = annotation.map(|annotation| context.shallow_resolve(annotation));
+    let annotation = annotation.as_ref();
     match loc_expr.value {
         Expression::VariableRef(variable_ref) => {
-            let expr_kind =
-                resolved_ast::ExpressionKind::VariableRef(resolved_ast::VariableRefExpr {
-                    name: variable_ref.name.clone(),
-                });
-
-            if let Some(ty) = context.scopes.borrow().get(&variable_ref.name) {
+            let variable = context
+                .scopes
+                .borrow()
+                .lookup(&variable_ref.name)
+                .map(|(depth, variable)| (depth, variable.clone()));
+            if let Some((depth, variable)) = variable {
+                capture_variable(context, depth, &variable);
                 // Always use the variable's actual type, not the annotation
                 // The annotation is used for type checking, but the expression keeps its original type
                 Ok(resolved_ast::ResolvedExpression {
-                    ty: ty.clone(),
-                    kind: expr_kind,
+                    ty: variable.ty.clone(),
+                    kind: resolved_ast::ExpressionKind::VariableRef(
+                        resolved_ast::VariableRefExpr {
+                            name: variable.resolved_name.clone(),
+                        },
+                    ),
+                    location: context.location(loc_expr.range),
                 })
+            } else if let Some(callee) = find_function_value(context, &variable_ref.name) {
+                // 同じ名前の変数がなければ、トップレベルの関数を値として使う
+                resolve_function_ref(context, loc_expr.range, &callee)
             } else {
                 context.errors.borrow_mut().push(CompileError::new(
                     loc_expr.range,
@@ -48,63 +77,26 @@ pub(crate) fn resolve_expression(
                 ));
                 Ok(ResolvedExpression {
                     ty: ResolvedType::Unknown,
-                    kind: expr_kind,
+                    kind: resolved_ast::ExpressionKind::VariableRef(
+                        resolved_ast::VariableRefExpr {
+                            name: variable_ref.name.clone(),
+                        },
+                    ),
+                    location: context.location(loc_expr.range),
                 })
             }
         }
         Expression::NumberLiteral(number_literal) => {
-            let kind = resolved_ast::ExpressionKind::NumberLiteral(resolved_ast::NumberLiteral {
-                value: number_literal.value.clone(),
-            });
-            let is_float = number_literal.value.contains('.');
-            let ty = if let Some(annotation) = annotation {
-                annotation.clone()
-            } else if is_float {
-                // Float literal: default to f64
-                if number_literal.value.parse::<f32>().is_ok() {
-                    ResolvedType::F64
-                } else {
-                    ResolvedType::F64
-                }
-            } else if number_literal.value.parse::<i32>().is_ok() {
-                ResolvedType::I32
-            } else if number_literal.value.parse::<i64>().is_ok() {
-                ResolvedType::I64
-            } else if number_literal.value.parse::<u64>().is_ok() {
-                ResolvedType::U64
-            } else {
-                context.errors.borrow_mut().push(CompileError::new(
-                    loc_expr.range,
-                    CompileErrorKind::InvalidNumberLiteral {
-                        value: number_literal.value.clone(),
-                    },
-                ));
-                ResolvedType::Unknown
-            };
-
-            Ok(ResolvedExpression { ty, kind })
+            resolve_number_literal(context, number_literal, loc_expr.range, annotation, false)
+        }
+        Expression::Cast(cast_expr) => {
+            resolve_cast_expr(context, &Located::transfer(loc_expr, cast_expr))
         }
         Expression::Binary(bin_expr) => {
             resolve_binary_expression(context, &Located::transfer(loc_expr, bin_expr))
         }
         Expression::Unary(unary_expr) => {
-            let operand = resolve_expression(context, unary_expr.operand.as_deref(), None)?;
-            if matches!(unary_expr.op, UnaryOp::Not) && !matches!(operand.ty, ResolvedType::Bool) {
-                context.errors.borrow_mut().push(CompileError::new(
-                    loc_expr.range,
-                    CompileErrorKind::TypeMismatch {
-                        expected: ResolvedType::Bool,
-                        actual: operand.ty.clone(),
-                    },
-                ));
-            }
-            Ok(resolved_ast::ResolvedExpression {
-                kind: resolved_ast::ExpressionKind::Unary(resolved_ast::UnaryExpr {
-                    op: unary_expr.op,
-                    operand: Box::new(operand),
-                }),
-                ty: ResolvedType::Bool,
-            })
+            resolve_unary_expression(context, &Located::transfer(loc_expr, unary_expr), annotation)
         }
         Expression::Multi(multi_expr) => {
             let mut resolved_operands = Vec::new();
@@ -114,13 +106,15 @@ pub(crate) fn resolve_expression(
             }
             match multi_expr.op {
                 MultiOp::And | MultiOp::Or => {
-                    for operand in &resolved_operands {
-                        if !matches!(operand.ty, ResolvedType::Bool) {
+                    for (operand, loc_operand) in
+                        resolved_operands.iter().zip(&multi_expr.operands)
+                    {
+                        if !context.unify(&ResolvedType::Bool, &operand.ty) {
                             context.errors.borrow_mut().push(CompileError::new(
-                                loc_expr.range,
+                                loc_operand.range,
                                 CompileErrorKind::TypeMismatch {
                                     expected: ResolvedType::Bool,
-                                    actual: operand.ty.clone(),
+                                    actual: context.zonk_for_error(&operand.ty),
                                 },
                             ));
                         }
@@ -131,6 +125,7 @@ pub(crate) fn resolve_expression(
                             operands: resolved_operands,
                         }),
                         ty: ResolvedType::Bool,
+                        location: context.location(loc_expr.range),
                     })
                 }
             }
@@ -140,8 +135,8 @@ pub(crate) fn resolve_expression(
         }
         Expression::DerefExpr(deref_expr) => {
             let target = resolve_expression(context, deref_expr.target.as_deref(), None)?;
-            let resolved_ty = if let ResolvedType::Ptr(inner) = &target.ty {
-                *inner.clone()
+            let resolved_ty = if let ResolvedType::Ptr(inner) = context.structurally_resolve(&target.ty) {
+                *inner
             } else {
                 context.errors.borrow_mut().push(CompileError::new(
                     loc_expr.range,
@@ -157,6 +152,7 @@ pub(crate) fn resolve_expression(
                     target: Box::new(target),
                 }),
                 ty: resolved_ty,
+                location: context.location(loc_expr.range),
             })
         }
         Expression::AddressOf(address_of_expr) => {
@@ -167,6 +163,7 @@ pub(crate) fn resolve_expression(
                     target: Box::new(target),
                 }),
                 ty: resolved_ty,
+                location: context.location(loc_expr.range),
             })
         }
         Expression::IndexAccess(index_access_expr) => {
@@ -176,8 +173,12 @@ pub(crate) fn resolve_expression(
                 index_access_expr.index.as_deref(),
                 Some(&ResolvedType::USize),
             )?;
-            let resolved_ty = if let ResolvedType::Ptr(ptr) = &target.ty {
-                *ptr.clone()
+            let target_ty = context.structurally_resolve(&target.ty);
+            let resolved_ty = if let ResolvedType::Ptr(ptr) = target_ty {
+                *ptr
+            } else if let ResolvedType::Array(element, _) = &target_ty {
+                check_array_index(context, &target_ty, &index, index_access_expr.index.range);
+                (**element).clone()
             } else {
                 context.errors.borrow_mut().push(CompileError::new(
                     loc_expr.range,
@@ -193,11 +194,13 @@ pub(crate) fn resolve_expression(
                     index: Box::new(index),
                 }),
                 ty: resolved_ty,
+                location: context.location(loc_expr.range),
             })
         }
         Expression::FieldAccess(field_access_expr) => {
             let target = resolve_expression(context, field_access_expr.target.as_deref(), None)?;
-            let resolved_ty = if let ResolvedType::StructLike(struct_ty) = &target.ty {
+            let target_ty = context.zonk(&context.structurally_resolve(&target.ty));
+            let resolved_ty = if let ResolvedType::StructLike(struct_ty) = &target_ty {
                 if let Some((_name, ty)) = struct_ty
                     .fields
                     .iter()
@@ -214,6 +217,41 @@ pub(crate) fn resolve_expression(
                     ));
                     ResolvedType::Unknown
                 }
+            } else if let (ResolvedType::Array(_, len), "len") =
+                (&target_ty, field_access_expr.field_name.as_str())
+            {
+                // 固定長配列の長さはコンパイル時に決まる定数になる
+                return Ok(ResolvedExpression {
+                    ty: ResolvedType::I32,
+                    kind: ExpressionKind::NumberLiteral(resolved_ast::NumberLiteral {
+                        value: NumberLiteralValue::Integer(*len as u64),
+                    }),
+                    location: context.location(loc_expr.range),
+                });
+            } else if let ResolvedType::Tuple(elements) = &target_ty {
+                let element = field_access_expr
+                    .field_name
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| elements.get(index));
+                if let Some(ty) = element {
+                    ty.clone()
+                } else {
+                    context.errors.borrow_mut().push(CompileError::new(
+                        loc_expr.range,
+                        CompileErrorKind::FieldNotFound {
+                            field_name: field_access_expr.field_name.clone(),
+                            type_name: context.zonk_for_error(&target_ty).to_string(),
+                        },
+                    ));
+                    ResolvedType::Unknown
+                }
+            } else if let ResolvedType::Var(_) = target_ty {
+                context.errors.borrow_mut().push(CompileError::new(
+                    field_access_expr.target.range,
+                    CompileErrorKind::TypeAnnotationRequired,
+                ));
+                ResolvedType::Unknown
             } else {
                 context.errors.borrow_mut().push(CompileError::new(
                     loc_expr.range,
@@ -230,6 +268,7 @@ pub(crate) fn resolve_expression(
                     field_name: field_access_expr.field_name.clone(),
                 }),
                 ty: resolved_ty,
+                location: context.location(loc_expr.range),
             })
         }
         Expression::StringLiteral(str_literal) => Ok(resolved_ast::ResolvedExpression {
@@ -237,15 +276,18 @@ pub(crate) fn resolve_expression(
                 value: str_literal.value.clone(),
             }),
             ty: ResolvedType::Ptr(Box::new(ResolvedType::U8)),
+            location: context.location(loc_expr.range),
         }),
         Expression::BoolLiteral(bool_literal) => Ok(resolved_ast::ResolvedExpression {
             kind: resolved_ast::ExpressionKind::BoolLiteral(resolved_ast::BoolLiteral {
                 value: bool_literal.value,
             }),
             ty: ResolvedType::Bool,
+            location: context.location(loc_expr.range),
         }),
         Expression::StructLiteral(struct_literal_expr) => {
             let mut resolved_fields = Vec::new();
+            let mut field_types = Vec::new();
             let mut resolved_generic_args = Vec::new();
 
             let typedef = context
@@ -265,6 +307,7 @@ pub(crate) fn resolve_expression(
                     kind: resolved_ast::ExpressionKind::StructLiteral(
                         resolved_ast::StructLiteral { fields: Vec::new() },
                     ),
+                    location: context.location(loc_expr.range),
                 });
             };
             let typedef = typedef.unwrap();
@@ -273,10 +316,18 @@ pub(crate) fn resolve_expression(
             in_new_scope!(context.types, {
                 if let Some(generic_args_in_def) = &struct_def.generic_args {
                     for (i, generic_arg) in generic_args_in_def.iter().enumerate() {
-                        let resolved_generic_arg = resolve_type(
-                            context,
-                            &struct_literal_expr.generic_args.as_ref().unwrap()[i],
-                        )?;
+                        // 省略されたジェネリック引数はフィールドの値から推論する
+                        let resolved_generic_arg = match &struct_literal_expr.generic_args {
+                            Some(generic_args) => resolve_type(context, &generic_args[i])?,
+                            None => context.fresh_type_var(
+                                TypeVarKind::General,
+                                loc_expr.range,
+                                format!(
+                                    "generic argument `{}` of `{}`",
+                                    generic_arg.name, struct_literal_expr.name
+                                ),
+                            ),
+                        };
                         resolved_generic_args.push(resolved_generic_arg.clone());
                         context
                             .types
@@ -301,36 +352,36 @@ pub(crate) fn resolve_expression(
                         ));
                         continue;
                     };
-                    if let Some(generic_args) = &struct_def.generic_args {
-                        for (i, generic_arg) in generic_args.iter().enumerate() {
-                            let resolved_type = resolve_type(
-                                context,
-                                &struct_literal_expr.generic_args.as_ref().unwrap()[i],
-                            )?;
-                            context
-                                .types
-                                .borrow_mut()
-                                .add(generic_arg.name.clone(), resolved_type);
-                        }
-                    }
-
                     let expected_ty = resolve_type(context, ty)?;
                     let resolved_field = resolve_expression(
                         context,
                         field_in_expr.1.as_deref(),
-                        Some(&expected_ty.clone()),
+                        value_annotation(&expected_ty),
+                    )?;
+                    let resolved_field = coerce_to_dyn(
+                        context,
+                        &expected_ty,
+                        resolved_field,
+                        field_in_expr.1.range,
                     )?;
 
-                    if !expected_ty.can_insert(&resolved_field.ty) {
+                    if !context.can_insert(&expected_ty, &resolved_field.ty) {
                         context.errors.borrow_mut().push(CompileError::new(
                             loc_expr.range,
                             CompileErrorKind::TypeMismatch {
-                                expected: expected_ty.clone(),
-                                actual: resolved_field.ty.clone(),
+                                expected: context.zonk_for_error(&expected_ty),
+                                actual: context.zonk_for_error(&resolved_field.ty),
                             },
                         ));
                     }
+                    let resolved_field = coerce_integer(
+                        context,
+                        &expected_ty,
+                        resolved_field,
+                        field_in_expr.1.range,
+                    );
 
+                    field_types.push((field_name.clone(), expected_ty));
                     resolved_fields.push((field_name.clone(), resolved_field));
                 }
             });
@@ -347,10 +398,7 @@ pub(crate) fn resolve_expression(
                 ty: ResolvedType::StructLike(ResolvedStructType {
                     name: struct_name,
                     non_generic_name: typedef.name.clone(),
-                    fields: resolved_fields
-                        .iter()
-                        .map(|(name, expr)| (name.clone(), expr.ty.clone()))
-                        .collect(),
+                    fields: field_types,
                     generic_args: if resolved_generic_args.is_empty() {
                         None
                     } else {
@@ -360,6 +408,7 @@ pub(crate) fn resolve_expression(
                 kind: resolved_ast::ExpressionKind::StructLiteral(resolved_ast::StructLiteral {
                     fields: resolved_fields,
                 }),
+                location: context.location(loc_expr.range),
             })
         }
         Expression::SizeOf(sizeof_expr) => {
@@ -367,28 +416,34 @@ pub(crate) fn resolve_expression(
             Ok(resolved_ast::ResolvedExpression {
                 kind: resolved_ast::ExpressionKind::SizeOf(resolved_ty),
                 ty: ResolvedType::USize,
+                location: context.location(loc_expr.range),
             })
         }
         Expression::If(if_expr) => {
             let condition_expr =
                 resolve_expression(context, if_expr.cond.as_deref(), Some(&ResolvedType::Bool))?;
-            if !matches!(condition_expr.ty, ResolvedType::Bool) {
+            if !context.unify(&ResolvedType::Bool, &condition_expr.ty) {
                 context.errors.borrow_mut().push(CompileError::new(
                     loc_expr.range,
                     CompileErrorKind::TypeMismatch {
                         expected: ResolvedType::Bool,
-                        actual: condition_expr.ty.clone(),
+                        actual: context.zonk_for_error(&condition_expr.ty),
                     },
                 ));
             }
-            let then_expr = resolve_expression(context, if_expr.then.as_deref(), annotation)?;
-            let else_expr = resolve_expression(context, if_expr.els.as_deref(), annotation)?;
-            if then_expr.ty != else_expr.ty {
+            // 分岐の中で宣言した変数は、その分岐の中でしか見えない
+            let then_expr = in_new_scope!(context.scopes, {
+                resolve_expression(context, if_expr.then.as_deref(), annotation)
+            })?;
+            let else_expr = in_new_scope!(context.scopes, {
+                resolve_expression(context, if_expr.els.as_deref(), annotation)
+            })?;
+            if !context.unify(&then_expr.ty, &else_expr.ty) {
                 context.errors.borrow_mut().push(CompileError::new(
                     loc_expr.range,
                     CompileErrorKind::TypeMismatch {
-                        expected: then_expr.ty.clone(),
-                        actual: else_expr.ty.clone(),
+                        expected: context.zonk_for_error(&then_expr.ty),
+                        actual: context.zonk_for_error(&else_expr.ty),
                     },
                 ));
             }
@@ -399,6 +454,7 @@ pub(crate) fn resolve_expression(
                     then: Box::new(then_expr),
                     els: Box::new(else_expr),
                 }),
+                location: context.location(loc_expr.range),
             })
         }
         Expression::When(when_expr) => {
@@ -407,47 +463,44 @@ pub(crate) fn resolve_expression(
                 when_expr.cond.as_deref(),
                 Some(&ResolvedType::Bool),
             )?;
-            if !matches!(condition_expr.ty, ResolvedType::Bool) {
+            if !context.unify(&ResolvedType::Bool, &condition_expr.ty) {
                 context.errors.borrow_mut().push(CompileError::new(
                     loc_expr.range,
                     CompileErrorKind::TypeMismatch {
                         expected: ResolvedType::Bool,
-                        actual: condition_expr.ty.clone(),
+                        actual: context.zonk_for_error(&condition_expr.ty),
                     },
                 ));
             }
-            let then_expr = resolve_expression(context, when_expr.then.as_deref(), annotation)?;
+            let then_expr = in_new_scope!(context.scopes, {
+                resolve_expression(context, when_expr.then.as_deref(), annotation)
+            })?;
             Ok(resolved_ast::ResolvedExpression {
                 ty: ResolvedType::Void,
                 kind: resolved_ast::ExpressionKind::When(resolved_ast::WhenExpr {
                     cond: Box::new(condition_expr),
                     then: Box::new(then_expr),
                 }),
+                location: context.location(loc_expr.range),
             })
         }
+        Expression::Do(do_expr) => {
+            resolve_do_expr(context, &Located::transfer(loc_expr, do_expr), annotation)
+        }
         Expression::While(while_expr) => {
-            let condition_expr = resolve_expression(
-                context,
-                while_expr.cond.as_deref(),
-                Some(&ResolvedType::Bool),
-            )?;
-            if !matches!(condition_expr.ty, ResolvedType::Bool) {
-                context.errors.borrow_mut().push(CompileError::new(
-                    loc_expr.range,
-                    CompileErrorKind::TypeMismatch {
-                        expected: ResolvedType::Bool,
-                        actual: condition_expr.ty.clone(),
-                    },
-                ));
-            }
-            let body_expr = resolve_expression(context, while_expr.body.as_deref(), annotation)?;
-            Ok(resolved_ast::ResolvedExpression {
-                ty: ResolvedType::Void,
-                kind: resolved_ast::ExpressionKind::While(resolved_ast::WhileExpr {
-                    cond: Box::new(condition_expr),
-                    body: Box::new(body_expr),
-                }),
-            })
+            resolve_while_expr(context, &Located::transfer(loc_expr, while_expr), annotation)
+        }
+        Expression::For(for_expr) => {
+            resolve_for_expr(context, &Located::transfer(loc_expr, for_expr))
+        }
+        Expression::Break(break_expr) => {
+            resolve_break_expr(context, &Located::transfer(loc_expr, break_expr))
+        }
+        Expression::Continue(continue_expr) => {
+            resolve_continue_expr(context, &Located::transfer(loc_expr, continue_expr))
+        }
+        Expression::Lambda(lambda_expr) => {
+            resolve_lambda_expr(context, &Located::transfer(loc_expr, lambda_expr), annotation)
         }
         Expression::Assignment(assign_expr) => {
             resolve_assignment(context, &Located::transfer(loc_expr, assign_expr))
@@ -455,16 +508,24 @@ pub(crate) fn resolve_expression(
         Expression::VariableDecl(variable_decl_expr) => {
             resolve_variable_decl(context, &Located::transfer(loc_expr, variable_decl_expr))
         }
+        Expression::TupleLiteral(tuple_literal) => {
+            resolve_tuple_literal(context, &Located::transfer(loc_expr, tuple_literal), annotation)
+        }
         Expression::ArrayLiteral(array_literal) => {
-            // Extract element type from annotation if it's Vec<T>
-            let element_type_annotation = if let Some(ResolvedType::StructLike(struct_ty)) = annotation {
-                if struct_ty.non_generic_name == "Vec" {
-                    struct_ty.generic_args.as_ref().and_then(|args| args.first().cloned())
-                } else {
-                    None
+            // 注釈が `[T; N]` ならスタックに置く固定長配列、それ以外はヒープに確保する `Vec<T>` になる
+            let annotation = annotation.map(|annotation| context.shallow_resolve(annotation));
+            let annotation = annotation.as_ref();
+            let element_type_annotation = match annotation {
+                Some(ResolvedType::StructLike(struct_ty))
+                    if struct_ty.non_generic_name == "Vec" =>
+                {
+                    struct_ty
+                        .generic_args
+                        .as_ref()
+                        .and_then(|args| args.first().cloned())
                 }
-            } else {
-                None
+                Some(ResolvedType::Array(element, _)) => Some((**element).clone()),
+                _ => None,
             };
 
             let mut resolved_elements = Vec::new();
@@ -474,8 +535,14 @@ pub(crate) fn resolve_expression(
                 let resolved_element = resolve_expression(
                     context,
                     element.as_deref(),
-                    inferred_element_type.as_ref(),
+                    inferred_element_type.as_ref().and_then(value_annotation),
                 )?;
+                let resolved_element = match &element_type_annotation {
+                    Some(element_ty) => {
+                        coerce_to_dyn(context, element_ty, resolved_element, element.range)?
+                    }
+                    None => resolved_element,
+                };
                 if inferred_element_type.is_none() {
                     inferred_element_type = Some(resolved_element.ty.clone());
                 }
@@ -484,20 +551,27 @@ pub(crate) fn resolve_expression(
 
             // Verify all elements have the same type
             let element_ty = inferred_element_type.clone().unwrap_or(ResolvedType::Unknown);
-            for (i, elem) in resolved_elements.iter().enumerate() {
-                if !element_ty.can_insert(&elem.ty) {
-                    context.errors.borrow_mut().push(CompileError::new(
-                        array_literal.elements[i].range,
-                        CompileErrorKind::TypeMismatch {
-                            expected: element_ty.clone(),
-                            actual: elem.ty.clone(),
-                        },
-                    ));
-                }
-            }
+            let resolved_elements = resolved_elements
+                .into_iter()
+                .zip(&array_literal.elements)
+                .map(|(elem, element)| {
+                    if !context.can_insert(&element_ty, &elem.ty) {
+                        context.errors.borrow_mut().push(CompileError::new(
+                            element.range,
+                            CompileErrorKind::TypeMismatch {
+                                expected: context.zonk_for_error(&element_ty),
+                                actual: context.zonk_for_error(&elem.ty),
+                            },
+                        ));
+                    }
+                    coerce_integer(context, &element_ty, elem, element.range)
+                })
+                .collect::<Vec<_>>();
 
             // If annotation is provided (Vec<T>), use it as the result type
-            let result_ty = if let Some(ann) = annotation {
+            let result_ty = if let Some(ann) =
+                annotation.filter(|ann| !matches!(ann, ResolvedType::Var(_)))
+            {
                 ann.clone()
             } else if let Some(elem_ty) = inferred_element_type {
                 // Create Vec<elem_ty> type
@@ -537,12 +611,149 @@ pub(crate) fn resolve_expression(
                 ResolvedType::Unknown
             };
 
+            if let ResolvedType::Array(_, len) = &result_ty {
+                if *len != resolved_elements.len() {
+                    context.errors.borrow_mut().push(CompileError::new(
+                        loc_expr.range,
+                        CompileErrorKind::ArrayLengthMismatch {
+                            ty: context.zonk_for_error(&result_ty),
+                            expected: *len,
+                            actual: resolved_elements.len(),
+                        },
+                    ));
+                }
+            }
+
             Ok(resolved_ast::ResolvedExpression {
                 ty: result_ty,
                 kind: resolved_ast::ExpressionKind::ArrayLiteral(resolved_ast::ArrayLiteral {
                     elements: resolved_elements,
                 }),
+                location: context.location(loc_expr.range),
             })
         }
     }
 }
+
+// 数値リテラルを解決する。`negative` は `(- 5)` のオペランドであることを表し、値の範囲の検査に使う
+pub(super) fn resolve_number_literal(
+    context: &ResolverContext,
+    number_literal: &ast::NumberLiteralExpr,
+    range: ast::Range,
+    annotation: Option<&ResolvedType>,
+    negative: bool,
+) -> Result<ResolvedExpression, FaitalError> {
+    let literal = match parse_number_literal(&number_literal.value) {
+        Ok(literal) => literal,
+        Err(kind) => {
+            context
+                .errors
+                .borrow_mut()
+                .push(CompileError::new(range, kind));
+            return Ok(ResolvedExpression {
+                ty: ResolvedType::Unknown,
+                kind: ExpressionKind::Unknown,
+                location: context.location(range),
+            });
+        }
+    };
+    let is_float = matches!(literal.value, NumberLiteralValue::Float(_));
+    let ty = if let Some(suffix) = literal.suffix {
+        suffix
+    } else if let Some(annotation) = annotation.filter(|annotation| {
+        // 整数リテラルは数値型全般に、小数リテラルは浮動小数点数型にのみ合わせる
+        annotation.is_float_type() || (!is_float && annotation.is_integer_type())
+    }) {
+        annotation.clone()
+    } else if is_float
+        || matches!(literal.value, NumberLiteralValue::Integer(n) if n <= i32::MAX as u64 + negative as u64)
+    {
+        // 型の指定されていないリテラルは型変数にしておき、使われ方から型を決める
+        let literal_ty = context.fresh_type_var(
+            if is_float {
+                TypeVarKind::Float
+            } else {
+                TypeVarKind::Integer
+            },
+            range,
+            format!("number literal `{}`", number_literal.value),
+        );
+        if let Some(annotation) =
+            annotation.filter(|annotation| matches!(annotation, ResolvedType::Var(_)))
+        {
+            context.unify(annotation, &literal_ty);
+        }
+        literal_ty
+    } else if negative
+        || matches!(literal.value, NumberLiteralValue::Integer(n) if n <= i64::MAX as u64)
+    {
+        // 符号を反転したリテラルは符号なしの型にできない。収まらなければ検査で報告する
+        ResolvedType::I64
+    } else {
+        ResolvedType::U64
+    };
+
+    // 型変数のままであれば、推論が終わってから値の範囲を検査する
+    if matches!(ty, ResolvedType::Var(_)) {
+        context
+            .inference
+            .borrow_mut()
+            .defer_literal_check(PendingLiteral {
+                text: number_literal.value.clone(),
+                value: literal.value,
+                negative,
+                ty: ty.clone(),
+                range,
+            });
+    } else {
+        check_literal(
+            context,
+            &number_literal.value,
+            literal.value,
+            negative,
+            &ty,
+            range,
+        );
+    }
+
+    Ok(ResolvedExpression {
+        ty,
+        kind: resolved_ast::ExpressionKind::NumberLiteral(resolved_ast::NumberLiteral {
+            value: literal.value,
+        }),
+        location: context.location(range),
+    })
+}
+
+// 値として参照されたトップレベルの関数を、同じパッケージの関数と `use` で取り込んだ名前も含めて探す
+fn find_function_value(context: &ResolverContext, name: &str) -> Option<ast::Function> {
+    let name = context.qualify_function_name(name)?;
+    context.function_by_name.borrow().get(&name).cloned()
+}
+
+// 添字が定数であれば、固定長配列の範囲に収まっているかをコンパイル時に調べる
+pub(super) fn check_array_index(
+    context: &ResolverContext,
+    array_ty: &ResolvedType,
+    index: &ResolvedExpression,
+    range: ast::Range,
+) {
+    let ResolvedType::Array(_, len) = array_ty else {
+        return;
+    };
+    let index_value = match &index.kind {
+        ExpressionKind::NumberLiteral(resolved_ast::NumberLiteral {
+            value: NumberLiteralValue::Integer(value),
+        }) => *value,
+        _ => return,
+    };
+    if index_value >= *len as u64 {
+        context.errors.borrow_mut().push(CompileError::new(
+            range,
+            CompileErrorKind::IndexOutOfBounds {
+                index: index_value,
+                ty: context.zonk_for_error(array_ty),
+            },
+        ));
+    }
+}
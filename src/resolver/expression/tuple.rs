@@ -0,0 +1,103 @@
+use super::*;
+
+use crate::ast::{Range, TupleLiteralExpr};
+use crate::resolver::ResolverContext;
+
+use super::call::resolve_argument;
+
+// タプルは名前のない構造体なので、要素の番号をフィールド名にした構造体リテラルにする
+pub(super) fn resolve_tuple_literal(
+    context: &ResolverContext,
+    tuple_literal: &Located<&TupleLiteralExpr>,
+    annotation: Option<&ResolvedType>,
+) -> Result<ResolvedExpression, FaitalError> {
+    // 要素の数が同じタプルが期待されていれば、各要素の型をそこから決める
+    let expected = match annotation.map(|annotation| context.shallow_resolve(annotation)) {
+        Some(ResolvedType::Tuple(elements)) if elements.len() == tuple_literal.elements.len() => {
+            Some(elements)
+        }
+        _ => None,
+    };
+    let mut fields = Vec::new();
+    let mut element_types = Vec::new();
+    for (i, element) in tuple_literal.elements.iter().enumerate() {
+        let resolved_element = match &expected {
+            Some(expected) => {
+                let resolved_element = resolve_argument(context, element, &expected[i])?;
+                element_types.push(expected[i].clone());
+                resolved_element
+            }
+            None => {
+                let resolved_element = resolve_expression(context, element.as_inner_deref(), None)?;
+                element_types.push(resolved_element.ty.clone());
+                resolved_element
+            }
+        };
+        fields.push((i.to_string(), resolved_element));
+    }
+    Ok(ResolvedExpression {
+        ty: ResolvedType::Tuple(element_types),
+        kind: ExpressionKind::StructLiteral(resolved_ast::StructLiteral { fields }),
+        location: context.location(tuple_literal.range),
+    })
+}
+
+// `(:= (a b) value)` を、値を一時変数に入れてから要素ごとに変数を宣言する形にする
+pub(super) fn destructure_tuple(
+    context: &ResolverContext,
+    names: &[String],
+    ty: ResolvedType,
+    value: ResolvedExpression,
+    range: Range,
+) -> Vec<resolved_ast::VariableDecl> {
+    let element_types = names
+        .iter()
+        .map(|_| context.fresh_type_var(TypeVarKind::General, range, "an element of this tuple"))
+        .collect::<Vec<_>>();
+    if !context.unify(&ResolvedType::Tuple(element_types.clone()), &ty) {
+        context.errors.borrow_mut().push(CompileError::new(
+            range,
+            CompileErrorKind::InvalidDestructuring {
+                ty: context.zonk_for_error(&ty),
+                count: names.len(),
+            },
+        ));
+        for element_type in &element_types {
+            context.unify(element_type, &ResolvedType::Unknown);
+        }
+    }
+
+    let location = value.location;
+    let tuple_name = context.scopes.borrow_mut().fresh_hidden_name("tuple");
+    let mut decls = vec![resolved_ast::VariableDecl {
+        name: tuple_name.clone(),
+        ty: ty.clone(),
+        value: Box::new(value),
+    }];
+    for (i, (name, element_type)) in names.iter().zip(element_types).enumerate() {
+        let element = ResolvedExpression {
+            ty: element_type.clone(),
+            kind: ExpressionKind::FieldAccess(resolved_ast::FieldAccessExpr {
+                target: Box::new(ResolvedExpression {
+                    ty: ty.clone(),
+                    kind: ExpressionKind::VariableRef(resolved_ast::VariableRefExpr {
+                        name: tuple_name.clone(),
+                    }),
+                    location,
+                }),
+                field_name: i.to_string(),
+            }),
+            location,
+        };
+        let resolved_name = context
+            .scopes
+            .borrow_mut()
+            .add(name.clone(), element_type.clone());
+        decls.push(resolved_ast::VariableDecl {
+            name: resolved_name,
+            ty: element_type,
+            value: Box::new(element),
+        });
+    }
+    decls
+}
@@ -0,0 +1,91 @@
+use crate::resolver::ResolverContext;
+
+use self::ast::{UnaryExpr, UnaryOp};
+
+use super::*;
+
+pub(super) fn resolve_unary_expression(
+    context: &ResolverContext,
+    unary_expr: &Located<&UnaryExpr>,
+    annotation: Option<&ResolvedType>,
+) -> Result<ResolvedExpression, FaitalError> {
+    if unary_expr.op == UnaryOp::Not {
+        let operand = resolve_expression(context, unary_expr.operand.as_deref(), None)?;
+        if !context.unify(&ResolvedType::Bool, &operand.ty) {
+            context.errors.borrow_mut().push(CompileError::new(
+                unary_expr.range,
+                CompileErrorKind::TypeMismatch {
+                    expected: ResolvedType::Bool,
+                    actual: context.zonk_for_error(&operand.ty),
+                },
+            ));
+        }
+        return Ok(resolved_ast::ResolvedExpression {
+            kind: resolved_ast::ExpressionKind::Unary(resolved_ast::UnaryExpr {
+                op: unary_expr.op,
+                operand: Box::new(operand),
+            }),
+            ty: ResolvedType::Bool,
+            location: context.location(unary_expr.range),
+        });
+    }
+
+    // `(- 1)` の型は注釈があればそれに合わせる。
+    // 符号を反転したリテラルは、反転後の値が型に収まるかをリテラルの側で検査する
+    let negated_literal = match (unary_expr.op, &*unary_expr.operand.value) {
+        (UnaryOp::Neg, Expression::NumberLiteral(number_literal)) => Some(number_literal),
+        _ => None,
+    };
+    let operand = match negated_literal {
+        Some(number_literal) => resolve_number_literal(
+            context,
+            number_literal,
+            unary_expr.operand.range,
+            annotation,
+            true,
+        )?,
+        None => resolve_expression(context, unary_expr.operand.as_deref(), annotation)?,
+    };
+    let operand_ty = if unary_expr.op == UnaryOp::BitNot {
+        // ビット反転は整数にしか使えないので、ここで型を確定させる
+        context.structurally_resolve(&operand.ty)
+    } else {
+        context.shallow_resolve(&operand.ty)
+    };
+    let error = match (unary_expr.op, &operand_ty) {
+        (_, ResolvedType::Var(_)) => None,
+        // 符号なしの型に合わせたリテラルは、値の範囲の検査で報告される
+        (UnaryOp::Neg, _) if negated_literal.is_some() && operand_ty.is_integer_type() => None,
+        (
+            UnaryOp::Neg,
+            ResolvedType::U8 | ResolvedType::U32 | ResolvedType::U64 | ResolvedType::USize,
+        ) => Some(CompileErrorKind::CannotNegateUnsigned {
+            ty: operand_ty.clone(),
+        }),
+        (UnaryOp::Neg, ty) if !ty.is_integer_type() && !ty.is_float_type() => {
+            Some(CompileErrorKind::InvalidNumericOperand { actual: ty.clone() })
+        }
+        (UnaryOp::BitNot, ty) if !ty.is_integer_type() => {
+            Some(CompileErrorKind::InvalidIntegerOperand { actual: ty.clone() })
+        }
+        _ => None,
+    };
+    let ty = if let Some(error) = error {
+        context
+            .errors
+            .borrow_mut()
+            .push(CompileError::new(unary_expr.range, error));
+        ResolvedType::Unknown
+    } else {
+        operand_ty
+    };
+
+    Ok(resolved_ast::ResolvedExpression {
+        kind: resolved_ast::ExpressionKind::Unary(resolved_ast::UnaryExpr {
+            op: unary_expr.op,
+            operand: Box::new(operand),
+        }),
+        ty,
+        location: context.location(unary_expr.range),
+    })
+}
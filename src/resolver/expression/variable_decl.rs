@@ -1,5 +1,6 @@
 use crate::{resolved_ast::VariableDecls, resolver::ResolverContext};
 
+use super::tuple::destructure_tuple;
 use super::*;
 use ast::*;
 
@@ -18,38 +19,67 @@ pub(super) fn resolve_variable_decl(
             let resolved_expr = resolve_expression(
                 context,
                 variable_decl_expr.value.value.as_deref(),
-                resolved_annotation.as_ref(),
+                resolved_annotation.as_ref().and_then(value_annotation),
             )?;
+            let resolved_expr = match &resolved_annotation {
+                Some(annotation) => coerce_to_dyn(
+                    context,
+                    annotation,
+                    resolved_expr,
+                    variable_decl_expr.value.value.range,
+                )?,
+                None => resolved_expr,
+            };
 
             // Determine the actual type of the variable
-            let variable_type = if let Some(resolved_annotation) = &resolved_annotation {
-                if !resolved_annotation.can_insert(&resolved_expr.ty) {
+            let (variable_type, resolved_expr) = if let Some(resolved_annotation) =
+                &resolved_annotation
+            {
+                if !context.can_insert(resolved_annotation, &resolved_expr.ty) {
                     context.errors.borrow_mut().push(CompileError::new(
                         variable_decl_expr.range,
                         CompileErrorKind::TypeMismatch {
-                            expected: resolved_annotation.clone(),
-                            actual: resolved_expr.ty.clone(),
+                            expected: context.zonk_for_error(resolved_annotation),
+                            actual: context.zonk_for_error(&resolved_expr.ty),
                         },
                     ));
                 }
-                resolved_annotation.clone()
+                let resolved_expr = coerce_integer(
+                    context,
+                    resolved_annotation,
+                    resolved_expr,
+                    variable_decl_expr.value.value.range,
+                );
+                (resolved_annotation.clone(), resolved_expr)
             } else {
-                resolved_expr.ty.clone()
+                (resolved_expr.ty.clone(), resolved_expr)
             };
 
-            context
-                .scopes
-                .borrow_mut()
-                .add(variable_decl_expr.name.clone(), variable_type.clone());
-            decls.push(resolved_ast::VariableDecl {
-                name: variable_decl_expr.name.clone(),
-                ty: variable_type,
-                value: Box::new(resolved_expr),
-            });
+            match &variable_decl_expr.pattern {
+                VariablePattern::Name(name) => {
+                    let resolved_name = context
+                        .scopes
+                        .borrow_mut()
+                        .add(name.clone(), variable_type.clone());
+                    decls.push(resolved_ast::VariableDecl {
+                        name: resolved_name,
+                        ty: variable_type,
+                        value: Box::new(resolved_expr),
+                    });
+                }
+                VariablePattern::Tuple(names) => decls.extend(destructure_tuple(
+                    context,
+                    names,
+                    variable_type,
+                    resolved_expr,
+                    variable_decl_expr.range,
+                )),
+            }
         }
         Ok(ResolvedExpression {
             ty: ResolvedType::Void,
             kind: ExpressionKind::VariableDecls(VariableDecls { decls }),
+            location: context.location(variable_decls_expr.range),
         })
     })
 }
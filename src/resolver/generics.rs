@@ -1,100 +1,214 @@
 use self::error::CompileErrorKind;
 
 use super::*;
+use crate::in_new_scope;
+use crate::resolver::mangle::impl_symbol;
 
-pub(crate) fn try_implements_interface(
+// 実装の対象の型を解決する。`impl name<T> for Vec<T>` のようなジェネリックな実装では、
+// ジェネリック引数を仮の型として登録してから解決する
+pub(crate) fn resolve_impl_target_ty(
     context: &ResolverContext,
-    ty: &ResolvedType,
-    interface: &Interface,
-) -> Result<(), CompileError> {
-    let impls_by_name = context.impls_by_name.borrow();
+    implementation: &Implementation,
+) -> Result<ResolvedType> {
+    in_new_scope!(context.types, {
+        for generic_arg in implementation.decl.generic_args.iter().flatten() {
+            context.types.borrow_mut().add(
+                generic_arg.name.clone(),
+                ResolvedType::Generics(resolved_ast::ResolvedGenericType {
+                    name: generic_arg.name.clone(),
+                    restrictions: Vec::new(),
+                }),
+            );
+        }
+        resolve_type(context, &implementation.decl.target_ty)
+    })
+}
 
-    // Check if there's an implementation of this interface for the given type
-    if let Some(impls) = impls_by_name.get(&interface.name) {
-        for implementation in impls {
-            if let Ok(impl_target_ty) =
-                crate::resolver::ty::resolve_type(context, &implementation.decl.target_ty)
-            {
-                if impl_target_ty == *ty {
-                    return Ok(());
-                }
+// 実装の対象の型が `ty` に一致するかを調べ、ジェネリック引数に当てはまる型を記録する
+fn match_impl_target(
+    pattern: &ResolvedType,
+    ty: &ResolvedType,
+    bindings: &mut HashMap<String, ResolvedType>,
+) -> bool {
+    match (pattern, ty) {
+        (ResolvedType::Generics(generic_ty), _) => match bindings.get(&generic_ty.name) {
+            Some(bound_ty) => bound_ty == ty,
+            None => {
+                bindings.insert(generic_ty.name.clone(), ty.clone());
+                true
             }
+        },
+        (ResolvedType::Ptr(pattern), ResolvedType::Ptr(ty)) => {
+            match_impl_target(pattern, ty, bindings)
+        }
+        (ResolvedType::StructLike(pattern), ResolvedType::StructLike(ty)) => {
+            pattern.non_generic_name == ty.non_generic_name
+                && match (&pattern.generic_args, &ty.generic_args) {
+                    (Some(patterns), Some(tys)) => {
+                        patterns.len() == tys.len()
+                            && patterns
+                                .iter()
+                                .zip(tys)
+                                .all(|(pattern, ty)| match_impl_target(pattern, ty, bindings))
+                    }
+                    (None, None) => true,
+                    _ => false,
+                }
         }
+        _ => pattern == ty,
     }
+}
 
-    Err(CompileError::new(
-        crate::ast::Range::default(),
-        CompileErrorKind::InterfaceNotImplemented {
-            name: interface.name.clone(),
-            ty: ty.clone(),
-        },
-    ))
+// 指定した型に対するインターフェースの実装と、その関数名、実装のジェネリック引数に当てはまる型を探す
+pub(crate) fn find_implementation(
+    context: &ResolverContext,
+    interface_name: &str,
+    ty: &ResolvedType,
+) -> Option<(Implementation, String, Vec<ResolvedType>)> {
+    let impls_by_name = context.impls_by_name.borrow();
+    impls_by_name.get(interface_name)?.iter().find_map(|implementation| {
+        let impl_target_ty = resolve_impl_target_ty(context, implementation).ok()?;
+        let mut bindings = HashMap::new();
+        if !match_impl_target(&impl_target_ty, ty, &mut bindings) {
+            return None;
+        }
+        let generic_args = implementation
+            .decl
+            .generic_args
+            .iter()
+            .flatten()
+            .map(|generic_arg| bindings.remove(&generic_arg.name))
+            .collect::<Option<Vec<_>>>()?;
+        Some((
+            implementation.clone(),
+            impl_symbol(interface_name, ty),
+            generic_args,
+        ))
+    })
 }
 
-pub(crate) fn infer_generic_argument_from_args(
-    _context: &ResolverContext,
-    declared_generic_args: &[Located<ast::GenericArgument>],
-    _resolved_args: &[ResolvedType],
-    generic_annotations: &[ResolvedType],
-) -> Result<Vec<ResolvedType>, CompileError> {
-    // If no generic arguments are declared, return empty vector
-    if declared_generic_args.is_empty() {
-        return Ok(vec![]);
+// 型がインターフェースを実装しているかどうか
+pub(crate) fn implements_interface(
+    context: &ResolverContext,
+    ty: &ResolvedType,
+    interface_name: &str,
+) -> bool {
+    // 解決中の関数のwhere句で要求されている
+    let is_assumed = context
+        .assumed_bounds
+        .borrow()
+        .iter()
+        .any(|(assumed_ty, name)| assumed_ty == ty && name == interface_name);
+    if is_assumed {
+        return true;
     }
-    // For now, just return the annotations if they match the count
-    if generic_annotations.len() == declared_generic_args.len() {
-        return Ok(generic_annotations.to_vec());
+    match ty {
+        // `dyn Interface` はそのインターフェースを実装しているとみなす
+        ResolvedType::Dyn(dyn_interface_name) if dyn_interface_name == interface_name => true,
+        // 境界付きのジェネリック型は、その境界を満たしているとみなす
+        ResolvedType::Generics(generic_ty) => generic_ty.restrictions.iter().any(|r| match r {
+            resolved_ast::Restriction::Interface(restriction) => {
+                restriction.name == interface_name
+            }
+        }),
+        _ => find_implementation(context, interface_name, ty).is_some(),
     }
-    // TODO: Implement actual type inference from arguments
-    Ok(vec![])
 }
 
-pub(crate) fn check_generic_bounds(
+fn check_bound(
     context: &ResolverContext,
-    declared_generic_args: &[Located<ast::GenericArgument>],
-    resolved_arg_types: &[ResolvedType],
-    actual_generic_args: &[ResolvedType],
-) -> Result<(), CompileError> {
-    // Infer or use explicit generic arguments
-    let generic_args_to_check = if actual_generic_args.len() == declared_generic_args.len() {
-        // Use explicit generic arguments if provided
-        actual_generic_args.to_vec()
-    } else {
-        // Try to infer generic arguments from function arguments
-        infer_generic_argument_from_args(
-            context,
-            declared_generic_args,
-            resolved_arg_types,
-            actual_generic_args,
-        )?
-    };
-
-    // Check interface bounds for each generic argument
-    for (i, arg) in declared_generic_args.iter().enumerate() {
-        // Get the concrete type for this generic argument
-        let concrete_ty = generic_args_to_check
-            .get(i)
-            .cloned()
-            .unwrap_or(ResolvedType::Unknown);
-
-        for restriction in &arg.restrictions {
-            match restriction {
-                ast::Restriction::Interface(name) => {
-                    let interface_by_name = context.interface_by_name.borrow();
-                    let interface = interface_by_name.get(name).ok_or_else(|| {
-                        CompileError::new(
-                            arg.range,
-                            CompileErrorKind::InterfaceNotFound { name: name.clone() },
-                        )
-                    })?;
-                    try_implements_interface(context, &concrete_ty, &interface)?;
-                }
+    ty: &ResolvedType,
+    restriction: &ast::Restriction,
+    bound: String,
+    bound_range: Range,
+    call_range: Range,
+) {
+    // 推論に失敗した型については、別のエラーが報告されている
+    if *ty == ResolvedType::Unknown {
+        return;
+    }
+    match restriction {
+        ast::Restriction::Interface(name) => {
+            if !context.interface_by_name.borrow().contains_key(name) {
+                context.errors.borrow_mut().push(CompileError::new(
+                    bound_range,
+                    CompileErrorKind::InterfaceNotFound { name: name.clone() },
+                ));
+                return;
+            }
+            if !implements_interface(context, ty, name) {
+                context.errors.borrow_mut().push(
+                    CompileError::new(
+                        call_range,
+                        CompileErrorKind::UnsatisfiedBound {
+                            ty: ty.clone(),
+                            interface: name.clone(),
+                            bound: bound.clone(),
+                        },
+                    )
+                    .with_note(bound_range, format!("the bound `{}` is declared here", bound)),
+                );
             }
         }
     }
+}
+
+// ジェネリック引数がすべて登録されたスコープで呼ぶ必要がある
+fn check_where_clauses(
+    context: &ResolverContext,
+    callee: &ast::FunctionDecl,
+    call_range: Range,
+) -> Result<()> {
+    for where_clause in &callee.where_clauses {
+        let ty = resolve_type(context, &where_clause.ty)?;
+        for restriction in &where_clause.restrictions {
+            check_bound(
+                context,
+                &ty,
+                restriction,
+                format!("{}: {}", where_clause.ty.value, restriction),
+                where_clause.range,
+                call_range,
+            );
+        }
+    }
     Ok(())
 }
 
+// 関数のインスタンス化ごとに、ジェネリック引数の境界とwhere句を検査する。
+// ジェネリックでない関数のwhere句は、本体を解決するときに一度だけ検査する
+pub(crate) fn check_generic_bounds(
+    context: &ResolverContext,
+    callee: &ast::FunctionDecl,
+    generic_args: &[ResolvedType],
+    call_range: Range,
+) -> Result<()> {
+    let Some(declared_generic_args) = &callee.generic_args else {
+        return check_where_clauses(context, callee, call_range);
+    };
+    for (declared, actual) in declared_generic_args.iter().zip(generic_args) {
+        for restriction in &declared.restrictions {
+            check_bound(
+                context,
+                actual,
+                restriction,
+                format!("{}: {}", declared.name, restriction),
+                declared.range,
+                call_range,
+            );
+        }
+    }
+    in_new_scope!(context.types, {
+        for (declared, actual) in declared_generic_args.iter().zip(generic_args) {
+            context
+                .types
+                .borrow_mut()
+                .add(declared.name.clone(), actual.clone());
+        }
+        check_where_clauses(context, callee, call_range)
+    })
+}
+
 // NOTE: This function is currently unused - generic argument resolution is handled
 // at call sites in resolve_function_call_expr. Preserved for potential future use.
 #[allow(dead_code)]
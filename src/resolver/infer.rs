@@ -0,0 +1,738 @@
+use crate::{
+    ast::{self, Range},
+    resolved_ast::{
+        Argument, ExpressionKind, NumberLiteralValue, ResolvedExpression, ResolvedFunctionType,
+        ResolvedStructType, ResolvedType, Statement,
+    },
+};
+
+use super::{ty::get_resolved_struct_name, ResolverContext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeVarKind {
+    // 任意の型になり得る型変数
+    General,
+    // 型の指定されていない整数リテラル。最後まで決まらなければi32になる
+    Integer,
+    // 型の指定されていない浮動小数点数リテラル。最後まで決まらなければf64になる
+    Float,
+}
+
+#[derive(Debug, Clone)]
+struct TypeVar {
+    kind: TypeVarKind,
+    binding: Option<ResolvedType>,
+    // 推論できなかった場合にエラーを報告する位置と、何の型だったかの説明
+    origin: Range,
+    description: String,
+}
+
+// 型推論が終わるまで保留しているジェネリック関数のインスタンス化
+#[derive(Debug, Clone)]
+pub struct DeferredInstantiation {
+    pub callee: ast::Function,
+    pub generic_args: Vec<ResolvedType>,
+    pub call_range: Range,
+}
+
+// 型が決まってから値の範囲を検査する数値リテラル
+#[derive(Debug, Clone)]
+pub struct PendingLiteral {
+    pub text: String,
+    pub value: NumberLiteralValue,
+    pub negative: bool,
+    pub ty: ResolvedType,
+    pub range: Range,
+}
+
+// 関数一つ分の推論の状態。関数の本体を解決し終えた時点で型変数を確定させる
+#[derive(Debug, Default)]
+pub struct InferenceFrame {
+    // このフレームを作った時点の型変数の数。確定させた後はここまで表を縮める
+    pub start: usize,
+    pub vars: Vec<usize>,
+    pub deferred: Vec<DeferredInstantiation>,
+    pub literals: Vec<PendingLiteral>,
+}
+
+#[derive(Debug, Default)]
+pub struct InferenceTable {
+    vars: Vec<TypeVar>,
+    frames: Vec<InferenceFrame>,
+    // 単一化の途中で書き換えた型変数の元の状態。失敗したときにこれだけを戻す
+    undo_log: Vec<(usize, TypeVarKind, Option<ResolvedType>)>,
+}
+
+impl InferenceTable {
+    pub fn push_frame(&mut self) {
+        self.frames.push(InferenceFrame {
+            start: self.vars.len(),
+            ..Default::default()
+        });
+    }
+
+    pub fn pop_frame(&mut self) -> InferenceFrame {
+        self.frames.pop().unwrap()
+    }
+
+    // 確定させて型を置き換え終えたフレームの型変数を捨てる。
+    // まだ解決中のフレームの型変数は残す
+    pub fn release_frame(&mut self, start: usize) {
+        let live = self
+            .frames
+            .iter()
+            .filter_map(|frame| frame.vars.last())
+            .max()
+            .map_or(0, |id| id + 1);
+        self.vars.truncate(start.max(live));
+    }
+
+    pub fn fresh(&mut self, kind: TypeVarKind, origin: Range, description: String) -> ResolvedType {
+        let id = self.vars.len();
+        self.vars.push(TypeVar {
+            kind,
+            binding: None,
+            origin,
+            description,
+        });
+        if let Some(frame) = self.frames.last_mut() {
+            frame.vars.push(id);
+        }
+        ResolvedType::Var(id)
+    }
+
+    pub fn defer_instantiation(&mut self, instantiation: DeferredInstantiation) -> usize {
+        let frame = self.frames.last_mut().unwrap();
+        frame.deferred.push(instantiation);
+        frame.deferred.len() - 1
+    }
+
+    pub fn defer_literal_check(&mut self, literal: PendingLiteral) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.literals.push(literal);
+        }
+    }
+
+    // 先頭の型変数だけを辿る
+    pub fn shallow(&self, ty: &ResolvedType) -> ResolvedType {
+        match ty {
+            ResolvedType::Var(id) => match &self.vars[*id].binding {
+                Some(bound) => self.shallow(bound),
+                None => ty.clone(),
+            },
+            _ => ty.clone(),
+        }
+    }
+
+    // 型の中の全ての型変数を、現時点で分かっている型に置き換える
+    pub fn zonk(&self, ty: &ResolvedType) -> ResolvedType {
+        match ty {
+            ResolvedType::Var(id) => match &self.vars[*id].binding {
+                Some(bound) => self.zonk(bound),
+                None => ty.clone(),
+            },
+            ResolvedType::Ptr(inner) => ResolvedType::Ptr(Box::new(self.zonk(inner))),
+            ResolvedType::StructLike(struct_ty) => {
+                let generic_args = struct_ty
+                    .generic_args
+                    .as_ref()
+                    .map(|args| args.iter().map(|arg| self.zonk(arg)).collect::<Vec<_>>());
+                // 構造体名にはジェネリック引数が含まれるので作り直す
+                let name = match &generic_args {
+                    Some(args) => get_resolved_struct_name(&struct_ty.non_generic_name, Some(args)),
+                    None => struct_ty.name.clone(),
+                };
+                ResolvedType::StructLike(ResolvedStructType {
+                    name,
+                    non_generic_name: struct_ty.non_generic_name.clone(),
+                    fields: struct_ty
+                        .fields
+                        .iter()
+                        .map(|(name, ty)| (name.clone(), self.zonk(ty)))
+                        .collect(),
+                    generic_args,
+                })
+            }
+            ResolvedType::Function(function_ty) => ResolvedType::Function(ResolvedFunctionType {
+                args: function_ty.args.iter().map(|arg| self.zonk(arg)).collect(),
+                return_type: Box::new(self.zonk(&function_ty.return_type)),
+            }),
+            ResolvedType::Tuple(elements) => {
+                ResolvedType::Tuple(elements.iter().map(|element| self.zonk(element)).collect())
+            }
+            ResolvedType::Array(element, len) => {
+                ResolvedType::Array(Box::new(self.zonk(element)), *len)
+            }
+            _ => ty.clone(),
+        }
+    }
+
+    // エラーメッセージ用。リテラル由来の型変数は既定の型として表示する
+    pub fn zonk_for_error(&self, ty: &ResolvedType) -> ResolvedType {
+        let zonked = self.zonk(ty);
+        self.default_literal_vars(&zonked)
+    }
+
+    // 置き換え済みの型に残ったリテラル由来の型変数を、表を書き換えずに既定の型にする
+    fn default_literal_vars(&self, ty: &ResolvedType) -> ResolvedType {
+        match ty {
+            ResolvedType::Var(id) => match self.vars[*id].kind {
+                TypeVarKind::General => ty.clone(),
+                TypeVarKind::Integer => ResolvedType::I32,
+                TypeVarKind::Float => ResolvedType::F64,
+            },
+            ResolvedType::Ptr(inner) => {
+                ResolvedType::Ptr(Box::new(self.default_literal_vars(inner)))
+            }
+            ResolvedType::StructLike(struct_ty) => {
+                let generic_args = struct_ty.generic_args.as_ref().map(|args| {
+                    args.iter()
+                        .map(|arg| self.default_literal_vars(arg))
+                        .collect::<Vec<_>>()
+                });
+                let name = match &generic_args {
+                    Some(args) => get_resolved_struct_name(&struct_ty.non_generic_name, Some(args)),
+                    None => struct_ty.name.clone(),
+                };
+                ResolvedType::StructLike(ResolvedStructType {
+                    name,
+                    non_generic_name: struct_ty.non_generic_name.clone(),
+                    fields: struct_ty
+                        .fields
+                        .iter()
+                        .map(|(name, ty)| (name.clone(), self.default_literal_vars(ty)))
+                        .collect(),
+                    generic_args,
+                })
+            }
+            ResolvedType::Function(function_ty) => ResolvedType::Function(ResolvedFunctionType {
+                args: function_ty
+                    .args
+                    .iter()
+                    .map(|arg| self.default_literal_vars(arg))
+                    .collect(),
+                return_type: Box::new(self.default_literal_vars(&function_ty.return_type)),
+            }),
+            ResolvedType::Tuple(elements) => ResolvedType::Tuple(
+                elements
+                    .iter()
+                    .map(|element| self.default_literal_vars(element))
+                    .collect(),
+            ),
+            ResolvedType::Array(element, len) => {
+                ResolvedType::Array(Box::new(self.default_literal_vars(element)), *len)
+            }
+            _ => ty.clone(),
+        }
+    }
+
+    pub fn contains_vars(&self, ty: &ResolvedType) -> bool {
+        match self.zonk(ty) {
+            ResolvedType::Var(_) => true,
+            ResolvedType::Ptr(inner) => self.contains_vars(&inner),
+            ResolvedType::StructLike(struct_ty) => struct_ty
+                .generic_args
+                .iter()
+                .flatten()
+                .any(|arg| self.contains_vars(arg)),
+            ResolvedType::Function(function_ty) => function_ty
+                .args
+                .iter()
+                .chain([&*function_ty.return_type])
+                .any(|ty| self.contains_vars(ty)),
+            ResolvedType::Tuple(elements) => elements.iter().any(|ty| self.contains_vars(ty)),
+            ResolvedType::Array(element, _) => self.contains_vars(&element),
+            _ => false,
+        }
+    }
+
+    // 型が具体的に分かっていないと先に進めない位置で使う。
+    // リテラル由来の型変数はその場で既定の型に確定させる
+    pub fn structurally_resolve(&mut self, ty: &ResolvedType) -> ResolvedType {
+        let ty = self.shallow(ty);
+        if let ResolvedType::Var(id) = ty {
+            self.apply_default(id);
+            return self.shallow(&ty);
+        }
+        ty
+    }
+
+    fn apply_default(&mut self, id: usize) {
+        let var = &mut self.vars[id];
+        if var.binding.is_some() {
+            return;
+        }
+        var.binding = match var.kind {
+            TypeVarKind::General => None,
+            TypeVarKind::Integer => Some(ResolvedType::I32),
+            TypeVarKind::Float => Some(ResolvedType::F64),
+        };
+    }
+
+    pub fn unify(&mut self, a: &ResolvedType, b: &ResolvedType) -> bool {
+        // 失敗した場合に途中までの束縛が残らないようにする
+        let unified = self.unify_inner(a, b);
+        let undo_log = std::mem::take(&mut self.undo_log);
+        if !unified {
+            for (id, kind, binding) in undo_log.into_iter().rev() {
+                self.vars[id].kind = kind;
+                self.vars[id].binding = binding;
+            }
+        }
+        unified
+    }
+
+    // 算術演算と比較の両辺を単一化する。型注釈と同じく、整数リテラルは浮動小数点数型にも合わせる
+    pub fn unify_numeric(&mut self, a: &ResolvedType, b: &ResolvedType) -> bool {
+        let a_shallow = self.shallow(a);
+        let b_shallow = self.shallow(b);
+        for (var, other) in [(&a_shallow, &b_shallow), (&b_shallow, &a_shallow)] {
+            let ResolvedType::Var(id) = var else {
+                continue;
+            };
+            let other_is_float = match other {
+                ResolvedType::Var(other_id) => self.vars[*other_id].kind == TypeVarKind::Float,
+                other => other.is_float_type(),
+            };
+            if self.vars[*id].kind == TypeVarKind::Integer && other_is_float {
+                // 単一化に失敗すれば、種類の変更も一緒に戻る
+                self.record_undo(*id);
+                self.vars[*id].kind = TypeVarKind::Float;
+            }
+        }
+        self.unify(a, b)
+    }
+
+    // 単一化の途中で型変数を書き換える前に、元の状態を記録する
+    fn record_undo(&mut self, id: usize) {
+        let var = &self.vars[id];
+        self.undo_log.push((id, var.kind, var.binding.clone()));
+    }
+
+    fn unify_inner(&mut self, a: &ResolvedType, b: &ResolvedType) -> bool {
+        let a = self.shallow(a);
+        let b = self.shallow(b);
+        match (&a, &b) {
+            (ResolvedType::Var(x), ResolvedType::Var(y)) => {
+                if x == y {
+                    return true;
+                }
+                let Some(kind) = merge_kinds(self.vars[*x].kind, self.vars[*y].kind) else {
+                    return false;
+                };
+                // 先に作られた型変数を残すことで、エラーの報告位置を最初の出現にする
+                let (from, to) = if x > y { (*x, *y) } else { (*y, *x) };
+                self.record_undo(to);
+                self.record_undo(from);
+                self.vars[to].kind = kind;
+                self.vars[from].binding = Some(ResolvedType::Var(to));
+                true
+            }
+            (ResolvedType::Var(id), ty) | (ty, ResolvedType::Var(id)) => self.bind(*id, ty),
+            // エラーが報告済みの型とは何とでも単一化できるものとする
+            (ResolvedType::Unknown, _) | (_, ResolvedType::Unknown) => true,
+            (ResolvedType::Ptr(a_inner), ResolvedType::Ptr(b_inner)) => {
+                self.unify_inner(a_inner, b_inner)
+            }
+            (ResolvedType::StructLike(a_struct), ResolvedType::StructLike(b_struct)) => {
+                if a_struct.non_generic_name != b_struct.non_generic_name {
+                    return false;
+                }
+                match (&a_struct.generic_args, &b_struct.generic_args) {
+                    (Some(a_args), Some(b_args)) => {
+                        a_args.len() == b_args.len()
+                            && a_args
+                                .iter()
+                                .zip(b_args)
+                                .all(|(a_arg, b_arg)| self.unify_inner(a_arg, b_arg))
+                    }
+                    (None, None) => true,
+                    _ => false,
+                }
+            }
+            (ResolvedType::Function(a_fn), ResolvedType::Function(b_fn)) => {
+                a_fn.args.len() == b_fn.args.len()
+                    && a_fn
+                        .args
+                        .iter()
+                        .zip(&b_fn.args)
+                        .all(|(a_arg, b_arg)| self.unify_inner(a_arg, b_arg))
+                    && self.unify_inner(&a_fn.return_type, &b_fn.return_type)
+            }
+            (ResolvedType::Tuple(a_elements), ResolvedType::Tuple(b_elements)) => {
+                a_elements.len() == b_elements.len()
+                    && a_elements
+                        .iter()
+                        .zip(b_elements)
+                        .all(|(a_element, b_element)| self.unify_inner(a_element, b_element))
+            }
+            (ResolvedType::Array(a_element, a_len), ResolvedType::Array(b_element, b_len)) => {
+                a_len == b_len && self.unify_inner(a_element, b_element)
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    fn bind(&mut self, id: usize, ty: &ResolvedType) -> bool {
+        // 無限の型にならないようにする (occurs check)
+        if self.occurs(id, ty) {
+            return false;
+        }
+        let accepts = match self.vars[id].kind {
+            _ if *ty == ResolvedType::Unknown => true,
+            TypeVarKind::General => true,
+            TypeVarKind::Integer => ty.is_integer_type(),
+            TypeVarKind::Float => ty.is_float_type(),
+        };
+        if accepts {
+            self.record_undo(id);
+            self.vars[id].binding = Some(ty.clone());
+        }
+        accepts
+    }
+
+    fn occurs(&self, id: usize, ty: &ResolvedType) -> bool {
+        match self.zonk(ty) {
+            ResolvedType::Var(other) => other == id,
+            ResolvedType::Ptr(inner) => self.occurs(id, &inner),
+            ResolvedType::StructLike(struct_ty) => struct_ty
+                .generic_args
+                .iter()
+                .flatten()
+                .chain(struct_ty.fields.iter().map(|(_, ty)| ty))
+                .any(|ty| self.occurs(id, ty)),
+            ResolvedType::Function(function_ty) => function_ty
+                .args
+                .iter()
+                .chain([&*function_ty.return_type])
+                .any(|ty| self.occurs(id, ty)),
+            ResolvedType::Tuple(elements) => elements.iter().any(|ty| self.occurs(id, ty)),
+            ResolvedType::Array(element, _) => self.occurs(id, &element),
+            _ => false,
+        }
+    }
+
+    // 値を期待される型の位置に置けるかを調べる。
+    // 型変数が含まれていれば単一化し、そうでなければ暗黙の型変換を含めて判定する
+    pub fn can_insert(&mut self, expected: &ResolvedType, actual: &ResolvedType) -> bool {
+        let expected = self.zonk(expected);
+        let actual = self.zonk(actual);
+        // void* を通して型変数が void に束縛されないようにする
+        if is_void_ptr(&expected) && actual.is_pointer_type()
+            || is_void_ptr(&actual) && expected.is_pointer_type()
+        {
+            return true;
+        }
+        if self.unify(&expected, &actual) {
+            return true;
+        }
+        self.zonk(&expected).can_insert(&self.zonk(&actual))
+    }
+
+    pub fn apply_literal_defaults(&mut self, vars: &[usize]) {
+        for id in vars {
+            if self.vars[*id].kind != TypeVarKind::General {
+                let root = self.shallow(&ResolvedType::Var(*id));
+                if let ResolvedType::Var(root_id) = root {
+                    self.apply_default(root_id);
+                }
+            }
+        }
+    }
+
+    // 最後まで型の決まらなかった型変数を返し、以降のエラーを防ぐためUnknownに束縛する
+    pub fn take_ambiguous(&mut self, vars: &[usize]) -> Vec<(Range, String)> {
+        let mut ambiguous = Vec::new();
+        for id in vars {
+            if let ResolvedType::Var(root_id) = self.shallow(&ResolvedType::Var(*id)) {
+                let var = &self.vars[root_id];
+                ambiguous.push((var.origin, var.description.clone()));
+                self.vars[root_id].binding = Some(ResolvedType::Unknown);
+            }
+        }
+        ambiguous
+    }
+
+    pub fn zonk_statement(&self, statement: &mut Statement, instantiated_names: &[Option<String>]) {
+        match statement {
+            Statement::Return(ret) => {
+                if let Some(expression) = &mut ret.expression {
+                    self.zonk_expression(expression, instantiated_names);
+                }
+            }
+            Statement::Effect(effect) => {
+                self.zonk_expression(&mut effect.expression, instantiated_names)
+            }
+        }
+    }
+
+    fn zonk_expression(&self, expr: &mut ResolvedExpression, names: &[Option<String>]) {
+        expr.ty = self.zonk(&expr.ty);
+        match &mut expr.kind {
+            ExpressionKind::SizeOf(ty) => *ty = self.zonk(ty),
+            ExpressionKind::VariableRef(_)
+            | ExpressionKind::NumberLiteral(_)
+            | ExpressionKind::StringLiteral(_)
+            | ExpressionKind::BoolLiteral(_)
+            | ExpressionKind::Continue(_)
+            | ExpressionKind::Unknown => {}
+            ExpressionKind::StructLiteral(struct_literal) => {
+                for (_, field) in &mut struct_literal.fields {
+                    self.zonk_expression(field, names);
+                }
+            }
+            ExpressionKind::ArrayLiteral(array_literal) => {
+                for element in &mut array_literal.elements {
+                    self.zonk_expression(element, names);
+                }
+            }
+            ExpressionKind::Binary(binary) => {
+                self.zonk_expression(&mut binary.lhs, names);
+                self.zonk_expression(&mut binary.rhs, names);
+            }
+            ExpressionKind::Unary(unary) => self.zonk_expression(&mut unary.operand, names),
+            ExpressionKind::Multi(multi) => {
+                for operand in &mut multi.operands {
+                    self.zonk_expression(operand, names);
+                }
+            }
+            ExpressionKind::CallExpr(call) => {
+                if let Some(index) = call.pending_instantiation {
+                    if let Some(name) = &names[index] {
+                        call.callee = name.clone();
+                    }
+                }
+                for arg in &mut call.args {
+                    self.zonk_expression(arg, names);
+                }
+            }
+            ExpressionKind::Deref(deref) => self.zonk_expression(&mut deref.target, names),
+            ExpressionKind::AddressOf(address_of) => {
+                self.zonk_expression(&mut address_of.target, names)
+            }
+            ExpressionKind::IndexAccess(index_access) => {
+                self.zonk_expression(&mut index_access.target, names);
+                self.zonk_expression(&mut index_access.index, names);
+            }
+            ExpressionKind::FieldAccess(field_access) => {
+                self.zonk_expression(&mut field_access.target, names)
+            }
+            ExpressionKind::If(if_expr) => {
+                self.zonk_expression(&mut if_expr.cond, names);
+                self.zonk_expression(&mut if_expr.then, names);
+                self.zonk_expression(&mut if_expr.els, names);
+            }
+            ExpressionKind::When(when_expr) => {
+                self.zonk_expression(&mut when_expr.cond, names);
+                self.zonk_expression(&mut when_expr.then, names);
+            }
+            ExpressionKind::While(while_expr) => {
+                self.zonk_expression(&mut while_expr.cond, names);
+                self.zonk_expression(&mut while_expr.body, names);
+                if let Some(step) = &mut while_expr.step {
+                    self.zonk_expression(step, names);
+                }
+            }
+            ExpressionKind::Block(block) => {
+                for statement in &mut block.statements {
+                    self.zonk_statement(statement, names);
+                }
+            }
+            ExpressionKind::Break(break_expr) => {
+                if let Some(value) = &mut break_expr.value {
+                    self.zonk_expression(value, names);
+                }
+            }
+            ExpressionKind::VariableDecls(variable_decls) => {
+                for decl in &mut variable_decls.decls {
+                    decl.ty = self.zonk(&decl.ty);
+                    self.zonk_expression(&mut decl.value, names);
+                }
+            }
+            ExpressionKind::Assignment(assignment) => {
+                self.zonk_expression(&mut assignment.value, names);
+                if let Some(index) = &mut assignment.index_access {
+                    self.zonk_expression(index, names);
+                }
+            }
+            ExpressionKind::Cast(cast) => self.zonk_expression(&mut cast.target, names),
+            ExpressionKind::DynCoerce(dyn_coerce) => {
+                self.zonk_expression(&mut dyn_coerce.target, names)
+            }
+            ExpressionKind::DynCall(dyn_call) => {
+                self.zonk_expression(&mut dyn_call.receiver, names);
+                for arg in &mut dyn_call.args {
+                    self.zonk_expression(arg, names);
+                }
+            }
+            ExpressionKind::Lambda(lambda) => {
+                for arg in &mut lambda.args {
+                    if let Argument::Normal(ty, _) = arg {
+                        *ty = self.zonk(ty);
+                    }
+                }
+                lambda.return_type = self.zonk(&lambda.return_type);
+                for (_, ty) in &mut lambda.captures {
+                    *ty = self.zonk(ty);
+                }
+                for statement in &mut lambda.body {
+                    self.zonk_statement(statement, names);
+                }
+            }
+            ExpressionKind::ClosureCall(closure_call) => {
+                self.zonk_expression(&mut closure_call.callee, names);
+                for arg in &mut closure_call.args {
+                    self.zonk_expression(arg, names);
+                }
+            }
+        }
+    }
+}
+
+fn merge_kinds(a: TypeVarKind, b: TypeVarKind) -> Option<TypeVarKind> {
+    match (a, b) {
+        (TypeVarKind::General, kind) | (kind, TypeVarKind::General) => Some(kind),
+        (a, b) if a == b => Some(a),
+        _ => None,
+    }
+}
+
+fn is_void_ptr(ty: &ResolvedType) -> bool {
+    matches!(ty, ResolvedType::Ptr(inner) if **inner == ResolvedType::Void)
+}
+
+impl ResolverContext {
+    pub(crate) fn fresh_type_var(
+        &self,
+        kind: TypeVarKind,
+        origin: Range,
+        description: impl Into<String>,
+    ) -> ResolvedType {
+        self.inference
+            .borrow_mut()
+            .fresh(kind, origin, description.into())
+    }
+
+    pub(crate) fn unify(&self, a: &ResolvedType, b: &ResolvedType) -> bool {
+        self.inference.borrow_mut().unify(a, b)
+    }
+
+    pub(crate) fn unify_numeric(&self, a: &ResolvedType, b: &ResolvedType) -> bool {
+        self.inference.borrow_mut().unify_numeric(a, b)
+    }
+
+    pub(crate) fn can_insert(&self, expected: &ResolvedType, actual: &ResolvedType) -> bool {
+        self.inference.borrow_mut().can_insert(expected, actual)
+    }
+
+    pub(crate) fn zonk(&self, ty: &ResolvedType) -> ResolvedType {
+        self.inference.borrow().zonk(ty)
+    }
+
+    pub(crate) fn zonk_for_error(&self, ty: &ResolvedType) -> ResolvedType {
+        self.inference.borrow().zonk_for_error(ty)
+    }
+
+    pub(crate) fn shallow_resolve(&self, ty: &ResolvedType) -> ResolvedType {
+        self.inference.borrow().shallow(ty)
+    }
+
+    pub(crate) fn structurally_resolve(&self, ty: &ResolvedType) -> ResolvedType {
+        self.inference.borrow_mut().structurally_resolve(ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vec_of(ty: ResolvedType) -> ResolvedType {
+        ResolvedType::StructLike(ResolvedStructType {
+            name: get_resolved_struct_name("Vec", Some(&[ty.clone()])),
+            non_generic_name: "Vec".to_string(),
+            fields: vec![("buf".to_string(), ResolvedType::Ptr(Box::new(ty.clone())))],
+            generic_args: Some(vec![ty]),
+        })
+    }
+
+    #[test]
+    fn test_unify_through_struct() {
+        let mut table = InferenceTable::default();
+        table.push_frame();
+        let elem = table.fresh(TypeVarKind::General, Range::default(), "T".into());
+        let literal = table.fresh(TypeVarKind::Integer, Range::default(), "literal".into());
+        assert!(table.unify(&vec_of(elem.clone()), &vec_of(literal.clone())));
+        let frame = table.pop_frame();
+        table.apply_literal_defaults(&frame.vars);
+        assert_eq!(table.zonk(&vec_of(elem)), vec_of(ResolvedType::I32));
+        assert!(table.take_ambiguous(&frame.vars).is_empty());
+    }
+
+    #[test]
+    fn test_unify_failure_rolls_back() {
+        let mut table = InferenceTable::default();
+        let var = table.fresh(TypeVarKind::General, Range::default(), "T".into());
+        let pair = |a, b| {
+            ResolvedType::StructLike(ResolvedStructType {
+                name: "Pair".to_string(),
+                non_generic_name: "Pair".to_string(),
+                fields: vec![],
+                generic_args: Some(vec![a, b]),
+            })
+        };
+        assert!(!table.unify(
+            &pair(var.clone(), ResolvedType::Bool),
+            &pair(ResolvedType::I32, ResolvedType::U8)
+        ));
+        assert_eq!(table.zonk(&var), var);
+        let literal = table.fresh(TypeVarKind::Integer, Range::default(), "literal".into());
+        assert!(!table.unify(&literal, &ResolvedType::Bool));
+        assert!(!table.unify(&var, &ResolvedType::Ptr(Box::new(var.clone()))));
+    }
+
+    #[test]
+    fn test_unify_function_types() {
+        let mut table = InferenceTable::default();
+        let function = |args, return_type| {
+            ResolvedType::Function(ResolvedFunctionType {
+                args,
+                return_type: Box::new(return_type),
+            })
+        };
+        let arg = table.fresh(TypeVarKind::General, Range::default(), "arg".into());
+        let ret = table.fresh(TypeVarKind::General, Range::default(), "ret".into());
+        assert!(table.unify(
+            &function(vec![arg.clone()], ret.clone()),
+            &function(vec![ResolvedType::I32], ResolvedType::Bool)
+        ));
+        assert_eq!(table.zonk(&arg), ResolvedType::I32);
+        assert_eq!(table.zonk(&ret), ResolvedType::Bool);
+        // 引数の数が違えば単一化できない
+        assert!(!table.unify(
+            &function(vec![], ResolvedType::Bool),
+            &function(vec![ResolvedType::I32], ResolvedType::Bool)
+        ));
+        let var = table.fresh(TypeVarKind::General, Range::default(), "T".into());
+        assert!(!table.unify(&var, &function(vec![var.clone()], ResolvedType::Void)));
+    }
+
+    #[test]
+    fn test_release_frame_keeps_outer_vars() {
+        let mut table = InferenceTable::default();
+        table.push_frame();
+        let outer = table.fresh(TypeVarKind::Integer, Range::default(), "outer".into());
+        // 外側の関数を解決している途中で、呼び出した関数を解決する
+        table.push_frame();
+        let inner = table.fresh(TypeVarKind::General, Range::default(), "inner".into());
+        assert!(table.unify(&inner, &ResolvedType::Bool));
+        let frame = table.pop_frame();
+        table.release_frame(frame.start);
+        assert_eq!(table.vars.len(), 1);
+        assert_eq!(table.zonk_for_error(&outer), ResolvedType::I32);
+
+        assert!(table.unify(&outer, &ResolvedType::U8));
+        let frame = table.pop_frame();
+        table.release_frame(frame.start);
+        assert!(table.vars.is_empty());
+    }
+}
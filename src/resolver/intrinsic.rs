@@ -6,23 +6,25 @@ use nom::{
 };
 
 use crate::{
-    ast::{self, Function, FunctionDecl},
+    ast::{self, Function},
     parser::parse_module,
     resolved_ast::ResolvedType,
 };
 
 use super::TypeScopes;
 
+// 宣言しなくても使えるCの関数。ユーザーが書く `extern "C" fn` と同じように扱う
 const INTRINSIC_DECLS: &str = r#"
-fn malloc(size: usize) : *void {}
-fn free(ptr: *void) : *void {}
-fn memcpy(dst: *void, src: *void, size: usize) : void {}
-fn memset(dst: *void, value: *void, size: usize) : void {}
-fn strlen(s: *u8) : usize {}
-fn strcmp(s1: *u8, s2: *u8) : i32 {}
-fn strcpy(dst: *u8, src: *u8) : *u8 {}
-fn strcat(dst: *u8, src: *u8) : *u8 {}
-fn printf(s: *u8, ...) : i32 {}
+extern "C" fn malloc(size: usize) : *void;
+extern "C" fn free(ptr: *void) : *void;
+extern "C" fn memcpy(dst: *void, src: *void, size: usize) : void;
+extern "C" fn memset(dst: *void, value: *void, size: usize) : void;
+extern "C" fn strlen(s: *u8) : usize;
+extern "C" fn strcmp(s1: *u8, s2: *u8) : i32;
+extern "C" fn strcpy(dst: *u8, src: *u8) : *u8;
+extern "C" fn strcat(dst: *u8, src: *u8) : *u8;
+extern "C" fn printf(s: *u8, ...) : i32;
+extern "C" fn exit(status: i32) : void;
 "#;
 
 // 組み込み関数の定義を追加する
@@ -46,17 +48,7 @@ pub(super) fn register_intrinsic_functions(function_by_name: &mut HashMap<String
     for toplevel in intrinsic_module.toplevels {
         match toplevel.value {
             ast::TopLevel::Function(function) => {
-                let function_name = function.decl.name.clone();
-                function_by_name.insert(
-                    function_name,
-                    Function {
-                        decl: FunctionDecl {
-                            is_intrinsic: true,
-                            ..function.decl
-                        },
-                        body: function.body,
-                    },
-                );
+                function_by_name.insert(function.decl.name.clone(), function);
             }
             ast::TopLevel::TypeDef(_) => {}
             ast::TopLevel::Implemantation(_) => unreachable!(),
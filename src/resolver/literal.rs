@@ -0,0 +1,265 @@
+use crate::{
+    common::target::PointerSizedIntWidth,
+    resolved_ast::{NumberLiteralValue, ResolvedType},
+};
+
+use super::error::CompileErrorKind;
+
+#[derive(Debug, PartialEq)]
+pub struct ParsedNumberLiteral {
+    pub value: NumberLiteralValue,
+    // `10u8` や `2.5f32` のように接尾辞で指定された型
+    pub suffix: Option<ResolvedType>,
+}
+
+fn suffix_to_type(suffix: &str) -> Option<ResolvedType> {
+    Some(match suffix {
+        "u8" => ResolvedType::U8,
+        "u32" => ResolvedType::U32,
+        "u64" => ResolvedType::U64,
+        "usize" => ResolvedType::USize,
+        "i32" => ResolvedType::I32,
+        "i64" => ResolvedType::I64,
+        "f32" => ResolvedType::F32,
+        "f64" => ResolvedType::F64,
+        _ => return None,
+    })
+}
+
+// 数値リテラルの字句を解釈する。
+// 10進数 (小数を含む) と 0x / 0b / 0o の基数付き整数、`_` による区切り、型の接尾辞に対応する
+pub fn parse_number_literal(text: &str) -> Result<ParsedNumberLiteral, CompileErrorKind> {
+    let invalid = || CompileErrorKind::InvalidNumberLiteral {
+        value: text.to_string(),
+    };
+
+    let (radix, body) = match text.get(..2) {
+        Some("0x") => (16, &text[2..]),
+        Some("0b") => (2, &text[2..]),
+        Some("0o") => (8, &text[2..]),
+        _ => (10, text),
+    };
+    // 16進数では a-f が桁になるので、接尾辞は u か i から始まるものに限られる
+    let suffix_start = body
+        .find(|c: char| {
+            if radix == 16 {
+                c == 'u' || c == 'i'
+            } else {
+                c.is_ascii_alphabetic()
+            }
+        })
+        .unwrap_or(body.len());
+    let (digits, suffix) = body.split_at(suffix_start);
+    let suffix = if suffix.is_empty() {
+        None
+    } else {
+        Some(suffix_to_type(suffix).ok_or_else(invalid)?)
+    };
+
+    if digits.starts_with('_') || !digits.chars().any(|c| c.is_digit(radix)) {
+        return Err(invalid());
+    }
+    let digits = digits.replace('_', "");
+
+    let is_float = digits.contains('.') || suffix.as_ref().is_some_and(|ty| ty.is_float_type());
+    if is_float {
+        // 基数付きの小数や、小数に整数型の接尾辞を付けたものは受け付けない
+        if radix != 10 || suffix.as_ref().is_some_and(|ty| ty.is_integer_type()) {
+            return Err(invalid());
+        }
+        let value = digits.parse::<f64>().map_err(|_| invalid())?;
+        return Ok(ParsedNumberLiteral {
+            value: NumberLiteralValue::Float(value),
+            suffix,
+        });
+    }
+
+    let value = u128::from_str_radix(&digits, radix).map_err(|_| invalid())?;
+    let value = u64::try_from(value).map_err(|_| CompileErrorKind::NumberLiteralOutOfRange {
+        value: text.to_string(),
+        ty: suffix.clone().unwrap_or(ResolvedType::U64),
+        min: "0".to_string(),
+        max: u64::MAX.to_string(),
+    })?;
+    Ok(ParsedNumberLiteral {
+        value: NumberLiteralValue::Integer(value),
+        suffix,
+    })
+}
+
+// 型が取り得る整数の範囲 (最小値, 最大値)。整数型でなければNone
+pub fn integer_range(
+    ty: &ResolvedType,
+    ptr_sized_int_type: PointerSizedIntWidth,
+) -> Option<(i128, i128)> {
+    Some(match ty {
+        ResolvedType::U8 => (0, u8::MAX as i128),
+        ResolvedType::U32 => (0, u32::MAX as i128),
+        ResolvedType::U64 => (0, u64::MAX as i128),
+        ResolvedType::USize => match ptr_sized_int_type {
+            PointerSizedIntWidth::ThirtyTwo => (0, u32::MAX as i128),
+            PointerSizedIntWidth::SixtyFour => (0, u64::MAX as i128),
+        },
+        ResolvedType::I32 => (i32::MIN as i128, i32::MAX as i128),
+        ResolvedType::I64 => (i64::MIN as i128, i64::MAX as i128),
+        _ => return None,
+    })
+}
+
+// リテラルの値が型に収まるかを確かめる。数値型以外の型はここでは検査しない。
+// `negative` は `(- 5)` のように符号を反転したリテラルであることを表す
+pub fn check_number_literal(
+    text: &str,
+    value: NumberLiteralValue,
+    negative: bool,
+    ty: &ResolvedType,
+    ptr_sized_int_type: PointerSizedIntWidth,
+) -> Result<(), CompileErrorKind> {
+    match value {
+        NumberLiteralValue::Integer(n) => {
+            if let Some((min, max)) = integer_range(ty, ptr_sized_int_type) {
+                let n = if negative { -(n as i128) } else { n as i128 };
+                if n < min || n > max {
+                    return Err(CompileErrorKind::NumberLiteralOutOfRange {
+                        value: if negative {
+                            format!("-{}", text)
+                        } else {
+                            text.to_string()
+                        },
+                        ty: ty.clone(),
+                        min: min.to_string(),
+                        max: max.to_string(),
+                    });
+                }
+            }
+        }
+        NumberLiteralValue::Float(n) => {
+            if ty.is_integer_type() {
+                return Err(CompileErrorKind::TypeMismatch {
+                    expected: ty.clone(),
+                    actual: ResolvedType::F64,
+                });
+            }
+            if *ty == ResolvedType::F32 && n.is_finite() && n.abs() > f32::MAX as f64 {
+                return Err(CompileErrorKind::NumberLiteralOutOfRange {
+                    value: text.to_string(),
+                    ty: ty.clone(),
+                    min: f32::MIN.to_string(),
+                    max: f32::MAX.to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> ParsedNumberLiteral {
+        parse_number_literal(text).unwrap()
+    }
+
+    #[test]
+    fn test_parse_number_literal() {
+        assert_eq!(parse("42").value, NumberLiteralValue::Integer(42));
+        assert_eq!(
+            parse("1_000_000").value,
+            NumberLiteralValue::Integer(1_000_000)
+        );
+        assert_eq!(parse("0xff").value, NumberLiteralValue::Integer(255));
+        assert_eq!(parse("0b1010").value, NumberLiteralValue::Integer(10));
+        assert_eq!(parse("0o17").value, NumberLiteralValue::Integer(15));
+        assert_eq!(parse(".5").value, NumberLiteralValue::Float(0.5));
+
+        let literal = parse("1_000_000i64");
+        assert_eq!(literal.value, NumberLiteralValue::Integer(1_000_000));
+        assert_eq!(literal.suffix, Some(ResolvedType::I64));
+        let literal = parse("0xFFu8");
+        assert_eq!(literal.value, NumberLiteralValue::Integer(255));
+        assert_eq!(literal.suffix, Some(ResolvedType::U8));
+        let literal = parse("2.5f32");
+        assert_eq!(literal.value, NumberLiteralValue::Float(2.5));
+        assert_eq!(literal.suffix, Some(ResolvedType::F32));
+        let literal = parse("3f64");
+        assert_eq!(literal.value, NumberLiteralValue::Float(3.0));
+    }
+
+    #[test]
+    fn test_parse_invalid_number_literal() {
+        for text in ["1abc", "0x", "0b102", "2.5i32", "0b1f32", "10u16"] {
+            assert!(
+                matches!(
+                    parse_number_literal(text),
+                    Err(CompileErrorKind::InvalidNumberLiteral { .. })
+                ),
+                "{text}"
+            );
+        }
+        assert!(matches!(
+            parse_number_literal("18446744073709551616"),
+            Err(CompileErrorKind::NumberLiteralOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn test_check_number_literal() {
+        let width = PointerSizedIntWidth::SixtyFour;
+        let check = |text: &str, ty: ResolvedType| {
+            check_number_literal(text, parse(text).value, false, &ty, width)
+        };
+        let check_negative = |text: &str, ty: ResolvedType| {
+            check_number_literal(text, parse(text).value, true, &ty, width)
+        };
+        assert!(check("255", ResolvedType::U8).is_ok());
+        assert!(matches!(
+            check("256", ResolvedType::U8),
+            Err(CompileErrorKind::NumberLiteralOutOfRange { .. })
+        ));
+        assert!(check("2147483647", ResolvedType::I32).is_ok());
+        assert!(check("2147483648", ResolvedType::I32).is_err());
+        assert!(check("4294967296", ResolvedType::USize).is_ok());
+        assert!(check_number_literal(
+            "4294967296",
+            NumberLiteralValue::Integer(4294967296),
+            false,
+            &ResolvedType::USize,
+            PointerSizedIntWidth::ThirtyTwo
+        )
+        .is_err());
+        // 符号を反転したリテラルは下限も検査する
+        assert!(check_negative("2147483648", ResolvedType::I32).is_ok());
+        assert!(matches!(
+            check_negative("2147483649", ResolvedType::I32),
+            Err(CompileErrorKind::NumberLiteralOutOfRange { .. })
+        ));
+        assert!(check_negative("9223372036854775808", ResolvedType::I64).is_ok());
+        assert!(check_negative("9223372036854775809", ResolvedType::I64).is_err());
+        assert!(check_negative("0", ResolvedType::U8).is_ok());
+        for ty in [
+            ResolvedType::U8,
+            ResolvedType::U32,
+            ResolvedType::U64,
+            ResolvedType::USize,
+        ] {
+            assert!(matches!(
+                check_negative("1", ty),
+                Err(CompileErrorKind::NumberLiteralOutOfRange { .. })
+            ));
+        }
+        assert!(check_negative("2.5", ResolvedType::F64).is_ok());
+        assert!(check("1", ResolvedType::F64).is_ok());
+        assert!(matches!(
+            check("2.5", ResolvedType::I32),
+            Err(CompileErrorKind::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            check(
+                "1_000_000_000_000_000_000_000_000_000_000_000_000_000.0",
+                ResolvedType::F32
+            ),
+            Err(CompileErrorKind::NumberLiteralOutOfRange { .. })
+        ));
+    }
+}
@@ -0,0 +1,381 @@
+use crate::{
+    ast,
+    common::typename::*,
+    resolved_ast::{ResolvedStructType, ResolvedType},
+};
+
+// シンボル名の規則。英数字と `_` だけからなり、どの名前も元の関数と型に一意に戻せる
+//
+// <symbol> ::= "_H" "F" <path> [ "I" <type>+ "E" ]   関数 (ジェネリック関数はジェネリック引数を付ける)
+//            | "_H" "M" <type> <ident>                 `<type>` に対するインターフェース `<ident>` の実装
+// <path>   ::= <ident>+ "E"                            `::` で区切った名前
+// <ident>  ::= <length> <name>                         英数字と `_` だけからなる名前
+//            | "u" <length> <escaped>                  それ以外の文字を含む名前 (`->str` など)。
+//                                                      `_` は `__` に、それ以外の文字は `_` と2桁の16進数にする
+// <type>   ::= "i" (i32) | "x" (i64) | "j" (u32) | "y" (u64) | "m" (usize) | "h" (u8)
+//            | "f" (f32) | "d" (f64) | "b" (bool) | "v" (void)
+//            | "P" <type>                              ポインタ
+//            | "S" <ident> [ "I" <type>+ "E" ]         構造体とそのジェネリック引数
+//            | "D" <ident>                             `dyn` インターフェース
+//            | "Q" <type>* "E" <type>                  関数の値の引数と戻り値
+//            | "T" <type>* "E"                         タプル
+//            | "A" <length> "_" <type>                 固定長配列
+//            | "G" <ident>                             ジェネリック引数
+//            | "X"                                     型が決まらなかった (エラーになっている)
+//
+// 例: `Vec::len<i32>` は `_HF3Vec3lenEIiE`、`impl ->str for Point` は `_HMS5Pointu9_2d_3estr`
+const SYMBOL_PREFIX: &str = "_H";
+
+fn is_plain_ident(ident: &str) -> bool {
+    ident.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn push_ident(symbol: &mut String, ident: &str) {
+    if is_plain_ident(ident) {
+        symbol.push_str(&ident.len().to_string());
+        symbol.push_str(ident);
+        return;
+    }
+    let mut escaped = String::new();
+    for byte in ident.bytes() {
+        match byte {
+            b'_' => escaped.push_str("__"),
+            _ if byte.is_ascii_alphanumeric() => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("_{:02x}", byte)),
+        }
+    }
+    symbol.push('u');
+    symbol.push_str(&escaped.len().to_string());
+    symbol.push_str(&escaped);
+}
+
+fn push_type(symbol: &mut String, ty: &ResolvedType) {
+    match ty {
+        ResolvedType::I32 => symbol.push('i'),
+        ResolvedType::I64 => symbol.push('x'),
+        ResolvedType::U32 => symbol.push('j'),
+        ResolvedType::U64 => symbol.push('y'),
+        ResolvedType::USize => symbol.push('m'),
+        ResolvedType::U8 => symbol.push('h'),
+        ResolvedType::F32 => symbol.push('f'),
+        ResolvedType::F64 => symbol.push('d'),
+        ResolvedType::Bool => symbol.push('b'),
+        ResolvedType::Void => symbol.push('v'),
+        ResolvedType::Ptr(inner) => {
+            symbol.push('P');
+            push_type(symbol, inner);
+        }
+        ResolvedType::StructLike(ResolvedStructType {
+            non_generic_name,
+            generic_args,
+            ..
+        }) => {
+            symbol.push('S');
+            push_ident(symbol, non_generic_name);
+            if let Some(generic_args) = generic_args.as_ref().filter(|args| !args.is_empty()) {
+                push_generic_args(symbol, generic_args);
+            }
+        }
+        ResolvedType::Dyn(interface_name) => {
+            symbol.push('D');
+            push_ident(symbol, interface_name);
+        }
+        ResolvedType::Function(function_ty) => {
+            symbol.push('Q');
+            for arg in &function_ty.args {
+                push_type(symbol, arg);
+            }
+            symbol.push('E');
+            push_type(symbol, &function_ty.return_type);
+        }
+        ResolvedType::Tuple(elements) => {
+            symbol.push('T');
+            for element in elements {
+                push_type(symbol, element);
+            }
+            symbol.push('E');
+        }
+        ResolvedType::Array(element, len) => {
+            symbol.push('A');
+            symbol.push_str(&len.to_string());
+            symbol.push('_');
+            push_type(symbol, element);
+        }
+        ResolvedType::Generics(generic_ty) => {
+            symbol.push('G');
+            push_ident(symbol, &generic_ty.name);
+        }
+        ResolvedType::Unknown | ResolvedType::Var(_) => symbol.push('X'),
+    }
+}
+
+fn push_generic_args(symbol: &mut String, generic_args: &[ResolvedType]) {
+    symbol.push('I');
+    for generic_arg in generic_args {
+        push_type(symbol, generic_arg);
+    }
+    symbol.push('E');
+}
+
+fn mangle_function(name: &str, generic_args: &[ResolvedType]) -> String {
+    let mut symbol = format!("{}F", SYMBOL_PREFIX);
+    for segment in name.split("::") {
+        push_ident(&mut symbol, segment);
+    }
+    symbol.push('E');
+    if !generic_args.is_empty() {
+        push_generic_args(&mut symbol, generic_args);
+    }
+    symbol
+}
+
+// 関数を解決した後の名前。main と extern 関数は、書かれた名前のままリンクする
+pub(crate) fn function_symbol(decl: &ast::FunctionDecl, generic_args: &[ResolvedType]) -> String {
+    if decl.is_extern || decl.name == "main" {
+        decl.name.clone()
+    } else {
+        mangle_function(&decl.name, generic_args)
+    }
+}
+
+// インターフェースの実装を関数として解決する際の名前
+pub(crate) fn impl_symbol(interface_name: &str, target_ty: &ResolvedType) -> String {
+    let mut symbol = format!("{}M", SYMBOL_PREFIX);
+    push_type(&mut symbol, target_ty);
+    push_ident(&mut symbol, interface_name);
+    symbol
+}
+
+struct Demangler<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Demangler<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.input[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a str> {
+        let bytes = self.input.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        std::str::from_utf8(bytes).ok()
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        if !self.eat(b'u') {
+            let len = self.number()?;
+            return self.take(len).map(|ident| ident.to_string());
+        }
+        let len = self.number()?;
+        let escaped = self.take(len)?.as_bytes();
+        let mut bytes = Vec::new();
+        let mut i = 0;
+        while i < escaped.len() {
+            if escaped[i] != b'_' {
+                bytes.push(escaped[i]);
+                i += 1;
+            } else if escaped.get(i + 1) == Some(&b'_') {
+                bytes.push(b'_');
+                i += 2;
+            } else {
+                let hex = std::str::from_utf8(escaped.get(i + 1..i + 3)?).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+        }
+        String::from_utf8(bytes).ok()
+    }
+
+    // `E` までの型を読む
+    fn types(&mut self) -> Option<Vec<String>> {
+        let mut types = Vec::new();
+        while !self.eat(b'E') {
+            types.push(self.ty()?);
+        }
+        Some(types)
+    }
+
+    fn ty(&mut self) -> Option<String> {
+        let ty = match self.next()? {
+            b'i' => I32_TYPE_NAME.to_string(),
+            b'x' => I64_TYPE_NAME.to_string(),
+            b'j' => U32_TYPE_NAME.to_string(),
+            b'y' => U64_TYPE_NAME.to_string(),
+            b'm' => USIZE_TYPE_NAME.to_string(),
+            b'h' => U8_TYPE_NAME.to_string(),
+            b'f' => F32_TYPE_NAME.to_string(),
+            b'd' => F64_TYPE_NAME.to_string(),
+            b'b' => BOOL_TYPE_NAME.to_string(),
+            b'v' => VOID_TYPE_NAME.to_string(),
+            b'X' => UNKNOWN_TYPE_NAME.to_string(),
+            b'P' => format!("*{}", self.ty()?),
+            b'S' => {
+                let name = self.ident()?;
+                if self.eat(b'I') {
+                    format!("{}<{}>", name, self.types()?.join(", "))
+                } else {
+                    name
+                }
+            }
+            b'D' => format!("dyn {}", self.ident()?),
+            b'Q' => {
+                let args = self.types()?;
+                format!("fn({}): {}", args.join(", "), self.ty()?)
+            }
+            b'T' => format!("({})", self.types()?.join(", ")),
+            b'A' => {
+                let len = self.number()?;
+                if !self.eat(b'_') {
+                    return None;
+                }
+                format!("[{}; {}]", self.ty()?, len)
+            }
+            b'G' => self.ident()?,
+            _ => return None,
+        };
+        Some(ty)
+    }
+
+    fn symbol(&mut self) -> Option<String> {
+        let demangled = match self.next()? {
+            b'F' => {
+                let mut segments = Vec::new();
+                while !self.eat(b'E') {
+                    segments.push(self.ident()?);
+                }
+                let path = segments.join("::");
+                if self.eat(b'I') {
+                    format!("{}<{}>", path, self.types()?.join(", "))
+                } else {
+                    path
+                }
+            }
+            b'M' => {
+                let target_ty = self.ty()?;
+                format!("<{} as {}>", target_ty, self.ident()?)
+            }
+            _ => return None,
+        };
+        Some(demangled)
+    }
+}
+
+// シンボル名を `Vec::len<i32>` や `<Point as ->str>` のような読める形に戻す
+pub fn demangle(symbol: &str) -> Option<String> {
+    let mut demangler = Demangler {
+        input: symbol.strip_prefix(SYMBOL_PREFIX)?.as_bytes(),
+        pos: 0,
+    };
+    let demangled = demangler.symbol()?;
+    (demangler.pos == demangler.input.len()).then_some(demangled)
+}
+
+// プロファイラやクラッシュの出力に含まれるシンボル名を、読める形に置き換える。
+// `vtable._HM...` や `_HM...$dyn` のように前後に付いたものはそのまま残す
+pub fn demangle_text(text: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(SYMBOL_PREFIX) {
+        let (before, candidate) = rest.split_at(start);
+        result.push_str(before);
+        let len = candidate
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(candidate.len());
+        let (word, after) = candidate.split_at(len);
+        match demangle(word) {
+            Some(demangled) => result.push_str(&demangled),
+            None => result.push_str(word),
+        }
+        rest = after;
+    }
+    result.push_str(rest);
+    result
+}
+
+#[test]
+fn test_mangle_and_demangle() {
+    use crate::resolved_ast::ResolvedFunctionType;
+
+    let vec_of = |ty: ResolvedType| {
+        ResolvedType::StructLike(ResolvedStructType {
+            name: format!("Vec<{}>", ty),
+            non_generic_name: "Vec".into(),
+            fields: vec![],
+            generic_args: Some(vec![ty]),
+        })
+    };
+    let len = mangle_function("Vec::len", &[ResolvedType::I32]);
+    assert_eq!(len, "_HF3Vec3lenEIiE");
+    assert_eq!(demangle(&len).unwrap(), "Vec::len<i32>");
+
+    let map = mangle_function(
+        "Vec::map",
+        &[
+            ResolvedType::Ptr(Box::new(ResolvedType::U8)),
+            ResolvedType::Tuple(vec![ResolvedType::I64, vec_of(ResolvedType::Bool)]),
+        ],
+    );
+    assert!(map.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+    assert_eq!(demangle(&map).unwrap(), "Vec::map<*u8, (i64, Vec<bool>)>");
+
+    let callback = ResolvedType::Function(ResolvedFunctionType {
+        args: vec![ResolvedType::Array(Box::new(ResolvedType::F64), 4)],
+        return_type: Box::new(ResolvedType::Void),
+    });
+    let to_str = impl_symbol("->str", &callback);
+    assert!(to_str
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_'));
+    assert_eq!(demangle(&to_str).unwrap(), "<fn([f64; 4]): void as ->str>");
+
+    // 表示が同じになる型でも、シンボル名は区別される
+    let generic_t = ResolvedType::Generics(crate::resolved_ast::ResolvedGenericType {
+        name: "T".into(),
+        restrictions: vec![],
+    });
+    let struct_t = ResolvedType::StructLike(ResolvedStructType {
+        name: "T".into(),
+        non_generic_name: "T".into(),
+        fields: vec![],
+        generic_args: None,
+    });
+    assert_eq!(generic_t.to_string(), struct_t.to_string());
+    assert_ne!(
+        impl_symbol("->str", &generic_t),
+        impl_symbol("->str", &struct_t)
+    );
+
+    assert_eq!(
+        demangle_text(&format!("at {} (vtable.{})", len, to_str)),
+        "at Vec::len<i32> (vtable.<fn([f64; 4]): void as ->str>)"
+    );
+    assert_eq!(demangle("_HF3Vec"), None);
+    assert_eq!(demangle("malloc"), None);
+}
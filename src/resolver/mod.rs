@@ -1,7 +1,11 @@
+pub mod cache;
 mod error;
 mod expression;
 mod generics;
+mod infer;
 mod intrinsic;
+mod literal;
+pub mod mangle;
 mod statement;
 mod stdlib;
 mod ty;
@@ -10,14 +14,21 @@ use std::{cell::RefCell, collections::HashMap, ops::DerefMut, rc::Rc};
 
 use crate::{
     ast,
-    common::target::PointerSizedIntWidth,
+    common::{target::PointerSizedIntWidth, Linkage},
+    metadata::LibraryMetadata,
     resolved_ast::{self, ResolvedType},
     resolver::ty::resolve_type,
 };
 
 use self::{
-    error::{CompileError, FaitalError},
+    cache::{IncrementalCache, ResolveRequest},
+    error::{CompileError, CompileErrorKind, FaitalError},
+    expression::{coerce_integer, coerce_to_dyn},
+    generics::{check_generic_bounds, resolve_impl_target_ty},
+    infer::{DeferredInstantiation, InferenceTable},
     intrinsic::{register_intrinsic_functions, register_intrinsic_types},
+    literal::check_number_literal,
+    mangle::{function_symbol, impl_symbol},
     statement::resolve_statement,
     stdlib::register_stdlib,
 };
@@ -35,8 +46,52 @@ pub struct ResolverContext {
     pub interface_by_name: Rc<RefCell<HashMap<String, ast::Interface>>>,
     pub impls_by_name: Rc<RefCell<HashMap<String, Vec<Implementation>>>>,
     pub resolved_functions: Rc<RefCell<HashMap<String, resolved_ast::Function>>>,
+    pub inference: Rc<RefCell<InferenceTable>>,
     pub imported_names: Rc<RefCell<HashMap<String, String>>>, // Maps short name to full namespaced name
     pub ptr_sized_int_type: PointerSizedIntWidth,
+    // 値が変わり得る暗黙の整数変換をエラーにするかどうか
+    pub strict_conversions: bool,
+    // 解決中の関数で、現在の位置を囲んでいるループ (内側が末尾)
+    pub loops: Rc<RefCell<Vec<LoopScope>>>,
+    pub loop_count: Rc<RefCell<usize>>,
+    // 解決中の関数の戻り値の型。ブロックの中のreturnの値をこれに合わせる
+    pub return_type: Rc<RefCell<ResolvedType>>,
+    // 解決中の関数で、現在の位置を囲んでいる `fn` 式 (内側が末尾)
+    pub lambdas: Rc<RefCell<Vec<LambdaScope>>>,
+    // 解決中の関数のシンボル名と、その中の `fn` 式の数。`fn` 式から作る関数の名前に使う
+    pub symbol: Rc<RefCell<String>>,
+    pub lambda_count: Rc<RefCell<usize>>,
+    // 解決中の関数が書かれたファイル
+    pub file: Rc<RefCell<FileId>>,
+    // 解決中の関数のwhere句が保証している、型とそれが実装しているインターフェースの組
+    pub assumed_bounds: Rc<RefCell<Vec<(ResolvedType, String)>>>,
+    // `--extern` で読み込んだライブラリ。モジュールより先に登録する
+    pub libraries: Vec<LibraryMetadata>,
+    // パッケージとしてビルドするときの、依存パッケージのファイルとそのパッケージの名前。
+    // 依存パッケージの関数は `util::add` のようにパッケージの名前を付けて登録されている
+    pub namespace_by_file: HashMap<FileId, String>,
+    // 前回までのコンパイルで解決した関数。Noneならキャッシュを使わない
+    pub cache: Option<RefCell<IncrementalCache>>,
+}
+
+pub struct LoopScope {
+    pub id: usize,
+    pub label: Option<String>,
+    // `(while true ...)` のように、breakでしか抜けられないループかどうか
+    pub is_infinite: bool,
+    pub annotation: Option<ResolvedType>,
+    // `(break value)` の値の型。値付きのbreakがなければNone
+    pub value_ty: Option<ResolvedType>,
+    // 値を持たないbreakの位置。値付きのbreakと混在していればエラーにする
+    pub plain_breaks: Vec<Range>,
+}
+
+// 解決中の `fn` 式。これより外側のスコープの変数を参照すると、その変数を取り込む
+pub struct LambdaScope {
+    // `fn` 式の外側にある変数のスコープの数
+    pub depth: usize,
+    // 取り込んだ変数の resolved_ast での名前と型
+    pub captures: Vec<(String, ResolvedType)>,
 }
 
 impl ResolverContext {
@@ -48,8 +103,21 @@ impl ResolverContext {
             type_defs: Default::default(),
             function_by_name: Default::default(),
             resolved_functions: Default::default(),
+            inference: Default::default(),
             imported_names: Default::default(),
             ptr_sized_int_type,
+            strict_conversions: false,
+            loops: Default::default(),
+            loop_count: Default::default(),
+            return_type: Rc::new(RefCell::new(ResolvedType::Void)),
+            lambdas: Default::default(),
+            symbol: Default::default(),
+            lambda_count: Default::default(),
+            file: Default::default(),
+            assumed_bounds: Default::default(),
+            libraries: Vec::new(),
+            namespace_by_file: HashMap::new(),
+            cache: None,
             interface_by_name: Default::default(),
             impls_by_name: Default::default(),
         }
@@ -57,62 +125,213 @@ impl ResolverContext {
     pub fn is_64_bit(&self) -> bool {
         self.ptr_sized_int_type == PointerSizedIntWidth::SixtyFour
     }
+    // 解決中の関数のファイルでの位置
+    pub fn location(&self, range: Range) -> SourceLocation {
+        SourceLocation {
+            file: *self.file.borrow(),
+            range,
+        }
+    }
+    // 呼び出した名前を function_by_name のキーに直す。依存パッケージのファイルでは同じパッケージの関数を優先し、
+    // 見つからなければ書かれた名前のまま、最後に `use` で取り込んだ名前を探す
+    pub fn qualify_function_name(&self, name: &str) -> Option<String> {
+        let qualified = self.lookup_function_name(name);
+        cache::record_lookup(self, name, qualified.as_deref());
+        qualified
+    }
+    // キャッシュの検証では、記録せずに名前を引く
+    fn lookup_function_name(&self, name: &str) -> Option<String> {
+        let function_by_name = self.function_by_name.borrow();
+        if let Some(namespace) = self.namespace_by_file.get(&*self.file.borrow()) {
+            let local_name = format!("{}::{}", namespace, name);
+            if function_by_name.contains_key(&local_name) {
+                return Some(local_name);
+            }
+        }
+        if function_by_name.contains_key(name) {
+            return Some(name.to_string());
+        }
+        self.imported_names
+            .borrow()
+            .get(name)
+            .filter(|full_name| function_by_name.contains_key(*full_name))
+            .cloned()
+    }
 }
 
-pub(crate) fn mangle_fn_name(
-    name: &str,
-    arg_types: &[&ResolvedType],
-    ret: &ResolvedType,
-) -> String {
-    let mut mangled_name = name.to_owned();
-    mangled_name.push('(');
-    // for arg in arg_type_scopes {
-    //     mangled_name.push_str(&arg.to_string());
-    //     mangled_name.push_str(",");
-    // }
-    mangled_name.push_str(
-        &arg_types
-            .iter()
-            .map(|arg| arg.to_string())
-            .collect::<Vec<_>>()
-            .join(","),
-    );
-    mangled_name.push(')');
-    mangled_name.push_str("->");
-    mangled_name.push_str(&ret.to_string());
-    mangled_name
+// 関数の解決中に見つかったエラーに、その関数が書かれたファイルを記録する
+fn record_error_file(context: &ResolverContext, errors_before: usize, file: FileId) {
+    for error in &mut context.errors.borrow_mut()[errors_before..] {
+        error.set_file_if_unset(file);
+    }
 }
 
+// Cの関数との間で受け渡せるのは、数値とポインタ、それらだけを持つ構造体に限る
+fn check_ffi_safe(context: &ResolverContext, name: &str, ty: &ResolvedType, range: Range) {
+    fn is_ffi_safe(ty: &ResolvedType) -> bool {
+        match ty {
+            ResolvedType::StructLike(struct_ty) => {
+                struct_ty.fields.iter().all(|(_, ty)| is_ffi_safe(ty))
+            }
+            ResolvedType::Dyn(_)
+            | ResolvedType::Function(_)
+            | ResolvedType::Tuple(_)
+            | ResolvedType::Array(_, _)
+            | ResolvedType::Generics(_)
+            | ResolvedType::Var(_) => false,
+            _ => true,
+        }
+    }
+    if !is_ffi_safe(ty) {
+        context.errors.borrow_mut().push(CompileError::new(
+            range,
+            crate::resolver::error::CompileErrorKind::NotFfiSafe {
+                name: name.to_string(),
+                ty: ty.clone(),
+            },
+        ));
+    }
+}
+
+fn is_c_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// テストは引数を取らず `bool` を返す、本体を持つジェネリックでない関数に限る。
+// 型の別名はないので、戻り値の型は名前で判定できる
+fn is_valid_test_function(decl: &ast::FunctionDecl) -> bool {
+    !decl.is_extern
+        && decl.generic_args.is_none()
+        && decl.args.is_empty()
+        && matches!(&decl.return_type.value, UnresolvedType::TypeRef(typeref) if typeref.name == "bool" && typeref.generic_args.is_none())
+}
+
+// extern 関数はジェネリックにできず、`#[link]` は extern 関数にしか付けられない。
+// `#[export]` は本体を持つジェネリックでない関数に、Cの識別子として使える名前で付ける
+fn check_function_attributes(context: &ResolverContext, func: &ast::Function, range: Range) {
+    if func.decl.is_extern && func.decl.generic_args.is_some() {
+        context.errors.borrow_mut().push(CompileError::new(
+            range,
+            crate::resolver::error::CompileErrorKind::GenericExternFunction {
+                name: func.decl.name.clone(),
+            },
+        ));
+    }
+    for attribute in &func.decl.attributes {
+        match &attribute.value {
+            ast::Attribute::Link(_) if !func.decl.is_extern => {
+                context.errors.borrow_mut().push(CompileError::new(
+                    attribute.range,
+                    crate::resolver::error::CompileErrorKind::LinkAttributeOnNonExternFunction,
+                ));
+            }
+            ast::Attribute::Export(_)
+                if func.decl.is_extern || func.decl.generic_args.is_some() =>
+            {
+                context.errors.borrow_mut().push(CompileError::new(
+                    attribute.range,
+                    crate::resolver::error::CompileErrorKind::InvalidExportTarget {
+                        name: func.decl.name.clone(),
+                    },
+                ));
+            }
+            ast::Attribute::Test if !is_valid_test_function(&func.decl) => {
+                context.errors.borrow_mut().push(CompileError::new(
+                    attribute.range,
+                    crate::resolver::error::CompileErrorKind::InvalidTestFunction {
+                        name: func.decl.name.clone(),
+                    },
+                ));
+            }
+            ast::Attribute::Export(name) => {
+                let name = name.as_ref().unwrap_or(&func.decl.name);
+                if !is_c_identifier(name) {
+                    context.errors.borrow_mut().push(CompileError::new(
+                        attribute.range,
+                        crate::resolver::error::CompileErrorKind::InvalidExportName {
+                            name: name.clone(),
+                        },
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalVariable {
+    pub ty: ResolvedType,
+    // resolved_ast で使う名前。builderはこの名前で変数を探す
+    pub resolved_name: String,
+}
+
+// 変数のスコープ。関数本体、`do` ブロック、`for`、`if` / `when` / `while` の分岐と本体がそれぞれスコープを作る。
+// 見えている変数と同じ名前の宣言はその変数を隠す。同じスコープの中での再宣言も、それ以降の式から元の変数を隠す。
+// 隠す側の変数には一意な名前を付けるので、builderは名前だけで変数を区別できる
 #[derive(Debug, Clone)]
 pub struct VariableScopes {
-    scopes: Vec<HashMap<String, ResolvedType>>,
+    scopes: Vec<HashMap<String, LocalVariable>>,
+    shadowed_count: usize,
 }
 
 impl VariableScopes {
     fn new() -> Self {
-        Self { scopes: Vec::new() }
+        Self {
+            scopes: Vec::new(),
+            shadowed_count: 0,
+        }
     }
 
     fn push_new(&mut self) {
         self.scopes.push(HashMap::new());
     }
 
-    fn push(&mut self, scope: HashMap<String, ResolvedType>) {
+    fn push(&mut self, scope: HashMap<String, LocalVariable>) {
         self.scopes.push(scope);
     }
 
-    fn pop(&mut self) -> HashMap<String, ResolvedType> {
+    fn pop(&mut self) -> HashMap<String, LocalVariable> {
         self.scopes.pop().unwrap()
     }
 
-    fn add(&mut self, name: String, ty: ResolvedType) {
-        self.scopes.last_mut().unwrap().insert(name, ty);
+    // 変数を宣言し、resolved_ast で使う名前を返す
+    fn add(&mut self, name: String, ty: ResolvedType) -> String {
+        let resolved_name = if self.get(&name).is_some() {
+            self.shadowed_count += 1;
+            format!("{}.{}", name, self.shadowed_count)
+        } else {
+            name.clone()
+        };
+        self.scopes.last_mut().unwrap().insert(
+            name,
+            LocalVariable {
+                ty,
+                resolved_name: resolved_name.clone(),
+            },
+        );
+        resolved_name
     }
 
-    fn get(&self, name: &str) -> Option<&ResolvedType> {
-        for scope in self.scopes.iter().rev() {
-            if let Some(ty) = scope.get(name) {
-                return Some(ty);
+    // ソースコードからは参照できない一時変数の名前を返す
+    fn fresh_hidden_name(&mut self, prefix: &str) -> String {
+        self.shadowed_count += 1;
+        format!("{}.{}", prefix, self.shadowed_count)
+    }
+
+    fn get(&self, name: &str) -> Option<&LocalVariable> {
+        self.lookup(name).map(|(_, variable)| variable)
+    }
+
+    // 変数と、その変数を宣言したスコープの位置 (外側から数えた番号) を返す
+    fn lookup(&self, name: &str) -> Option<(usize, &LocalVariable)> {
+        for (depth, scope) in self.scopes.iter().enumerate().rev() {
+            if let Some(variable) = scope.get(name) {
+                return Some((depth, variable));
             }
         }
         None
@@ -149,10 +368,6 @@ impl TypeScopes {
         self.scopes.last_mut().unwrap().insert(name, ty);
     }
 
-    pub fn add_to_root(&mut self, name: String, ty: ResolvedType) {
-        self.scopes.first_mut().unwrap().insert(name, ty);
-    }
-
     fn get(&self, name: &str) -> Option<&ResolvedType> {
         for scope in self.scopes.iter().rev() {
             if let Some(ty) = scope.get(name) {
@@ -194,6 +409,34 @@ macro_rules! in_new_scope {
     }};
 }
 
+// 具体的な型が決まっていないジェネリック引数を、境界だけを持つ型として表す
+fn generic_param_type(generic_arg: &ast::GenericArgument) -> ResolvedType {
+    let restrictions = generic_arg
+        .restrictions
+        .iter()
+        .map(|r| match r {
+            ast::Restriction::Interface(name) => resolved_ast::Restriction::Interface(
+                resolved_ast::InterfaceRestriction { name: name.clone() },
+            ),
+        })
+        .collect();
+    ResolvedType::Generics(resolved_ast::ResolvedGenericType {
+        name: generic_arg.name.clone(),
+        restrictions,
+    })
+}
+
+// ジェネリックなものと標準ライブラリのものは、ライブラリと利用する側で同じ本体を作ることがある
+fn function_linkage(is_imported: bool, is_generic: bool, file: FileId) -> Linkage {
+    if is_imported {
+        Linkage::Imported
+    } else if is_generic || file == FileId::STDLIB {
+        Linkage::Instance
+    } else {
+        Linkage::Unique
+    }
+}
+
 // ジェネリック関数の場合は事前に型を登録しておく必要がある
 fn resolve_function(
     context: &ResolverContext,
@@ -205,155 +448,449 @@ fn resolve_function(
             for generic_arg in generic_args {
                 // Check if a concrete type is already registered (from call site)
                 if context.types.borrow().get(&generic_arg.name).is_none() {
-                    let restrictions = generic_arg
-                        .restrictions
-                        .iter()
-                        .map(|r| match r {
-                            ast::Restriction::Interface(name) => {
-                                resolved_ast::Restriction::Interface(
-                                    resolved_ast::InterfaceRestriction { name: name.clone() },
-                                )
-                            }
-                        })
-                        .collect();
-                    context.types.borrow_mut().add(
-                        generic_arg.name.clone(),
-                        ResolvedType::Generics(resolved_ast::ResolvedGenericType {
-                            name: generic_arg.name.clone(),
-                            restrictions,
-                        }),
-                    );
+                    context
+                        .types
+                        .borrow_mut()
+                        .add(generic_arg.name.clone(), generic_param_type(generic_arg));
                 }
             }
         }
 
-        let result_type = resolve_type(context, &current_fn.decl.return_type)?;
-        in_new_scope!(context.scopes, {
-            let mut resolved_args: Vec<resolved_ast::Argument> = Vec::new();
-            for arg in &current_fn.decl.args {
-                match arg {
-                    Argument::VarArgs => {
-                        resolved_args.push(resolved_ast::Argument::VarArgs);
-                    }
-                    Argument::SelfArg => {
-                        // SelfArg is only valid in interface implementations
-                        unreachable!("SelfArg is not allowed in regular functions")
-                    }
-                    Argument::Normal(arg_ty, arg_name) => {
-                        let arg_type = resolve_type(context, arg_ty)?;
-                        context
-                            .scopes
-                            .borrow_mut()
-                            .add(arg_name.clone(), arg_type.clone());
-                        resolved_args
-                            .push(resolved_ast::Argument::Normal(arg_type, arg_name.clone()));
-                    }
+        in_new_scope!(context.scopes, { resolve_function_in_scope(context, current_fn) })
+    })
+}
+
+fn resolve_function_in_scope(
+    context: &ResolverContext,
+    current_fn: &ast::Function,
+) -> Result<(), FaitalError> {
+    let result_type = resolve_type(context, &current_fn.decl.return_type)?;
+    let export_name = current_fn.decl.export_name();
+    // Cとの間で直接受け渡す引数と戻り値は、Cで表せる型でなければならない
+    let has_c_signature = current_fn.decl.is_extern || export_name.is_some();
+    if has_c_signature {
+        check_ffi_safe(
+            context,
+            &current_fn.decl.name,
+            &result_type,
+            current_fn.decl.return_type.range,
+        );
+    }
+    let mut resolved_args: Vec<resolved_ast::Argument> = Vec::new();
+    for arg in &current_fn.decl.args {
+        match arg {
+            Argument::VarArgs => {
+                resolved_args.push(resolved_ast::Argument::VarArgs);
+            }
+            Argument::SelfArg => {
+                // SelfArg is only valid in interface implementations
+                unreachable!("SelfArg is not allowed in regular functions")
+            }
+            Argument::Normal(arg_ty, arg_name) => {
+                let arg_type = resolve_type(context, arg_ty)?;
+                if has_c_signature {
+                    check_ffi_safe(context, &current_fn.decl.name, &arg_type, arg_ty.range);
                 }
+                let resolved_name = context
+                    .scopes
+                    .borrow_mut()
+                    .add(arg_name.clone(), arg_type.clone());
+                resolved_args.push(resolved_ast::Argument::Normal(arg_type, resolved_name));
             }
+        }
+    }
 
-        let name = if current_fn.decl.generic_args.is_some() {
-            let arg_type_scopes = resolved_args
-                .iter()
-                .map(|x| match x {
-                    resolved_ast::Argument::Normal(ty, _) => ty,
-                    _ => panic!("unexpected argument type"),
-                })
-                .collect::<Vec<_>>();
-            mangle_fn_name(&current_fn.decl.name, &arg_type_scopes, &result_type)
-        } else {
-            current_fn.decl.name.clone()
-        };
+    // ジェネリック関数は、インスタンス化したときにジェネリック引数に当てはめた型で区別する
+    let generic_args = current_fn
+        .decl
+        .generic_args
+        .iter()
+        .flatten()
+        .map(|generic_arg| {
+            context
+                .types
+                .borrow()
+                .get(&generic_arg.name)
+                .cloned()
+                .unwrap_or(ResolvedType::Unknown)
+        })
+        .collect::<Vec<_>>();
+    let name = function_symbol(&current_fn.decl, &generic_args);
+    cache::record_request(
+        context,
+        ResolveRequest::Function {
+            name: current_fn.decl.name.clone(),
+            generic_args,
+        },
+    );
 
-        if context.resolved_functions.borrow().contains_key(&name) {
-            return Ok(());
-        }
+    if context.resolved_functions.borrow().contains_key(&name) {
+        return Ok(());
+    }
+    // 関数と、本体から呼んでいる関数のシグネチャが前回と同じなら、前回の結果を使う
+    let cache_key = cache::function_key(context, &name, current_fn);
+    if cache::reuse_function(context, &name, cache_key.as_ref(), current_fn.file)? {
+        return Ok(());
+    }
+    cache::start_recording(context);
 
-        let mut resolved_statements = Vec::new();
-        for statement in &current_fn.body {
-            resolved_statements.push(resolve_statement(context, statement)?);
+    context.inference.borrow_mut().push_frame();
+    // 呼び出し先の関数をその場で解決することがあるので、呼び出し元のループと戻り値の型、`fn` 式は退避しておく
+    let outer_loops = std::mem::take(&mut *context.loops.borrow_mut());
+    let outer_lambdas = std::mem::take(&mut *context.lambdas.borrow_mut());
+    let outer_return_type = context.return_type.replace(result_type.clone());
+    let outer_file = context.file.replace(current_fn.file);
+    let outer_symbol = context.symbol.replace(name.clone());
+    let outer_lambda_count = context.lambda_count.replace(0);
+    let errors_before = context.errors.borrow().len();
+    if current_fn.decl.generic_args.is_none() {
+        check_generic_bounds(context, &current_fn.decl, &[], current_fn.decl_range)?;
+    }
+    // where句は呼び出し側か上で検査されるので、本体ではそれが満たされているものとして扱う
+    let mut assumed_bounds = Vec::new();
+    for where_clause in &current_fn.decl.where_clauses {
+        let Ok(ty) = resolve_type(context, &where_clause.ty) else {
+            continue;
+        };
+        for restriction in &where_clause.restrictions {
+            let ast::Restriction::Interface(name) = restriction;
+            assumed_bounds.push((ty.clone(), name.clone()));
         }
-        // 必ずReturnするための特別な処理
-        if !current_fn.decl.is_intrinsic {
-            if resolved_statements.is_empty() {
-                resolved_statements.push(resolved_ast::Statement::Return(resolved_ast::Return {
-                    expression: None,
-                }));
-            } else {
-                let last_stmt = resolved_statements.pop().unwrap();
-                match last_stmt {
-                    resolved_ast::Statement::Return(_) => {
-                        resolved_statements.push(last_stmt);
-                    }
-                    resolved_ast::Statement::Effect(effect) => {
-                        if result_type == ResolvedType::Void {
-                            resolved_statements.push(resolved_ast::Statement::Effect(
-                                resolved_ast::Effect {
-                                    expression: effect.expression.clone(),
-                                },
-                            ));
-                            resolved_statements.push(resolved_ast::Statement::Return(
-                                resolved_ast::Return { expression: None },
-                            ));
-                        } else {
-                            resolved_statements.push(resolved_ast::Statement::Return(
-                                resolved_ast::Return {
-                                    expression: Some(effect.expression.clone()),
-                                },
-                            ));
-                        }
+    }
+    let outer_assumed_bounds = context.assumed_bounds.replace(assumed_bounds);
+    let mut resolved_statements = Vec::new();
+    for statement in &current_fn.body {
+        resolved_statements.push(resolve_statement(context, statement)?);
+    }
+    *context.loops.borrow_mut() = outer_loops;
+    *context.lambdas.borrow_mut() = outer_lambdas;
+    context.return_type.replace(outer_return_type);
+    // 必ずReturnするための特別な処理。本体がCやライブラリにある関数には不要
+    let has_body = !current_fn.decl.is_extern && !current_fn.decl.is_imported;
+    if has_body {
+        if resolved_statements.is_empty() {
+            resolved_statements.push(resolved_ast::Statement::Return(resolved_ast::Return {
+                expression: None,
+                location: context.location(current_fn.decl_range),
+            }));
+        } else {
+            let last_stmt = resolved_statements.pop().unwrap();
+            match last_stmt {
+                resolved_ast::Statement::Return(_) => {
+                    resolved_statements.push(last_stmt);
+                }
+                resolved_ast::Statement::Effect(effect) => {
+                    if result_type == ResolvedType::Void {
+                        resolved_statements.push(resolved_ast::Statement::Effect(
+                            resolved_ast::Effect {
+                                expression: effect.expression.clone(),
+                                location: effect.location,
+                            },
+                        ));
+                        resolved_statements.push(resolved_ast::Statement::Return(
+                            resolved_ast::Return {
+                                expression: None,
+                                location: effect.location,
+                            },
+                        ));
+                    } else {
+                        let expression = coerce_to_dyn(
+                            context,
+                            &result_type,
+                            effect.expression.clone(),
+                            current_fn.body.last().unwrap().range,
+                        )?;
+                        resolved_statements.push(resolved_ast::Statement::Return(
+                            resolved_ast::Return {
+                                expression: Some(expression),
+                                location: effect.location,
+                            },
+                        ));
                     }
                 }
             }
         }
+    }
 
-        if ResolvedType::Void != result_type && !current_fn.decl.is_intrinsic {
-            let return_stmt = resolved_statements.last().unwrap();
-            let actual_return_ty = match return_stmt {
-                resolved_ast::Statement::Return(ret) => &ret.expression.as_ref().unwrap().ty,
-                _ => unreachable!(),
-            };
-            if !result_type.can_insert(actual_return_ty) {
-                context.errors.borrow_mut().push(CompileError::new(
-                    current_fn.body.last().unwrap().range,
-                    crate::resolver::error::CompileErrorKind::TypeMismatch {
-                        expected: result_type.clone(),
-                        actual: actual_return_ty.clone(),
-                    },
-                ));
-            }
+    if ResolvedType::Void != result_type && has_body {
+        let return_range = current_fn.body.last().unwrap().range;
+        let ret = match resolved_statements.last_mut().unwrap() {
+            resolved_ast::Statement::Return(ret) => ret,
+            _ => unreachable!(),
+        };
+        let expression = ret.expression.take().unwrap();
+        if !context.can_insert(&result_type, &expression.ty) {
+            context.errors.borrow_mut().push(CompileError::new(
+                return_range,
+                crate::resolver::error::CompileErrorKind::TypeMismatch {
+                    expected: context.zonk_for_error(&result_type),
+                    actual: context.zonk_for_error(&expression.ty),
+                },
+            ));
         }
+        ret.expression = Some(coerce_integer(context, &result_type, expression, return_range));
+    }
 
-        let resolved_function = resolved_ast::Function {
-            decl: resolved_ast::FunctionDecl {
-                name: name.clone(),
-                args: resolved_args,
-                return_type: result_type,
-            },
-            body: resolved_statements,
-        };
+    finalize_inference(context, &mut resolved_statements)?;
 
-        context
-            .resolved_functions
-            .borrow_mut()
-            .insert(name, resolved_function);
-        });
-    });
+    let resolved_function = resolved_ast::Function {
+        decl: resolved_ast::FunctionDecl {
+            name: name.clone(),
+            args: resolved_args,
+            return_type: result_type,
+            is_extern: current_fn.decl.is_extern,
+            export_name,
+            linkage: function_linkage(
+                current_fn.decl.is_imported,
+                current_fn.decl.generic_args.is_some(),
+                current_fn.file,
+            ),
+            location: context.location(current_fn.decl_range),
+        },
+        body: resolved_statements,
+    };
+    context.file.replace(outer_file);
+    context.symbol.replace(outer_symbol);
+    context.lambda_count.replace(outer_lambda_count);
+    context.assumed_bounds.replace(outer_assumed_bounds);
+    record_error_file(context, errors_before, current_fn.file);
+    cache::store_function(context, &name, cache_key, errors_before, &resolved_function);
+
+    context
+        .resolved_functions
+        .borrow_mut()
+        .insert(name, resolved_function);
+    Ok(())
+}
+
+// 関数本体の解決が終わった時点で型変数を確定させる。
+// リテラルの型を既定の型に決め、保留していたジェネリック関数をインスタンス化し、
+// 最後まで決まらなかった型変数を曖昧な型としてエラーにする
+fn finalize_inference(
+    context: &ResolverContext,
+    statements: &mut [resolved_ast::Statement],
+) -> Result<(), FaitalError> {
+    let frame = context.inference.borrow_mut().pop_frame();
+    let frame_start = frame.start;
+    context
+        .inference
+        .borrow_mut()
+        .apply_literal_defaults(&frame.vars);
+
+    let mut instantiated_names = Vec::new();
+    for deferred in frame.deferred {
+        instantiated_names.push(instantiate_deferred(context, deferred)?);
+    }
+
+    let ambiguous = context.inference.borrow_mut().take_ambiguous(&frame.vars);
+    for (range, description) in ambiguous {
+        context.errors.borrow_mut().push(CompileError::new(
+            range,
+            CompileErrorKind::AmbiguousType { description },
+        ));
+    }
+
+    for literal in frame.literals {
+        check_literal(
+            context,
+            &literal.text,
+            literal.value,
+            literal.negative,
+            &context.zonk(&literal.ty),
+            literal.range,
+        );
+    }
+
+    let mut inference = context.inference.borrow_mut();
+    for statement in statements {
+        inference.zonk_statement(statement, &instantiated_names);
+    }
+    // 型変数はもう本体に残っていないので、表が関数の数に比例して伸び続けないように捨てる
+    inference.release_frame(frame_start);
     Ok(())
 }
 
-// Resolve an implementation as a function
+// 数値リテラルが型に収まらなければエラーを報告する
+fn check_literal(
+    context: &ResolverContext,
+    text: &str,
+    value: resolved_ast::NumberLiteralValue,
+    negative: bool,
+    ty: &ResolvedType,
+    range: Range,
+) {
+    if let Err(kind) = check_number_literal(text, value, negative, ty, context.ptr_sized_int_type) {
+        context.errors.borrow_mut().push(CompileError::new(range, kind));
+    }
+}
+
+// 型推論によって具体的な型が決まったジェネリック関数をインスタンス化し、その関数名を返す
+fn instantiate_deferred(
+    context: &ResolverContext,
+    deferred: DeferredInstantiation,
+) -> Result<Option<String>, FaitalError> {
+    let generic_args = deferred
+        .generic_args
+        .iter()
+        .map(|ty| context.zonk(ty))
+        .collect::<Vec<_>>();
+    // 決まらなかった型変数は呼び出し元で曖昧な型として報告される
+    if generic_args
+        .iter()
+        .any(|ty| context.inference.borrow().contains_vars(ty))
+    {
+        return Ok(None);
+    }
+    check_generic_bounds(
+        context,
+        &deferred.callee.decl,
+        &generic_args,
+        deferred.call_range,
+    )?;
+
+    let callee_generic_args = deferred.callee.decl.generic_args.as_ref().unwrap();
+    in_global_scope!(context.scopes, {
+        in_global_scope!(context.types, {
+            in_new_scope!(context.types, {
+                for (generic_arg, ty) in callee_generic_args.iter().zip(&generic_args) {
+                    context
+                        .types
+                        .borrow_mut()
+                        .add(generic_arg.name.clone(), ty.clone());
+                }
+                resolve_function(context, &deferred.callee)
+            })
+        })
+    })?;
+
+    Ok(Some(function_symbol(&deferred.callee.decl, &generic_args)))
+}
+
+// Resolve an implementation as a function and return its return type.
+// `generic_args` はジェネリックな実装の場合に、実装のジェネリック引数に当てはまる型
 pub(crate) fn resolve_implementation(
     context: &ResolverContext,
     implementation: &ast::Implementation,
     fn_name: &str,
+    generic_args: &[ResolvedType],
+) -> Result<ResolvedType, FaitalError> {
+    // 実装は呼び出し元のスコープとは無関係に解決する
+    in_global_scope!(context.scopes, {
+        in_global_scope!(context.types, {
+            in_new_scope!(context.types, {
+                for (generic_arg, ty) in implementation
+                    .decl
+                    .generic_args
+                    .iter()
+                    .flatten()
+                    .zip(generic_args)
+                {
+                    context
+                        .types
+                        .borrow_mut()
+                        .add(generic_arg.name.clone(), ty.clone());
+                }
+                resolve_implementation_in_scope(context, implementation, fn_name)
+            })
+        })
+    })
+}
+
+// 実装の引数の数と型が、インターフェースの宣言と一致しているか調べる。
+// dynの呼び出しと `for` は、インターフェースの引数の型に合わせた値をそのまま実装に渡す
+fn check_implementation_args(
+    context: &ResolverContext,
+    implementation: &ast::Implementation,
+    interface: &ast::Interface,
 ) -> Result<(), FaitalError> {
-    // Check if already resolved
-    if context.resolved_functions.borrow().contains_key(fn_name) {
+    if implementation.decl.args.len() != interface.args.len() {
+        context.errors.borrow_mut().push(CompileError::new(
+            implementation.decl.target_ty.range,
+            CompileErrorKind::MismatchFunctionArgCount {
+                name: interface.name.clone(),
+                expected: interface.args.len(),
+                actual: implementation.decl.args.len(),
+            },
+        ));
         return Ok(());
     }
+    for (impl_arg, interface_arg) in implementation.decl.args.iter().zip(&interface.args) {
+        let (Argument::Normal(impl_ty, _), Argument::Normal(interface_ty, _)) =
+            (impl_arg, interface_arg)
+        else {
+            continue;
+        };
+        // インターフェースのジェネリック引数を含む型は、実装ごとに変わるので比べない
+        if mentions_generic_arg(interface_ty, &interface.generic_args) {
+            continue;
+        }
+        let expected = resolve_type(context, interface_ty)?;
+        let actual = resolve_type(context, impl_ty)?;
+        if actual != expected {
+            context.errors.borrow_mut().push(CompileError::new(
+                impl_ty.range,
+                CompileErrorKind::TypeMismatch { expected, actual },
+            ));
+        }
+    }
+    Ok(())
+}
 
+fn mentions_generic_arg(ty: &UnresolvedType, generic_args: &[Located<GenericArgument>]) -> bool {
+    match ty {
+        UnresolvedType::TypeRef(type_ref) => {
+            generic_args
+                .iter()
+                .any(|generic_arg| generic_arg.name == type_ref.name)
+                || type_ref
+                    .generic_args
+                    .iter()
+                    .flatten()
+                    .any(|arg| mentions_generic_arg(arg, generic_args))
+        }
+        UnresolvedType::Ptr(inner) | UnresolvedType::Array(inner, _) => {
+            mentions_generic_arg(inner, generic_args)
+        }
+        UnresolvedType::Tuple(elements) => elements
+            .iter()
+            .any(|element| mentions_generic_arg(element, generic_args)),
+        UnresolvedType::Function(function_ty) => {
+            function_ty
+                .args
+                .iter()
+                .any(|arg| mentions_generic_arg(arg, generic_args))
+                || mentions_generic_arg(&function_ty.return_type, generic_args)
+        }
+        UnresolvedType::Dyn(_) | UnresolvedType::Infer => false,
+    }
+}
+
+fn resolve_implementation_in_scope(
+    context: &ResolverContext,
+    implementation: &ast::Implementation,
+    fn_name: &str,
+) -> Result<ResolvedType, FaitalError> {
     // Resolve the target type (e.g., i32)
     let target_ty = resolve_type(context, &implementation.decl.target_ty)?;
+    cache::record_request(
+        context,
+        ResolveRequest::Implementation {
+            interface: implementation.decl.name.clone(),
+            target_ty: target_ty.clone(),
+        },
+    );
+
+    // Check if already resolved
+    if let Some(resolved_function) = context.resolved_functions.borrow().get(fn_name) {
+        return Ok(resolved_function.decl.return_type.clone());
+    }
+    let cache_key = cache::function_key(context, fn_name, implementation);
+    if cache::reuse_function(context, fn_name, cache_key.as_ref(), implementation.file)? {
+        return Ok(context.resolved_functions.borrow()[fn_name]
+            .decl
+            .return_type
+            .clone());
+    }
 
     // Get the interface to get return type
     let interface_name = &implementation.decl.name;
@@ -366,7 +903,28 @@ pub(crate) fn resolve_implementation(
             FaitalError(format!("Interface {} not found", interface_name))
         })?;
 
-    let return_type = resolve_type(context, &interface.return_type)?;
+    // 実装に戻り値の型が書かれていればそれを使う。
+    // ジェネリックでないインターフェースでは、インターフェースの戻り値の型と一致している必要がある
+    let return_type = match &implementation.decl.return_type {
+        Some(impl_return_type) => {
+            let return_type = resolve_type(context, impl_return_type)?;
+            if interface.generic_args.is_empty() {
+                let interface_return_type = resolve_type(context, &interface.return_type)?;
+                if return_type != interface_return_type {
+                    context.errors.borrow_mut().push(CompileError::new(
+                        impl_return_type.range,
+                        CompileErrorKind::TypeMismatch {
+                            expected: interface_return_type,
+                            actual: return_type.clone(),
+                        },
+                    ));
+                }
+            }
+            return_type
+        }
+        None => resolve_type(context, &interface.return_type)?,
+    };
+    check_implementation_args(context, implementation, &interface)?;
 
     in_new_scope!(context.scopes, {
         // Register 'self' as the target type in scope
@@ -376,18 +934,45 @@ pub(crate) fn resolve_implementation(
             .add("self".to_string(), target_ty.clone());
 
         // Build resolved arguments (self)
-        let resolved_args = vec![resolved_ast::Argument::Normal(target_ty, "self".to_string())];
+        let mut resolved_args = vec![resolved_ast::Argument::Normal(target_ty, "self".to_string())];
+        // self以外の引数
+        for arg in &implementation.decl.args {
+            if let Argument::Normal(arg_ty, arg_name) = arg {
+                let arg_type = resolve_type(context, arg_ty)?;
+                let resolved_name = context
+                    .scopes
+                    .borrow_mut()
+                    .add(arg_name.clone(), arg_type.clone());
+                resolved_args.push(resolved_ast::Argument::Normal(arg_type, resolved_name));
+            }
+        }
 
         // Resolve body statements
+        cache::start_recording(context);
+        context.inference.borrow_mut().push_frame();
+        let outer_loops = std::mem::take(&mut *context.loops.borrow_mut());
+        let outer_lambdas = std::mem::take(&mut *context.lambdas.borrow_mut());
+        let outer_return_type = context.return_type.replace(return_type.clone());
+        let outer_file = context.file.replace(implementation.file);
+        let outer_symbol = context.symbol.replace(fn_name.to_string());
+        let outer_lambda_count = context.lambda_count.replace(0);
+        let errors_before = context.errors.borrow().len();
+        let outer_assumed_bounds = std::mem::take(&mut *context.assumed_bounds.borrow_mut());
         let mut resolved_statements = Vec::new();
         for statement in &implementation.body {
             resolved_statements.push(resolve_statement(context, statement)?);
         }
+        *context.loops.borrow_mut() = outer_loops;
+        *context.lambdas.borrow_mut() = outer_lambdas;
+        context.return_type.replace(outer_return_type);
 
         // Add return statement if needed
-        if resolved_statements.is_empty() {
+        if implementation.decl.is_imported {
+            // 本体はライブラリにある
+        } else if resolved_statements.is_empty() {
             resolved_statements.push(resolved_ast::Statement::Return(resolved_ast::Return {
                 expression: None,
+                location: context.location(implementation.decl.target_ty.range),
             }));
         } else {
             let last_stmt = resolved_statements.pop().unwrap();
@@ -397,29 +982,55 @@ pub(crate) fn resolve_implementation(
                 }
                 resolved_ast::Statement::Effect(effect) => {
                     if return_type == ResolvedType::Void {
+                        let location = effect.location;
                         resolved_statements.push(resolved_ast::Statement::Effect(effect));
                         resolved_statements.push(resolved_ast::Statement::Return(
-                            resolved_ast::Return { expression: None },
+                            resolved_ast::Return {
+                                expression: None,
+                                location,
+                            },
                         ));
                     } else {
                         resolved_statements.push(resolved_ast::Statement::Return(
                             resolved_ast::Return {
                                 expression: Some(effect.expression.clone()),
+                                location: effect.location,
                             },
                         ));
                     }
                 }
             }
         }
+        finalize_inference(context, &mut resolved_statements)?;
 
         let resolved_function = resolved_ast::Function {
             decl: resolved_ast::FunctionDecl {
                 name: fn_name.to_string(),
                 args: resolved_args,
-                return_type,
+                return_type: return_type.clone(),
+                is_extern: false,
+                export_name: None,
+                linkage: function_linkage(
+                    implementation.decl.is_imported,
+                    implementation.decl.generic_args.is_some(),
+                    implementation.file,
+                ),
+                location: context.location(implementation.decl.target_ty.range),
             },
             body: resolved_statements,
         };
+        context.file.replace(outer_file);
+        context.symbol.replace(outer_symbol);
+        context.lambda_count.replace(outer_lambda_count);
+        context.assumed_bounds.replace(outer_assumed_bounds);
+        record_error_file(context, errors_before, implementation.file);
+        cache::store_function(
+            context,
+            fn_name,
+            cache_key,
+            errors_before,
+            &resolved_function,
+        );
 
         context
             .resolved_functions
@@ -427,42 +1038,121 @@ pub(crate) fn resolve_implementation(
             .insert(fn_name.to_string(), resolved_function);
     });
 
-    Ok(())
+    Ok(return_type)
 }
 
 /// Process use statements to populate imported names
-fn process_use_statement(
-    context: &ResolverContext,
-    use_stmt: &ast::UseStatement,
-) {
-    if !use_stmt.wildcard {
-        // Only wildcard imports are supported for now
-        return;
-    }
-
-    let namespace = use_stmt.path.to_string();
+fn process_use_statement(context: &ResolverContext, use_stmt: &ast::UseStatement, range: Range) {
+    let path = use_stmt.path.to_string();
     let function_by_name = context.function_by_name.borrow();
 
-    // Find all functions that start with this namespace
-    for (full_name, _) in function_by_name.iter() {
-        if let Some(suffix) = full_name.strip_prefix(&format!("{}::", namespace)) {
-            // Only import if the suffix doesn't contain another `::`
-            // This prevents importing nested namespaces
-            if !suffix.contains("::") {
-                context.imported_names.borrow_mut().insert(
-                    suffix.to_string(),
-                    full_name.clone(),
-                );
+    // 依存パッケージのファイルでは、同じパッケージの中を先に探す
+    let local_path = context
+        .namespace_by_file
+        .get(&use_stmt.file)
+        .map(|namespace| format!("{}::{}", namespace, path));
+    let mut imports = Vec::new();
+    for namespace in local_path.iter().chain(std::iter::once(&path)) {
+        if use_stmt.wildcard {
+            // Find all functions that start with this namespace
+            for full_name in function_by_name.keys() {
+                if let Some(suffix) = full_name.strip_prefix(&format!("{}::", namespace)) {
+                    // Only import if the suffix doesn't contain another `::`
+                    // This prevents importing nested namespaces
+                    if !suffix.contains("::") {
+                        imports.push((suffix.to_string(), full_name.clone()));
+                    }
+                }
             }
+        } else if function_by_name.contains_key(namespace) {
+            let name = use_stmt.path.segments.last().unwrap();
+            imports.push((name.clone(), namespace.clone()));
+        }
+        if !imports.is_empty() {
+            break;
         }
     }
+
+    if imports.is_empty() {
+        let mut error = CompileError::new(
+            range,
+            crate::resolver::error::CompileErrorKind::UnresolvedImport {
+                path: if use_stmt.wildcard {
+                    format!("{}::*", path)
+                } else {
+                    path
+                },
+            },
+        );
+        error.set_file_if_unset(use_stmt.file);
+        context.errors.borrow_mut().push(error);
+    }
+    context.imported_names.borrow_mut().extend(imports);
 }
 
-pub(crate) fn resolve_module(
-    context: &ResolverContext,
-    module: &crate::ast::Module,
-    is_build_only: bool,
-) -> Result<crate::resolved_ast::ResolvedModule, FaitalError> {
+// ライブラリのメタデータに含まれる宣言を、モジュールのものと同じように名前で引けるようにする。
+// 本体を持たない宣言は、呼び出されるとライブラリの関数の宣言として解決する
+fn register_library(context: &ResolverContext, library: &LibraryMetadata, file: FileId) {
+    let mut function_by_name = context.function_by_name.borrow_mut();
+    for decl in &library.function_decls {
+        let function = ast::Function {
+            decl: ast::FunctionDecl {
+                is_imported: true,
+                ..decl.clone()
+            },
+            decl_range: Range::default(),
+            body: Vec::new(),
+            file,
+        };
+        function_by_name.insert(decl.name.clone(), function);
+    }
+    for function in &library.generic_functions {
+        let function = ast::Function {
+            file,
+            ..function.clone()
+        };
+        function_by_name.insert(function.decl.name.clone(), function);
+    }
+
+    let mut type_defs = context.type_defs.borrow_mut();
+    for typedef in &library.type_defs {
+        type_defs.insert(typedef.name.clone(), typedef.clone());
+    }
+    let mut interface_by_name = context.interface_by_name.borrow_mut();
+    for interface in &library.interfaces {
+        interface_by_name.insert(interface.name.clone(), interface.clone());
+    }
+    let mut impls_by_name = context.impls_by_name.borrow_mut();
+    let implementations = library
+        .implementation_decls
+        .iter()
+        .map(|decl| ast::Implementation {
+            decl: ast::ImplementationDecl {
+                is_imported: true,
+                ..decl.clone()
+            },
+            body: Vec::new(),
+            file,
+        })
+        .chain(
+            library
+                .generic_implementations
+                .iter()
+                .map(|implementation| ast::Implementation {
+                    file,
+                    ..implementation.clone()
+                }),
+        );
+    for implementation in implementations {
+        impls_by_name
+            .entry(implementation.decl.name.clone())
+            .or_default()
+            .push(implementation);
+    }
+}
+
+// 組み込み関数と標準ライブラリ、モジュールの関数や型、インターフェース、実装を名前で引けるようにする
+fn register_module(context: &ResolverContext, module: &crate::ast::Module) {
     context.scopes.borrow_mut().push_new();
     context.types.borrow_mut().push_new();
     // 組み込み関数の型を登録する
@@ -472,13 +1162,15 @@ pub(crate) fn resolve_module(
     }
     register_intrinsic_types(context.types.borrow_mut().deref_mut());
 
-    // 標準ライブラリを登録する
+    // 標準ライブラリを登録する。パースした結果はキャッシュにあればそれを使う
     {
+        let stdlib = cache::load_stdlib(context);
         let mut type_defs = context.type_defs.borrow_mut();
         let mut interface_by_name = context.interface_by_name.borrow_mut();
         let mut impls_by_name = context.impls_by_name.borrow_mut();
         let mut function_by_name = context.function_by_name.borrow_mut();
         register_stdlib(
+            stdlib,
             &mut type_defs,
             &mut interface_by_name,
             &mut impls_by_name,
@@ -486,10 +1178,17 @@ pub(crate) fn resolve_module(
         );
     }
 
+    for (index, library) in context.libraries.iter().enumerate() {
+        register_library(context, library, FileId::library(index));
+    }
+
     for toplevel in &module.toplevels {
         match &toplevel.value {
             // 関数を名前で引けるようにしておく
             TopLevel::Function(func) => {
+                let errors_before = context.errors.borrow().len();
+                check_function_attributes(context, func, toplevel.range);
+                record_error_file(context, errors_before, func.file);
                 context
                     .function_by_name
                     .borrow_mut()
@@ -516,7 +1215,7 @@ pub(crate) fn resolve_module(
     // Process use statements after all functions are registered
     for toplevel in &module.toplevels {
         if let TopLevel::Use(use_stmt) = &toplevel.value {
-            process_use_statement(context, use_stmt);
+            process_use_statement(context, use_stmt, toplevel.range);
         }
     }
 
@@ -529,11 +1228,12 @@ pub(crate) fn resolve_module(
                     .entry(implementation.decl.name.clone())
                     .or_insert_with(Vec::new);
 
-                if resolve_type(context, &implementation.decl.target_ty).is_ok() {
+                if resolve_impl_target_ty(context, implementation).is_ok() {
                     impl_by_target.push(implementation.clone());
                     continue;
                 }
 
+                let errors_before = context.errors.borrow().len();
                 match &implementation.decl.target_ty.value {
                     UnresolvedType::TypeRef(typeref) => {
                         if context.interface_by_name.borrow().contains_key(&typeref.name)
@@ -559,17 +1259,50 @@ pub(crate) fn resolve_module(
                             crate::resolver::error::CompileErrorKind::ImplForPointerIsInvalid,
                         ));
                     }
+                    UnresolvedType::Dyn(_) => {
+                        context.errors.borrow_mut().push(CompileError::new(
+                            implementation.decl.target_ty.range,
+                            crate::resolver::error::CompileErrorKind::ImplForDynIsInvalid,
+                        ));
+                    }
                     UnresolvedType::Infer => {
                         context.errors.borrow_mut().push(CompileError::new(
                             implementation.decl.target_ty.range,
                             crate::resolver::error::CompileErrorKind::ImplForInferenceIsInvalid,
                         ));
                     }
+                    UnresolvedType::Function(_) => {
+                        context.errors.borrow_mut().push(CompileError::new(
+                            implementation.decl.target_ty.range,
+                            crate::resolver::error::CompileErrorKind::ImplForFunctionIsInvalid,
+                        ));
+                    }
+                    UnresolvedType::Tuple(_) => {
+                        context.errors.borrow_mut().push(CompileError::new(
+                            implementation.decl.target_ty.range,
+                            crate::resolver::error::CompileErrorKind::ImplForTupleIsInvalid,
+                        ));
+                    }
+                    UnresolvedType::Array(_, _) => {
+                        context.errors.borrow_mut().push(CompileError::new(
+                            implementation.decl.target_ty.range,
+                            crate::resolver::error::CompileErrorKind::ImplForArrayIsInvalid,
+                        ));
+                    }
                 }
+                record_error_file(context, errors_before, implementation.file);
             }
             _ => {}
         }
     }
+}
+
+pub(crate) fn resolve_module(
+    context: &ResolverContext,
+    module: &crate::ast::Module,
+    is_build_only: bool,
+) -> Result<crate::resolved_ast::ResolvedModule, FaitalError> {
+    register_module(context, module);
 
     let function_by_name = context.function_by_name.borrow();
     let main_fn = function_by_name
@@ -580,6 +1313,17 @@ pub(crate) fn resolve_module(
 
     // main関数から辿れる関数を全て解決する
     resolve_function(&context, main_fn)?;
+    // Cに公開する関数は、mainから呼ばれなくても解決する
+    for toplevel in &module.toplevels {
+        if let TopLevel::Function(function) = &toplevel.value {
+            if function.decl.export_name().is_some()
+                && !function.decl.is_extern
+                && function.decl.generic_args.is_none()
+            {
+                resolve_function(context, function)?;
+            }
+        }
+    }
 
     for resolved_function in context.resolved_functions.borrow().values() {
         resolved_toplevels
@@ -621,3 +1365,643 @@ pub(crate) fn resolve_module(
         toplevels: resolved_toplevels.into_inner(),
     })
 }
+
+// 実装の関数名。実装するインターフェースが見つからなければエラーを報告してNoneを返す
+fn implementation_symbol(
+    context: &ResolverContext,
+    implementation: &ast::Implementation,
+    target_ty: &ResolvedType,
+) -> Option<String> {
+    if !context
+        .interface_by_name
+        .borrow()
+        .contains_key(&implementation.decl.name)
+    {
+        context.errors.borrow_mut().push(CompileError::new(
+            implementation.decl.target_ty.range,
+            CompileErrorKind::InterfaceNotFound {
+                name: implementation.decl.name.clone(),
+            },
+        ));
+        return None;
+    }
+    Some(impl_symbol(&implementation.decl.name, target_ty))
+}
+
+// mainを持たないライブラリとして解決する。ジェネリックでない関数と実装を起点にする。
+// 非公開の関数も、利用する側でインスタンス化したジェネリックな関数から呼ばれ得るので生成する。
+// ジェネリックなものは利用する側でインスタンス化する
+pub(crate) fn resolve_library(
+    context: &ResolverContext,
+    module: &crate::ast::Module,
+) -> Result<crate::resolved_ast::ResolvedModule, FaitalError> {
+    register_module(context, module);
+
+    for toplevel in &module.toplevels {
+        match &toplevel.value {
+            TopLevel::Function(function)
+                if !function.decl.is_extern && function.decl.generic_args.is_none() =>
+            {
+                resolve_function(context, function)?;
+            }
+            TopLevel::Implemantation(implementation)
+                if implementation.decl.generic_args.is_none() =>
+            {
+                // 対象の型が解決できない実装については、登録時にエラーが報告されている
+                let Ok(target_ty) = resolve_type(context, &implementation.decl.target_ty) else {
+                    continue;
+                };
+                let Some(fn_name) = implementation_symbol(context, implementation, &target_ty) else {
+                    continue;
+                };
+                resolve_implementation(context, implementation, &fn_name, &[])?;
+            }
+            _ => {}
+        }
+    }
+
+    let toplevels = context
+        .resolved_functions
+        .borrow()
+        .values()
+        .map(|resolved_function| resolved_ast::TopLevel::Function(resolved_function.clone()))
+        .collect();
+    Ok(resolved_ast::ResolvedModule { toplevels })
+}
+
+// `hirou test` のために、`#[test]` を付けた関数を起点に解決する。mainはなくてもよい。
+// 依存パッケージのテストは実行しないので、起点にしない
+pub(crate) fn resolve_tests(
+    context: &ResolverContext,
+    module: &crate::ast::Module,
+) -> Result<crate::resolved_ast::ResolvedModule, FaitalError> {
+    register_module(context, module);
+
+    for toplevel in &module.toplevels {
+        let TopLevel::Function(function) = &toplevel.value else {
+            continue;
+        };
+        // 付けられない関数の `#[test]` については、登録時にエラーが報告されている
+        if function.decl.is_test()
+            && is_valid_test_function(&function.decl)
+            && !context.namespace_by_file.contains_key(&function.file)
+        {
+            resolve_function(context, function)?;
+        }
+    }
+
+    let toplevels = context
+        .resolved_functions
+        .borrow()
+        .values()
+        .map(|resolved_function| resolved_ast::TopLevel::Function(resolved_function.clone()))
+        .collect();
+    Ok(resolved_ast::ResolvedModule { toplevels })
+}
+
+// mainから辿れるかどうかに関わらず、モジュールの全ての関数と実装の本体を型検査する。
+// ジェネリック引数は具体的な型に置き換えず、境界だけを持つ型として一度だけ検査する
+pub(crate) fn check_module(
+    context: &ResolverContext,
+    module: &crate::ast::Module,
+) -> Result<(), FaitalError> {
+    register_module(context, module);
+
+    for toplevel in &module.toplevels {
+        match &toplevel.value {
+            TopLevel::Function(function) => resolve_function(context, function)?,
+            TopLevel::Implemantation(implementation) => {
+                let generic_args = implementation
+                    .decl
+                    .generic_args
+                    .iter()
+                    .flatten()
+                    .map(|generic_arg| generic_param_type(generic_arg))
+                    .collect::<Vec<_>>();
+                // 対象の型が解決できない実装については、登録時にエラーが報告されている
+                let Ok(target_ty) = in_new_scope!(context.types, {
+                    for (generic_arg, ty) in implementation
+                        .decl
+                        .generic_args
+                        .iter()
+                        .flatten()
+                        .zip(&generic_args)
+                    {
+                        context
+                            .types
+                            .borrow_mut()
+                            .add(generic_arg.name.clone(), ty.clone());
+                    }
+                    resolve_type(context, &implementation.decl.target_ty)
+                }) else {
+                    continue;
+                };
+                let Some(fn_name) = implementation_symbol(context, implementation, &target_ty) else {
+                    continue;
+                };
+                resolve_implementation(context, implementation, &fn_name, &generic_args)?;
+            }
+            TopLevel::TypeDef(_) | TopLevel::Interface(_) | TopLevel::Use(_) => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ソースを `resolve` で解決し、その結果と報告されたエラーを返す
+    fn resolve_source_with<T>(
+        source: &str,
+        resolve: impl FnOnce(&ResolverContext, &ast::Module) -> Result<T, FaitalError>,
+    ) -> (T, Vec<CompileError>) {
+        use crate::parser::parse_module;
+        use nom::Finish;
+
+        let (_, module) = parse_module(source.into()).finish().unwrap();
+        let context = ResolverContext::new(PointerSizedIntWidth::SixtyFour);
+        let resolved = resolve(&context, &module).unwrap();
+        let errors = context.errors.take();
+        (resolved, errors)
+    }
+
+    // mainから使われる関数を解決し、報告されたエラーを返す
+    fn resolve_source(source: &str) -> Vec<CompileError> {
+        resolve_source_with(source, |context, module| {
+            resolve_module(context, module, true)
+        })
+        .1
+    }
+
+    #[test]
+    fn test_variable_scopes_shadowing() {
+        let mut scopes = VariableScopes::new();
+        scopes.push_new();
+        assert_eq!(scopes.add("x".into(), ResolvedType::I32), "x");
+        // 同じスコープでの再宣言も、内側のスコープでの宣言も元の変数を隠す
+        let redeclared = scopes.add("x".into(), ResolvedType::I64);
+        assert_ne!(redeclared, "x");
+        scopes.push_new();
+        let inner = scopes.add("x".into(), ResolvedType::Bool);
+        assert_ne!(inner, redeclared);
+        assert_eq!(scopes.get("x").unwrap().resolved_name, inner);
+        scopes.pop();
+        assert_eq!(scopes.get("x").unwrap().resolved_name, redeclared);
+        assert_eq!(scopes.get("x").unwrap().ty, ResolvedType::I64);
+        // 隠していない変数は名前を変えない
+        assert_eq!(scopes.add("y".into(), ResolvedType::I32), "y");
+    }
+
+    #[test]
+    fn test_locations_keep_source_file() {
+        use crate::parser::parse_module;
+        use nom::Finish;
+
+        let source = "fn main(): i32 {\n  (:= v [1, 2, 3])\n  (Vec::len v)\n}\n";
+        let (_, module) = parse_module(source.into()).finish().unwrap();
+        let context = ResolverContext::new(PointerSizedIntWidth::SixtyFour);
+        let resolved = resolve_module(&context, &module, true).unwrap();
+        assert!(context.errors.borrow().is_empty());
+
+        let function = |name: &str| {
+            resolved
+                .toplevels
+                .iter()
+                .map(|resolved_ast::TopLevel::Function(function)| function)
+                .find(|function| {
+                    mangle::demangle(&function.decl.name)
+                        .unwrap_or_else(|| function.decl.name.clone())
+                        == name
+                })
+                .unwrap()
+        };
+        let main = function("main");
+        assert_eq!(main.decl.location.file, FileId::MAIN);
+        // 2行目の `:=` と、3行目の戻り値になった呼び出し
+        let resolved_ast::Statement::Effect(decl) = &main.body[0] else {
+            panic!("expected an effect");
+        };
+        assert_eq!(decl.expression.location.range.from.line, 2);
+        let resolved_ast::Statement::Return(ret) = main.body.last().unwrap() else {
+            panic!("expected a return");
+        };
+        let call = ret.expression.as_ref().unwrap();
+        assert_eq!(call.location.file, FileId::MAIN);
+        assert_eq!(call.location.range.from.line, 3);
+
+        // インスタンス化した標準ライブラリの関数は、標準ライブラリの位置を持つ
+        let len = function("Vec::len<i32>");
+        assert_eq!(len.decl.location.file, FileId::STDLIB);
+        let resolved_ast::Statement::Return(ret) = &len.body[0] else {
+            panic!("expected a return");
+        };
+        let value = ret.expression.as_ref().unwrap();
+        assert_eq!(value.location.file, FileId::STDLIB);
+    }
+
+    #[test]
+    fn test_integer_literal_in_float_arithmetic() {
+        let source = "fn main(): bool {\n  (:= x: f64 1.5)\n  (:= y (* 2 x))\n  (< y 4)\n}\n";
+        let (resolved, errors) = resolve_source_with(source, |context, module| {
+            resolve_module(context, module, true)
+        });
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let main = resolved
+            .toplevels
+            .iter()
+            .map(|resolved_ast::TopLevel::Function(function)| function)
+            .find(|function| function.decl.name == "main")
+            .unwrap();
+        // 整数リテラルは、もう一方の辺の浮動小数点数型になる
+        let resolved_ast::Statement::Effect(decl) = &main.body[1] else {
+            panic!("expected an effect");
+        };
+        let resolved_ast::ExpressionKind::VariableDecls(decls) = &decl.expression.kind else {
+            panic!("expected a variable declaration");
+        };
+        let resolved_ast::ExpressionKind::Binary(mul) = &decls.decls[0].value.kind else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(mul.lhs.ty, ResolvedType::F64);
+        assert_eq!(decls.decls[0].ty, ResolvedType::F64);
+        let resolved_ast::Statement::Return(ret) = &main.body[2] else {
+            panic!("expected a return");
+        };
+        let resolved_ast::ExpressionKind::Binary(lt) = &ret.expression.as_ref().unwrap().kind
+        else {
+            panic!("expected a comparison");
+        };
+        assert_eq!(lt.rhs.ty, ResolvedType::F64);
+
+        // ビット演算では浮動小数点数型に合わせない
+        let errors = resolve_source("fn main(): f64 {\n  (:= x: f64 1.5)\n  (& 2 x)\n}\n");
+        assert!(
+            errors
+                .iter()
+                .any(|error| matches!(error.kind(), CompileErrorKind::TypeMismatch { .. })),
+            "{:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn test_where_clauses_on_non_generic_functions() {
+        let source = r#"
+interface ->bool(self) : bool
+
+impl ->bool for i32 (self) {
+  (> self 0)
+}
+
+fn positive(x: i32): bool where i32: ->bool {
+  (->bool x)
+}
+
+fn flag(b: bool): bool where bool: ->bool {
+  (->bool b)
+}
+
+fn main(): bool {
+  (positive 1)
+}
+"#;
+        let errors = resolve_source(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        // ジェネリックでない関数のwhere句も、満たされていなければ報告する
+        let errors = resolve_source(&source.replace("(positive 1)", "(flag true)"));
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(
+            matches!(errors[0].kind(), CompileErrorKind::UnsatisfiedBound { .. }),
+            "{:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn test_check_module_without_main() {
+        let source = r#"
+interface ->bool(self) : bool
+
+impl ->bool for i32 (self) {
+  (> self 0)
+}
+
+fn truthy<T: ->bool>(v: T): bool {
+  (->bool v)
+}
+
+fn any_items<T>(v: Vec<T>): bool where Vec<T>: ->bool {
+  (->bool v)
+}
+
+fn unused(): i32 {
+  true
+}
+
+fn unbounded<T>(v: T): bool {
+  (->bool v)
+}
+"#;
+        let ((), errors) = resolve_source_with(source, check_module);
+
+        // 呼ばれていない関数の誤りと、境界のないジェネリック引数に対する呼び出しだけが報告される
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(
+            matches!(errors[0].kind(), CompileErrorKind::TypeMismatch { .. }),
+            "{:?}",
+            errors
+        );
+        assert!(
+            matches!(
+                errors[1].kind(),
+                CompileErrorKind::InterfaceNotImplemented { .. }
+            ),
+            "{:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn test_dyn_call_passes_arguments() {
+        let source = r#"
+interface scale(self, factor: i64, offset: i32) : i64
+
+impl scale for i32 (self, factor: i64, offset: i32) {
+  (+ (* (as i64 self) factor) (as i64 offset))
+}
+
+fn apply(v: dyn scale): i64 {
+  (scale v 3 1)
+}
+
+fn main(): i64 {
+  (apply 2)
+}
+"#;
+        let (resolved, errors) = resolve_source_with(source, |context, module| {
+            resolve_module(context, module, true)
+        });
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let apply = resolved
+            .toplevels
+            .iter()
+            .map(|resolved_ast::TopLevel::Function(function)| function)
+            .find(|function| mangle::demangle(&function.decl.name).as_deref() == Some("apply"))
+            .unwrap();
+        let resolved_ast::Statement::Return(ret) = &apply.body[0] else {
+            panic!("expected a return");
+        };
+        let resolved_ast::ExpressionKind::DynCall(dyn_call) =
+            &ret.expression.as_ref().unwrap().kind
+        else {
+            panic!("expected a dyn call");
+        };
+        // self以外の引数は、インターフェースの引数の型に合わせて渡される
+        let arg_types = dyn_call.args.iter().map(|arg| &arg.ty).collect::<Vec<_>>();
+        assert_eq!(arg_types, vec![&ResolvedType::I64, &ResolvedType::I32]);
+
+        // 引数の数が合わない呼び出しは報告する
+        let errors = resolve_source(&source.replace("(scale v 3 1)", "(scale v 3)"));
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(
+            matches!(
+                errors[0].kind(),
+                CompileErrorKind::MismatchFunctionArgCount { .. }
+            ),
+            "{:?}",
+            errors
+        );
+
+        // インターフェースと引数の数や型が違う実装は、実装の位置で報告する
+        let errors = resolve_source(&source.replace(
+            "(self, factor: i64, offset: i32) {",
+            "(self, factor: i64) {",
+        ));
+        assert!(
+            matches!(
+                errors[0].kind(),
+                CompileErrorKind::MismatchFunctionArgCount { .. }
+            ),
+            "{:?}",
+            errors
+        );
+        let errors = resolve_source(&source.replace(
+            "(self, factor: i64, offset: i32) {",
+            "(self, factor: i64, offset: i64) {",
+        ));
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        let CompileErrorKind::TypeMismatch { expected, actual } = errors[0].kind() else {
+            panic!("{:?}", errors);
+        };
+        assert_eq!((expected, actual), (&ResolvedType::I32, &ResolvedType::I64));
+    }
+
+    #[test]
+    fn test_for_checks_iterator_implementations() {
+        let source = r#"
+impl iter-len for i32 (self): i32 {
+  self
+}
+
+impl iter-get for i32 (self, index: i32): i32 {
+  (* index 2)
+}
+
+fn main(): i32 {
+  (:= total 0)
+  (for x 3
+    (:=< total (+ total x)))
+  total
+}
+"#;
+        let errors = resolve_source(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        // `for` はi32の添字を iter-get に渡し、iter-len の戻り値と比べる
+        for (from, to) in [
+            ("(self, index: i32)", "(self, index: u64)"),
+            ("(self): i32", "(self): u64"),
+        ] {
+            let source = source
+                .replace(from, to)
+                .replace("(* index 2)", "(as i32 index)")
+                .replace("  self\n", "  (as u64 self)\n");
+            let errors = resolve_source(&source);
+            assert_eq!(errors.len(), 1, "{:?}", errors);
+            let CompileErrorKind::TypeMismatch { expected, actual } = errors[0].kind() else {
+                panic!("{:?}", errors);
+            };
+            assert_eq!((expected, actual), (&ResolvedType::I32, &ResolvedType::U64));
+        }
+    }
+
+    #[test]
+    fn test_resolve_library_roots() {
+        let source = r#"
+interface ->bool(self) : bool
+
+impl ->bool for i32 (self) {
+  (> self 0)
+}
+
+fn helper(x: i32): i32 {
+  (* x 2)
+}
+
+fn unused(): i32 {
+  1
+}
+
+pub fn double(x: i32): i32 {
+  (helper x)
+}
+
+pub fn truthy<T: ->bool>(v: T): bool {
+  (->bool v)
+}
+"#;
+        let (resolved, errors) = resolve_source_with(source, resolve_library);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        // ジェネリックでない関数と実装だけを解決する。非公開の関数はジェネリックな関数から呼ばれ得るので残す
+        let mut names = resolved
+            .toplevels
+            .iter()
+            .map(|resolved_ast::TopLevel::Function(function)| {
+                mangle::demangle(&function.decl.name)
+                    .unwrap_or_else(|| function.decl.name.clone())
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["<i32 as ->bool>", "double", "helper", "unused"]);
+    }
+
+    #[test]
+    fn test_resolve_tests() {
+        use crate::parser::parse_module;
+        use nom::Finish;
+
+        let source = r#"
+use Vec::*
+use Missing::*
+
+fn helper(x: i32): i32 {
+  (* x 2)
+}
+
+fn unused(): i32 {
+  1
+}
+
+#[test]
+fn doubles(): bool {
+  (= (helper 2) 4)
+}
+
+#[test]
+fn takes_argument(x: i32): bool {
+  (= x 1)
+}
+"#;
+        let (_, module) = parse_module(source.into()).finish().unwrap();
+        let context = ResolverContext::new(PointerSizedIntWidth::SixtyFour);
+        let resolved = resolve_tests(&context, &module).unwrap();
+
+        // mainがなくても、テストとそこから呼ばれる関数だけを解決する
+        let mut names = resolved
+            .toplevels
+            .iter()
+            .map(|resolved_ast::TopLevel::Function(function)| {
+                mangle::demangle(&function.decl.name)
+                    .unwrap_or_else(|| function.decl.name.clone())
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["doubles", "helper"]);
+
+        let errors = format!("{:?}", context.errors.borrow());
+        assert_eq!(context.errors.borrow().len(), 2, "{}", errors);
+        assert!(errors.contains("InvalidTestFunction"), "{}", errors);
+        assert!(errors.contains("UnresolvedImport"), "{}", errors);
+    }
+
+    #[test]
+    fn test_resolve_with_library_metadata() {
+        use crate::parser::parse_module;
+        use nom::Finish;
+
+        let library_source = r#"
+interface ->bool(self) : bool
+
+impl ->bool for i32 (self) {
+  (> self 0)
+}
+
+pub fn double(x: i32): i32 {
+  (* x 2)
+}
+
+pub fn truthy<T: ->bool>(v: T): bool {
+  (->bool v)
+}
+
+fn offset(): i32 {
+  1
+}
+
+pub fn shifted<T>(v: T): i32 {
+  (offset)
+}
+"#;
+        let (_, library) = parse_module(library_source.into()).finish().unwrap();
+        let metadata = LibraryMetadata::new(&library, std::path::Path::new("lib.hr"));
+
+        // ライブラリのソースは使わず、メタデータだけを読み込んで解決する
+        let source = "fn main(): i32 {\n  (if (truthy (double 2)) (shifted true) 0)\n}\n";
+        let (_, module) = parse_module(source.into()).finish().unwrap();
+        let mut context = ResolverContext::new(PointerSizedIntWidth::SixtyFour);
+        context.libraries.push(metadata.clone());
+        let resolved = resolve_module(&context, &module, true).unwrap();
+        assert!(
+            context.errors.borrow().is_empty(),
+            "{:?}",
+            context.errors.borrow()
+        );
+
+        let linkage = |name: &str| {
+            resolved
+                .toplevels
+                .iter()
+                .map(|resolved_ast::TopLevel::Function(function)| function)
+                .find(|function| {
+                    mangle::demangle(&function.decl.name)
+                        .unwrap_or_else(|| function.decl.name.clone())
+                        == name
+                })
+                .map(|function| (function.decl.linkage, function.body.is_empty()))
+                .unwrap()
+        };
+        assert_eq!(linkage("main"), (Linkage::Unique, false));
+        // 本体はライブラリにある
+        assert_eq!(linkage("double"), (Linkage::Imported, true));
+        assert_eq!(linkage("<i32 as ->bool>"), (Linkage::Imported, true));
+        // ジェネリック関数はここでインスタンス化する
+        assert_eq!(linkage("truthy<i32>"), (Linkage::Instance, false));
+        // 公開したジェネリック関数から呼ばれる非公開の関数も、本体はライブラリにある
+        assert_eq!(linkage("shifted<bool>"), (Linkage::Instance, false));
+        assert_eq!(linkage("offset"), (Linkage::Imported, true));
+
+        // 非公開の関数を直接呼び出すことはできない
+        let source = "fn main(): i32 {\n  (offset)\n}\n";
+        let (_, module) = parse_module(source.into()).finish().unwrap();
+        let mut context = ResolverContext::new(PointerSizedIntWidth::SixtyFour);
+        context.libraries.push(metadata);
+        resolve_module(&context, &module, true).unwrap();
+        let errors = format!("{:?}", context.errors.borrow());
+        assert!(errors.contains("PrivateFunction"), "{}", errors);
+    }
+}
@@ -1,26 +1,73 @@
-use crate::ast::{Located, Statement};
-use crate::resolved_ast::{self};
+use crate::ast::{Located, Range, ReturnStatement, Statement};
+use crate::resolved_ast::{self, ResolvedType};
 
-use super::error::FaitalError;
-use super::expression::resolve_expression;
+use super::error::{CompileError, CompileErrorKind, FaitalError};
+use super::expression::{coerce_integer, coerce_to_dyn, resolve_expression};
 use super::ResolverContext;
 
 pub fn resolve_statement(
     context: &ResolverContext,
     loc_statement: &Located<Statement>,
 ) -> Result<resolved_ast::Statement, FaitalError> {
+    let location = context.location(loc_statement.range);
     Ok(match &loc_statement.value {
         Statement::Return(ret) => {
             if let Some(expr) = &ret.expression {
                 resolved_ast::Statement::Return(resolved_ast::Return {
                     expression: Some(resolve_expression(context, expr.as_ref().into(), None)?),
+                    location,
                 })
             } else {
-                resolved_ast::Statement::Return(resolved_ast::Return { expression: None })
+                resolved_ast::Statement::Return(resolved_ast::Return {
+                    expression: None,
+                    location,
+                })
             }
         }
         Statement::Effect(effect) => resolved_ast::Statement::Effect(resolved_ast::Effect {
             expression: resolve_expression(context, effect.expression.as_ref(), None)?,
+            location,
         }),
     })
 }
+
+// `(do ... return x)` のように、関数の途中から抜けるreturn。値は関数の戻り値の型に合わせる
+pub fn resolve_return_in_block(
+    context: &ResolverContext,
+    ret: &ReturnStatement,
+    range: Range,
+) -> Result<resolved_ast::Statement, FaitalError> {
+    let return_type = context.return_type.borrow().clone();
+    let expression = match &ret.expression {
+        Some(expr) => {
+            let value = resolve_expression(context, expr.as_ref().into(), Some(&return_type))?;
+            let value = coerce_to_dyn(context, &return_type, value, expr.range)?;
+            if !context.can_insert(&return_type, &value.ty) {
+                context.errors.borrow_mut().push(CompileError::new(
+                    expr.range,
+                    CompileErrorKind::TypeMismatch {
+                        expected: context.zonk_for_error(&return_type),
+                        actual: context.zonk_for_error(&value.ty),
+                    },
+                ));
+            }
+            Some(coerce_integer(context, &return_type, value, expr.range))
+        }
+        None => {
+            if !context.unify(&return_type, &ResolvedType::Void) {
+                context.errors.borrow_mut().push(CompileError::new(
+                    range,
+                    CompileErrorKind::TypeMismatch {
+                        expected: context.zonk_for_error(&return_type),
+                        actual: ResolvedType::Void,
+                    },
+                ));
+            }
+            None
+        }
+    };
+    Ok(resolved_ast::Statement::Return(resolved_ast::Return {
+        expression,
+        location: context.location(range),
+    }))
+}
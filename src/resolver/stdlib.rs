@@ -4,15 +4,16 @@ use nom::{
     error::{convert_error, VerboseError},
     Finish,
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    ast::{self, Function, Implementation, Interface, TypeDef},
+    ast::{self, FileId, Function, Implementation, Interface, TypeDef},
     parser::parse_module,
 };
 
 /// Standard library definitions
 /// These are parsed and registered before user code.
-const STDLIB_DEFINITIONS: &str = r#"
+pub(super) const STDLIB_DEFINITIONS: &str = r#"
 // Vec<T> - Dynamic array type
 struct Vec<T> {
     capacity: i32,
@@ -34,7 +35,7 @@ fn Vec::new<T>(): Vec<T> {
 
 fn Vec::get<T>(vec: Vec<T>, index: i32): T {
     (:= buf vec.buf)
-    (:= idx : usize index)
+    (:= idx (as usize index))
     buf[idx]
 }
 
@@ -52,7 +53,7 @@ fn Vec::is_empty<T>(vec: Vec<T>): bool {
 
 fn Vec::set<T>(vec: Vec<T>, index: i32, value: T): void {
     (:= buf vec.buf)
-    (:= idx : usize index)
+    (:= idx (as usize index))
     (:=< buf[idx] value)
 }
 
@@ -61,10 +62,14 @@ fn Vec::first<T>(vec: Vec<T>): T {
     buf[0]
 }
 
+// 空のVecから要素を取り出そうとしたら、範囲外を読む前に止める
 fn Vec::last<T>(vec: Vec<T>): T {
+    (when (= vec.size 0)
+        (do
+            (printf "Vec::last: the Vec is empty\n")
+            (exit 1)))
     (:= buf vec.buf)
-    (:= last_idx_i32 (- vec.size 1))
-    (:= last_idx : usize last_idx_i32)
+    (:= last_idx (as usize (- vec.size 1)))
     buf[last_idx]
 }
 
@@ -74,11 +79,10 @@ fn Vec::free<T>(vec: Vec<T>): void {
 
 fn Vec::alloc_and_copy<T>(old_buf: *T, old_size: i32, new_capacity: i32): *T {
     (:= elem_size (sizeof T))
-    (:= alloc_size (* new_capacity elem_size))
+    (:= alloc_size (* (as usize new_capacity) elem_size))
     (:= new_buf : *T (malloc alloc_size))
-    (:= copy_size (* old_size elem_size))
-    (:= copy_size_usize : usize copy_size)
-    (memcpy new_buf old_buf copy_size_usize)
+    (:= copy_size (* (as usize old_size) elem_size))
+    (memcpy new_buf old_buf copy_size)
     (free old_buf)
     new_buf
 }
@@ -93,7 +97,7 @@ fn Vec::push<T>(vec: Vec<T>, value: T): Vec<T> {
             (Vec::alloc_and_copy<T> old_buf old_size new_capacity)
             old_buf
         )
-        size_idx : usize old_size)
+        size_idx (as usize old_size))
     (:=< new_buf[size_idx] value)
     (:= new_size (+ old_size 1)
         result Vec<T> {
@@ -104,15 +108,48 @@ fn Vec::push<T>(vec: Vec<T>, value: T): Vec<T> {
     result
 }
 
-fn Vec::pop<T>(vec: Vec<T>): Vec<T> {
-    Vec<T> {
-        capacity: vec.capacity,
-        size: (- vec.size 1),
-        buf: vec.buf
-    }
+// 最後の要素と、それを取り除いたVecの組を返す
+fn Vec::pop<T>(vec: Vec<T>): (T, Vec<T>) {
+    (:= last (Vec::last<T> vec)
+        rest Vec<T> {
+            capacity: vec.capacity,
+            size: (- vec.size 1),
+            buf: vec.buf
+        })
+    (tuple last rest)
+}
+
+// 要素ごとに関数を適用した新しいVecを返す
+fn Vec::map<T, U>(vec: Vec<T>, f: fn(T): U): Vec<U> {
+    (:= result (Vec::new<U>))
+    (for x vec
+        (:=< result (Vec::push<U> result (f x))))
+    result
+}
+
+// 条件を満たす要素だけを集めた新しいVecを返す
+fn Vec::filter<T>(vec: Vec<T>, pred: fn(T): bool): Vec<T> {
+    (:= result (Vec::new<T>))
+    (for x vec
+        (when (pred x)
+            (:=< result (Vec::push<T> result x))))
+    result
+}
+
+// Iteration interfaces used by `(for x collection body)`
+interface iter-len(self): i32
+interface iter-get<T>(self, index: i32): T
+
+impl iter-len<T> for Vec<T> (self) {
+    self.size
+}
+
+impl iter-get<T> for Vec<T> (self, index: i32): T {
+    (Vec::get<T> self index)
 }
 "#;
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StdlibRegistration {
     pub type_defs: Vec<TypeDef>,
     pub interfaces: Vec<Interface>,
@@ -148,10 +185,16 @@ pub fn parse_stdlib() -> Result<StdlibRegistration, String> {
                 interfaces.push(interface);
             }
             ast::TopLevel::Implemantation(implementation) => {
-                implementations.push(implementation);
+                implementations.push(Implementation {
+                    file: FileId::STDLIB,
+                    ..implementation
+                });
             }
             ast::TopLevel::Function(function) => {
-                functions.push(function);
+                functions.push(Function {
+                    file: FileId::STDLIB,
+                    ..function
+                });
             }
             ast::TopLevel::Use(_) => {
                 // Use statements in stdlib are ignored - they're just for documentation
@@ -169,12 +212,13 @@ pub fn parse_stdlib() -> Result<StdlibRegistration, String> {
 
 /// Register stdlib definitions into the resolver context
 pub fn register_stdlib(
+    stdlib: Result<StdlibRegistration, String>,
     type_defs: &mut HashMap<String, TypeDef>,
     interface_by_name: &mut HashMap<String, Interface>,
     impls_by_name: &mut HashMap<String, Vec<Implementation>>,
     function_by_name: &mut HashMap<String, Function>,
 ) {
-    match parse_stdlib() {
+    match stdlib {
         Ok(stdlib) => {
             for typedef in stdlib.type_defs {
                 type_defs.insert(typedef.name.clone(), typedef);
@@ -221,5 +265,18 @@ mod tests {
         assert!(fn_names.contains(&"Vec::len"), "Vec::len function not found");
         assert!(fn_names.contains(&"Vec::push"), "Vec::push function not found");
         assert!(fn_names.contains(&"Vec::pop"), "Vec::pop function not found");
+        assert!(fn_names.contains(&"Vec::map"), "Vec::map function not found");
+        assert!(fn_names.contains(&"Vec::filter"), "Vec::filter function not found");
+
+        // Vec::pop は取り出した要素と残りのVecの組を返す
+        let pop = stdlib
+            .functions
+            .iter()
+            .find(|f| f.decl.name == "Vec::pop")
+            .unwrap();
+        let ast::UnresolvedType::Tuple(elements) = &pop.decl.return_type.value else {
+            panic!("Vec::pop should return a tuple");
+        };
+        assert_eq!(elements.len(), 2);
     }
 }
@@ -1,6 +1,7 @@
 use crate::{in_new_scope, resolved_ast::ResolvedType};
 
-use self::resolved_ast::ResolvedStructType;
+use self::resolved_ast::{ResolvedFunctionType, ResolvedStructType};
+use super::infer::TypeVarKind;
 
 use super::*;
 
@@ -29,20 +30,6 @@ pub(super) fn resolve_type(
                                 } else {
                                     in_new_scope!(context.types, {
                                         for (i, generic_arg) in generic_args.iter().enumerate() {
-                                            if matches!(generic_arg.value, UnresolvedType::Infer) {
-                                                context
-                                                    .errors
-                                                    .borrow_mut()
-                                                    .push(CompileError::new(
-                                                    loc_ty.range,
-                                                    error::CompileErrorKind::CannotInferGenericArgs {
-                                                        name: typ_ref.name.clone(),
-                                                        message: "Generic inference here is not supported yet.".into()
-                                                    },
-                                                ));
-                                                return Ok(ResolvedType::Unknown);
-                                            }
-
                                             let resolved_generic_arg =
                                                 resolve_type(context, generic_arg)?;
                                             resolved_generic_args
@@ -138,7 +125,51 @@ pub(super) fn resolve_type(
             let inner_type: ResolvedType = resolve_type(context, inner_type)?;
             Ok(ResolvedType::Ptr(Box::new(inner_type)))
         }
-        UnresolvedType::Infer => Ok(ResolvedType::Unknown),
+        UnresolvedType::Dyn(interface_name) => {
+            if context
+                .interface_by_name
+                .borrow()
+                .contains_key(interface_name)
+            {
+                Ok(ResolvedType::Dyn(interface_name.clone()))
+            } else {
+                context.errors.borrow_mut().push(CompileError::new(
+                    loc_ty.range,
+                    error::CompileErrorKind::InterfaceNotFound {
+                        name: interface_name.clone(),
+                    },
+                ));
+                Ok(ResolvedType::Unknown)
+            }
+        }
+        UnresolvedType::Function(function_ty) => {
+            let args = function_ty
+                .args
+                .iter()
+                .map(|arg| resolve_type(context, arg))
+                .collect::<Result<Vec<_>>>()?;
+            let return_type = resolve_type(context, &function_ty.return_type)?;
+            Ok(ResolvedType::Function(ResolvedFunctionType {
+                args,
+                return_type: Box::new(return_type),
+            }))
+        }
+        UnresolvedType::Tuple(elements) => Ok(ResolvedType::Tuple(
+            elements
+                .iter()
+                .map(|element| resolve_type(context, element))
+                .collect::<Result<Vec<_>>>()?,
+        )),
+        UnresolvedType::Array(element, len) => Ok(ResolvedType::Array(
+            Box::new(resolve_type(context, element)?),
+            *len,
+        )),
+        // `_` は型推論で決まる型変数になる
+        UnresolvedType::Infer => Ok(context.fresh_type_var(
+            TypeVarKind::General,
+            loc_ty.range,
+            "this `_` placeholder",
+        )),
     }
 }
 